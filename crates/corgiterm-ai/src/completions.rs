@@ -1,6 +1,6 @@
 //! Smart command completions powered by AI
 
-use crate::{AiProvider, Message, Result, Role};
+use crate::{AiProvider, Message, RedactingProvider, Result, Role};
 
 /// AI-powered completion engine
 #[allow(dead_code)]
@@ -12,7 +12,7 @@ pub struct CompletionEngine {
 impl CompletionEngine {
     pub fn new(provider: Box<dyn AiProvider>) -> Self {
         Self {
-            provider,
+            provider: RedactingProvider::wrap(provider),
             context_lines: 10,
         }
     }
@@ -86,11 +86,11 @@ impl Conversation {
         }
     }
 
-    /// Add a message to the conversation
+    /// Add a message to the conversation, with secrets redacted
     pub fn add_message(&mut self, role: Role, content: &str) {
         self.messages.push(StoredMessage {
             role: role.into(),
-            content: content.to_string(),
+            content: corgiterm_core::redaction::redact(content),
             timestamp: Utc::now(),
         });
         self.updated_at = Utc::now();
@@ -1,12 +1,19 @@
 //! Command history storage and pattern extraction
 //!
-//! Tracks command history, analyzes patterns, and provides learning context
+//! Tracks command history, analyzes patterns, and provides learning context.
+//! Commands are stored in the shared [`HistoryDb`], written on its
+//! [`HistoryWriter`] thread; the store keeps the most recent `max_entries`
+//! in memory for pattern extraction.
 
 use crate::learning::{CommandPatternInfo, CommandPreference, FrequentCommand, LearningContext};
+use chrono::DateTime;
+use corgiterm_core::history::{CommandEntry, HistoryScope};
+use corgiterm_core::{HistoryDb, HistoryWriter};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Command history entry
@@ -22,16 +29,76 @@ pub struct HistoryEntry {
     pub exit_code: Option<i32>,
     /// Duration in milliseconds
     pub duration_ms: Option<u64>,
+    /// Terminal session the command ran in
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Remote host (None for local commands)
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Project the command ran in
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// Git branch checked out where the command ran
+    #[serde(default)]
+    pub git_branch: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Whether the entry is in `scope`
+    pub fn in_scope(&self, scope: &HistoryScope) -> bool {
+        scope.matches(
+            self.session.as_deref(),
+            self.project_id.as_deref(),
+            self.host.as_deref(),
+        )
+    }
+}
+
+impl From<CommandEntry> for HistoryEntry {
+    fn from(entry: CommandEntry) -> Self {
+        Self {
+            command: entry.command,
+            directory: entry.cwd.to_string_lossy().into_owned(),
+            timestamp: entry.timestamp.timestamp().max(0) as u64,
+            exit_code: entry.exit_code,
+            duration_ms: entry.duration_ms,
+            session: entry.session,
+            host: entry.host,
+            project_id: entry.project_id,
+            git_branch: entry.git_branch,
+        }
+    }
+}
+
+impl From<&HistoryEntry> for CommandEntry {
+    fn from(entry: &HistoryEntry) -> Self {
+        let mut command = CommandEntry::new(entry.command.clone(), entry.directory.clone().into());
+        command.timestamp = DateTime::from_timestamp(entry.timestamp as i64, 0).unwrap_or_default();
+        command.exit_code = entry.exit_code;
+        command.success = entry.exit_code.map(|code| code == 0);
+        command.duration_ms = entry.duration_ms;
+        command.session = entry.session.clone();
+        command.host = entry.host.clone();
+        command.project_id = entry.project_id.clone();
+        command.git_branch = entry.git_branch.clone();
+        command
+    }
 }
 
 /// Persistent command history store
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandHistoryStore {
-    /// All history entries
+    /// Most recent history entries, oldest first
     entries: Vec<HistoryEntry>,
     /// Max entries to keep
     #[serde(default = "default_max_entries")]
     max_entries: usize,
+    /// Shared history database (the JSON file is only used without one)
+    #[serde(skip)]
+    db: Option<Arc<HistoryDb>>,
+    /// Background writer for `db`
+    #[serde(skip)]
+    writer: Option<HistoryWriter>,
 }
 
 fn default_max_entries() -> usize {
@@ -43,6 +110,8 @@ impl Default for CommandHistoryStore {
         Self {
             entries: Vec::new(),
             max_entries: default_max_entries(),
+            db: None,
+            writer: None,
         }
     }
 }
@@ -53,8 +122,52 @@ impl CommandHistoryStore {
         Self::default()
     }
 
-    /// Load from config directory
+    /// Open the shared history database, importing the old JSON file
+    /// on first use; falls back to the JSON file if the database fails
     pub fn load() -> Self {
+        match HistoryDb::open_default() {
+            Ok(db) => Self::with_database(Arc::new(db)),
+            Err(e) => {
+                tracing::warn!("Failed to open history database: {}", e);
+                Self::load_json()
+            }
+        }
+    }
+
+    /// Back the store with `db`, loading its most recent entries
+    pub fn with_database(db: Arc<HistoryDb>) -> Self {
+        let mut store = Self {
+            writer: Some(HistoryWriter::spawn(db.clone())),
+            db: Some(db),
+            ..Self::default()
+        };
+        store.reload();
+        store
+    }
+
+    /// The history database backing the store, if any
+    pub fn database(&self) -> Option<&Arc<HistoryDb>> {
+        self.db.as_ref()
+    }
+
+    /// The writer recording to the database, if any
+    pub fn writer(&self) -> Option<&HistoryWriter> {
+        self.writer.as_ref()
+    }
+
+    /// Re-read the most recent entries from the database, e.g. after an import
+    pub fn reload(&mut self) {
+        let Some(ref db) = self.db else {
+            return;
+        };
+        match db.recent(self.max_entries) {
+            Ok(recent) => self.entries = recent.into_iter().rev().map(Into::into).collect(),
+            Err(e) => tracing::warn!("Failed to read history database: {}", e),
+        }
+    }
+
+    /// Load from the JSON file in the config directory
+    fn load_json() -> Self {
         let path = Self::storage_path();
         if path.exists() {
             match fs::read_to_string(&path) {
@@ -68,8 +181,11 @@ impl CommandHistoryStore {
         Self::default()
     }
 
-    /// Save to config directory
+    /// Save to config directory (a no-op with a database, which is always current)
     pub fn save(&self) -> Result<(), std::io::Error> {
+        if self.db.is_some() {
+            return Ok(());
+        }
         let path = Self::storage_path();
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
@@ -86,7 +202,7 @@ impl CommandHistoryStore {
             .join("command_history.json")
     }
 
-    /// Record a command execution
+    /// Record a command execution, with secrets redacted
     pub fn record(
         &mut self,
         command: String,
@@ -99,13 +215,26 @@ impl CommandHistoryStore {
             .map(|d| d.as_secs())
             .unwrap_or(0);
 
-        self.entries.push(HistoryEntry {
+        self.record_entry(HistoryEntry {
             command,
             directory,
             timestamp,
             exit_code,
             duration_ms,
+            session: None,
+            host: None,
+            project_id: None,
+            git_branch: None,
         });
+    }
+
+    /// Record a history entry, with secrets redacted
+    pub fn record_entry(&mut self, mut entry: HistoryEntry) {
+        entry.command = corgiterm_core::redaction::redact(&entry.command);
+        if let Some(ref writer) = self.writer {
+            writer.insert(CommandEntry::from(&entry));
+        }
+        self.entries.push(entry);
 
         // Trim to max entries
         if self.entries.len() > self.max_entries {
@@ -129,6 +258,36 @@ impl CommandHistoryStore {
             .collect()
     }
 
+    /// Get commands in a session, project or host
+    pub fn for_scope(&self, scope: &HistoryScope, limit: usize) -> Vec<&HistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.in_scope(scope))
+            .take(limit)
+            .collect()
+    }
+
+    /// Distinct commands in `scope`, most recent first, from all
+    /// recorded history when there is a database
+    pub fn recall(&self, scope: &HistoryScope, limit: usize) -> Vec<String> {
+        if let Some(ref db) = self.db {
+            match db.recall(scope, limit) {
+                Ok(commands) => return commands,
+                Err(e) => tracing::warn!("History recall failed: {}", e),
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.in_scope(scope))
+            .filter(|e| seen.insert(e.command.as_str()))
+            .take(limit)
+            .map(|e| e.command.clone())
+            .collect()
+    }
+
     /// Get entry count
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -328,24 +487,38 @@ impl CommandHistoryStore {
             .collect()
     }
 
+    /// Search all recorded history, not just the in-memory window
+    pub fn search_all(&self, query: &str, limit: usize) -> Vec<HistoryEntry> {
+        if let Some(ref db) = self.db {
+            match db.search(query, limit) {
+                Ok(found) => return found.into_iter().map(Into::into).collect(),
+                Err(e) => tracing::warn!("History search failed: {}", e),
+            }
+        }
+        self.search(query, limit).into_iter().cloned().collect()
+    }
+
     /// Fuzzy search history with scoring
     pub fn fuzzy_search(&self, query: &str, limit: usize) -> Vec<(&HistoryEntry, f32)> {
+        self.fuzzy_search_in(query, &HistoryScope::Global, limit)
+    }
+
+    /// Fuzzy search the commands in a session, project or host
+    pub fn fuzzy_search_in(
+        &self,
+        query: &str,
+        scope: &HistoryScope,
+        limit: usize,
+    ) -> Vec<(&HistoryEntry, f32)> {
         let query_lower = query.to_lowercase();
         let query_chars: Vec<char> = query_lower.chars().collect();
+        let in_scope = self.entries.iter().filter(|e| e.in_scope(scope));
 
         if query_chars.is_empty() {
-            return self
-                .entries
-                .iter()
-                .rev()
-                .take(limit)
-                .map(|e| (e, 1.0))
-                .collect();
+            return in_scope.rev().take(limit).map(|e| (e, 1.0)).collect();
         }
 
-        let mut scored: Vec<(&HistoryEntry, f32)> = self
-            .entries
-            .iter()
+        let mut scored: Vec<(&HistoryEntry, f32)> = in_scope
             .filter_map(|entry| {
                 let cmd_lower = entry.command.to_lowercase();
                 let score = fuzzy_match_score(&query_chars, &cmd_lower);
@@ -518,6 +691,62 @@ mod tests {
         assert_eq!(store.len(), 1);
     }
 
+    #[test]
+    fn test_database_store() {
+        let db = Arc::new(HistoryDb::open_in_memory().unwrap());
+        let mut store = CommandHistoryStore::with_database(db.clone());
+        store.record_entry(HistoryEntry {
+            command: "ssh deploy@web1".to_string(),
+            directory: "/srv".to_string(),
+            timestamp: 1_700_000_000,
+            exit_code: Some(0),
+            duration_ms: Some(50),
+            session: Some("pid 42".to_string()),
+            host: Some("web1".to_string()),
+            project_id: None,
+            git_branch: Some("main".to_string()),
+        });
+        store.writer().unwrap().flush();
+        assert_eq!(db.len().unwrap(), 1);
+
+        let reopened = CommandHistoryStore::with_database(db);
+        assert_eq!(reopened.len(), 1);
+        let found = reopened.search_all("deploy", 10);
+        assert_eq!(found[0].host.as_deref(), Some("web1"));
+        assert_eq!(found[0].timestamp, 1_700_000_000);
+        assert_eq!(found[0].git_branch.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_scoped_search() {
+        let mut store = CommandHistoryStore::new();
+        for (command, project, host) in [
+            ("cargo build", Some("app"), None),
+            ("cargo test", Some("app"), None),
+            ("cargo build", Some("app"), None),
+            ("cargo clean", None, Some("web1")),
+        ] {
+            store.record_entry(HistoryEntry {
+                command: command.to_string(),
+                directory: "/src".to_string(),
+                timestamp: 1_700_000_000,
+                exit_code: Some(0),
+                duration_ms: None,
+                session: Some("pid 1".to_string()),
+                host: host.map(str::to_string),
+                project_id: project.map(str::to_string),
+                git_branch: None,
+            });
+        }
+
+        let app = HistoryScope::Project("app".to_string());
+        assert_eq!(store.fuzzy_search_in("cargo", &app, 10).len(), 3);
+        assert_eq!(store.fuzzy_search("cargo", 10).len(), 4);
+        let web1 = HistoryScope::Host(Some("web1".to_string()));
+        assert_eq!(store.for_scope(&web1, 10)[0].command, "cargo clean");
+        assert_eq!(store.recall(&app, 10), ["cargo build", "cargo test"]);
+    }
+
     #[test]
     fn test_frequent_commands() {
         let mut store = CommandHistoryStore::new();
@@ -554,6 +783,10 @@ mod tests {
                 timestamp: now + i * 60,
                 exit_code: Some(0),
                 duration_ms: None,
+                session: None,
+                host: None,
+                project_id: None,
+                git_branch: None,
             });
             store.entries.push(HistoryEntry {
                 command: "pytest tests/".to_string(),
@@ -561,6 +794,10 @@ mod tests {
                 timestamp: now + i * 60 + 30,
                 exit_code: Some(0),
                 duration_ms: None,
+                session: None,
+                host: None,
+                project_id: None,
+                git_branch: None,
             });
         }
 
@@ -2,7 +2,9 @@
 //!
 //! Enhances AI suggestions with learned command patterns and user preferences
 
-use crate::{AiProvider, CommandContext, CommandSuggestion, Message, Result, Role};
+use crate::{
+    AiProvider, CommandContext, CommandSuggestion, Message, RedactingProvider, Result, Role,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -52,7 +54,7 @@ pub struct LearningAi {
 impl LearningAi {
     pub fn new(provider: Box<dyn AiProvider>) -> Self {
         Self {
-            provider,
+            provider: RedactingProvider::wrap(provider),
             learning_context: LearningContext::default(),
         }
     }
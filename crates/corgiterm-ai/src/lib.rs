@@ -12,18 +12,22 @@
 //! - Smart auto-completion
 //! - Safe Mode AI explanations
 //! - MCP (Model Context Protocol) support
+//! - Secret redaction of everything sent to providers
 
 pub mod completions;
 pub mod conversation;
 pub mod history;
 pub mod learning;
 pub mod mcp;
+pub mod mcp_transport;
 pub mod models;
 pub mod natural_language;
 pub mod providers;
+pub mod redaction;
 
 // Re-export commonly used types from models module
 pub use models::{CachedModelList, ModelInfo, ModelRegistry, ModelRegistryConfig};
+pub use redaction::RedactingProvider;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -129,7 +133,9 @@ pub struct NaturalLanguage {
 
 impl NaturalLanguage {
     pub fn new(provider: Box<dyn AiProvider>) -> Self {
-        Self { provider }
+        Self {
+            provider: RedactingProvider::wrap(provider),
+        }
     }
 
     /// Translate natural language to shell command
@@ -258,7 +264,7 @@ impl AiManager {
 
     /// Add a provider
     pub fn add_provider(&mut self, provider: Box<dyn AiProvider>) {
-        self.providers.push(provider);
+        self.providers.push(RedactingProvider::wrap(provider));
     }
 
     /// Set default provider by name
@@ -6,26 +6,44 @@
 //! # Architecture
 //!
 //! The MCP server provides JSON-RPC 2.0 interface for tools execution:
+//! - Handshake via `initialize` and `notifications/initialized`
 //! - Tool discovery via `tools/list`
 //! - Tool execution via `tools/call`
 //! - Resource listing via `resources/list`
 //!
+//! [`McpSession`] tracks the handshake for one client connection; the
+//! transports in [`crate::mcp_transport`] create one per connection.
+//!
 //! # Tool Integration
 //!
-//! Tools are currently implemented with placeholder logic. To integrate with
-//! the actual terminal, implement the `TerminalBackend` trait and pass it to
-//! `McpServer::with_backend()`.
+//! Tools run against a [`TerminalBackend`]. The running app provides one
+//! backed by its open tabs and panes; [`HeadlessBackend`] runs each command in
+//! a fresh PTY for `corgiterm mcp` without a window. Without a backend the
+//! tools return placeholder text.
 //!
 //! Example:
 //! ```ignore
-//! let backend = MyTerminalBackend::new();
+//! let backend = Arc::new(MyTerminalBackend::new());
 //! let server = McpServer::with_backend(backend);
 //! ```
 
 use async_trait::async_trait;
+use corgiterm_core::redaction;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// MCP protocol revisions this server speaks, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// JSON-RPC error codes
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const INTERNAL_ERROR: i32 = -32603;
+/// A request other than `initialize` or `ping` arrived before the handshake
+pub const SERVER_NOT_INITIALIZED: i32 = -32002;
+
 /// Backend interface for terminal operations
 ///
 /// Implement this trait to connect MCP tools to your terminal implementation.
@@ -217,66 +235,58 @@ impl McpServer {
     }
 
     /// Handle an MCP request
+    ///
+    /// Stateless: handshake ordering is enforced by [`McpSession`].
     pub async fn handle_request(&self, request: McpRequest) -> McpResponse {
+        let id = request.id.clone().unwrap_or(serde_json::Value::Null);
         match request.method.as_str() {
-            "tools/list" => match serde_json::to_value(&self.tools) {
-                Ok(value) => McpResponse {
-                    id: request.id,
-                    result: Some(value),
-                    error: None,
-                },
-                Err(e) => McpResponse {
-                    id: request.id,
-                    result: None,
-                    error: Some(McpError {
-                        code: -32603,
-                        message: format!("Failed to serialize tools: {}", e),
-                    }),
-                },
-            },
+            "initialize" => McpResponse::success(id, self.initialize_result(&request)),
+            "ping" => McpResponse::success(id, serde_json::json!({})),
+            "tools/list" => McpResponse::success(id, serde_json::json!({ "tools": self.tools })),
             "tools/call" => {
                 // Handle tool execution
-                self.execute_tool(request.id, request.params).await
+                self.execute_tool(id, request.params).await
             }
-            "resources/list" => match serde_json::to_value(&self.resources) {
-                Ok(value) => McpResponse {
-                    id: request.id,
-                    result: Some(value),
-                    error: None,
-                },
-                Err(e) => McpResponse {
-                    id: request.id,
-                    result: None,
-                    error: Some(McpError {
-                        code: -32603,
-                        message: format!("Failed to serialize resources: {}", e),
-                    }),
-                },
-            },
-            _ => McpResponse {
-                id: request.id,
-                result: None,
-                error: Some(McpError {
-                    code: -32601,
-                    message: "Method not found".to_string(),
-                }),
-            },
+            "resources/list" => {
+                McpResponse::success(id, serde_json::json!({ "resources": self.resources }))
+            }
+            _ => McpResponse::failure(id, METHOD_NOT_FOUND, "Method not found"),
         }
     }
 
-    async fn execute_tool(&self, id: String, params: Option<serde_json::Value>) -> McpResponse {
+    /// Result for `initialize`: agree on a protocol version and advertise capabilities
+    fn initialize_result(&self, request: &McpRequest) -> serde_json::Value {
+        let requested = request
+            .params
+            .as_ref()
+            .and_then(|p| p.get("protocolVersion"))
+            .and_then(|v| v.as_str());
+        // Echo the client's version when we speak it, otherwise offer our newest
+        let version = requested
+            .filter(|v| SUPPORTED_PROTOCOL_VERSIONS.contains(v))
+            .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+
+        serde_json::json!({
+            "protocolVersion": version,
+            "capabilities": {
+                "tools": { "listChanged": false },
+                "resources": { "listChanged": false }
+            },
+            "serverInfo": {
+                "name": "corgiterm",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        })
+    }
+
+    async fn execute_tool(
+        &self,
+        id: serde_json::Value,
+        params: Option<serde_json::Value>,
+    ) -> McpResponse {
         let params = match params {
             Some(p) => p,
-            None => {
-                return McpResponse {
-                    id,
-                    result: None,
-                    error: Some(McpError {
-                        code: -32602,
-                        message: "Missing parameters".to_string(),
-                    }),
-                }
-            }
+            None => return McpResponse::failure(id, INVALID_PARAMS, "Missing parameters"),
         };
 
         let tool_name = params.get("name").and_then(|v| v.as_str()).unwrap_or("");
@@ -284,36 +294,25 @@ impl McpServer {
 
         // Validate tool exists
         if !self.tools.iter().any(|t| t.name == tool_name) {
-            return McpResponse {
+            return McpResponse::failure(
                 id,
-                result: None,
-                error: Some(McpError {
-                    code: -32602,
-                    message: format!("Unknown tool: {}", tool_name),
-                }),
-            };
+                INVALID_PARAMS,
+                format!("Unknown tool: {}", tool_name),
+            );
         }
 
-        // Execute the tool
+        // Execute the tool; output may hold secrets, so redact it first
         match self.execute_tool_impl(tool_name, tool_args).await {
-            Ok(result) => McpResponse {
+            Ok(result) => McpResponse::success(
                 id,
-                result: Some(serde_json::json!({
+                serde_json::json!({
                     "content": [{
                         "type": "text",
-                        "text": result
+                        "text": redaction::redact(&result)
                     }]
-                })),
-                error: None,
-            },
-            Err(err) => McpResponse {
-                id,
-                result: None,
-                error: Some(McpError {
-                    code: -32603,
-                    message: err,
                 }),
-            },
+            ),
+            Err(err) => McpResponse::failure(id, INTERNAL_ERROR, redaction::redact(&err)),
         }
     }
 
@@ -482,6 +481,7 @@ impl Default for McpServer {
 pub struct McpTool {
     pub name: String,
     pub description: String,
+    #[serde(rename = "inputSchema")]
     pub input_schema: serde_json::Value,
 }
 
@@ -491,26 +491,57 @@ pub struct McpResource {
     pub uri: String,
     pub name: String,
     pub description: Option<String>,
+    #[serde(rename = "mimeType")]
     pub mime_type: Option<String>,
 }
 
 /// MCP JSON-RPC request
+///
+/// A message without an `id` is a notification and gets no response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpRequest {
     pub jsonrpc: String,
-    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
     pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<serde_json::Value>,
 }
 
 /// MCP JSON-RPC response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpResponse {
-    pub id: String,
+    pub jsonrpc: String,
+    pub id: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub error: Option<McpError>,
 }
 
+impl McpResponse {
+    pub fn success(id: serde_json::Value, result: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn failure(id: serde_json::Value, code: i32, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(McpError {
+                code,
+                message: message.into(),
+            }),
+        }
+    }
+}
+
 /// MCP error
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpError {
@@ -518,6 +549,207 @@ pub struct McpError {
     pub message: String,
 }
 
+/// Handshake progress of one client connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Handshake {
+    AwaitingInitialize,
+    AwaitingInitialized,
+    Ready,
+}
+
+/// Protocol state for one client connection
+///
+/// Enforces the `initialize` → `notifications/initialized` handshake and
+/// swallows notifications, then hands requests to the shared [`McpServer`].
+pub struct McpSession {
+    server: Arc<McpServer>,
+    handshake: Handshake,
+}
+
+impl McpSession {
+    pub fn new(server: Arc<McpServer>) -> Self {
+        Self {
+            server,
+            handshake: Handshake::AwaitingInitialize,
+        }
+    }
+
+    /// Has the client completed the handshake?
+    pub fn is_ready(&self) -> bool {
+        self.handshake == Handshake::Ready
+    }
+
+    /// Handle one incoming message; notifications never get a response
+    pub async fn handle(&mut self, request: McpRequest) -> Option<McpResponse> {
+        if request.jsonrpc != "2.0" {
+            return request
+                .id
+                .map(|id| McpResponse::failure(id, INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+        }
+
+        let Some(id) = request.id.clone() else {
+            if request.method == "notifications/initialized"
+                && self.handshake == Handshake::AwaitingInitialized
+            {
+                self.handshake = Handshake::Ready;
+            }
+            return None;
+        };
+
+        match request.method.as_str() {
+            "initialize" => {
+                let response = self.server.handle_request(request).await;
+                self.handshake = Handshake::AwaitingInitialized;
+                Some(response)
+            }
+            "ping" => Some(self.server.handle_request(request).await),
+            _ if self.handshake == Handshake::AwaitingInitialize => Some(McpResponse::failure(
+                id,
+                SERVER_NOT_INITIALIZED,
+                "Server not initialized: send initialize first",
+            )),
+            _ => Some(self.server.handle_request(request).await),
+        }
+    }
+}
+
+/// Terminal backend that runs each command in a fresh PTY
+///
+/// Used by `corgiterm mcp` when no CorgiTerm window is running. There are no
+/// persistent sessions, so only `execute_command` and `search_history` work.
+/// Safe Mode refuses dangerous commands before they are spawned.
+pub struct HeadlessBackend {
+    /// Terminal size commands run in
+    pub size: corgiterm_core::TerminalSize,
+}
+
+impl HeadlessBackend {
+    /// Default command timeout when the agent does not pass one
+    pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+
+    pub fn new() -> Self {
+        Self {
+            size: corgiterm_core::TerminalSize {
+                rows: 200,
+                cols: 160,
+            },
+        }
+    }
+
+    fn no_sessions() -> String {
+        "No terminal sessions in headless mode; enable ai.mcp in a running CorgiTerm to use its tabs"
+            .to_string()
+    }
+}
+
+impl Default for HeadlessBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl TerminalBackend for HeadlessBackend {
+    async fn execute_command(
+        &self,
+        command: &str,
+        cwd: Option<&str>,
+        timeout_ms: Option<u64>,
+    ) -> Result<String, String> {
+        use corgiterm_core::{headless, HeadlessOptions, HeadlessOutput};
+
+        let timeout_ms = timeout_ms.unwrap_or(Self::DEFAULT_TIMEOUT_MS);
+        let mut options = HeadlessOptions::new(command);
+        options.size = self.size;
+        options.working_dir = cwd.map(std::path::PathBuf::from);
+        options.timeout = Some(std::time::Duration::from_millis(timeout_ms));
+        options.output = HeadlessOutput::Screen;
+        options.safe_mode = true;
+
+        let result =
+            tokio::task::spawn_blocking(move || headless::run(&options, &mut std::io::sink()))
+                .await
+                .map_err(|e| format!("Command task failed: {}", e))?
+                .map_err(|e| e.to_string())?;
+
+        if let Some(preview) = result.blocked {
+            return Err(format!(
+                "Safe Mode blocked this command ({}): {}",
+                preview.risk.label(),
+                preview.explanation.join("; ")
+            ));
+        }
+        if result.timed_out {
+            return Err(format!("Command timed out after {}ms", timeout_ms));
+        }
+
+        Ok(format!(
+            "{}\n[exit code: {}]",
+            result.screen.join("\n"),
+            result.exit_code
+        ))
+    }
+
+    async fn get_output(&self, _lines: u64, _session_id: Option<&str>) -> Result<String, String> {
+        Err(Self::no_sessions())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<SessionInfo>, String> {
+        Ok(Vec::new())
+    }
+
+    async fn create_session(
+        &self,
+        _name: Option<&str>,
+        _cwd: Option<&str>,
+        _project_id: Option<&str>,
+    ) -> Result<SessionInfo, String> {
+        Err(Self::no_sessions())
+    }
+
+    async fn switch_session(&self, _session_id: &str) -> Result<(), String> {
+        Err(Self::no_sessions())
+    }
+
+    async fn search_history(
+        &self,
+        query: &str,
+        search_type: &str,
+    ) -> Result<Vec<HistoryEntry>, String> {
+        search_command_history(
+            &crate::history::CommandHistoryStore::load(),
+            query,
+            search_type,
+        )
+    }
+}
+
+/// Search the AI command history store on behalf of `search_history`
+///
+/// Output history is not persisted, so `"output"` searches return nothing.
+pub fn search_command_history(
+    store: &crate::history::CommandHistoryStore,
+    query: &str,
+    search_type: &str,
+) -> Result<Vec<HistoryEntry>, String> {
+    match search_type {
+        "commands" | "all" => Ok(store
+            .search_all(query, 50)
+            .into_iter()
+            .map(|entry| HistoryEntry {
+                timestamp: entry.timestamp as i64,
+                content: entry.command,
+                entry_type: "command".to_string(),
+            })
+            .collect()),
+        "output" => Ok(Vec::new()),
+        other => Err(format!(
+            "Unknown search type: {} (expected commands, output or all)",
+            other
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -544,7 +776,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-1".to_string(),
+            id: Some("test-1".into()),
             method: "tools/list".to_string(),
             params: None,
         };
@@ -559,7 +791,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-2".to_string(),
+            id: Some("test-2".into()),
             method: "tools/call".to_string(),
             params: Some(serde_json::json!({
                 "name": "execute_command",
@@ -575,12 +807,105 @@ mod tests {
         assert!(response.result.is_some());
     }
 
+    /// Backend whose terminal prints a secret
+    struct SecretBackend;
+
+    #[async_trait]
+    impl TerminalBackend for SecretBackend {
+        async fn execute_command(
+            &self,
+            _command: &str,
+            _cwd: Option<&str>,
+            _timeout_ms: Option<u64>,
+        ) -> Result<String, String> {
+            Ok("deploying\nDEPLOY_TOKEN=abc123\n".to_string())
+        }
+
+        async fn get_output(
+            &self,
+            _lines: u64,
+            _session_id: Option<&str>,
+        ) -> Result<String, String> {
+            Ok("$ cat .env\nDB_PASSWORD=hunter22\n$ ".to_string())
+        }
+
+        async fn list_sessions(&self) -> Result<Vec<SessionInfo>, String> {
+            Ok(Vec::new())
+        }
+
+        async fn create_session(
+            &self,
+            _name: Option<&str>,
+            _cwd: Option<&str>,
+            _project_id: Option<&str>,
+        ) -> Result<SessionInfo, String> {
+            Err("no sessions".to_string())
+        }
+
+        async fn switch_session(&self, _session_id: &str) -> Result<(), String> {
+            Err("session not found; last output: API_TOKEN=s3cr3t".to_string())
+        }
+
+        async fn search_history(
+            &self,
+            _query: &str,
+            _search_type: &str,
+        ) -> Result<Vec<HistoryEntry>, String> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn call(tool: &str, arguments: serde_json::Value) -> McpRequest {
+        McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some("test-redact".into()),
+            method: "tools/call".to_string(),
+            params: Some(serde_json::json!({ "name": tool, "arguments": arguments })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_output_is_redacted() {
+        let server = McpServer::with_backend(Arc::new(SecretBackend));
+
+        let response = server
+            .handle_request(call(
+                "execute_command",
+                serde_json::json!({ "command": "./deploy" }),
+            ))
+            .await;
+        let text = response.result.unwrap().to_string();
+        assert!(text.contains("DEPLOY_TOKEN=[REDACTED:secret]"));
+        assert!(!text.contains("abc123"));
+
+        let response = server
+            .handle_request(call(
+                "get_terminal_output",
+                serde_json::json!({ "lines": 10 }),
+            ))
+            .await;
+        let text = response.result.unwrap().to_string();
+        assert!(text.contains("DB_PASSWORD=[REDACTED:secret]"));
+        assert!(!text.contains("hunter22"));
+
+        // Errors go back to the agent too
+        let response = server
+            .handle_request(call(
+                "switch_session",
+                serde_json::json!({ "session_id": "x" }),
+            ))
+            .await;
+        let message = response.error.unwrap().message;
+        assert!(message.contains("API_TOKEN=[REDACTED:secret]"));
+        assert!(!message.contains("s3cr3t"));
+    }
+
     #[tokio::test]
     async fn test_missing_required_param() {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-3".to_string(),
+            id: Some("test-3".into()),
             method: "tools/call".to_string(),
             params: Some(serde_json::json!({
                 "name": "execute_command",
@@ -598,7 +923,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-4".to_string(),
+            id: Some("test-4".into()),
             method: "tools/call".to_string(),
             params: Some(serde_json::json!({
                 "name": "nonexistent_tool",
@@ -616,7 +941,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-5".to_string(),
+            id: Some("test-5".into()),
             method: "tools/call".to_string(),
             params: Some(serde_json::json!({
                 "name": "get_terminal_output",
@@ -636,7 +961,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-6".to_string(),
+            id: Some("test-6".into()),
             method: "tools/call".to_string(),
             params: Some(serde_json::json!({
                 "name": "list_sessions",
@@ -654,7 +979,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-7".to_string(),
+            id: Some("test-7".into()),
             method: "tools/call".to_string(),
             params: Some(serde_json::json!({
                 "name": "search_history",
@@ -675,7 +1000,7 @@ mod tests {
         let server = McpServer::new();
         let request = McpRequest {
             jsonrpc: "2.0".to_string(),
-            id: "test-8".to_string(),
+            id: Some("test-8".into()),
             method: "unknown/method".to_string(),
             params: None,
         };
@@ -684,4 +1009,98 @@ mod tests {
         assert!(response.error.is_some());
         assert_eq!(response.error.unwrap().code, -32601);
     }
+
+    fn request(id: Option<serde_json::Value>, method: &str) -> McpRequest {
+        McpRequest {
+            jsonrpc: "2.0".to_string(),
+            id,
+            method: method.to_string(),
+            params: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_handshake() {
+        let mut session = McpSession::new(Arc::new(McpServer::new()));
+
+        let mut init = request(Some(1.into()), "initialize");
+        init.params = Some(serde_json::json!({ "protocolVersion": "2025-03-26" }));
+        let response = session.handle(init).await.unwrap();
+        let result = response.result.unwrap();
+        assert_eq!(result["protocolVersion"], "2025-03-26");
+        assert_eq!(result["serverInfo"]["name"], "corgiterm");
+        assert!(!session.is_ready());
+
+        let ack = session
+            .handle(request(None, "notifications/initialized"))
+            .await;
+        assert!(ack.is_none());
+        assert!(session.is_ready());
+
+        let response = session
+            .handle(request(Some(2.into()), "tools/list"))
+            .await
+            .unwrap();
+        let tools = &response.result.unwrap()["tools"];
+        assert!(tools[0].get("inputSchema").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_session_unknown_protocol_version() {
+        let mut session = McpSession::new(Arc::new(McpServer::new()));
+        let mut init = request(Some(1.into()), "initialize");
+        init.params = Some(serde_json::json!({ "protocolVersion": "1999-01-01" }));
+
+        let response = session.handle(init).await.unwrap();
+        assert_eq!(
+            response.result.unwrap()["protocolVersion"],
+            SUPPORTED_PROTOCOL_VERSIONS[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_rejects_requests_before_initialize() {
+        let mut session = McpSession::new(Arc::new(McpServer::new()));
+
+        let response = session
+            .handle(request(Some(1.into()), "tools/list"))
+            .await
+            .unwrap();
+        assert_eq!(response.error.unwrap().code, SERVER_NOT_INITIALIZED);
+
+        // ping is allowed at any time
+        let response = session
+            .handle(request(Some(2.into()), "ping"))
+            .await
+            .unwrap();
+        assert!(response.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_ignores_notifications() {
+        let mut session = McpSession::new(Arc::new(McpServer::new()));
+        assert!(session
+            .handle(request(None, "notifications/cancelled"))
+            .await
+            .is_none());
+
+        let mut bad = request(Some(1.into()), "ping");
+        bad.jsonrpc = "1.0".to_string();
+        let response = session.handle(bad).await.unwrap();
+        assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_response_serialization() {
+        let response = McpResponse::success(7.into(), serde_json::json!({}));
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(value["jsonrpc"], "2.0");
+        assert_eq!(value["id"], 7);
+        assert!(value.get("error").is_none());
+
+        let request: McpRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+                .unwrap();
+        assert!(request.id.is_none());
+    }
 }
@@ -0,0 +1,242 @@
+//! Transports for the MCP server
+//!
+//! MCP clients (Claude Code, Codex, Gemini CLI) launch `corgiterm mcp` and
+//! exchange JSON-RPC 2.0 messages over its stdin/stdout, one message per line.
+//! A running CorgiTerm with `ai.mcp.enabled` serves the same protocol on a
+//! Unix socket; `corgiterm mcp` then relays stdio to that socket so agents
+//! work in the user's open tabs instead of a throwaway PTY.
+//!
+//! Every connection gets its own [`McpSession`], so each client performs its
+//! own `initialize` handshake.
+
+use crate::mcp::{McpRequest, McpResponse, McpServer, McpSession, INVALID_REQUEST, PARSE_ERROR};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Socket file name used when `ai.mcp.socket` is not set
+pub const SOCKET_FILE: &str = "corgiterm-mcp.sock";
+
+/// Default MCP socket path: `$XDG_RUNTIME_DIR/corgiterm-mcp.sock`
+///
+/// Without a runtime or cache directory the socket goes in the shared temp
+/// directory, named after the user so users do not collide; binding and
+/// connecting both refuse a socket owned by someone else.
+pub fn default_socket_path() -> PathBuf {
+    match dirs::runtime_dir().or_else(dirs::cache_dir) {
+        Some(dir) => dir.join(SOCKET_FILE),
+        #[cfg(unix)]
+        None => std::env::temp_dir().join(format!(
+            "corgiterm-mcp-{}.sock",
+            corgiterm_core::ipc::user_id()
+        )),
+        #[cfg(not(unix))]
+        None => std::env::temp_dir().join(SOCKET_FILE),
+    }
+}
+
+/// Serve one client reading requests from `reader` and writing responses to `writer`
+///
+/// Returns when the reader reaches end of input.
+pub async fn serve_lines<R, W>(server: Arc<McpServer>, reader: R, mut writer: W) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut session = McpSession::new(server);
+    let mut lines = reader.lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let response = match parse_message(line) {
+            Ok(request) => session.handle(request).await,
+            Err(response) => Some(response),
+        };
+
+        if let Some(response) = response {
+            let mut encoded = serde_json::to_string(&response).map_err(io::Error::other)?;
+            encoded.push('\n');
+            writer.write_all(encoded.as_bytes()).await?;
+            writer.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode one line, or build the error response for it
+fn parse_message(line: &str) -> Result<McpRequest, McpResponse> {
+    let value: serde_json::Value = serde_json::from_str(line).map_err(|e| {
+        McpResponse::failure(
+            serde_json::Value::Null,
+            PARSE_ERROR,
+            format!("Parse error: {}", e),
+        )
+    })?;
+    let id = value.get("id").cloned().unwrap_or(serde_json::Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| McpResponse::failure(id, INVALID_REQUEST, format!("Invalid request: {}", e)))
+}
+
+/// Serve a single client on stdin/stdout
+pub async fn serve_stdio(server: Arc<McpServer>) -> io::Result<()> {
+    serve_lines(
+        server,
+        BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+    )
+    .await
+}
+
+/// Accept clients on a Unix socket at `path` until the task is dropped
+///
+/// Uses the same stale-socket handling and owner-only permissions as the IPC
+/// control socket.
+#[cfg(unix)]
+pub async fn serve_unix(server: Arc<McpServer>, path: &std::path::Path) -> io::Result<()> {
+    let listener = corgiterm_core::ipc::bind_listener(path).map_err(io::Error::other)?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::UnixListener::from_std(listener)?;
+    tracing::info!("MCP socket listening on {}", path.display());
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("MCP accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let server = server.clone();
+        tokio::spawn(async move {
+            let (read, write) = stream.into_split();
+            if let Err(e) = serve_lines(server, BufReader::new(read), write).await {
+                tracing::debug!("MCP connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// Relay stdin/stdout to a connected MCP socket
+///
+/// Returns once the socket closes, either because the app answered the last
+/// request after stdin ended or because the app went away.
+#[cfg(unix)]
+pub async fn bridge_stdio(stream: tokio::net::UnixStream) -> io::Result<()> {
+    let (mut read, mut write) = stream.into_split();
+
+    let upstream = tokio::spawn(async move {
+        tokio::io::copy(&mut tokio::io::stdin(), &mut write).await?;
+        write.shutdown().await
+    });
+
+    let mut stdout = tokio::io::stdout();
+    let result = tokio::io::copy(&mut read, &mut stdout).await;
+    stdout.flush().await?;
+    upstream.abort();
+    result.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    async fn exchange(input: &str) -> Vec<Value> {
+        let (mut client, server_end) = tokio::io::duplex(64 * 1024);
+        let (read, write) = tokio::io::split(server_end);
+        let serve = tokio::spawn(serve_lines(
+            Arc::new(McpServer::new()),
+            BufReader::new(read),
+            write,
+        ));
+
+        client.write_all(input.as_bytes()).await.unwrap();
+        client.shutdown().await.unwrap();
+        serve.await.unwrap().unwrap();
+
+        let mut output = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut output)
+            .await
+            .unwrap();
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_serve_lines_handshake() {
+        let input = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                   "params": {"protocolVersion": "2024-11-05"}}),
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+        ]
+        .iter()
+        .map(|m| format!("{}\n", m))
+        .collect::<String>();
+
+        let responses = exchange(&input).await;
+        // The notification gets no response
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(responses[1]["id"], 2);
+        assert!(responses[1]["result"]["tools"].is_array());
+    }
+
+    #[tokio::test]
+    async fn test_serve_lines_bad_input() {
+        let responses = exchange("not json\n\n{\"id\": 5}\n").await;
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], Value::Null);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["id"], 5);
+        assert_eq!(responses[1]["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_unix_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("corgiterm-mcp-{}.sock", uuid::Uuid::new_v4()));
+        let serve = tokio::spawn({
+            let path = path.clone();
+            async move { serve_unix(Arc::new(McpServer::new()), &path).await }
+        });
+
+        let mut stream = None;
+        for _ in 0..50 {
+            if let Ok(s) = tokio::net::UnixStream::connect(&path).await {
+                stream = Some(s);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let (read, mut write) = stream.expect("MCP socket never came up").into_split();
+
+        write
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n")
+            .await
+            .unwrap();
+        let line = BufReader::new(read)
+            .lines()
+            .next_line()
+            .await
+            .unwrap()
+            .unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"], json!({}));
+
+        serve.abort();
+        let _ = std::fs::remove_file(&path);
+    }
+}
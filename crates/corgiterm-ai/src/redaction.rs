@@ -0,0 +1,167 @@
+//! Secret redaction for prompts
+//!
+//! Every provider is wrapped in a [`RedactingProvider`], so scrollback,
+//! history and selections pass through the process-wide
+//! [`corgiterm_core::redaction`] redactor before they leave the machine.
+
+use crate::{AiProvider, AiResponse, Message, Result};
+use async_trait::async_trait;
+use corgiterm_config::PrivacyConfig;
+use corgiterm_core::redaction::{self, Redactor};
+
+/// Provider that redacts secrets from messages before sending them
+pub struct RedactingProvider {
+    inner: Box<dyn AiProvider>,
+}
+
+impl RedactingProvider {
+    pub fn new(inner: Box<dyn AiProvider>) -> Self {
+        Self { inner }
+    }
+
+    /// Wrap a provider in a boxed redacting provider
+    pub fn wrap(inner: Box<dyn AiProvider>) -> Box<dyn AiProvider> {
+        Box::new(Self::new(inner))
+    }
+}
+
+/// Set up the process-wide redactor from the privacy settings. A bad
+/// allowlist pattern is reported and the built-in patterns are used alone.
+pub fn configure(config: &PrivacyConfig) -> corgiterm_core::Result<()> {
+    if !config.redact_secrets {
+        redaction::set_global(Redactor::disabled());
+        return Ok(());
+    }
+    match Redactor::new().with_allowlist(&config.redaction_allowlist) {
+        Ok(redactor) => {
+            redaction::set_global(redactor);
+            Ok(())
+        }
+        Err(e) => {
+            redaction::set_global(Redactor::new());
+            Err(e)
+        }
+    }
+}
+
+/// Messages with their contents redacted
+pub fn redact_messages(messages: &[Message]) -> Vec<Message> {
+    let redactor = redaction::global();
+    messages
+        .iter()
+        .map(|m| Message {
+            role: m.role,
+            content: redactor.redact(&m.content),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl AiProvider for RedactingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn set_model(&mut self, model: &str) {
+        self.inner.set_model(model);
+    }
+
+    fn available_models(&self) -> Vec<String> {
+        self.inner.available_models()
+    }
+
+    async fn is_available(&self) -> bool {
+        self.inner.is_available().await
+    }
+
+    async fn complete(&self, messages: &[Message]) -> Result<AiResponse> {
+        self.inner.complete(&redact_messages(messages)).await
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        callback: Box<dyn Fn(String) + Send>,
+    ) -> Result<AiResponse> {
+        self.inner
+            .complete_stream(&redact_messages(messages), callback)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    struct Recorder {
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl AiProvider for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn model(&self) -> &str {
+            "test"
+        }
+
+        fn set_model(&mut self, _model: &str) {}
+
+        fn available_models(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        async fn is_available(&self) -> bool {
+            true
+        }
+
+        async fn complete(&self, messages: &[Message]) -> Result<AiResponse> {
+            self.seen
+                .lock()
+                .extend(messages.iter().map(|m| m.content.clone()));
+            Ok(AiResponse {
+                content: String::new(),
+                provider: "recorder".to_string(),
+                model: "test".to_string(),
+                tokens_used: None,
+                latency_ms: 0,
+            })
+        }
+
+        async fn complete_stream(
+            &self,
+            messages: &[Message],
+            _callback: Box<dyn Fn(String) + Send>,
+        ) -> Result<AiResponse> {
+            self.complete(messages).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompts_are_redacted() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let provider = RedactingProvider::wrap(Box::new(Recorder { seen: seen.clone() }));
+        assert_eq!(provider.name(), "recorder");
+
+        provider
+            .complete(&[Message {
+                role: Role::User,
+                content: "why does export DB_PASSWORD=hunter2 fail?".to_string(),
+            }])
+            .await
+            .unwrap();
+        assert_eq!(
+            seen.lock().as_slice(),
+            ["why does export DB_PASSWORD=[REDACTED:secret] fail?"]
+        );
+    }
+}
@@ -49,6 +49,10 @@ pub struct Config {
     pub ai: AiConfig,
     /// Safe Mode settings
     pub safe_mode: SafeModeConfig,
+    /// Secret redaction
+    pub privacy: PrivacyConfig,
+    /// Command and output history
+    pub history: HistoryConfig,
     /// Session management
     pub sessions: SessionsConfig,
     /// Performance settings
@@ -265,7 +269,7 @@ pub struct TerminalConfig {
     pub bell_audio: Option<PathBuf>,
     /// Word separators for double-click selection
     pub word_separators: String,
-    /// Enable hyperlinks
+    /// Honor OSC 8 hyperlinks printed by programs (hover, Ctrl+click, hints)
     pub hyperlinks: bool,
     /// Enable bracketed paste
     pub bracketed_paste: bool,
@@ -275,6 +279,12 @@ pub struct TerminalConfig {
     pub term: String,
     /// Close tab on exit
     pub close_on_exit: CloseOnExit,
+    /// Load OSC 133 shell integration into bash, zsh and fish
+    pub shell_integration: bool,
+    /// Whether programs may read the clipboard through OSC 52
+    pub clipboard_read: ClipboardReadPolicy,
+    /// When pasted text is shown for review before it is sent
+    pub paste_protection: PasteProtectionConfig,
 }
 
 impl Default for TerminalConfig {
@@ -294,6 +304,9 @@ impl Default for TerminalConfig {
             env: std::collections::HashMap::new(),
             term: "xterm-256color".to_string(),
             close_on_exit: CloseOnExit::IfClean,
+            shell_integration: true,
+            clipboard_read: ClipboardReadPolicy::Ask,
+            paste_protection: PasteProtectionConfig::default(),
         }
     }
 }
@@ -308,6 +321,59 @@ pub enum BellStyle {
     Both,
 }
 
+/// Answer to programs asking to read the clipboard (OSC 52)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardReadPolicy {
+    Allow,
+    Deny,
+    /// Prompt in the terminal; the answer can be kept for the session
+    Ask,
+}
+
+/// When a paste is held for review
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteReview {
+    /// Every paste
+    Always,
+    /// Pastes with hidden characters, look-alike characters or risky lines,
+    /// and as set below for line breaks
+    Suspicious,
+    /// Send pastes as they are
+    Never,
+}
+
+/// Paste review settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasteProtectionConfig {
+    /// When to show the review dialog
+    pub review: PasteReview,
+    /// Review pastes of more than one line
+    pub review_multiline: bool,
+    /// Review pastes whose line breaks would run lines right away (the
+    /// program has not turned on bracketed paste)
+    pub review_newlines: bool,
+    /// Review pastes with a line at this Safe Mode risk or above
+    pub review_risk: ConfirmAt,
+    /// Drop control characters, escape sequences and invisible characters
+    /// from reviewed pastes
+    pub strip_hidden: bool,
+}
+
+impl Default for PasteProtectionConfig {
+    fn default() -> Self {
+        Self {
+            review: PasteReview::Suspicious,
+            review_multiline: true,
+            review_newlines: true,
+            review_risk: ConfirmAt::Danger,
+            strip_hidden: true,
+        }
+    }
+}
+
 /// When to close tab on exit
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -375,6 +441,14 @@ pub struct ShortcutsConfig {
     pub zoom_out: Option<String>,
     pub reset_zoom: Option<String>,
 
+    // Command blocks
+    pub previous_prompt: Option<String>,
+    pub next_prompt: Option<String>,
+    pub copy_last_output: Option<String>,
+    pub toggle_output_collapse: Option<String>,
+    pub previous_project_command: Option<String>,
+    pub next_project_command: Option<String>,
+
     // UI features
     pub toggle_ai: Option<String>,
     pub toggle_sidebar: Option<String>,
@@ -384,6 +458,7 @@ pub struct ShortcutsConfig {
     pub ascii_art: Option<String>,
     pub open_file: Option<String>,
     pub history_search: Option<String>,
+    pub output_search: Option<String>,
 
     // Application
     pub quit: Option<String>,
@@ -427,6 +502,14 @@ impl Default for ShortcutsConfig {
             zoom_out: Some("Ctrl+Minus".to_string()),
             reset_zoom: Some("Ctrl+0".to_string()),
 
+            // Command blocks
+            previous_prompt: Some("Ctrl+Shift+Up".to_string()),
+            next_prompt: Some("Ctrl+Shift+Down".to_string()),
+            copy_last_output: Some("Ctrl+Shift+L".to_string()),
+            toggle_output_collapse: Some("Ctrl+Shift+E".to_string()),
+            previous_project_command: Some("Alt+Shift+Up".to_string()),
+            next_project_command: Some("Alt+Shift+Down".to_string()),
+
             // UI features
             toggle_ai: Some("Ctrl+Shift+A".to_string()),
             toggle_sidebar: Some("Ctrl+Shift+B".to_string()),
@@ -436,6 +519,7 @@ impl Default for ShortcutsConfig {
             ascii_art: Some("Ctrl+Shift+G".to_string()),
             open_file: Some("Ctrl+Shift+O".to_string()),
             history_search: Some("Ctrl+R".to_string()),
+            output_search: Some("Ctrl+Shift+R".to_string()),
 
             // Application
             quit: Some("Ctrl+Q".to_string()),
@@ -469,6 +553,8 @@ pub struct AiConfig {
     pub panel_position: AiPanelPosition,
     /// Command learning settings
     pub learning: LearningConfig,
+    /// MCP server for external AI agents
+    pub mcp: McpConfig,
 }
 
 impl Default for AiConfig {
@@ -485,6 +571,7 @@ impl Default for AiConfig {
             show_panel: true, // Show panel by default for discoverability
             panel_position: AiPanelPosition::Right,
             learning: LearningConfig::default(),
+            mcp: McpConfig::default(),
         }
     }
 }
@@ -532,6 +619,16 @@ impl Default for LearningConfig {
     }
 }
 
+/// MCP (Model Context Protocol) server settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct McpConfig {
+    /// Let agents drive open tabs through `corgiterm mcp`
+    pub enabled: bool,
+    /// Socket path (defaults to $XDG_RUNTIME_DIR/corgiterm-mcp.sock)
+    pub socket: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ClaudeConfig {
@@ -626,6 +723,21 @@ pub struct SafeModeConfig {
     pub dangerous_patterns: Vec<String>,
     /// Custom safe patterns
     pub safe_patterns: Vec<String>,
+    /// Rule file layered over the built-in rules
+    pub rules_file: Option<PathBuf>,
+    /// Move files approved `rm` commands delete into CorgiTerm's trash
+    pub trash_deletions: bool,
+    /// Days before trashed files are deleted for good
+    pub trash_max_age_days: u32,
+    /// Size the trash may grow to before the oldest deletions are purged
+    pub trash_max_size_mb: u64,
+    /// Check typed command lines when Enter is pressed (needs shell
+    /// integration; applies to new terminals)
+    pub check_typed: bool,
+    /// Profile for sessions whose project and SSH host do not choose one
+    pub profile: String,
+    /// When commands wait for confirmation, by origin
+    pub confirm: CommandConfirmConfig,
 }
 
 impl Default for SafeModeConfig {
@@ -637,6 +749,123 @@ impl Default for SafeModeConfig {
             ai_explanations: true,
             dangerous_patterns: Vec::new(),
             safe_patterns: Vec::new(),
+            rules_file: None, // Will default to config_dir/safe-mode.toml
+            trash_deletions: false,
+            trash_max_age_days: 30,
+            trash_max_size_mb: 1024,
+            check_typed: false,
+            profile: "standard".to_string(),
+            confirm: CommandConfirmConfig::default(),
+        }
+    }
+}
+
+impl SafeModeConfig {
+    /// The user's Safe Mode rule file
+    pub fn rules_path(&self) -> PathBuf {
+        self.rules_file
+            .clone()
+            .unwrap_or_else(|| config_dir().join("safe-mode.toml"))
+    }
+
+    /// The directory of the user's Safe Mode profiles
+    pub fn profiles_dir(&self) -> PathBuf {
+        config_dir().join("safe-mode-profiles")
+    }
+}
+
+/// Lowest Safe Mode risk at which a command waits for confirmation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmAt {
+    /// Every command, even safe ones
+    Always,
+    /// Anything not known to be safe
+    Caution,
+    /// Only dangerous commands
+    Danger,
+    /// Never ask
+    Never,
+}
+
+/// Confirmation thresholds for each source of commands that were not typed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandConfirmConfig {
+    /// AI panel and natural-language suggestions
+    pub ai: ConfirmAt,
+    /// Snippets
+    pub snippet: ConfirmAt,
+    /// SSH manager connections
+    pub ssh: ConfirmAt,
+    /// Plugins
+    pub plugin: ConfirmAt,
+    /// AI agents over MCP
+    pub mcp: ConfirmAt,
+    /// Commands sent to every pane of a broadcasting tab
+    pub broadcast: ConfirmAt,
+    /// Commands picked from history search
+    pub history: ConfirmAt,
+    /// CorgiTerm's own tools, such as the ASCII art generator
+    pub tool: ConfirmAt,
+    /// Command lines typed at the shell prompt, with `check_typed` on
+    pub typed: ConfirmAt,
+    /// Commands run over the IPC control socket (`corgiterm msg`)
+    pub ipc: ConfirmAt,
+}
+
+impl Default for CommandConfirmConfig {
+    fn default() -> Self {
+        Self {
+            ai: ConfirmAt::Caution,
+            snippet: ConfirmAt::Danger,
+            ssh: ConfirmAt::Danger,
+            plugin: ConfirmAt::Caution,
+            mcp: ConfirmAt::Caution,
+            broadcast: ConfirmAt::Caution,
+            history: ConfirmAt::Danger,
+            tool: ConfirmAt::Danger,
+            typed: ConfirmAt::Danger,
+            ipc: ConfirmAt::Caution,
+        }
+    }
+}
+
+/// Secret redaction for text sent to AI providers and MCP clients or
+/// written to history, conversations and recordings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    /// Replace API keys, tokens and passwords with `[REDACTED:<kind>]`
+    pub redact_secrets: bool,
+    /// Regexes for secrets, variable names or flag names to keep
+    pub redaction_allowlist: Vec<String>,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            redact_secrets: true,
+            redaction_allowlist: Vec::new(),
+        }
+    }
+}
+
+/// Command and output history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Index terminal output for global search
+    pub index_output: bool,
+    /// Output kept in the history database, in MiB (oldest dropped first)
+    pub output_max_mb: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            index_output: true,
+            output_max_mb: 256,
         }
     }
 }
@@ -752,6 +981,9 @@ pub struct AdvancedConfig {
     pub plugin_dir: Option<PathBuf>,
     /// IPC socket path
     pub ipc_socket: Option<PathBuf>,
+    /// Accept `pane.send_raw`, which writes bytes to a pane without Safe
+    /// Mode seeing them
+    pub ipc_raw_input: bool,
 }
 
 impl Default for AdvancedConfig {
@@ -764,6 +996,7 @@ impl Default for AdvancedConfig {
             custom_css: None,
             plugin_dir: None,
             ipc_socket: None,
+            ipc_raw_input: false,
         }
     }
 }
@@ -1259,6 +1492,7 @@ pub fn parse_ssh_config(contents: &str, default_port: u16) -> Vec<SshHost> {
                     options: Vec::new(),
                     tags: Vec::new(),
                     favorite: false,
+                    safe_mode_profile: None,
                 });
             }
             "hostname" => {
@@ -1482,6 +1716,15 @@ pub struct SshConfig {
     pub merge_imports: bool,
 }
 
+impl SshConfig {
+    /// The saved host an ssh destination or reported host name refers to
+    pub fn host_for(&self, destination: &str) -> Option<&SshHost> {
+        self.hosts
+            .iter()
+            .find(|host| host.matches_destination(destination))
+    }
+}
+
 impl Default for SshConfig {
     fn default() -> Self {
         Self {
@@ -1516,6 +1759,9 @@ pub struct SshHost {
     /// Favorite host
     #[serde(default)]
     pub favorite: bool,
+    /// Safe Mode profile for sessions on this host
+    #[serde(default)]
+    pub safe_mode_profile: Option<String>,
 }
 
 fn default_ssh_port() -> u16 {
@@ -1584,6 +1830,28 @@ impl SshHost {
         }
         // Favorite flag: keep true if either is true
         self.favorite = self.favorite || other.favorite;
+        if self.safe_mode_profile.is_none() {
+            self.safe_mode_profile = other.safe_mode_profile.clone();
+        }
+    }
+
+    /// Whether an ssh destination (`host` or `user@host`) or a host name a
+    /// remote shell reported refers to this host
+    pub fn matches_destination(&self, destination: &str) -> bool {
+        let (user, host) = match destination.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, destination),
+        };
+        if let (Some(user), Some(username)) = (user, &self.username) {
+            if user != username {
+                return false;
+            }
+        }
+        // Shells often report the short name of a fully qualified host
+        let short_name = self.hostname.split('.').next().unwrap_or_default();
+        host.eq_ignore_ascii_case(&self.name)
+            || host.eq_ignore_ascii_case(&self.hostname)
+            || (!host.contains('.') && host.eq_ignore_ascii_case(short_name))
     }
 }
 
@@ -1607,6 +1875,29 @@ mod tests {
         assert!(toml.contains("[appearance]"));
     }
 
+    #[test]
+    fn test_command_confirm_thresholds() {
+        let config: Config =
+            toml::from_str("[safe_mode.confirm]\nsnippet = \"always\"\nmcp = \"never\"\n").unwrap();
+        assert_eq!(config.safe_mode.confirm.snippet, ConfirmAt::Always);
+        assert_eq!(config.safe_mode.confirm.mcp, ConfirmAt::Never);
+        assert_eq!(config.safe_mode.confirm.ai, ConfirmAt::Caution);
+        assert_eq!(config.safe_mode.confirm.ipc, ConfirmAt::Caution);
+    }
+
+    #[test]
+    fn test_paste_protection() {
+        let config: Config = toml::from_str(
+            "[terminal.paste_protection]\nreview = \"always\"\nreview_risk = \"caution\"\n",
+        )
+        .unwrap();
+        let paste = &config.terminal.paste_protection;
+        assert_eq!(paste.review, PasteReview::Always);
+        assert_eq!(paste.review_risk, ConfirmAt::Caution);
+        assert!(paste.review_multiline);
+        assert!(paste.strip_hidden);
+    }
+
     #[test]
     fn test_ssh_host_command() {
         let host = SshHost {
@@ -1618,6 +1909,7 @@ mod tests {
             options: vec!["-o".to_string(), "StrictHostKeyChecking=no".to_string()],
             tags: vec!["production".to_string()],
             favorite: false,
+            safe_mode_profile: Some("production".to_string()),
         };
 
         let cmd = host.build_command();
@@ -1639,6 +1931,7 @@ mod tests {
             options: Vec::new(),
             tags: Vec::new(),
             favorite: false,
+            safe_mode_profile: None,
         };
 
         assert_eq!(host.display_string(), "user@example.com:22");
@@ -1655,6 +1948,7 @@ mod tests {
             options: vec!["-o StrictHostKeyChecking=no".to_string()],
             tags: vec!["prod".to_string()],
             favorite: false,
+            safe_mode_profile: None,
         };
 
         let other = SshHost {
@@ -1666,6 +1960,7 @@ mod tests {
             options: vec!["-o LogLevel=ERROR".to_string()],
             tags: vec!["db".to_string()],
             favorite: true,
+            safe_mode_profile: Some("production".to_string()),
         };
 
         base.merge_from(&other);
@@ -1677,6 +1972,34 @@ mod tests {
             .contains(&"-o StrictHostKeyChecking=no".to_string()));
         assert!(base.options.contains(&"-o LogLevel=ERROR".to_string()));
         assert!(base.favorite);
+        assert_eq!(base.safe_mode_profile.as_deref(), Some("production"));
+    }
+
+    #[test]
+    fn test_ssh_host_destination() {
+        let host = SshHost {
+            name: "db".to_string(),
+            hostname: "db-1.example.com".to_string(),
+            port: 22,
+            username: Some("admin".to_string()),
+            identity_file: None,
+            options: Vec::new(),
+            tags: Vec::new(),
+            favorite: false,
+            safe_mode_profile: Some("production".to_string()),
+        };
+        assert!(host.matches_destination("db"));
+        assert!(host.matches_destination("admin@db-1.example.com"));
+        assert!(host.matches_destination("DB-1"));
+        assert!(!host.matches_destination("root@db"));
+        assert!(!host.matches_destination("db-1.example.org"));
+
+        let ssh = SshConfig {
+            hosts: vec![host],
+            ..SshConfig::default()
+        };
+        assert_eq!(ssh.host_for("db-1").map(|h| h.name.as_str()), Some("db"));
+        assert!(ssh.host_for("web").is_none());
     }
 
     #[test]
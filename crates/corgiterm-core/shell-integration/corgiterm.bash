@@ -0,0 +1,120 @@
+# CorgiTerm shell integration for bash
+#
+# Emits OSC 133 semantic prompt marks so CorgiTerm can tell prompts, commands
+# and their output apart and learn each command's exit status:
+#   A  prompt start        B  command input start
+#   C  output start        D  command finished (with exit status)
+# Each prompt also reports the working directory as an OSC 7 file:// URL and
+# whether the line editor uses vi or emacs key bindings (OSC 6977 keymap).
+# The submitted command line rides along with C as a percent-encoded
+# `cmdline_url` field. Requires bash 4.4+ for C (PS0); older versions still
+# get A, B and D.
+#
+# With CORGITERM_CHECK_COMMANDS set, Enter, Ctrl-J and Ctrl-O send the line to
+# CorgiTerm for a Safe Mode check (OSC 6977) with a fresh id, and CorgiTerm
+# answers with a key bound for that id once the line may run. The line runs
+# only if it is still the one that was checked; otherwise it is checked again.
+
+[[ $- == *i* ]] || return 0
+[[ -z "${__corgiterm_loaded-}" ]] || return 0
+__corgiterm_loaded=1
+
+__corgiterm_urlencode() {
+    local LC_ALL=C input=$1 out= c i
+    for (( i = 0; i < ${#input}; i++ )); do
+        c=${input:i:1}
+        case $c in
+            [a-zA-Z0-9.~_/-]) out+=$c ;;
+            *) printf -v c '%%%02X' "'$c"; out+=$c ;;
+        esac
+    done
+    printf '%s' "$out"
+}
+
+# Runs first in PROMPT_COMMAND so $? is still the command's status
+__corgiterm_precmd() {
+    local status=$?
+    if [[ -n "${__corgiterm_prompted-}" ]]; then
+        printf '\e]133;D;%s\a' "$status"
+    fi
+    __corgiterm_prompted=1
+    __corgiterm_last_history=$(HISTTIMEFORMAT= builtin history 1)
+    printf '\e]7;file://%s%s\a' "$HOSTNAME" "$(__corgiterm_urlencode "$PWD")"
+    if [[ -o vi ]]; then
+        printf '\e]6977;keymap;vi\a'
+    else
+        printf '\e]6977;keymap;emacs\a'
+    fi
+    printf '\e]133;A\a'
+    return $status
+}
+
+# Runs last so prompt frameworks that rebuild PS1 keep the B mark
+__corgiterm_postcmd() {
+    local status=$?
+    if [[ $PS1 != *'\e]133;B\a'* ]]; then
+        PS1+='\[\e]133;B\a\]'
+    fi
+    return $status
+}
+
+# Expanded in a subshell via PS0 after a command line is read
+__corgiterm_preexec() {
+    local entry
+    entry=$(HISTTIMEFORMAT= builtin history 1)
+    if [[ -n $entry && $entry != "$__corgiterm_last_history" ]]; then
+        entry=${entry#*[0-9] }
+        entry=${entry#"${entry%%[![:space:]]*}"}
+        printf '\e]133;C;cmdline_url=%s\a' "$(__corgiterm_urlencode "$entry")"
+    else
+        printf '\e]133;C\a'
+    fi
+}
+
+PROMPT_COMMAND="__corgiterm_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}; __corgiterm_postcmd"
+PS0+='$(__corgiterm_preexec)'
+
+if [[ -n "${CORGITERM_CHECK_COMMANDS-}" ]]; then
+    unset CORGITERM_CHECK_COMMANDS
+
+    # Enter: remember the line and ask CorgiTerm to check it. Only the latest
+    # check's answer key is bound, to run \e[6978~ (compare) then \e[6979~.
+    __corgiterm_check_line() {
+        [[ -n ${__corgiterm_check_id-} ]] && bind -r "\e[6977;${__corgiterm_check_id}~"
+        __corgiterm_check_id=$RANDOM$RANDOM
+        __corgiterm_checked=$READLINE_LINE
+        bind "\"\e[6977;${__corgiterm_check_id}~\": \"\e[6978~\e[6979~\""
+        printf '\e]6977;check;id=%s;cmdline_url=%s\a' "$__corgiterm_check_id" \
+            "$(__corgiterm_urlencode "$READLINE_LINE")"
+    }
+
+    # The answer arrived: \e[6979~ runs the line if it is unchanged
+    __corgiterm_compare_line() {
+        bind -r "\e[6977;${__corgiterm_check_id}~"
+        unset __corgiterm_check_id
+        if [[ $READLINE_LINE == "$__corgiterm_checked" ]]; then
+            bind '"\e[6979~": accept-line'
+        else
+            bind -x '"\e[6979~": __corgiterm_check_line'
+        fi
+    }
+
+    # Put \e[6979~ back once the line has run
+    __corgiterm_reset_accept() {
+        local map
+        for map in emacs vi-insert vi-command; do
+            bind -m "$map" -x '"\e[6979~": __corgiterm_check_line'
+        done
+    }
+
+    # Every key that runs the line by default, operate-and-get-next included
+    for __corgiterm_map in emacs vi-insert vi-command; do
+        bind -m "$__corgiterm_map" -x '"\C-m": __corgiterm_check_line'
+        bind -m "$__corgiterm_map" -x '"\C-j": __corgiterm_check_line'
+        bind -m "$__corgiterm_map" -x '"\e[6978~": __corgiterm_compare_line'
+    done
+    bind -m emacs -x '"\C-o": __corgiterm_check_line'
+    unset __corgiterm_map
+    __corgiterm_reset_accept
+    PROMPT_COMMAND+="; __corgiterm_reset_accept"
+fi
@@ -0,0 +1,108 @@
+# CorgiTerm shell integration for fish, found through XDG_DATA_DIRS
+#
+# Emits OSC 133 semantic prompt marks so CorgiTerm can tell prompts, commands
+# and their output apart and learn each command's exit status:
+#   A  prompt start        B  command input start
+#   C  output start        D  command finished (with exit status)
+# Each prompt also reports the working directory as an OSC 7 file:// URL and
+# whether the line editor uses vi or emacs key bindings (OSC 6977 keymap).
+# The submitted command line rides along with C as a percent-encoded
+# `cmdline_url` field.
+#
+# With CORGITERM_CHECK_COMMANDS set, Enter and Ctrl-J send the line to
+# CorgiTerm for a Safe Mode check (OSC 6977) with a fresh id, and CorgiTerm
+# answers with a key bound for that id once the line may run. The line runs
+# only if it is still the one that was checked; otherwise it is checked again.
+
+# Hide the bootstrap directory from programs started by this shell
+if set -q CORGITERM_XDG_DATA_DIRS
+    set -gx XDG_DATA_DIRS $CORGITERM_XDG_DATA_DIRS
+    set -e CORGITERM_XDG_DATA_DIRS
+else
+    set -e XDG_DATA_DIRS
+end
+
+status is-interactive; or exit 0
+set -q __corgiterm_loaded; and exit 0
+set -g __corgiterm_loaded 1
+
+if set -q CORGITERM_CHECK_COMMANDS
+    set -e CORGITERM_CHECK_COMMANDS
+    set -g __corgiterm_check 1
+end
+
+# Enter: remember the line and ask CorgiTerm to check it, binding only this
+# check's answer key
+function __corgiterm_check_line
+    __corgiterm_unbind_answer
+    set -g __corgiterm_check_id (random)(random)
+    set -g __corgiterm_checked (commandline | string collect)
+    for mode in default insert
+        bind -M $mode \e\[6977\;$__corgiterm_check_id~ __corgiterm_answer
+    end
+    printf '\e]6977;check;id=%s;cmdline_url=%s\a' $__corgiterm_check_id \
+        (commandline | string collect | string escape --style=url)
+end
+
+function __corgiterm_unbind_answer
+    set -q __corgiterm_check_id; or return 0
+    for mode in default insert
+        bind -M $mode -e \e\[6977\;$__corgiterm_check_id~
+    end
+    set -e __corgiterm_check_id
+end
+
+# The answer arrived: run the line if it is unchanged
+function __corgiterm_answer
+    __corgiterm_unbind_answer
+    set -l line (commandline | string collect)
+    if test "$line" = "$__corgiterm_checked"
+        commandline -f execute
+    else
+        __corgiterm_check_line
+    end
+end
+
+# Key bindings are reset when fish_key_bindings changes, so bind again then
+function __corgiterm_bind_check --on-variable fish_key_bindings
+    set -q __corgiterm_check; or return
+    for mode in default insert
+        bind -M $mode \r __corgiterm_check_line
+        bind -M $mode \n __corgiterm_check_line
+    end
+end
+
+function __corgiterm_prompt_start --on-event fish_prompt
+    if set -q __corgiterm_running
+        printf '\e]133;D;%s\a' $__corgiterm_status
+        set -e __corgiterm_running
+    end
+    printf '\e]7;file://%s%s\a' $hostname (string escape --style=url -- $PWD)
+    if string match -q '*vi*' -- $fish_key_bindings
+        printf '\e]6977;keymap;vi\a'
+    else
+        printf '\e]6977;keymap;emacs\a'
+    end
+    printf '\e]133;A\a'
+
+    # config.fish has run by the first prompt; wrap whatever prompt it set up
+    # and bind Enter over the user's key bindings
+    if not set -q __corgiterm_prompt_wrapped
+        set -g __corgiterm_prompt_wrapped 1
+        __corgiterm_bind_check
+        functions -q fish_prompt; and functions --copy fish_prompt __corgiterm_user_prompt
+        function fish_prompt
+            functions -q __corgiterm_user_prompt; and __corgiterm_user_prompt
+            printf '\e]133;B\a'
+        end
+    end
+end
+
+function __corgiterm_preexec --on-event fish_preexec
+    set -g __corgiterm_running 1
+    printf '\e]133;C;cmdline_url=%s\a' (string escape --style=url -- "$argv")
+end
+
+function __corgiterm_postexec --on-event fish_postexec
+    set -g __corgiterm_status $status
+end
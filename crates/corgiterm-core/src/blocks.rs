@@ -0,0 +1,336 @@
+//! Command blocks
+//!
+//! With shell integration (see [`crate::shell_integration`]) the scrollback is
+//! a list of command blocks: the prompt and command line, the command's output
+//! and how it exited. The engine records each block's lines as the OSC 133
+//! marks arrive and keeps them in step with scrollback eviction and reflow.
+//!
+//! Lines are absolute buffer lines, the same indices as
+//! [`TerminalEngine::all_text`](crate::TerminalEngine::all_text): 0 is the
+//! oldest line in scrollback.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::terminal::TerminalEvent;
+
+/// One command and its output
+#[derive(Debug, Clone)]
+pub struct CommandBlock {
+    /// First line of the prompt
+    pub prompt_line: usize,
+    /// First line of output, once the command was submitted
+    pub output_line: Option<usize>,
+    /// First line after the output, once the command finished
+    pub end_line: Option<usize>,
+    /// Command line, when the shell reports it
+    pub command: Option<String>,
+    /// Exit status, when the shell reports it
+    pub exit_code: Option<i32>,
+    /// Wall-clock time from submission to completion
+    pub duration: Option<Duration>,
+    /// Output is folded away in the view
+    pub collapsed: bool,
+    started: Option<Instant>,
+}
+
+impl CommandBlock {
+    fn new(prompt_line: usize) -> Self {
+        Self {
+            prompt_line,
+            output_line: None,
+            end_line: None,
+            command: None,
+            exit_code: None,
+            duration: None,
+            collapsed: false,
+            started: None,
+        }
+    }
+
+    /// The command has finished
+    pub fn is_finished(&self) -> bool {
+        self.end_line.is_some()
+    }
+
+    /// The command is still running
+    pub fn is_running(&self) -> bool {
+        self.output_line.is_some() && self.end_line.is_none()
+    }
+
+    /// `Some(true)` for exit status 0, `None` while running or when unknown
+    pub fn succeeded(&self) -> Option<bool> {
+        self.exit_code.map(|code| code == 0)
+    }
+
+    /// Lines holding the output of a finished command
+    pub fn output_range(&self) -> Option<Range<usize>> {
+        Some(self.output_line?..self.end_line?)
+    }
+
+    fn positions_mut(&mut self) -> impl Iterator<Item = &mut usize> {
+        std::iter::once(&mut self.prompt_line)
+            .chain(self.output_line.as_mut())
+            .chain(self.end_line.as_mut())
+    }
+}
+
+/// The blocks of one terminal, oldest first
+#[derive(Debug, Default, Clone)]
+pub struct CommandBlocks {
+    blocks: Vec<CommandBlock>,
+}
+
+impl CommandBlocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All blocks, oldest first
+    pub fn blocks(&self) -> &[CommandBlock] {
+        &self.blocks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Forget every block
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Record a shell integration mark seen with the cursor at `line`
+    ///
+    /// For [`TerminalEvent::CommandFinished`], `line` is the first line after
+    /// the output.
+    pub fn apply(&mut self, event: &TerminalEvent, line: usize) {
+        match event {
+            TerminalEvent::PromptStart => {
+                // A command that never reported finishing (shell killed, exec'd
+                // into another shell) ends where the new prompt begins
+                if let Some(last) = self.blocks.last_mut() {
+                    if last.is_running() {
+                        last.end_line = Some(line.max(last.output_line.unwrap_or(line)));
+                    } else if last.output_line.is_none() {
+                        // A prompt that never ran a command is redrawn
+                        self.blocks.pop();
+                    }
+                }
+                self.blocks.push(CommandBlock::new(line));
+            }
+            TerminalEvent::OutputStart { command } => {
+                let last = match self.blocks.last_mut() {
+                    Some(last) if last.output_line.is_none() => last,
+                    _ => {
+                        self.blocks.push(CommandBlock::new(line));
+                        self.blocks.last_mut().expect("just pushed")
+                    }
+                };
+                last.output_line = Some(line);
+                last.command = command.clone();
+                last.started = Some(Instant::now());
+            }
+            TerminalEvent::CommandFinished { exit_code } => match self.blocks.last_mut() {
+                Some(last) if last.is_running() => {
+                    last.end_line = Some(line.max(last.output_line.unwrap_or(line)));
+                    last.exit_code = *exit_code;
+                    last.duration = last.started.map(|started| started.elapsed());
+                }
+                // An empty command line: nothing ran
+                Some(last) if last.output_line.is_none() => {
+                    self.blocks.pop();
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// The oldest `lines` lines left the scrollback
+    pub(crate) fn evict(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.blocks
+            .retain(|block| block.end_line.is_none_or(|end| end > lines));
+        for block in &mut self.blocks {
+            for position in block.positions_mut() {
+                *position = position.saturating_sub(lines);
+            }
+        }
+    }
+
+    /// Move every line through `map`, dropping blocks with a line that is gone
+    pub(crate) fn remap(&mut self, map: impl Fn(usize) -> Option<usize>) {
+        self.blocks.retain_mut(|block| {
+            for position in block.positions_mut() {
+                match map(*position) {
+                    Some(mapped) => *position = mapped,
+                    None => return false,
+                }
+            }
+            true
+        });
+    }
+
+    /// Index of the block `line` belongs to
+    pub fn block_at(&self, line: usize) -> Option<usize> {
+        let index = self
+            .blocks
+            .partition_point(|block| block.prompt_line <= line)
+            .checked_sub(1)?;
+        let block = &self.blocks[index];
+        let end = block
+            .end_line
+            .or_else(|| self.blocks.get(index + 1).map(|next| next.prompt_line))
+            .unwrap_or(usize::MAX);
+        (line < end || block.prompt_line == line).then_some(index)
+    }
+
+    /// Closest prompt above `line`
+    pub fn previous_prompt(&self, line: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .rev()
+            .map(|block| block.prompt_line)
+            .find(|&prompt| prompt < line)
+    }
+
+    /// Closest prompt below `line`
+    pub fn next_prompt(&self, line: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .map(|block| block.prompt_line)
+            .find(|&prompt| prompt > line)
+    }
+
+    /// Index of the most recent finished command
+    pub fn last_finished(&self) -> Option<usize> {
+        self.blocks.iter().rposition(CommandBlock::is_finished)
+    }
+
+    /// Fold or unfold a finished block's output, returning whether it changed
+    pub fn toggle_collapsed(&mut self, index: usize) -> bool {
+        match self.blocks.get_mut(index) {
+            Some(block) if block.output_range().is_some_and(|r| !r.is_empty()) => {
+                block.collapsed = !block.collapsed;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Output ranges of collapsed blocks, in order, with their block index
+    pub fn folds(&self) -> Vec<(Range<usize>, usize)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.collapsed)
+            .filter_map(|(index, block)| Some((block.output_range()?, index)))
+            .filter(|(range, _)| !range.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(blocks: &mut CommandBlocks, prompt: usize, output: usize, end: usize, code: i32) {
+        blocks.apply(&TerminalEvent::PromptStart, prompt);
+        blocks.apply(&TerminalEvent::CommandStart, prompt);
+        blocks.apply(
+            &TerminalEvent::OutputStart {
+                command: Some(format!("cmd{}", prompt)),
+            },
+            output,
+        );
+        blocks.apply(
+            &TerminalEvent::CommandFinished {
+                exit_code: Some(code),
+            },
+            end,
+        );
+    }
+
+    #[test]
+    fn test_marks_build_blocks() {
+        let mut blocks = CommandBlocks::new();
+        run(&mut blocks, 0, 1, 4, 0);
+        run(&mut blocks, 4, 5, 6, 2);
+        blocks.apply(&TerminalEvent::PromptStart, 6);
+
+        assert_eq!(blocks.len(), 3);
+        let first = &blocks.blocks()[0];
+        assert_eq!(first.output_range(), Some(1..4));
+        assert_eq!(first.command.as_deref(), Some("cmd0"));
+        assert_eq!(first.succeeded(), Some(true));
+        assert!(first.duration.is_some());
+        assert_eq!(blocks.blocks()[1].exit_code, Some(2));
+        assert!(!blocks.blocks()[2].is_finished());
+        assert_eq!(blocks.last_finished(), Some(1));
+    }
+
+    #[test]
+    fn test_empty_command_lines_are_dropped() {
+        let mut blocks = CommandBlocks::new();
+        blocks.apply(&TerminalEvent::PromptStart, 0);
+        blocks.apply(&TerminalEvent::CommandFinished { exit_code: Some(0) }, 1);
+        blocks.apply(&TerminalEvent::PromptStart, 1);
+        blocks.apply(&TerminalEvent::PromptStart, 2);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks.blocks()[0].prompt_line, 2);
+    }
+
+    #[test]
+    fn test_navigation_and_lookup() {
+        let mut blocks = CommandBlocks::new();
+        run(&mut blocks, 0, 1, 4, 0);
+        run(&mut blocks, 4, 5, 9, 1);
+        blocks.apply(&TerminalEvent::PromptStart, 9);
+
+        assert_eq!(blocks.previous_prompt(5), Some(4));
+        assert_eq!(blocks.previous_prompt(4), Some(0));
+        assert_eq!(blocks.previous_prompt(0), None);
+        assert_eq!(blocks.next_prompt(0), Some(4));
+        assert_eq!(blocks.next_prompt(9), None);
+
+        assert_eq!(blocks.block_at(3), Some(0));
+        assert_eq!(blocks.block_at(4), Some(1));
+        assert_eq!(blocks.block_at(20), Some(2));
+    }
+
+    #[test]
+    fn test_eviction_shifts_and_drops() {
+        let mut blocks = CommandBlocks::new();
+        run(&mut blocks, 0, 1, 4, 0);
+        run(&mut blocks, 4, 5, 9, 0);
+        blocks.evict(5);
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks.blocks()[0];
+        assert_eq!(block.prompt_line, 0);
+        assert_eq!(block.output_range(), Some(0..4));
+    }
+
+    #[test]
+    fn test_collapse_and_folds() {
+        let mut blocks = CommandBlocks::new();
+        run(&mut blocks, 0, 1, 4, 0);
+        run(&mut blocks, 4, 5, 5, 0);
+        blocks.apply(&TerminalEvent::PromptStart, 5);
+
+        assert!(blocks.toggle_collapsed(0));
+        // No output to fold
+        assert!(!blocks.toggle_collapsed(1));
+        // Still running
+        assert!(!blocks.toggle_collapsed(2));
+        assert_eq!(blocks.folds(), vec![(1..4, 0)]);
+        assert!(blocks.toggle_collapsed(0));
+        assert!(blocks.folds().is_empty());
+    }
+}
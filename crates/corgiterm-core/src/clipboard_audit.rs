@@ -0,0 +1,94 @@
+//! Audit log of programs reading the clipboard
+//!
+//! Programs can ask the terminal for the clipboard contents with OSC 52 (tmux,
+//! neovim and friends do this over SSH). Every request the UI answers, allowed
+//! or denied, is appended to a JSON-lines log so the user can see which
+//! session read the clipboard and when. The clipboard contents are never
+//! logged, only their size.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// One answered clipboard read request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClipboardReadRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Terminal session that asked, by its history session id
+    pub session: String,
+    /// Foreground program at the time of the request, when known
+    pub program: Option<String>,
+    /// Working directory of the session, when known
+    pub directory: Option<PathBuf>,
+    /// The primary selection was requested rather than the clipboard
+    pub primary: bool,
+    pub allowed: bool,
+    /// Bytes handed to the program (0 when denied)
+    pub bytes: usize,
+}
+
+/// Default log location: `<local data dir>/corgiterm/clipboard-audit.log`
+pub fn default_log_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("corgiterm").join("clipboard-audit.log"))
+}
+
+/// Append `record` to the log at `path`, creating it if needed
+pub fn append(path: &Path, record: &ClipboardReadRecord) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Read every record in the log at `path`, skipping lines that do not parse
+pub fn read(path: &Path) -> std::io::Result<Vec<ClipboardReadRecord>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read() {
+        let path = std::env::temp_dir()
+            .join(format!("corgiterm-clip-{}", uuid::Uuid::new_v4()))
+            .join("clipboard-audit.log");
+        let record = ClipboardReadRecord {
+            timestamp: Utc::now(),
+            session: "pid 42".to_string(),
+            program: Some("nvim".to_string()),
+            directory: Some(PathBuf::from("/tmp")),
+            primary: false,
+            allowed: true,
+            bytes: 12,
+        };
+        append(&path, &record).unwrap();
+        append(
+            &path,
+            &ClipboardReadRecord {
+                allowed: false,
+                bytes: 0,
+                ..record.clone()
+            },
+        )
+        .unwrap();
+
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], record);
+        assert!(!records[1].allowed);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
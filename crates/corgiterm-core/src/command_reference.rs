@@ -0,0 +1,697 @@
+//! Offline command explanations
+//!
+//! Breaks a command line into programs, subcommands, flags and operands and
+//! says what each word means, the way explainshell does. The meanings come
+//! from a reference of common commands shipped with CorgiTerm (see
+//! `safe-mode/commands.toml` for the format), so Safe Mode previews and the
+//! AI panel's Explain mode have a breakdown to show without an AI provider.
+//!
+//! ```text
+//! git commit -am Fix typo
+//!   git          Distributed version control
+//!   commit       Record the staged changes as a new commit
+//!   -a           Stage changes to tracked files first
+//!   -m Fix typo  Use MSG as the commit message
+//! ```
+
+use crate::error::{CoreError, Result};
+use crate::safe_mode_rules::one_or_many;
+use crate::shell_syntax::{self, RedirectOp, Redirection, SimpleCommand};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Reference shipped with CorgiTerm
+const BUILTIN_REFERENCE: &str = include_str!("../safe-mode/commands.toml");
+
+/// Commands run by wrappers beyond this are not followed
+const MAX_NESTING: usize = 16;
+
+/// Widest word that still gets its meaning aligned with the others
+const MAX_ALIGNED_WIDTH: usize = 24;
+
+/// What a word in a command is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WordKind {
+    /// The program being run
+    Program,
+    /// A subcommand such as `commit` in `git commit`
+    Subcommand,
+    /// An option, with its value if it takes one
+    Flag,
+    /// Any other argument
+    Operand,
+    /// `NAME=value` set for the command
+    Assignment,
+    /// `> file` and the like
+    Redirection,
+}
+
+/// One word of a command and what it means
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExplainedWord {
+    /// The word as written; a flag's value is included
+    pub text: String,
+    pub kind: WordKind,
+    /// `None` when the reference does not know it
+    pub meaning: Option<String>,
+}
+
+/// What one simple command in a line does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandExplanation {
+    /// The command's words
+    pub command: String,
+    /// The program and subcommands, such as `docker compose up`
+    pub name: String,
+    /// What it does; `None` for programs the reference does not know
+    pub summary: Option<String>,
+    /// Each word, in order
+    pub words: Vec<ExplainedWord>,
+}
+
+impl CommandExplanation {
+    /// `name: summary`, for programs the reference knows
+    pub fn headline(&self) -> Option<String> {
+        self.summary
+            .as_ref()
+            .map(|summary| format!("{}: {}", self.name, summary))
+    }
+}
+
+impl fmt::Display for CommandExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command)?;
+        if self.summary.is_none() {
+            write!(
+                f,
+                "\n  {} is not in the built-in command reference",
+                self.name
+            )?;
+        }
+        let width = self
+            .words
+            .iter()
+            .map(|word| word.text.chars().count())
+            .filter(|&width| width <= MAX_ALIGNED_WIDTH)
+            .max()
+            .unwrap_or(0);
+        for word in &self.words {
+            let meaning = match (&word.meaning, word.kind) {
+                (Some(meaning), _) => meaning.as_str(),
+                (None, WordKind::Flag) => "Unknown option",
+                (None, _) => continue,
+            };
+            write!(f, "\n  {:width$}  {}", word.text, meaning, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// A command or subcommand as written in the reference file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    /// Only top-level commands have names; subcommands are keyed by theirs
+    #[serde(default, deserialize_with = "one_or_many")]
+    name: Vec<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    operands: Option<String>,
+    #[serde(default)]
+    wraps: Option<usize>,
+    #[serde(default)]
+    flags: BTreeMap<String, String>,
+    #[serde(default)]
+    subcommands: BTreeMap<String, Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReferenceFile {
+    #[serde(default)]
+    command: Vec<Entry>,
+}
+
+/// What the reference knows about a command or subcommand
+#[derive(Debug, Clone, Default)]
+struct CommandDoc {
+    summary: Option<String>,
+    operands: Option<String>,
+    /// Another command starts after this many operands
+    wraps: Option<usize>,
+    flags: Vec<FlagDoc>,
+    subcommands: HashMap<String, CommandDoc>,
+}
+
+#[derive(Debug, Clone)]
+struct FlagDoc {
+    /// `-a`, `--all`, `-name` or `if`
+    spellings: Vec<String>,
+    /// Name of the value the flag takes
+    value: Option<String>,
+    meaning: String,
+}
+
+impl From<Entry> for CommandDoc {
+    fn from(entry: Entry) -> Self {
+        let flags = entry
+            .flags
+            .into_iter()
+            .map(|(key, meaning)| {
+                let mut value = None;
+                let spellings = key
+                    .split('|')
+                    .map(|spelling| match spelling.split_once('=') {
+                        Some((name, takes)) => {
+                            value = Some(takes.to_string());
+                            name.to_string()
+                        }
+                        None => spelling.to_string(),
+                    })
+                    .collect();
+                FlagDoc {
+                    spellings,
+                    value,
+                    meaning,
+                }
+            })
+            .collect();
+        Self {
+            summary: entry.summary,
+            operands: entry.operands,
+            wraps: entry.wraps,
+            flags,
+            subcommands: entry
+                .subcommands
+                .into_iter()
+                .map(|(name, entry)| (name, entry.into()))
+                .collect(),
+        }
+    }
+}
+
+/// Flags and operands of the common commands
+#[derive(Debug, Clone, Default)]
+pub struct CommandReference {
+    commands: HashMap<String, CommandDoc>,
+}
+
+impl CommandReference {
+    /// The reference shipped with CorgiTerm
+    pub fn builtin() -> Self {
+        let mut reference = Self::default();
+        reference
+            .add_toml(BUILTIN_REFERENCE)
+            .expect("built-in command reference is valid");
+        reference
+    }
+
+    /// Add the commands in TOML text; a command already known is replaced
+    pub fn add_toml(&mut self, text: &str) -> Result<()> {
+        let parsed: ReferenceFile =
+            toml::from_str(text).map_err(|e| CoreError::Config(e.to_string()))?;
+        for mut entry in parsed.command {
+            let names = std::mem::take(&mut entry.name);
+            if names.is_empty() {
+                return Err(CoreError::Config(
+                    "command reference entry without a name".to_string(),
+                ));
+            }
+            let doc = CommandDoc::from(entry);
+            for name in names {
+                self.commands.insert(name, doc.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of command names the reference knows
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Whether the reference knows `program`
+    pub fn knows(&self, program: &str) -> bool {
+        self.commands.contains_key(program)
+    }
+
+    /// Explain every simple command in a line, including those in
+    /// pipelines, lists and substitutions; a wrapper such as `sudo` is
+    /// followed by the command it runs
+    pub fn explain(&self, line: &str) -> Vec<CommandExplanation> {
+        let parsed = shell_syntax::parse(line);
+        let mut explanations = Vec::new();
+        for simple in parsed.simple_commands() {
+            self.simple(simple, &mut explanations);
+        }
+        explanations
+    }
+
+    fn simple(&self, simple: &SimpleCommand, explanations: &mut Vec<CommandExplanation>) {
+        let words: Vec<&str> = simple.words.iter().map(|w| w.text.as_str()).collect();
+        let first = explanations.len();
+        self.words(&words, 0, explanations);
+        let Some(explanation) = explanations.get_mut(first) else {
+            return;
+        };
+
+        let mut words: Vec<ExplainedWord> = simple
+            .assignments
+            .iter()
+            .map(|word| ExplainedWord {
+                text: word.text.clone(),
+                kind: WordKind::Assignment,
+                meaning: Some(assignment_meaning(&word.text)),
+            })
+            .collect();
+        words.append(&mut explanation.words);
+        explanation.words = words;
+        explanation
+            .words
+            .extend(simple.redirections.iter().map(|redirection| ExplainedWord {
+                text: format!(
+                    "{}{}{}",
+                    redirection.fd.map(|fd| fd.to_string()).unwrap_or_default(),
+                    redirection.op.symbol(),
+                    redirection.target.text
+                ),
+                kind: WordKind::Redirection,
+                meaning: Some(redirection_meaning(redirection)),
+            }));
+    }
+
+    /// Explain a command's words, then the command it wraps if any
+    fn words(&self, words: &[&str], depth: usize, explanations: &mut Vec<CommandExplanation>) {
+        let Some((&first, args)) = words.split_first() else {
+            return;
+        };
+        let program = first.rsplit('/').next().unwrap_or(first);
+        let doc = self.commands.get(program);
+        let mut explanation = CommandExplanation {
+            command: words.join(" "),
+            name: program.to_string(),
+            summary: doc.and_then(|doc| doc.summary.clone()),
+            words: vec![ExplainedWord {
+                text: first.to_string(),
+                kind: WordKind::Program,
+                meaning: doc.and_then(|doc| doc.summary.clone()),
+            }],
+        };
+
+        // Flags of the command and each subcommand so far, innermost last
+        let mut scopes: Vec<&CommandDoc> = doc.into_iter().collect();
+        let mut operands = 0;
+        let mut options_ended = false;
+        let mut index = 0;
+        while index < args.len() {
+            let arg = args[index];
+            index += 1;
+
+            if !options_ended {
+                if let Some((flag, attached)) = find_flag(&scopes, arg) {
+                    let mut text = arg.to_string();
+                    if flag.value.is_some() && attached.is_none() {
+                        if let Some(value) = args.get(index) {
+                            text = format!("{} {}", arg, value);
+                            index += 1;
+                        }
+                    }
+                    explanation.words.push(flag_word(text, Some(flag)));
+                    continue;
+                }
+                if arg == "--" {
+                    options_ended = true;
+                    explanation.words.push(ExplainedWord {
+                        text: arg.to_string(),
+                        kind: WordKind::Flag,
+                        meaning: Some("End of options; the rest are operands".to_string()),
+                    });
+                    continue;
+                }
+                if arg.starts_with("--") {
+                    explanation.words.push(flag_word(arg.to_string(), None));
+                    continue;
+                }
+                if arg.starts_with('-') && arg != "-" {
+                    index += short_flags(&scopes, arg, args.get(index), &mut explanation.words);
+                    continue;
+                }
+            }
+
+            let Some(&current) = scopes.last() else {
+                explanation.words.push(ExplainedWord {
+                    text: arg.to_string(),
+                    kind: WordKind::Operand,
+                    meaning: None,
+                });
+                continue;
+            };
+            if operands == 0 {
+                if let Some(sub) = current.subcommands.get(arg) {
+                    explanation.name = format!("{} {}", explanation.name, arg);
+                    if sub.summary.is_some() {
+                        explanation.summary = sub.summary.clone();
+                    }
+                    explanation.words.push(ExplainedWord {
+                        text: arg.to_string(),
+                        kind: WordKind::Subcommand,
+                        meaning: sub.summary.clone(),
+                    });
+                    scopes.push(sub);
+                    continue;
+                }
+            }
+            if let Some(skip) = current.wraps {
+                if is_assignment(arg) {
+                    explanation.words.push(ExplainedWord {
+                        text: arg.to_string(),
+                        kind: WordKind::Assignment,
+                        meaning: Some(assignment_meaning(arg)),
+                    });
+                    continue;
+                }
+                if operands == skip && depth < MAX_NESTING {
+                    explanation.words.push(ExplainedWord {
+                        text: args[index - 1..].join(" "),
+                        kind: WordKind::Operand,
+                        meaning: Some("The command it runs, explained next".to_string()),
+                    });
+                    explanations.push(explanation);
+                    self.words(&args[index - 1..], depth + 1, explanations);
+                    return;
+                }
+            }
+            operands += 1;
+            explanation.words.push(ExplainedWord {
+                text: arg.to_string(),
+                kind: WordKind::Operand,
+                meaning: current.operands.clone(),
+            });
+        }
+        explanations.push(explanation);
+    }
+}
+
+/// The flag spelled `arg`, innermost scope first, with a value given
+/// after `=`
+fn find_flag<'a, 'b>(
+    scopes: &[&'a CommandDoc],
+    arg: &'b str,
+) -> Option<(&'a FlagDoc, Option<&'b str>)> {
+    for scope in scopes.iter().rev() {
+        for flag in &scope.flags {
+            for spelling in &flag.spellings {
+                if arg == spelling {
+                    return Some((flag, None));
+                }
+                if flag.value.is_some() {
+                    if let Some(value) = arg
+                        .strip_prefix(spelling.as_str())
+                        .and_then(|rest| rest.strip_prefix('='))
+                    {
+                        return Some((flag, Some(value)));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Explain a bundle of short flags such as `-la` or `-n5`, returning how
+/// many of the following arguments were taken as a value
+fn short_flags(
+    scopes: &[&CommandDoc],
+    arg: &str,
+    next: Option<&&str>,
+    words: &mut Vec<ExplainedWord>,
+) -> usize {
+    let bundle = &arg[1..];
+    let known = |c: char| find_flag(scopes, &format!("-{c}")).map(|(flag, _)| flag);
+    // Single-dash long options the reference does not know stay whole
+    if !bundle.chars().any(|c| known(c).is_some()) {
+        words.push(flag_word(arg.to_string(), None));
+        return 0;
+    }
+    for (offset, c) in bundle.char_indices() {
+        let flag = known(c);
+        if flag.is_some_and(|flag| flag.value.is_some()) {
+            let rest = &bundle[offset + c.len_utf8()..];
+            if !rest.is_empty() {
+                words.push(flag_word(format!("-{c}{rest}"), flag));
+                return 0;
+            }
+            return match next {
+                Some(value) => {
+                    words.push(flag_word(format!("-{c} {value}"), flag));
+                    1
+                }
+                None => {
+                    words.push(flag_word(format!("-{c}"), flag));
+                    0
+                }
+            };
+        }
+        words.push(flag_word(format!("-{c}"), flag));
+    }
+    0
+}
+
+fn flag_word(text: String, flag: Option<&FlagDoc>) -> ExplainedWord {
+    ExplainedWord {
+        text,
+        kind: WordKind::Flag,
+        meaning: flag.map(|flag| flag.meaning.clone()),
+    }
+}
+
+/// `NAME=value` with a valid variable name
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn assignment_meaning(word: &str) -> String {
+    let name = word.split_once('=').map_or(word, |(name, _)| name);
+    format!("Sets {} for this command", name)
+}
+
+fn stream(fd: u32) -> String {
+    match fd {
+        0 => "standard input".to_string(),
+        1 => "standard output".to_string(),
+        2 => "standard error".to_string(),
+        fd => format!("file descriptor {}", fd),
+    }
+}
+
+fn redirection_meaning(redirection: &Redirection) -> String {
+    let target = &redirection.target.text;
+    let input = || stream(redirection.fd.unwrap_or(0));
+    let output = || stream(redirection.fd.unwrap_or(1));
+    match redirection.op {
+        RedirectOp::Input => format!("Reads {} from {}", input(), target),
+        RedirectOp::Output | RedirectOp::Clobber => {
+            format!("Writes {} to {}, replacing its contents", output(), target)
+        }
+        RedirectOp::Append => format!("Appends {} to {}", output(), target),
+        RedirectOp::ReadWrite => format!("Opens {} for reading and writing", target),
+        RedirectOp::HereDoc => format!(
+            "Feeds the lines that follow, up to {}, to standard input",
+            target
+        ),
+        RedirectOp::HereString => format!("Feeds {} to standard input", target),
+        RedirectOp::DupInput => match target.parse::<u32>() {
+            Ok(fd) => format!("Reads {} from {}", input(), stream(fd)),
+            Err(_) => format!("Closes {}", input()),
+        },
+        RedirectOp::DupOutput if redirection.writes_file() => format!(
+            "Writes standard output and standard error to {}, replacing its contents",
+            target
+        ),
+        RedirectOp::DupOutput => match target.parse::<u32>() {
+            Ok(fd) => format!("Sends {} to where {} goes", output(), stream(fd)),
+            Err(_) => format!("Closes {}", output()),
+        },
+        RedirectOp::OutputAll => format!(
+            "Writes standard output and standard error to {}, replacing its contents",
+            target
+        ),
+        RedirectOp::AppendAll => {
+            format!("Appends standard output and standard error to {}", target)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meanings(explanation: &CommandExplanation) -> Vec<(&str, Option<&str>)> {
+        explanation
+            .words
+            .iter()
+            .map(|word| (word.text.as_str(), word.meaning.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_reference_covers_common_commands() {
+        let reference = CommandReference::builtin();
+        assert!(reference.len() >= 150, "only {} commands", reference.len());
+        for program in [
+            "ls", "rm", "git", "docker", "kubectl", "apt", "npm", "cargo",
+        ] {
+            assert!(reference.knows(program), "{program} is missing");
+        }
+    }
+
+    #[test]
+    fn test_short_flag_bundles_are_split() {
+        let explained = CommandReference::builtin().explain("ls -la /tmp");
+        assert_eq!(explained.len(), 1);
+        let words = meanings(&explained[0]);
+        assert_eq!(words[1].0, "-l");
+        assert_eq!(words[2].0, "-a");
+        assert!(words[2].1.unwrap().contains("hidden"));
+        assert_eq!(words[3], ("/tmp", Some("Files or directories to list")));
+        assert_eq!(
+            explained[0].headline().as_deref(),
+            Some("ls: List directory contents")
+        );
+    }
+
+    #[test]
+    fn test_flag_values_in_every_form() {
+        let reference = CommandReference::builtin();
+        for line in [
+            "head -n 5 f",
+            "head -n5 f",
+            "head --lines=5 f",
+            "head --lines 5 f",
+        ] {
+            let explained = reference.explain(line);
+            let words = meanings(&explained[0]);
+            assert_eq!(words.len(), 3, "{line}: {words:?}");
+            assert_eq!(words[1].1, Some("Print the first NUM lines instead of 10"));
+            assert_eq!(words[2].0, "f");
+        }
+    }
+
+    #[test]
+    fn test_subcommands_and_their_flags() {
+        let explained = CommandReference::builtin().explain("git -C repo commit -am 'Fix typo'");
+        let explanation = &explained[0];
+        assert_eq!(explanation.name, "git commit");
+        assert_eq!(
+            explanation.summary.as_deref(),
+            Some("Record the staged changes as a new commit")
+        );
+        let words = meanings(explanation);
+        assert_eq!(words[1].0, "-C repo");
+        assert_eq!(words[2].0, "commit");
+        assert_eq!(words[3].0, "-a");
+        assert_eq!(
+            words[4],
+            ("-m Fix typo", Some("Use MSG as the commit message"))
+        );
+
+        let explained = CommandReference::builtin().explain("docker compose up -d --build");
+        assert_eq!(explained[0].name, "docker compose up");
+        assert!(meanings(&explained[0])
+            .iter()
+            .all(|(_, meaning)| meaning.is_some()));
+    }
+
+    #[test]
+    fn test_exact_spellings() {
+        let reference = CommandReference::builtin();
+        let words = meanings(&reference.explain("find . -name '*.rs' -delete")[0]).len();
+        assert_eq!(words, 4);
+        let explained = reference.explain("kill -9 1234");
+        assert!(explained[0].words[1]
+            .meaning
+            .as_deref()
+            .unwrap()
+            .contains("Force"));
+        let explained = reference.explain("dd if=disk.img of=/dev/sdb bs=4M");
+        assert!(explained[0].words[2]
+            .meaning
+            .as_deref()
+            .unwrap()
+            .contains("overwrites"));
+    }
+
+    #[test]
+    fn test_wrappers_explain_the_wrapped_command() {
+        let explained = CommandReference::builtin().explain("sudo -u bob FOO=1 rm -rf build");
+        assert_eq!(explained.len(), 2);
+        assert_eq!(explained[0].name, "sudo");
+        assert_eq!(explained[0].words[1].text, "-u bob");
+        assert_eq!(explained[0].words[2].kind, WordKind::Assignment);
+        assert_eq!(explained[1].name, "rm");
+        assert_eq!(explained[1].command, "rm -rf build");
+
+        let explained = CommandReference::builtin().explain("timeout 5 make -j4");
+        assert_eq!(explained[1].name, "make");
+        assert_eq!(explained[1].words[1].text, "-j4");
+    }
+
+    #[test]
+    fn test_pipelines_redirections_and_unknown_words() {
+        let explained = CommandReference::builtin()
+            .explain("LANG=C grep -q --frobnicate x < in 2>&1 | frob -z");
+        assert_eq!(explained.len(), 2);
+        let grep = &explained[0];
+        assert_eq!(grep.words[0].kind, WordKind::Assignment);
+        assert_eq!(grep.words[3], flag_word("--frobnicate".to_string(), None));
+        let redirections: Vec<_> = grep
+            .words
+            .iter()
+            .filter(|word| word.kind == WordKind::Redirection)
+            .map(|word| word.meaning.clone().unwrap())
+            .collect();
+        assert_eq!(
+            redirections,
+            [
+                "Reads standard input from in",
+                "Sends standard error to where standard output goes"
+            ]
+        );
+
+        let frob = &explained[1];
+        assert_eq!(frob.summary, None);
+        assert_eq!(frob.headline(), None);
+        assert!(frob
+            .to_string()
+            .contains("not in the built-in command reference"));
+        assert!(frob.to_string().contains("Unknown option"));
+    }
+
+    #[test]
+    fn test_user_entries_replace_builtin_ones() {
+        let mut reference = CommandReference::builtin();
+        reference
+            .add_toml(
+                r#"
+                [[command]]
+                name = "ls"
+                summary = "Show files"
+                "#,
+            )
+            .unwrap();
+        let explained = reference.explain("ls");
+        assert_eq!(explained[0].summary.as_deref(), Some("Show files"));
+        assert!(reference.add_toml("[[command]]\nsummary = \"x\"").is_err());
+    }
+}
@@ -20,11 +20,18 @@
 use alacritty_terminal::event::{Event, EventListener};
 use alacritty_terminal::grid::{Dimensions, Scroll};
 use alacritty_terminal::index::{Column, Line};
-use alacritty_terminal::term::cell::{Cell, Flags};
-use alacritty_terminal::term::{Config, Term};
+use alacritty_terminal::term::cell::{Cell, Flags, Hyperlink};
+use alacritty_terminal::term::{ClipboardType, Config, Osc52, Term, TermMode};
 use alacritty_terminal::vte::ansi::{Color, CursorShape, NamedColor, Processor};
 
-use crate::terminal::{ClipboardAction, TerminalEvent, TerminalSize};
+use crate::blocks::CommandBlocks;
+use crate::images::{
+    GraphicsProtocol, GraphicsScanner, GraphicsSequence, ImageStore, InlineImage, KittyParser,
+    SixelParser,
+};
+use crate::key_encoding::KeyboardFlags;
+use crate::shell_integration::OscScanner;
+use crate::terminal::{ClipboardAction, ClipboardReply, TerminalEvent, TerminalSize};
 
 /// A logical color for a rendered cell. Resolution to concrete RGBA is the
 /// renderer's job (it owns the active theme palette), so the model layer never
@@ -69,6 +76,84 @@ pub struct RenderCell {
     pub flags: RenderFlags,
     /// Column span: 1 for normal cells, 2 for wide (CJK / emoji) cells.
     pub width: u8,
+    /// Explicit OSC 8 hyperlink the cell belongs to, if any.
+    pub hyperlink: Option<CellHyperlink>,
+}
+
+/// An OSC 8 hyperlink. Every cell printed under one link shares it, so equal
+/// values mean the same link even when its text spans several runs or rows.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CellHyperlink(Hyperlink);
+
+impl CellHyperlink {
+    /// Link target as sent by the program.
+    pub fn uri(&self) -> &str {
+        self.0.uri()
+    }
+
+    /// Link id: the program's `id=` parameter, or a generated unique one.
+    pub fn id(&self) -> &str {
+        self.0.id()
+    }
+
+    /// True for schemes that are safe to hand to the desktop's URL opener.
+    /// Programs choose link targets freely, so others are not opened.
+    pub fn is_openable(&self) -> bool {
+        let scheme = self.uri().split_once(':').map(|(scheme, _)| scheme);
+        scheme.is_some_and(|scheme| {
+            ["http", "https", "ftp", "mailto", "file"]
+                .iter()
+                .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+        })
+    }
+}
+
+/// A run of cells on one viewport row that belong to the same hyperlink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperlinkSpan {
+    pub row: usize,
+    pub col_start: usize,
+    /// Exclusive.
+    pub col_end: usize,
+    pub link: CellHyperlink,
+}
+
+/// Group the hyperlinked cells of [`TerminalEngine::render_cells`] output into
+/// spans, in row-major order.
+pub fn hyperlink_spans(cells: &[RenderCell]) -> Vec<HyperlinkSpan> {
+    let mut spans: Vec<HyperlinkSpan> = Vec::new();
+    for cell in cells {
+        let Some(link) = &cell.hyperlink else {
+            continue;
+        };
+        let end = cell.col + cell.width.max(1) as usize;
+        match spans.last_mut() {
+            Some(span)
+                if span.row == cell.row && span.col_end == cell.col && span.link == *link =>
+            {
+                span.col_end = end;
+            }
+            _ => spans.push(HyperlinkSpan {
+                row: cell.row,
+                col_start: cell.col,
+                col_end: end,
+                link: link.clone(),
+            }),
+        }
+    }
+    spans
+}
+
+/// An inline image intersecting the viewport.
+#[derive(Debug, Clone, Copy)]
+pub struct VisibleImage<'a> {
+    /// Viewport row of the image's top edge; negative when it starts above
+    /// the view.
+    pub row: i64,
+    pub col: usize,
+    pub width_cells: usize,
+    pub height_cells: usize,
+    pub image: &'a InlineImage,
 }
 
 /// Cursor shape, neutral over engines.
@@ -121,6 +206,36 @@ pub trait TerminalEngine {
     fn selection_text(&self) -> Option<String>;
     /// Adjust the maximum scrollback (in lines).
     fn set_max_scrollback(&mut self, lines: usize);
+    /// Command blocks reported through shell integration.
+    fn command_blocks(&self) -> &CommandBlocks;
+    /// Fold or unfold a finished block's output. Returns whether it changed.
+    fn toggle_block_collapsed(&mut self, index: usize) -> bool;
+    /// The buffer line (an [`all_text`](Self::all_text) index) shown on each
+    /// visible row. A collapsed block's placeholder row reports its first
+    /// hidden line. Empty while the alternate screen is active.
+    fn viewport_lines(&self) -> Vec<usize>;
+    /// Scroll so `line` is the top visible row, as far as the buffer allows.
+    fn scroll_to_line(&mut self, line: usize);
+    /// Kitty keyboard protocol flags the program negotiated (`CSI > u` and
+    /// friends); empty means legacy key encoding.
+    fn keyboard_flags(&self) -> KeyboardFlags;
+    /// Whether the program asked for pastes to be bracketed (`CSI ? 2004 h`).
+    fn bracketed_paste(&self) -> bool;
+    /// Inline images (kitty graphics, sixel) on the current screen that
+    /// intersect the view.
+    fn visible_images(&self) -> Vec<VisibleImage<'_>>;
+    /// Cell size in pixels, for sizing images that are given in pixels.
+    fn set_cell_pixel_size(&mut self, width: u32, height: u32);
+    /// Lines that have left the top of the buffer since the terminal
+    /// started, so `line + lines_evicted()` names a line for good.
+    fn lines_evicted(&self) -> usize;
+    /// Text of the buffer lines in `lines` (clamped to the buffer), trailing
+    /// blanks trimmed.
+    fn lines_text(&self, lines: std::ops::Range<usize>) -> Vec<String>;
+    /// Buffer line the cursor is on; the lines above it are complete.
+    fn cursor_buffer_line(&self) -> usize;
+    /// Whether a full-screen program has the alternate screen up.
+    fn alternate_screen(&self) -> bool;
 }
 
 /// Minimal [`Dimensions`] for constructing / resizing a `Term`. History is
@@ -161,9 +276,16 @@ impl EventListener for EventProxy {
             Event::ClipboardStore(_, text) => {
                 Some(TerminalEvent::Clipboard(ClipboardAction::Copy(text)))
             }
-            // ClipboardLoad / ColorRequest / TextAreaSizeRequest carry reply
-            // closures; Wakeup / MouseCursorDirty / CursorBlinkingChange / Exit /
-            // ChildExit are not surfaced through this channel.
+            // The UI decides whether the program may read the clipboard
+            Event::ClipboardLoad(ty, format) => {
+                Some(TerminalEvent::Clipboard(ClipboardAction::Paste {
+                    primary: ty == ClipboardType::Selection,
+                    reply: ClipboardReply::new(move |text| format(text)),
+                }))
+            }
+            // ColorRequest / TextAreaSizeRequest carry reply closures; Wakeup /
+            // MouseCursorDirty / CursorBlinkingChange / Exit / ChildExit are not
+            // surfaced through this channel.
             _ => None,
         };
         if let Some(ev) = mapped {
@@ -172,11 +294,59 @@ impl EventListener for EventProxy {
     }
 }
 
+/// Extra history `alacritty_terminal` may grow into before the engine trims it
+/// back to the configured scrollback. Trimming ourselves is what tells us how
+/// many lines left the top of the buffer, which keeps command block line
+/// numbers exact.
+const HISTORY_SLACK: usize = 1024;
+
 /// Terminal model backed by `alacritty_terminal`.
 pub struct AlacrittyEngine {
     term: Term<EventProxy>,
     processor: Processor,
     size: TerminalSize,
+    /// Picks out OSC sequences alacritty drops (OSC 133 shell integration).
+    osc: OscScanner,
+    /// Picks out kitty graphics and sixel sequences, which alacritty drops.
+    graphics: GraphicsScanner,
+    events: crossbeam_channel::Sender<TerminalEvent>,
+    blocks: CommandBlocks,
+    /// Images on the primary screen, anchored to buffer lines.
+    images: ImageStore,
+    /// Images on the alternate screen, dropped when it is left.
+    alt_images: ImageStore,
+    /// Cell size in pixels, as last reported by the renderer.
+    cell_pixels: (u32, u32),
+    max_scrollback: usize,
+    /// Primary screen history size after the last trim.
+    history: usize,
+    /// Lines evicted from the top of the buffer so far.
+    evicted: usize,
+}
+
+/// A sequence found in PTY output that the engine acts on itself.
+enum Embedded {
+    Mark(TerminalEvent),
+    Graphics(GraphicsSequence),
+}
+
+/// A row of the folded view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ViewRow {
+    Line(usize),
+    /// Placeholder for a collapsed block's output.
+    Fold {
+        line: usize,
+        hidden: usize,
+    },
+}
+
+impl ViewRow {
+    fn line(self) -> usize {
+        match self {
+            ViewRow::Line(line) | ViewRow::Fold { line, .. } => line,
+        }
+    }
 }
 
 impl AlacrittyEngine {
@@ -192,18 +362,309 @@ impl AlacrittyEngine {
             screen_lines: size.rows.max(1),
         };
         let config = Config {
-            scrolling_history: max_scrollback,
+            scrolling_history: max_scrollback + HISTORY_SLACK,
+            // Reads are forwarded as events and gated by the UI's policy
+            osc52: Osc52::CopyPaste,
+            // Track `CSI > u` / `CSI < u` / `CSI = u` and answer `CSI ? u`
+            kitty_keyboard: true,
             ..Config::default()
         };
-        let term = Term::new(config, &dims, EventProxy { tx: events });
+        let term = Term::new(config, &dims, EventProxy { tx: events.clone() });
         Self {
             term,
             processor: Processor::new(),
             size,
+            osc: OscScanner::new(),
+            graphics: GraphicsScanner::new(),
+            events,
+            blocks: CommandBlocks::new(),
+            images: ImageStore::new(),
+            alt_images: ImageStore::new(),
+            cell_pixels: (10, 20),
+            max_scrollback,
+            history: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Parse `bytes` a slice at a time, so history never outgrows the slack.
+    fn advance(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(HISTORY_SLACK) {
+            let was_alt_screen = self.in_alt_screen();
+            self.processor.advance(&mut self.term, chunk);
+            if self.in_alt_screen() != was_alt_screen {
+                self.alt_images.clear();
+            }
+            self.sync_history();
+        }
+    }
+
+    fn in_alt_screen(&self) -> bool {
+        self.term.mode().contains(TermMode::ALT_SCREEN)
+    }
+
+    /// Buffer line of the cursor.
+    fn cursor_line(&self) -> usize {
+        let grid = self.term.grid();
+        (grid.history_size() as i32 + grid.cursor.point.line.0).max(0) as usize
+    }
+
+    /// Trim history back to the configured scrollback and shift command blocks
+    /// by the lines that left the top of the buffer.
+    fn sync_history(&mut self) {
+        // The alternate screen has no history; the primary one is untouched.
+        if self.in_alt_screen() {
+            return;
+        }
+        let history = self.term.grid().history_size();
+        // Shrinking history means it was cleared (CSI 3 J).
+        let mut evicted = self.history.saturating_sub(history);
+        if history > self.max_scrollback {
+            evicted += history - self.max_scrollback;
+            let grid = self.term.grid_mut();
+            grid.update_history(self.max_scrollback);
+            grid.update_history(self.max_scrollback + HISTORY_SLACK);
+        }
+        self.history = self.term.grid().history_size();
+        self.evicted += evicted;
+        self.blocks.evict(evicted);
+        self.images.evict(evicted);
+    }
+
+    /// Record a shell integration mark in the command blocks.
+    fn record_mark(&mut self, event: &TerminalEvent) {
+        // Full-screen programs do not print marks for the primary screen
+        if self.in_alt_screen() {
+            return;
+        }
+        let mut line = self.cursor_line();
+        if matches!(event, TerminalEvent::CommandFinished { .. })
+            && self.term.grid().cursor.point.column.0 > 0
+        {
+            // Output without a trailing newline ends on the cursor's line
+            line += 1;
+        }
+        self.blocks.apply(event, line);
+    }
+
+    /// Apply a kitty graphics or sixel sequence at the cursor, then move the
+    /// cursor past the image the way the protocol expects.
+    fn apply_graphics(&mut self, seq: GraphicsSequence) {
+        let line = self.cursor_line();
+        let col = self.term.grid().cursor.point.column.0;
+        let cell_pixels = self.cell_pixels;
+        let images = if self.in_alt_screen() {
+            &mut self.alt_images
+        } else {
+            &mut self.images
+        };
+        match seq.protocol {
+            GraphicsProtocol::Kitty => {
+                let Some(cmd) = KittyParser::new().parse(&seq.payload) else {
+                    return;
+                };
+                let outcome = images.apply_kitty(cmd, line, col, cell_pixels);
+                if let Some(reply) = outcome.reply {
+                    let _ = self.events.send(TerminalEvent::PtyWrite(reply));
+                }
+                // Kitty leaves the cursor after the image, on its last row
+                if let Some(placement) = outcome.placement.filter(|_| outcome.move_cursor) {
+                    let mut motion = "\n".repeat(placement.height_cells - 1);
+                    motion.push_str(&format!("\x1b[{}C", placement.width_cells));
+                    self.advance(motion.as_bytes());
+                }
+            }
+            GraphicsProtocol::Sixel => {
+                let Some(image) = SixelParser::new().parse(&seq.payload) else {
+                    return;
+                };
+                // Sixel leaves the cursor at the start of the line below
+                if let Some(placement) = images.place_sixel(image, line, col, cell_pixels) {
+                    let mut motion = "\n".repeat(placement.height_cells);
+                    motion.push('\r');
+                    self.advance(motion.as_bytes());
+                }
+            }
+        }
+    }
+
+    /// The buffer line on each viewport row of the current screen; `None`
+    /// for a collapsed block's placeholder row.
+    fn viewport_buffer_lines(&self) -> Vec<Option<usize>> {
+        if self.in_alt_screen() {
+            return (0..self.term.grid().screen_lines()).map(Some).collect();
+        }
+        match self.folded_view() {
+            Some(view) => view
+                .into_iter()
+                .map(|row| match row {
+                    ViewRow::Line(line) => Some(line),
+                    ViewRow::Fold { .. } => None,
+                })
+                .collect(),
+            None => self.viewport_lines().into_iter().map(Some).collect(),
+        }
+    }
+
+    /// For each line up to the cursor, the number of hard line breaks between
+    /// it and the cursor's line. Soft-wrapped rows share a count, so counts
+    /// survive reflow.
+    fn line_breaks_to_cursor(&self) -> Vec<usize> {
+        let grid = self.term.grid();
+        let history = grid.history_size() as i32;
+        let last_column = Column(grid.columns().saturating_sub(1));
+        let cursor = self.cursor_line();
+        let mut breaks = vec![0; cursor + 1];
+        for line in (0..cursor).rev() {
+            let wrapped = grid[Line(line as i32 - history)][last_column]
+                .flags
+                .contains(Flags::WRAPLINE);
+            breaks[line] = breaks[line + 1] + usize::from(!wrapped);
+        }
+        breaks
+    }
+
+    /// Buffer line shown on the bottom row.
+    fn bottom_line(&self) -> usize {
+        let grid = self.term.grid();
+        grid.total_lines() - 1 - grid.display_offset()
+    }
+
+    /// Collapsed output ranges that apply to the current screen.
+    fn folds(&self) -> Vec<(std::ops::Range<usize>, usize)> {
+        if self.in_alt_screen() {
+            Vec::new()
+        } else {
+            self.blocks.folds()
+        }
+    }
+
+    /// Rows of the viewport when some block is collapsed; `None` when the
+    /// viewport maps straight onto the grid.
+    fn folded_view(&self) -> Option<Vec<ViewRow>> {
+        let folds = self.folds();
+        if folds.is_empty() {
+            return None;
+        }
+        let rows = self.term.grid().screen_lines();
+        let mut view = Vec::with_capacity(rows);
+        let mut line = Some(self.bottom_line());
+        while let Some(current) = line.filter(|_| view.len() < rows) {
+            match fold_at(&folds, current) {
+                Some(range) => {
+                    view.push(ViewRow::Fold {
+                        line: range.start,
+                        hidden: range.len(),
+                    });
+                    line = range.start.checked_sub(1);
+                }
+                None => {
+                    view.push(ViewRow::Line(current));
+                    line = current.checked_sub(1);
+                }
+            }
+        }
+        view.reverse();
+        Some(view)
+    }
+
+    /// Text of one buffer line, trailing blanks trimmed.
+    fn line_text(&self, line: Line) -> String {
+        let grid = self.term.grid();
+        let row = &grid[line];
+        let mut s = String::new();
+        for c in 0..grid.columns() {
+            let cell = &row[Column(c)];
+            if !is_spacer(cell.flags) {
+                let text = cell_text(cell);
+                if text.is_empty() {
+                    s.push(' ');
+                } else {
+                    s.push_str(&text);
+                }
+            }
+        }
+        s.trim_end().to_string()
+    }
+
+    /// Grid line of a buffer line.
+    fn grid_line(&self, line: usize) -> Line {
+        Line(line as i32 - self.term.grid().history_size() as i32)
+    }
+
+    /// Placeholder text for a collapsed block's output.
+    fn fold_label(&self, hidden: usize) -> String {
+        let label = format!(
+            "[... {} line{} hidden ...]",
+            hidden,
+            if hidden == 1 { "" } else { "s" }
+        );
+        label.chars().take(self.term.grid().columns()).collect()
+    }
+
+    /// Move the bottom row so the view scrolls by `delta` rows, treating each
+    /// collapsed block as a single row.
+    fn scroll_folded(&mut self, delta: i32, folds: &[(std::ops::Range<usize>, usize)]) {
+        let last = self.term.grid().total_lines() - 1;
+        let mut bottom = fold_start(folds, self.bottom_line());
+        for _ in 0..delta.unsigned_abs() {
+            bottom = if delta > 0 {
+                match bottom.checked_sub(1) {
+                    Some(line) => fold_start(folds, line),
+                    None => break,
+                }
+            } else {
+                fold_end(folds, bottom).min(last)
+            };
+        }
+        self.set_bottom_line(bottom);
+    }
+
+    fn set_bottom_line(&mut self, bottom: usize) {
+        let last = self.term.grid().total_lines() - 1;
+        let target = last.saturating_sub(bottom) as i32;
+        let current = self.term.grid().display_offset() as i32;
+        if target != current {
+            self.term.scroll_display(Scroll::Delta(target - current));
         }
     }
 }
 
+/// The collapsed range `line` falls in.
+fn fold_at(
+    folds: &[(std::ops::Range<usize>, usize)],
+    line: usize,
+) -> Option<std::ops::Range<usize>> {
+    folds
+        .iter()
+        .map(|(range, _)| range)
+        .find(|range| range.contains(&line))
+        .cloned()
+}
+
+/// `line`, or the first line of the collapsed range it falls in.
+fn fold_start(folds: &[(std::ops::Range<usize>, usize)], line: usize) -> usize {
+    fold_at(folds, line).map_or(line, |range| range.start)
+}
+
+/// The line after `line`, skipping a collapsed range `line` falls in.
+fn fold_end(folds: &[(std::ops::Range<usize>, usize)], line: usize) -> usize {
+    fold_at(folds, line).map_or(line + 1, |range| range.end)
+}
+
+/// Where a line moved during reflow, given [`AlacrittyEngine::line_breaks_to_cursor`]
+/// before and after. Lines below the cursor keep their distance to it.
+fn reflowed_line(before: &[usize], after: &[usize], line: usize) -> Option<usize> {
+    let cursor_before = before.len() - 1;
+    let cursor_after = after.len() - 1;
+    if line >= cursor_before {
+        return Some(cursor_after + (line - cursor_before));
+    }
+    let breaks = before[line];
+    let moved = after.partition_point(|&b| b > breaks);
+    (after.get(moved) == Some(&breaks)).then_some(moved)
+}
+
 /// Map an `alacritty_terminal` cell color to a neutral [`CellColor`].
 fn map_color(color: Color) -> CellColor {
     match color {
@@ -280,6 +741,24 @@ fn cell_text(cell: &Cell) -> String {
     s
 }
 
+/// Convert a grid cell into a [`RenderCell`] at a viewport position.
+fn render_cell(cell: &Cell, row: usize, col: usize) -> RenderCell {
+    RenderCell {
+        row,
+        col,
+        text: cell_text(cell),
+        fg: map_color(cell.fg),
+        bg: map_color(cell.bg),
+        flags: map_flags(cell.flags),
+        width: if cell.flags.contains(Flags::WIDE_CHAR) {
+            2
+        } else {
+            1
+        },
+        hyperlink: cell.hyperlink().map(CellHyperlink),
+    }
+}
+
 /// True for spacer cells that must not be emitted (the wide glyph spans them).
 fn is_spacer(flags: Flags) -> bool {
     flags.contains(Flags::WIDE_CHAR_SPACER) || flags.contains(Flags::LEADING_WIDE_CHAR_SPACER)
@@ -287,7 +766,34 @@ fn is_spacer(flags: Flags) -> bool {
 
 impl TerminalEngine for AlacrittyEngine {
     fn feed(&mut self, bytes: &[u8]) {
-        self.processor.advance(&mut self.term, bytes);
+        // Feed up to the end of each shell integration mark or image before
+        // handling it, so the grid reflects the cursor position it refers to.
+        let marks = self.osc.scan(bytes).into_iter().filter_map(|seq| {
+            let event = crate::shell_integration::event_for_osc(&seq.payload)?;
+            Some((seq.end, Embedded::Mark(event)))
+        });
+        let mut embedded: Vec<_> = marks.collect();
+        embedded.extend(
+            self.graphics
+                .scan(bytes)
+                .into_iter()
+                .map(|seq| (seq.end, Embedded::Graphics(seq))),
+        );
+        embedded.sort_by_key(|(end, _)| *end);
+
+        let mut fed = 0;
+        for (end, item) in embedded {
+            self.advance(&bytes[fed..end]);
+            fed = end;
+            match item {
+                Embedded::Mark(event) => {
+                    self.record_mark(&event);
+                    let _ = self.events.send(event);
+                }
+                Embedded::Graphics(seq) => self.apply_graphics(seq),
+            }
+        }
+        self.advance(&bytes[fed..]);
     }
 
     fn resize(&mut self, size: TerminalSize) {
@@ -295,8 +801,30 @@ impl TerminalEngine for AlacrittyEngine {
             columns: size.cols.max(1),
             screen_lines: size.rows.max(1),
         };
+        let reflow = dims.columns != self.term.grid().columns()
+            && !(self.blocks.is_empty() && self.images.is_empty());
+        let in_alt_screen = self.in_alt_screen();
+        let before = (reflow && !in_alt_screen).then(|| self.line_breaks_to_cursor());
         self.term.resize(dims);
         self.size = size;
+
+        if in_alt_screen {
+            // The primary screen was resized out of sight; its lines can no
+            // longer be followed.
+            self.blocks.clear();
+            self.images.clear();
+            return;
+        }
+        if let Some(before) = before {
+            let after = self.line_breaks_to_cursor();
+            self.blocks
+                .remap(|line| reflowed_line(&before, &after, line));
+            self.images
+                .remap(|line| reflowed_line(&before, &after, line));
+        }
+        // Rows moving between screen and history are not evictions
+        self.history = self.term.grid().history_size();
+        self.sync_history();
     }
 
     fn size(&self) -> TerminalSize {
@@ -304,6 +832,43 @@ impl TerminalEngine for AlacrittyEngine {
     }
 
     fn render_cells(&self) -> Vec<RenderCell> {
+        if let Some(view) = self.folded_view() {
+            let grid = self.term.grid();
+            let mut cells = Vec::new();
+            for (row, view_row) in view.into_iter().enumerate() {
+                match view_row {
+                    ViewRow::Line(line) => {
+                        let grid_row = &grid[self.grid_line(line)];
+                        for col in 0..grid.columns() {
+                            let cell = &grid_row[Column(col)];
+                            if !is_spacer(cell.flags) {
+                                cells.push(render_cell(cell, row, col));
+                            }
+                        }
+                    }
+                    ViewRow::Fold { hidden, .. } => {
+                        for (col, c) in self.fold_label(hidden).chars().enumerate() {
+                            cells.push(RenderCell {
+                                row,
+                                col,
+                                text: c.to_string(),
+                                fg: CellColor::DefaultFg,
+                                bg: CellColor::DefaultBg,
+                                flags: RenderFlags {
+                                    dim: true,
+                                    italic: true,
+                                    ..RenderFlags::default()
+                                },
+                                width: 1,
+                                hyperlink: None,
+                            });
+                        }
+                    }
+                }
+            }
+            return cells;
+        }
+
         let content = self.term.renderable_content();
         let offset = content.display_offset as i32;
         let mut cells = Vec::new();
@@ -317,24 +882,24 @@ impl TerminalEngine for AlacrittyEngine {
             if row < 0 {
                 continue;
             }
-            cells.push(RenderCell {
-                row: row as usize,
-                col: indexed.point.column.0,
-                text: cell_text(cell),
-                fg: map_color(cell.fg),
-                bg: map_color(cell.bg),
-                flags: map_flags(flags),
-                width: if flags.contains(Flags::WIDE_CHAR) {
-                    2
-                } else {
-                    1
-                },
-            });
+            cells.push(render_cell(cell, row as usize, indexed.point.column.0));
         }
         cells
     }
 
     fn rows_text(&self) -> Vec<String> {
+        if let Some(view) = self.folded_view() {
+            let mut text: Vec<String> = view
+                .into_iter()
+                .map(|row| match row {
+                    ViewRow::Line(line) => self.line_text(self.grid_line(line)),
+                    ViewRow::Fold { hidden, .. } => self.fold_label(hidden),
+                })
+                .collect();
+            text.resize(self.size.rows, String::new());
+            return text;
+        }
+
         let rows = self.size.rows;
         let cols = self.size.cols;
         let mut grid = vec![vec![String::from(" "); cols]; rows];
@@ -362,27 +927,11 @@ impl TerminalEngine for AlacrittyEngine {
 
     fn all_text(&self) -> Vec<String> {
         let grid = self.term.grid();
-        let cols = grid.columns();
         let top = grid.topmost_line().0;
         let bottom = grid.bottommost_line().0;
-        let mut out = Vec::with_capacity((bottom - top + 1).max(0) as usize);
-        for line in top..=bottom {
-            let row = &grid[Line(line)];
-            let mut s = String::new();
-            for c in 0..cols {
-                let cell = &row[Column(c)];
-                if !is_spacer(cell.flags) {
-                    let text = cell_text(cell);
-                    if text.is_empty() {
-                        s.push(' ');
-                    } else {
-                        s.push_str(&text);
-                    }
-                }
-            }
-            out.push(s.trim_end().to_string());
-        }
-        out
+        (top..=bottom)
+            .map(|line| self.line_text(Line(line)))
+            .collect()
     }
 
     fn cursor(&self) -> EngineCursor {
@@ -390,7 +939,14 @@ impl TerminalEngine for AlacrittyEngine {
         let offset = content.display_offset as i32;
         let cursor = content.cursor;
         let shape = map_shape(cursor.shape);
-        let row = cursor.point.line.0 + offset;
+        let mut row = cursor.point.line.0 + offset;
+        if let Some(view) = self.folded_view() {
+            let line = self.cursor_line();
+            row = view
+                .iter()
+                .position(|r| *r == ViewRow::Line(line))
+                .map_or(-1, |r| r as i32);
+        }
         let in_view = row >= 0 && (row as usize) < self.size.rows;
         EngineCursor {
             row: row.max(0) as usize,
@@ -412,7 +968,12 @@ impl TerminalEngine for AlacrittyEngine {
     }
 
     fn scroll_lines(&mut self, delta: i32) {
-        self.term.scroll_display(Scroll::Delta(delta));
+        let folds = self.folds();
+        if folds.is_empty() {
+            self.term.scroll_display(Scroll::Delta(delta));
+        } else {
+            self.scroll_folded(delta, &folds);
+        }
     }
 
     fn scroll_to_bottom(&mut self) {
@@ -427,7 +988,112 @@ impl TerminalEngine for AlacrittyEngine {
     }
 
     fn set_max_scrollback(&mut self, lines: usize) {
-        self.term.grid_mut().update_history(lines);
+        self.max_scrollback = lines;
+        self.term.grid_mut().update_history(lines + HISTORY_SLACK);
+        self.sync_history();
+    }
+
+    fn command_blocks(&self) -> &CommandBlocks {
+        &self.blocks
+    }
+
+    fn toggle_block_collapsed(&mut self, index: usize) -> bool {
+        self.blocks.toggle_collapsed(index)
+    }
+
+    fn viewport_lines(&self) -> Vec<usize> {
+        if self.in_alt_screen() {
+            return Vec::new();
+        }
+        if let Some(view) = self.folded_view() {
+            return view.into_iter().map(ViewRow::line).collect();
+        }
+        let grid = self.term.grid();
+        let top =
+            grid.total_lines() as i64 - grid.screen_lines() as i64 - grid.display_offset() as i64;
+        (0..grid.screen_lines() as i64)
+            .map(|row| top + row)
+            .filter(|&line| line >= 0)
+            .map(|line| line as usize)
+            .collect()
+    }
+
+    fn scroll_to_line(&mut self, line: usize) {
+        let folds = self.folds();
+        let last = self.term.grid().total_lines() - 1;
+        let mut bottom = fold_start(&folds, line.min(last));
+        for _ in 1..self.term.grid().screen_lines() {
+            if bottom >= last {
+                break;
+            }
+            bottom = fold_end(&folds, bottom).min(last);
+        }
+        self.set_bottom_line(bottom);
+    }
+
+    fn keyboard_flags(&self) -> KeyboardFlags {
+        let mode = self.term.mode();
+        KeyboardFlags {
+            disambiguate: mode.contains(TermMode::DISAMBIGUATE_ESC_CODES),
+            report_event_types: mode.contains(TermMode::REPORT_EVENT_TYPES),
+            report_alternate_keys: mode.contains(TermMode::REPORT_ALTERNATE_KEYS),
+            report_all_keys: mode.contains(TermMode::REPORT_ALL_KEYS_AS_ESC),
+            report_associated_text: mode.contains(TermMode::REPORT_ASSOCIATED_TEXT),
+        }
+    }
+
+    fn bracketed_paste(&self) -> bool {
+        self.term.mode().contains(TermMode::BRACKETED_PASTE)
+    }
+
+    fn visible_images(&self) -> Vec<VisibleImage<'_>> {
+        let store = if self.in_alt_screen() {
+            &self.alt_images
+        } else {
+            &self.images
+        };
+        let rows = self.viewport_buffer_lines();
+        store
+            .visible_placements()
+            .filter_map(|placement| {
+                let lines = placement.row..placement.row + placement.height_cells;
+                // The first row showing part of the image fixes its position
+                let (row, line) = rows.iter().enumerate().find_map(|(row, line)| {
+                    line.filter(|line| lines.contains(line))
+                        .map(|line| (row, line))
+                })?;
+                Some(VisibleImage {
+                    row: row as i64 - (line - placement.row) as i64,
+                    col: placement.col,
+                    width_cells: placement.width_cells,
+                    height_cells: placement.height_cells,
+                    image: store.get_image(placement.image_id)?,
+                })
+            })
+            .collect()
+    }
+
+    fn set_cell_pixel_size(&mut self, width: u32, height: u32) {
+        self.cell_pixels = (width.max(1), height.max(1));
+    }
+
+    fn lines_evicted(&self) -> usize {
+        self.evicted
+    }
+
+    fn lines_text(&self, lines: std::ops::Range<usize>) -> Vec<String> {
+        let total = self.term.grid().total_lines();
+        (lines.start.min(total)..lines.end.min(total))
+            .map(|line| self.line_text(self.grid_line(line)))
+            .collect()
+    }
+
+    fn cursor_buffer_line(&self) -> usize {
+        self.cursor_line()
+    }
+
+    fn alternate_screen(&self) -> bool {
+        self.in_alt_screen()
     }
 }
 
@@ -609,6 +1275,304 @@ mod tests {
         }
     }
 
+    #[test]
+    fn shell_integration_marks_are_forwarded() {
+        let (mut e, rx) = engine_with_events(24, 80);
+        e.feed(b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C;cmdline_url=ls\x07");
+        e.feed(b"file\r\n\x1b]133;D;");
+        e.feed(b"2\x1b\\");
+
+        let events: Vec<_> = rx.try_iter().collect();
+        assert!(matches!(events[0], TerminalEvent::PromptStart));
+        assert!(matches!(events[1], TerminalEvent::CommandStart));
+        match &events[2] {
+            TerminalEvent::OutputStart { command } => assert_eq!(command.as_deref(), Some("ls")),
+            other => panic!("expected OutputStart event, got {other:?}"),
+        }
+        assert!(matches!(
+            events[3],
+            TerminalEvent::CommandFinished { exit_code: Some(2) }
+        ));
+        assert_eq!(events.len(), 4);
+        // The marks themselves draw nothing
+        assert_eq!(e.rows_text()[0].trim_end(), "$ ls");
+        assert_eq!(e.rows_text()[1].trim_end(), "file");
+    }
+
+    #[test]
+    fn osc8_hyperlinks_are_kept_per_cell() {
+        let mut e = engine(4, 40);
+        e.feed(b"see \x1b]8;;https://example.com/docs\x1b\\the docs\x1b]8;;\x1b\\ now\r\n");
+        e.feed(b"\x1b]8;id=x;javascript:alert(1)\x07a\x1b]8;;\x07 \x1b]8;id=x;javascript:alert(1)\x07b\x1b]8;;\x07");
+
+        let cells = e.render_cells();
+        let link_at = |row: usize, col: usize| {
+            cells
+                .iter()
+                .find(|c| c.row == row && c.col == col)
+                .and_then(|c| c.hyperlink.clone())
+        };
+        assert!(link_at(0, 3).is_none());
+        assert_eq!(
+            link_at(0, 4).map(|l| l.uri().to_string()).as_deref(),
+            Some("https://example.com/docs")
+        );
+        assert!(link_at(0, 12).is_none());
+
+        let spans = hyperlink_spans(&cells);
+        assert_eq!(spans.len(), 3);
+        assert_eq!(
+            (spans[0].row, spans[0].col_start, spans[0].col_end),
+            (0, 4, 12)
+        );
+        assert!(spans[0].link.is_openable());
+        // One id, two runs: the same link, which is not safe to open
+        assert_eq!(spans[1].link, spans[2].link);
+        assert_eq!((spans[2].col_start, spans[2].col_end), (2, 3));
+        assert!(!spans[1].link.is_openable());
+    }
+
+    #[test]
+    fn osc52_reads_are_forwarded_with_a_reply() {
+        let (mut e, rx) = engine_with_events(24, 80);
+        e.feed(b"\x1b]52;c;?\x07");
+
+        match rx.try_iter().next() {
+            Some(TerminalEvent::Clipboard(ClipboardAction::Paste { primary, reply })) => {
+                assert!(!primary);
+                assert_eq!(reply.format("corgi"), b"\x1b]52;c;Y29yZ2k=\x07");
+            }
+            other => panic!("expected clipboard read, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn kitty_keyboard_flags_are_negotiated() {
+        let (mut e, rx) = engine_with_events(24, 80);
+        assert!(e.keyboard_flags().is_legacy());
+
+        // Push disambiguate, then push disambiguate + all keys on top of it
+        e.feed(b"\x1b[>1u\x1b[>9u");
+        assert_eq!(e.keyboard_flags().bits(), 0b1001);
+        e.feed(b"\x1b[?u");
+        let replies: Vec<_> = rx
+            .try_iter()
+            .filter_map(|ev| match ev {
+                TerminalEvent::PtyWrite(bytes) => Some(bytes),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(replies, vec![b"\x1b[?9u".to_vec()]);
+
+        e.feed(b"\x1b[<u");
+        assert_eq!(e.keyboard_flags().bits(), 0b1);
+        // Set: OR in event types
+        e.feed(b"\x1b[=2;2u");
+        assert_eq!(e.keyboard_flags().bits(), 0b11);
+        e.feed(b"\x1b[<u");
+        assert!(e.keyboard_flags().is_legacy());
+    }
+
+    #[test]
+    fn bracketed_paste_mode_is_tracked() {
+        let mut e = engine(24, 80);
+        assert!(!e.bracketed_paste());
+        e.feed(b"\x1b[?2004h");
+        assert!(e.bracketed_paste());
+        e.feed(b"\x1b[?2004l");
+        assert!(!e.bracketed_paste());
+    }
+
+    fn kitty_image(width: usize, height: usize) -> Vec<u8> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        let pixels = STANDARD.encode(vec![255; width * height * 4]);
+        format!("\x1b_Ga=T,f=32,i=1,s={width},v={height};{pixels}\x1b\\").into_bytes()
+    }
+
+    #[test]
+    fn kitty_images_are_placed_at_the_cursor_and_scroll_with_text() {
+        let (mut e, rx) = engine_with_events(6, 40);
+        e.set_cell_pixel_size(10, 20);
+        e.feed(b"ab\r\n  ");
+        e.feed(&kitty_image(30, 50));
+
+        let images = e.visible_images();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].row, images[0].col), (1, 2));
+        assert_eq!((images[0].width_cells, images[0].height_cells), (3, 3));
+        assert_eq!(images[0].image.width, 30);
+        // After the image, on its last row
+        let cursor = e.cursor();
+        assert_eq!((cursor.row, cursor.col), (3, 5));
+        assert!(rx.try_iter().any(
+            |ev| matches!(ev, TerminalEvent::PtyWrite(reply) if reply == b"\x1b_Gi=1;OK\x1b\\")
+        ));
+
+        // Two lines later the image's top row has scrolled into history
+        e.feed(b"\r\n\r\n\r\n\r\n");
+        let images = e.visible_images();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].row, -1);
+
+        e.feed(b"\r\n\r\n\r\n");
+        assert!(e.visible_images().is_empty());
+        e.scroll_lines(4);
+        assert_eq!(e.visible_images()[0].row, 0);
+    }
+
+    #[test]
+    fn sixel_images_are_placed_at_the_cursor() {
+        let mut e = engine(10, 40);
+        e.set_cell_pixel_size(2, 6);
+        e.feed(b"\x1b[3;5H");
+        e.feed(b"\x1bP0;1;0q#1;2;100;0;0#1~~~~-~~~~\x1b\\$ ");
+
+        let images = e.visible_images();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].row, images[0].col), (2, 4));
+        assert_eq!((images[0].width_cells, images[0].height_cells), (2, 2));
+        // Text continues on the line below the image
+        assert_eq!(e.rows_text()[4].trim_end(), "$");
+    }
+
+    #[test]
+    fn alternate_screen_images_are_dropped_on_exit() {
+        let mut e = engine(6, 40);
+        e.feed(b"\x1b[?1049h");
+        e.feed(&kitty_image(10, 20));
+        assert_eq!(e.visible_images().len(), 1);
+        e.feed(b"\x1b[?1049l");
+        assert!(e.visible_images().is_empty());
+    }
+
+    #[test]
+    fn cwd_reports_are_forwarded() {
+        let (mut e, rx) = engine_with_events(24, 80);
+        e.feed(b"\x1b]7;file://remote-box/srv/my%20app\x1b\\$ ");
+
+        match rx.try_iter().next() {
+            Some(TerminalEvent::CwdChanged { host, path }) => {
+                assert_eq!(host.as_deref(), Some("remote-box"));
+                assert_eq!(path, std::path::Path::new("/srv/my app"));
+            }
+            other => panic!("expected CwdChanged event, got {other:?}"),
+        }
+        assert_eq!(e.rows_text()[0].trim_end(), "$");
+    }
+
+    /// Print a prompt, run `command` with `lines` lines of output, exit `code`.
+    fn run_command(e: &mut AlacrittyEngine, command: &str, lines: usize, code: i32) {
+        e.feed(format!("\x1b]133;A\x07$ \x1b]133;B\x07{command}\r\n").as_bytes());
+        e.feed(format!("\x1b]133;C;cmdline_url={command}\x07").as_bytes());
+        for i in 0..lines {
+            e.feed(format!("{command} {i}\r\n").as_bytes());
+        }
+        e.feed(format!("\x1b]133;D;{code}\x07").as_bytes());
+    }
+
+    #[test]
+    fn shell_marks_build_command_blocks() {
+        let mut e = engine(24, 80);
+        run_command(&mut e, "one", 3, 0);
+        run_command(&mut e, "two", 1, 1);
+        e.feed(b"\x1b]133;A\x07$ ");
+
+        let blocks = e.command_blocks().blocks();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].prompt_line, 0);
+        assert_eq!(blocks[0].output_range(), Some(1..4));
+        assert_eq!(blocks[0].command.as_deref(), Some("one"));
+        assert_eq!(blocks[1].prompt_line, 4);
+        assert_eq!(blocks[1].output_range(), Some(5..6));
+        assert_eq!(blocks[1].exit_code, Some(1));
+        assert_eq!(blocks[2].prompt_line, 6);
+
+        let text = e.all_text();
+        let output = blocks[0].output_range().unwrap();
+        assert_eq!(text[output].join("\n"), "one 0\none 1\none 2");
+    }
+
+    #[test]
+    fn collapsed_blocks_fold_the_view() {
+        let mut e = engine(10, 40);
+        run_command(&mut e, "big", 30, 0);
+        run_command(&mut e, "small", 1, 0);
+        e.feed(b"\x1b]133;A\x07$ ");
+
+        assert!(e.toggle_block_collapsed(0));
+        let rows = e.rows_text();
+        assert_eq!(rows.len(), 10);
+        assert_eq!(rows[0], "$ big");
+        assert_eq!(rows[1], "[... 30 lines hidden ...]");
+        assert_eq!(rows[2], "$ small");
+        assert_eq!(rows[3], "small 0");
+        assert_eq!(rows[4], "$");
+        assert_eq!(e.cursor().row, 4);
+        assert_eq!(e.viewport_lines()[..3], [0, 1, 31]);
+
+        // Scrolling up from the bottom cannot go past the top of the fold
+        e.scroll_lines(3);
+        assert_eq!(e.rows_text()[0], "$ big");
+
+        assert!(e.toggle_block_collapsed(0));
+        assert_eq!(e.rows_text()[1], "big 0");
+    }
+
+    #[test]
+    fn scroll_to_line_puts_line_on_top() {
+        let mut e = engine(5, 40);
+        run_command(&mut e, "a", 10, 0);
+        run_command(&mut e, "b", 10, 0);
+
+        let prompt = e.command_blocks().blocks()[1].prompt_line;
+        e.scroll_to_line(prompt);
+        assert_eq!(e.rows_text()[0], "$ b");
+        assert_eq!(e.viewport_lines()[0], prompt);
+        assert_eq!(
+            e.command_blocks().previous_prompt(prompt),
+            Some(e.command_blocks().blocks()[0].prompt_line)
+        );
+    }
+
+    #[test]
+    fn evicted_lines_shift_command_blocks() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut e = AlacrittyEngine::new(TerminalSize { rows: 5, cols: 40 }, tx, 20);
+        run_command(&mut e, "old", 30, 0);
+        run_command(&mut e, "mid", 20, 0);
+        run_command(&mut e, "new", 2, 3);
+
+        // All of "old" scrolled out of the 20-line history
+        let blocks = e.command_blocks().blocks();
+        assert_eq!(blocks.len(), 2);
+        let text = e.all_text();
+        assert_eq!(blocks[0].prompt_line, 0);
+        assert_eq!(text[blocks[0].prompt_line], "$ mid");
+        assert_eq!(text[blocks[1].prompt_line], "$ new");
+        assert_eq!(text[blocks[1].output_range().unwrap()], ["new 0", "new 1"]);
+        assert_eq!(
+            e.lines_text(blocks[1].output_range().unwrap()),
+            ["new 0", "new 1"]
+        );
+        // The prompt and 30 lines of "old"
+        assert_eq!(e.lines_evicted(), 31);
+    }
+
+    #[test]
+    fn reflow_keeps_command_blocks_on_their_lines() {
+        let mut e = engine(10, 40);
+        run_command(&mut e, &"x".repeat(30), 2, 0);
+        run_command(&mut e, "after", 1, 0);
+
+        e.resize(TerminalSize { rows: 10, cols: 20 });
+        let text = e.all_text();
+        let blocks = e.command_blocks().blocks();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(text[blocks[1].prompt_line], "$ after");
+        assert_eq!(text[blocks[1].output_range().unwrap()], ["after 0"]);
+        assert!(text[blocks[0].prompt_line].starts_with("$ xxx"));
+    }
+
     #[test]
     fn device_status_report_is_forwarded_to_pty() {
         let (mut e, rx) = engine_with_events(24, 80);
@@ -39,4 +39,12 @@ pub enum CoreError {
     /// Configuration error
     #[error("Configuration error: {0}")]
     Config(String),
+
+    /// IPC control socket error
+    #[error("IPC error: {0}")]
+    Ipc(String),
+
+    /// History database error
+    #[error("History database error: {0}")]
+    Database(#[from] rusqlite::Error),
 }
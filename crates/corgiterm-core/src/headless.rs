@@ -0,0 +1,217 @@
+//! Headless command execution
+//!
+//! Runs a single command inside a real PTY without any UI, feeding its output
+//! through [`AlacrittyEngine`] exactly as the GTK terminal would. Backs
+//! `corgiterm --execute`, which makes CorgiTerm usable as a terminal test
+//! harness in CI: the exit code is the child's, the raw byte stream or the
+//! final rendered screen goes to the caller's writer, and Safe Mode can veto
+//! dangerous commands before anything is spawned.
+//!
+//! ```text
+//! $ corgiterm -e "printf 'hello\n'; exit 3" --dump-screen --cols 40
+//! hello
+//! $ echo $?
+//! 3
+//! ```
+
+use crate::engine::{AlacrittyEngine, TerminalEngine};
+use crate::safe_mode::{CommandPreview, RiskLevel, SafeMode};
+use crate::safe_mode_rules::RuleSet;
+use crate::terminal::{TerminalEvent, TerminalSize};
+use crate::{Pty, PtySize, Result};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Exit code reported when the command outlived `--timeout` (matches `timeout(1)`).
+pub const EXIT_TIMED_OUT: i32 = 124;
+
+/// Exit code reported when Safe Mode refused to run the command (POSIX
+/// "command found but not executable").
+pub const EXIT_BLOCKED: i32 = 126;
+
+/// Scrollback kept by the headless engine. Only the visible screen is dumped,
+/// so this just needs to be large enough for the reflow on exit.
+const HEADLESS_SCROLLBACK: usize = 1000;
+
+/// What a headless run writes to its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeadlessOutput {
+    /// Pass the PTY byte stream through verbatim as it arrives
+    #[default]
+    Stream,
+    /// Print the final rendered screen once the command exits
+    Screen,
+}
+
+/// Options for a headless run
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// Command line, interpreted by the shell
+    pub command: String,
+    /// Shell used to run the command (defaults to $SHELL)
+    pub shell: Option<String>,
+    /// Terminal size in cells
+    pub size: TerminalSize,
+    /// Starting directory
+    pub working_dir: Option<PathBuf>,
+    /// Kill the command after this long
+    pub timeout: Option<Duration>,
+    /// What to write to the output
+    pub output: HeadlessOutput,
+    /// Refuse to run commands Safe Mode classifies as Danger
+    pub safe_mode: bool,
+    /// Safe Mode rules (defaults to the built-in rules)
+    pub safe_mode_rules: Option<RuleSet>,
+    /// TERM environment variable
+    pub term: Option<String>,
+}
+
+impl HeadlessOptions {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            shell: None,
+            size: TerminalSize::default(),
+            working_dir: None,
+            timeout: None,
+            output: HeadlessOutput::default(),
+            safe_mode: false,
+            safe_mode_rules: None,
+            term: None,
+        }
+    }
+}
+
+/// Outcome of a headless run
+#[derive(Debug, Clone)]
+pub struct HeadlessResult {
+    /// Process exit code, or [`EXIT_TIMED_OUT`] / [`EXIT_BLOCKED`]
+    pub exit_code: i32,
+    /// Visible screen rows when the command finished, trailing blank rows removed
+    pub screen: Vec<String>,
+    /// The command was killed because it exceeded the timeout
+    pub timed_out: bool,
+    /// Safe Mode preview when the command was refused
+    pub blocked: Option<CommandPreview>,
+}
+
+/// Run a command in a PTY, writing output to `out` as selected by `options.output`.
+pub fn run(options: &HeadlessOptions, out: &mut dyn Write) -> Result<HeadlessResult> {
+    if options.safe_mode {
+        let cwd = options
+            .working_dir
+            .clone()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let mut safe_mode = match &options.safe_mode_rules {
+            Some(rules) => SafeMode::with_rules(rules.clone()),
+            None => SafeMode::new(),
+        };
+        safe_mode.set_enabled(true);
+        let preview = safe_mode.analyze(&options.command, &cwd);
+        if preview.risk == RiskLevel::Danger {
+            tracing::warn!("Safe Mode blocked headless command: {}", options.command);
+            return Ok(HeadlessResult {
+                exit_code: EXIT_BLOCKED,
+                screen: Vec::new(),
+                timed_out: false,
+                blocked: Some(preview),
+            });
+        }
+    }
+
+    let pty_size = PtySize {
+        rows: options.size.rows.clamp(1, u16::MAX as usize) as u16,
+        cols: options.size.cols.clamp(1, u16::MAX as usize) as u16,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    let pty = Pty::spawn_command(
+        &options.command,
+        options.shell.as_deref(),
+        pty_size,
+        options.working_dir.as_deref(),
+        options.term.as_deref(),
+    )?;
+
+    let (event_tx, event_rx) = crossbeam_channel::unbounded();
+    let mut engine = AlacrittyEngine::new(options.size, event_tx, HEADLESS_SCROLLBACK);
+
+    // Blocking reads happen on a helper thread; the channel disconnects at EOF
+    // (all slave handles closed, i.e. the command and its children are gone).
+    let reader = pty.reader_clone();
+    let (chunk_tx, chunk_rx) = crossbeam_channel::unbounded::<Vec<u8>>();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = match reader.lock() {
+                Ok(mut guard) => guard.read(&mut buf),
+                Err(_) => break,
+            };
+            match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if chunk_tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let deadline = options.timeout.map(|t| Instant::now() + t);
+    let mut timed_out = false;
+    loop {
+        match chunk_rx.recv_timeout(Duration::from_millis(20)) {
+            Ok(chunk) => {
+                engine.feed(&chunk);
+                if options.output == HeadlessOutput::Stream {
+                    out.write_all(&chunk)?;
+                }
+            }
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => {}
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Answer terminal queries (DSR, DA) so interactive programs don't stall.
+        for event in event_rx.try_iter() {
+            if let TerminalEvent::PtyWrite(bytes) = event {
+                let _ = pty.write(&bytes);
+            }
+        }
+
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            tracing::warn!("Headless command timed out: {}", options.command);
+            timed_out = true;
+            let _ = pty.kill();
+            break;
+        }
+    }
+    out.flush()?;
+
+    let exit_code = if timed_out {
+        EXIT_TIMED_OUT
+    } else {
+        pty.wait()?.exit_code() as i32
+    };
+
+    let mut screen = engine.rows_text();
+    while screen.last().is_some_and(|row| row.is_empty()) {
+        screen.pop();
+    }
+
+    if options.output == HeadlessOutput::Screen {
+        for row in &screen {
+            writeln!(out, "{}", row)?;
+        }
+        out.flush()?;
+    }
+
+    Ok(HeadlessResult {
+        exit_code,
+        screen,
+        timed_out,
+        blocked: None,
+    })
+}
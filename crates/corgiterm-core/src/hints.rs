@@ -6,6 +6,8 @@
 use regex::Regex;
 use std::sync::LazyLock;
 
+use crate::engine::HyperlinkSpan;
+
 /// Types of hints that can be detected
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HintType {
@@ -21,6 +23,8 @@ pub enum HintType {
     GitHash,
     /// Port number (e.g., :8080)
     Port,
+    /// Explicit OSC 8 hyperlink; the hint text is the link target
+    Hyperlink,
 }
 
 impl HintType {
@@ -33,6 +37,7 @@ impl HintType {
             HintType::Email => "Copy to clipboard",
             HintType::GitHash => "Copy to clipboard",
             HintType::Port => "Copy to clipboard",
+            HintType::Hyperlink => "Open link",
         }
     }
 
@@ -45,6 +50,7 @@ impl HintType {
             HintType::Email => "",
             HintType::GitHash => "",
             HintType::Port => "",
+            HintType::Hyperlink => "",
         }
     }
 }
@@ -85,6 +91,8 @@ pub struct HintDetector {
     pub detect_emails: bool,
     /// Enable git hash detection
     pub detect_git_hashes: bool,
+    /// Include explicit OSC 8 hyperlinks
+    pub detect_hyperlinks: bool,
 }
 
 impl Default for HintDetector {
@@ -95,6 +103,7 @@ impl Default for HintDetector {
             detect_ips: true,
             detect_emails: true,
             detect_git_hashes: true,
+            detect_hyperlinks: true,
         }
     }
 }
@@ -235,15 +244,45 @@ impl HintDetector {
 
     /// Scan multiple lines and return labeled hints
     pub fn scan(&self, lines: &[String]) -> Vec<Hint> {
+        self.scan_with_hyperlinks(lines, &[])
+    }
+
+    /// Scan multiple lines plus the OSC 8 hyperlinks shown on them
+    ///
+    /// Hyperlinks win over text matches they overlap, so a link whose text is
+    /// not a URL still gets a hint that opens its target.
+    pub fn scan_with_hyperlinks(
+        &self,
+        lines: &[String],
+        hyperlinks: &[HyperlinkSpan],
+    ) -> Vec<Hint> {
         let mut raw_hints: Vec<(usize, HintType, String, usize, usize)> = Vec::new();
 
+        if self.detect_hyperlinks {
+            for span in hyperlinks {
+                raw_hints.push((
+                    span.row,
+                    HintType::Hyperlink,
+                    span.link.uri().to_string(),
+                    span.col_start,
+                    span.col_end,
+                ));
+            }
+        }
+        let link_count = raw_hints.len();
+
         // Collect all hints from all lines
         for (row, line) in lines.iter().enumerate() {
             let mut line_hints = Vec::new();
             self.scan_line(line, row, &mut line_hints);
 
             for (hint_type, text, col_start, col_end) in line_hints {
-                raw_hints.push((row, hint_type, text, col_start, col_end));
+                let overlaps_link = raw_hints[..link_count]
+                    .iter()
+                    .any(|(r, _, _, s, e)| *r == row && col_start < *e && col_end > *s);
+                if !overlaps_link {
+                    raw_hints.push((row, hint_type, text, col_start, col_end));
+                }
             }
         }
 
@@ -456,6 +495,34 @@ mod tests {
         assert!(hints.iter().any(|h| h.hint_type == HintType::FilePath));
     }
 
+    #[test]
+    fn test_hyperlink_hints() {
+        use crate::engine::{hyperlink_spans, AlacrittyEngine, TerminalEngine};
+        use crate::terminal::TerminalSize;
+
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut engine = AlacrittyEngine::new(TerminalSize { rows: 3, cols: 60 }, tx, 100);
+        engine.feed(b"\x1b]8;;https://example.com/a\x07https://shown.example\x1b]8;;\x07 ");
+        engine.feed(b"\x1b]8;;file:///tmp/report.txt\x07report\x1b]8;;\x07");
+        let spans = hyperlink_spans(&engine.render_cells());
+        let lines = engine.rows_text();
+
+        let hints = HintDetector::new().scan_with_hyperlinks(&lines, &spans);
+        assert_eq!(hints.len(), 2);
+        assert_eq!(hints[0].hint_type, HintType::Hyperlink);
+        assert_eq!(hints[0].text, "https://example.com/a");
+        assert_eq!(hints[1].text, "file:///tmp/report.txt");
+        assert_eq!((hints[1].col_start, hints[1].col_end), (22, 28));
+
+        let detector = HintDetector {
+            detect_hyperlinks: false,
+            ..HintDetector::new()
+        };
+        let hints = detector.scan_with_hyperlinks(&lines, &spans);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].text, "https://shown.example");
+    }
+
     #[test]
     fn test_hint_mode_state() {
         let mut state = HintModeState::new();
@@ -7,10 +7,12 @@
 //!
 //! This enables "Time-Travel" debugging and never losing output.
 
+use crate::history_db::HistoryDb;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// A single command in history
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +33,15 @@ pub struct CommandEntry {
     pub tags: Vec<String>,
     /// Project ID if part of a project
     pub project_id: Option<String>,
+    /// Terminal session the command ran in
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Remote host (None for local commands)
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Git branch checked out in `cwd`
+    #[serde(default)]
+    pub git_branch: Option<String>,
 }
 
 impl CommandEntry {
@@ -44,6 +55,9 @@ impl CommandEntry {
             success: None,
             tags: Vec::new(),
             project_id: None,
+            session: None,
+            host: None,
+            git_branch: None,
         }
     }
 
@@ -55,6 +69,46 @@ impl CommandEntry {
     }
 }
 
+/// Which commands a history lookup covers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HistoryScope {
+    /// Every session, project and host
+    #[default]
+    Global,
+    /// One terminal session
+    Session(String),
+    /// One project (its [`ProjectId`](crate::session::ProjectId))
+    Project(String),
+    /// One machine; `None` is this one
+    Host(Option<String>),
+}
+
+impl HistoryScope {
+    /// Whether a command with these tags is in scope
+    pub fn matches(
+        &self,
+        session: Option<&str>,
+        project_id: Option<&str>,
+        host: Option<&str>,
+    ) -> bool {
+        match self {
+            HistoryScope::Global => true,
+            HistoryScope::Session(id) => session == Some(id.as_str()),
+            HistoryScope::Project(id) => project_id == Some(id.as_str()),
+            HistoryScope::Host(name) => host == name.as_deref(),
+        }
+    }
+
+    /// Whether `entry` is in scope
+    pub fn includes(&self, entry: &CommandEntry) -> bool {
+        self.matches(
+            entry.session.as_deref(),
+            entry.project_id.as_deref(),
+            entry.host.as_deref(),
+        )
+    }
+}
+
 /// Command history with search capabilities
 pub struct CommandHistory {
     /// All commands (most recent first)
@@ -63,6 +117,8 @@ pub struct CommandHistory {
     max_entries: usize,
     /// Persistence file path
     persist_path: Option<PathBuf>,
+    /// Shared history database (replaces the JSON file when set)
+    db: Option<Arc<HistoryDb>>,
 }
 
 impl CommandHistory {
@@ -72,6 +128,7 @@ impl CommandHistory {
             entries: VecDeque::new(),
             max_entries,
             persist_path: None,
+            db: None,
         }
     }
 
@@ -81,14 +138,48 @@ impl CommandHistory {
         self
     }
 
-    /// Add a command to history
-    pub fn push(&mut self, entry: CommandEntry) {
+    /// Record commands in a shared history database
+    pub fn with_database(mut self, db: Arc<HistoryDb>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Add a command to history, with secrets redacted
+    pub fn push(&mut self, mut entry: CommandEntry) {
+        entry.command = crate::redaction::redact(&entry.command);
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.insert(&entry) {
+                tracing::warn!("Failed to record command: {}", e);
+            }
+        }
         self.entries.push_front(entry);
         while self.entries.len() > self.max_entries {
             self.entries.pop_back();
         }
     }
 
+    /// Complete the most recent unfinished run of `command`
+    pub fn complete(
+        &mut self,
+        command: &str,
+        exit_code: i32,
+        duration_ms: u64,
+    ) -> Option<&CommandEntry> {
+        let command = crate::redaction::redact(command);
+        let entry = self
+            .entries
+            .iter_mut()
+            .take(10)
+            .find(|e| e.command == command && e.exit_code.is_none())?;
+        entry.complete(exit_code, duration_ms);
+        if let Some(ref db) = self.db {
+            if let Err(e) = db.complete_latest(&command, exit_code, duration_ms) {
+                tracing::warn!("Failed to record command completion: {}", e);
+            }
+        }
+        Some(entry)
+    }
+
     /// Search commands by query
     pub fn search(&self, query: &str) -> Vec<&CommandEntry> {
         let query_lower = query.to_lowercase();
@@ -104,8 +195,13 @@ impl CommandHistory {
     }
 
     /// Get commands in a specific directory
-    pub fn in_directory(&self, cwd: &PathBuf) -> Vec<&CommandEntry> {
-        self.entries.iter().filter(|e| &e.cwd == cwd).collect()
+    pub fn in_directory(&self, cwd: &Path) -> Vec<&CommandEntry> {
+        self.entries.iter().filter(|e| e.cwd == cwd).collect()
+    }
+
+    /// Get commands in a session, project or host, most recent first
+    pub fn in_scope(&self, scope: &HistoryScope) -> Vec<&CommandEntry> {
+        self.entries.iter().filter(|e| scope.includes(e)).collect()
     }
 
     /// Get frequently used commands
@@ -118,13 +214,16 @@ impl CommandHistory {
         }
 
         let mut sorted: Vec<_> = counts.into_iter().collect();
-        sorted.sort_by(|a, b| b.1.cmp(&a.1));
+        sorted.sort_by_key(|b| std::cmp::Reverse(b.1));
         sorted.truncate(count);
         sorted
     }
 
-    /// Save history to disk
+    /// Save history to disk (a no-op with a database, which is always current)
     pub fn save(&self) -> std::io::Result<()> {
+        if self.db.is_some() {
+            return Ok(());
+        }
         if let Some(ref path) = self.persist_path {
             let content = serde_json::to_string_pretty(&self.entries.iter().collect::<Vec<_>>())?;
             std::fs::write(path, content)?;
@@ -134,6 +233,11 @@ impl CommandHistory {
 
     /// Load history from disk
     pub fn load(&mut self) -> std::io::Result<()> {
+        if let Some(ref db) = self.db {
+            let entries = db.recent(self.max_entries).map_err(std::io::Error::other)?;
+            self.entries = entries.into_iter().collect();
+            return Ok(());
+        }
         if let Some(ref path) = self.persist_path {
             if path.exists() {
                 let content = std::fs::read_to_string(path)?;
@@ -156,6 +260,13 @@ pub struct OutputChunk {
     pub command: Option<String>,
     /// Is this stderr?
     pub is_stderr: bool,
+    /// Terminal session that printed it
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Line serial of the first line (see
+    /// [`OutputIndexer`](crate::output_index::OutputIndexer))
+    #[serde(default)]
+    pub line: Option<usize>,
 }
 
 /// Searchable output history
@@ -215,6 +326,10 @@ impl OutputHistory {
                     context,
                     timestamp: chunk.timestamp,
                     command: chunk.command.clone(),
+                    session: chunk.session.clone(),
+                    line: chunk
+                        .line
+                        .map(|line| line + text_lower[..pos].matches('\n').count()),
                 });
             }
         }
@@ -241,6 +356,10 @@ pub struct SearchResult {
     pub timestamp: DateTime<Utc>,
     /// Associated command
     pub command: Option<String>,
+    /// Terminal session that printed it
+    pub session: Option<String>,
+    /// Line serial of the line holding the match
+    pub line: Option<usize>,
 }
 
 /// Combined searchable history trait
@@ -280,6 +399,36 @@ mod tests {
         assert_eq!(results.len(), 2);
     }
 
+    #[test]
+    fn test_command_scopes() {
+        let mut history = CommandHistory::new(100);
+        let mut local = CommandEntry::new("make", PathBuf::from("/src/app"));
+        local.session = Some("pid 1".to_string());
+        local.project_id = Some("app".to_string());
+        history.push(local);
+        let mut remote = CommandEntry::new("uptime", PathBuf::from("/root"));
+        remote.session = Some("pid 2".to_string());
+        remote.host = Some("web1".to_string());
+        history.push(remote);
+
+        assert_eq!(history.in_scope(&HistoryScope::Global).len(), 2);
+        let project = history.in_scope(&HistoryScope::Project("app".to_string()));
+        assert_eq!(project.len(), 1);
+        assert_eq!(project[0].command, "make");
+        assert_eq!(
+            history.in_scope(&HistoryScope::Session("pid 2".to_string()))[0].command,
+            "uptime"
+        );
+        assert_eq!(
+            history.in_scope(&HistoryScope::Host(Some("web1".to_string())))[0].command,
+            "uptime"
+        );
+        assert_eq!(
+            history.in_scope(&HistoryScope::Host(None))[0].command,
+            "make"
+        );
+    }
+
     #[test]
     fn test_output_search() {
         let mut output = OutputHistory::new(1000, 1024 * 1024);
@@ -288,6 +437,8 @@ mod tests {
             timestamp: Utc::now(),
             command: Some("cat missing.txt".to_string()),
             is_stderr: true,
+            session: None,
+            line: None,
         });
 
         let results = output.search("error");
@@ -0,0 +1,1131 @@
+//! Indexed command history database
+//!
+//! Every command from every session is kept in one SQLite database with a
+//! trigram full-text index, so history search, learning and AI context all
+//! read the same data and recording a command is a single row insert.
+//!
+//! Terminal output is indexed in the same database (see
+//! [`OutputIndexer`](crate::output_index::OutputIndexer)), so "search
+//! everything I've seen" is one query across sessions.
+//!
+//! A database file is opened twice: searches read through their own
+//! read-only connection, so they never wait for a write, and the UI sends
+//! its writes to a [`HistoryWriter`], so it never waits for a search.
+//!
+//! The JSON files written by older versions (`Vec<CommandEntry>` from
+//! [`CommandHistory`](crate::history::CommandHistory) and the
+//! `{"entries": [...]}` command history store) are imported on first open.
+
+use crate::error::{CoreError, Result};
+use crate::history::{
+    CommandEntry, GlobalSearchResults, HistoryScope, OutputChunk, SearchResult, SearchableHistory,
+};
+use crate::history_import::ImportedCommand;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Schema migrations; applying `MIGRATIONS[n]` takes `PRAGMA user_version`
+/// from `n` to `n + 1`
+const MIGRATIONS: &[&str] = &[
+    COMMANDS_SCHEMA,
+    OUTPUT_SCHEMA,
+    SCOPE_INDEXES,
+    GIT_BRANCH,
+    IMPORT_SOURCES,
+];
+
+const COMMANDS_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS commands (
+    id INTEGER PRIMARY KEY,
+    command TEXT NOT NULL,
+    cwd TEXT NOT NULL,
+    session TEXT,
+    host TEXT,
+    exit_code INTEGER,
+    duration_ms INTEGER,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    project_id TEXT,
+    tags TEXT NOT NULL DEFAULT ''
+);
+CREATE INDEX IF NOT EXISTS commands_started ON commands(started_at);
+CREATE INDEX IF NOT EXISTS commands_cwd ON commands(cwd, started_at);
+CREATE VIRTUAL TABLE IF NOT EXISTS commands_fts USING fts5(
+    command, content='commands', content_rowid='id', tokenize='trigram'
+);
+CREATE TRIGGER IF NOT EXISTS commands_ai AFTER INSERT ON commands BEGIN
+    INSERT INTO commands_fts(rowid, command) VALUES (new.id, new.command);
+END;
+CREATE TRIGGER IF NOT EXISTS commands_ad AFTER DELETE ON commands BEGIN
+    INSERT INTO commands_fts(commands_fts, rowid, command) VALUES ('delete', old.id, old.command);
+END;
+CREATE TRIGGER IF NOT EXISTS commands_au AFTER UPDATE OF command ON commands BEGIN
+    INSERT INTO commands_fts(commands_fts, rowid, command) VALUES ('delete', old.id, old.command);
+    INSERT INTO commands_fts(rowid, command) VALUES (new.id, new.command);
+END;
+";
+
+const OUTPUT_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS output (
+    id INTEGER PRIMARY KEY,
+    session TEXT,
+    command TEXT,
+    line INTEGER,
+    created_at INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    -- Last, so summing `bytes` does not read it
+    text TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS output_created ON output(created_at);
+CREATE VIRTUAL TABLE IF NOT EXISTS output_fts USING fts5(
+    text, content='output', content_rowid='id', tokenize='trigram'
+);
+CREATE TRIGGER IF NOT EXISTS output_ai AFTER INSERT ON output BEGIN
+    INSERT INTO output_fts(rowid, text) VALUES (new.id, new.text);
+END;
+CREATE TRIGGER IF NOT EXISTS output_ad AFTER DELETE ON output BEGIN
+    INSERT INTO output_fts(output_fts, rowid, text) VALUES ('delete', old.id, old.text);
+END;
+";
+
+const SCOPE_INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS commands_session ON commands(session, started_at);
+CREATE INDEX IF NOT EXISTS commands_project ON commands(project_id, started_at);
+CREATE INDEX IF NOT EXISTS commands_host ON commands(host, started_at);
+";
+
+const GIT_BRANCH: &str = "ALTER TABLE commands ADD COLUMN git_branch TEXT;";
+
+/// How far each imported history file has been read: the number of commands
+/// taken from it and the last of them, redacted
+const IMPORT_SOURCES: &str = "
+CREATE TABLE IF NOT EXISTS import_sources (
+    path TEXT PRIMARY KEY,
+    imported INTEGER NOT NULL,
+    last_command TEXT NOT NULL
+);
+";
+
+const OUTPUT_COLUMNS: &str = "id, session, command, text, line, created_at";
+
+/// Characters of context kept on each side of an output match
+const CONTEXT_CHARS: usize = 60;
+
+const COLUMNS: &str =
+    "command, cwd, session, host, exit_code, duration_ms, started_at, project_id, tags, git_branch";
+
+/// Shortest query the trigram index can answer; shorter ones use LIKE
+const MIN_FTS_QUERY: usize = 3;
+
+/// Shared command history database
+pub struct HistoryDb {
+    conn: Mutex<Connection>,
+    /// Read-only connection for queries; in-memory databases have none
+    reader: Option<Mutex<Connection>>,
+}
+
+impl std::fmt::Debug for HistoryDb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HistoryDb")
+            .field("path", &self.conn.lock().path().map(str::to_string))
+            .finish()
+    }
+}
+
+impl HistoryDb {
+    /// Default database location (`~/.config/corgiterm/history.db`)
+    pub fn default_path() -> PathBuf {
+        Self::config_dir().join("history.db")
+    }
+
+    /// Legacy JSON history files that [`open_default`](Self::open_default) imports
+    pub fn legacy_paths() -> Vec<PathBuf> {
+        vec![Self::config_dir().join("command_history.json")]
+    }
+
+    fn config_dir() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("corgiterm")
+    }
+
+    /// Open the default database, importing any legacy JSON history
+    pub fn open_default() -> Result<Self> {
+        let db = Self::open(&Self::default_path())?;
+        for path in Self::legacy_paths() {
+            if path.exists() {
+                match db.migrate_json(&path) {
+                    Ok(count) => {
+                        tracing::info!("Imported {} history entries from {}", count, path.display())
+                    }
+                    Err(e) => tracing::warn!("Failed to import {}: {}", path.display(), e),
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    /// Open (or create) a database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        let mut db = Self::init(conn)?;
+        let reader = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        db.reader = Some(Mutex::new(reader));
+        Ok(db)
+    }
+
+    /// Open a private in-memory database
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version == 0 {
+            // Only takes effect before the first table exists; lets
+            // `prune_output` hand space back to the file system
+            conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+        }
+        for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version.max(0) as usize) {
+            conn.execute_batch(migration)?;
+            conn.pragma_update(None, "user_version", applied as i32 + 1)?;
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            reader: None,
+        })
+    }
+
+    /// The connection queries go through
+    fn reader(&self) -> parking_lot::MutexGuard<'_, Connection> {
+        self.reader.as_ref().unwrap_or(&self.conn).lock()
+    }
+
+    /// Record a command, with secrets redacted; returns its row id
+    pub fn insert(&self, entry: &CommandEntry) -> Result<i64> {
+        let conn = self.conn.lock();
+        insert_entry(&conn, entry)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Record many commands in one transaction
+    pub fn insert_many<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a CommandEntry>,
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let mut count = 0;
+        for entry in entries {
+            insert_entry(&tx, entry)?;
+            count += 1;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Record the commands read from the history file at `source`, in file
+    /// order, and return how many were added
+    ///
+    /// Only commands past the ones taken from `source` last time are added,
+    /// so repeated commands in the file are all kept and importing twice
+    /// adds nothing. A timestamped command is also skipped when another
+    /// source already recorded it: the same command in the same second.
+    pub fn import(&self, source: &Path, commands: &[ImportedCommand]) -> Result<usize> {
+        let source = source.to_string_lossy();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let watermark: Option<(usize, String)> = tx
+            .query_row(
+                "SELECT imported, last_command FROM import_sources WHERE path = ?1",
+                params![source],
+                |row| Ok((row.get::<_, i64>(0)? as usize, row.get(1)?)),
+            )
+            .optional()?;
+        let redacted: Vec<String> = commands
+            .iter()
+            .map(|imported| crate::redaction::redact(&imported.entry.command))
+            .collect();
+        let start = watermark.map_or(0, |(imported, last)| resume_at(&redacted, imported, &last));
+        let before: i64 = tx.query_row("SELECT IFNULL(MAX(id), 0) FROM commands", [], |row| {
+            row.get(0)
+        })?;
+
+        let mut count = 0;
+        for (imported, command) in commands.iter().zip(&redacted).skip(start) {
+            if imported.timestamped {
+                let second = imported.entry.timestamp.timestamp() * 1000;
+                let exists = tx
+                    .prepare_cached(
+                        "SELECT 1 FROM commands WHERE id <= ?1
+                         AND started_at BETWEEN ?2 AND ?2 + 999 AND command = ?3",
+                    )?
+                    .exists(params![before, second, command])?;
+                if exists {
+                    continue;
+                }
+            }
+            insert_entry(&tx, &imported.entry)?;
+            count += 1;
+        }
+        if let Some(last) = redacted.last() {
+            tx.execute(
+                "INSERT INTO import_sources (path, imported, last_command) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(path) DO UPDATE SET imported = ?2, last_command = ?3",
+                params![source, redacted.len() as i64, last],
+            )?;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Fill in the exit code and duration of a recorded command
+    pub fn complete(&self, id: i64, exit_code: i32, duration_ms: u64) -> Result<()> {
+        self.conn.lock().execute(
+            "UPDATE commands SET exit_code = ?2, duration_ms = ?3, finished_at = started_at + ?3
+             WHERE id = ?1",
+            params![id, exit_code, duration_ms as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Complete the newest unfinished run of `command` (already redacted)
+    pub fn complete_latest(&self, command: &str, exit_code: i32, duration_ms: u64) -> Result<()> {
+        self.conn.lock().execute(
+            "UPDATE commands SET exit_code = ?2, duration_ms = ?3, finished_at = started_at + ?3
+             WHERE id = (SELECT id FROM commands WHERE command = ?1 AND exit_code IS NULL
+                         ORDER BY started_at DESC, id DESC LIMIT 1)",
+            params![command, exit_code, duration_ms as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent commands, newest first
+    pub fn recent(&self, limit: usize) -> Result<Vec<CommandEntry>> {
+        self.query(
+            &format!("SELECT {COLUMNS} FROM commands ORDER BY started_at DESC, id DESC LIMIT ?1"),
+            params![limit as i64],
+        )
+    }
+
+    /// Commands containing `query` (case-insensitive), newest first
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<CommandEntry>> {
+        self.search_in(query, &HistoryScope::Global, limit)
+    }
+
+    /// Commands in `scope` containing `query` (case-insensitive), newest
+    /// first; the most recent ones in `scope` when `query` is empty
+    pub fn search_in(
+        &self,
+        query: &str,
+        scope: &HistoryScope,
+        limit: usize,
+    ) -> Result<Vec<CommandEntry>> {
+        let (mut sql, mut values) = scope_filter(scope);
+        if query.chars().count() >= MIN_FTS_QUERY {
+            sql.push_str(" AND id IN (SELECT rowid FROM commands_fts WHERE commands_fts MATCH ?)");
+            values.push(fts_phrase(query).into());
+        } else if !query.is_empty() {
+            sql.push_str(" AND command LIKE ? ESCAPE '\\'");
+            values.push(like_pattern(query).into());
+        }
+        values.push((limit as i64).into());
+        self.query(
+            &format!(
+                "SELECT {COLUMNS} FROM commands WHERE {sql}
+                 ORDER BY started_at DESC, id DESC LIMIT ?"
+            ),
+            rusqlite::params_from_iter(values),
+        )
+    }
+
+    /// Distinct commands in `scope`, most recently run first, for recalling
+    /// them into the prompt
+    pub fn recall(&self, scope: &HistoryScope, limit: usize) -> Result<Vec<String>> {
+        let (sql, mut values) = scope_filter(scope);
+        values.push((limit as i64).into());
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT command FROM commands WHERE {sql}
+             GROUP BY command ORDER BY MAX(started_at) DESC, MAX(id) DESC LIMIT ?"
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Commands run in `cwd`, newest first
+    pub fn in_directory(&self, cwd: &Path, limit: usize) -> Result<Vec<CommandEntry>> {
+        self.query(
+            &format!(
+                "SELECT {COLUMNS} FROM commands WHERE cwd = ?1
+                 ORDER BY started_at DESC, id DESC LIMIT ?2"
+            ),
+            params![cwd.to_string_lossy(), limit as i64],
+        )
+    }
+
+    /// Number of recorded commands
+    pub fn len(&self) -> Result<usize> {
+        let count: i64 = self
+            .reader()
+            .query_row("SELECT COUNT(*) FROM commands", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Whether no commands are recorded
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Delete all but the newest `keep` commands; returns how many were removed
+    pub fn prune(&self, keep: usize) -> Result<usize> {
+        let conn = self.conn.lock();
+        let cutoff: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM commands ORDER BY started_at DESC, id DESC LIMIT 1 OFFSET ?1",
+                params![keep as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(cutoff) = cutoff else {
+            return Ok(0);
+        };
+        let removed = conn.execute(
+            "DELETE FROM commands WHERE (started_at, id) <=
+             (SELECT started_at, id FROM commands WHERE id = ?1)",
+            params![cutoff],
+        )?;
+        Ok(removed)
+    }
+
+    /// Import a legacy JSON history file; returns how many commands were added
+    pub fn import_json(&self, path: &Path) -> Result<usize> {
+        let content = std::fs::read_to_string(path)?;
+        let legacy: LegacyHistory = serde_json::from_str(&content).map_err(std::io::Error::from)?;
+        let entries = legacy.into_entries();
+        self.insert_many(&entries)
+    }
+
+    /// Import a legacy JSON history file, then rename it to `*.migrated`
+    pub fn migrate_json(&self, path: &Path) -> Result<usize> {
+        let count = self.import_json(path)?;
+        let mut migrated = path.as_os_str().to_owned();
+        migrated.push(".migrated");
+        std::fs::rename(path, migrated)?;
+        Ok(count)
+    }
+
+    /// Record terminal output, with secrets redacted; blank chunks are skipped
+    pub fn insert_output(&self, chunks: &[OutputChunk]) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let mut count = 0;
+        for chunk in chunks.iter().filter(|c| !c.text.trim().is_empty()) {
+            let text = crate::redaction::redact(&chunk.text);
+            tx.prepare_cached(
+                "INSERT INTO output (session, command, text, line, created_at, bytes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![
+                chunk.session,
+                chunk.command.as_deref().map(crate::redaction::redact),
+                text,
+                chunk.line.map(|line| line as i64),
+                chunk.timestamp.timestamp_millis(),
+                text.len() as i64,
+            ])?;
+            count += 1;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Output matching `query`, newest first, one result per matching line
+    ///
+    /// Plain patterns are matched case-insensitively through the trigram
+    /// index; regular expressions scan the output newest first until
+    /// `query.limit` results are found.
+    pub fn search_output(&self, query: &OutputQuery) -> Result<Vec<SearchResult>> {
+        if query.pattern.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pattern = if query.regex {
+            query.pattern.clone()
+        } else {
+            regex::escape(&query.pattern)
+        };
+        let matcher = regex::RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| CoreError::Config(format!("Invalid search pattern: {e}")))?;
+
+        let mut sql = format!("SELECT {OUTPUT_COLUMNS} FROM output WHERE 1");
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if !query.regex {
+            if query.pattern.chars().count() < MIN_FTS_QUERY {
+                sql.push_str(" AND text LIKE ? ESCAPE '\\'");
+                values.push(like_pattern(&query.pattern).into());
+            } else {
+                sql.push_str(" AND id IN (SELECT rowid FROM output_fts WHERE output_fts MATCH ?)");
+                values.push(fts_phrase(&query.pattern).into());
+            }
+        }
+        if let Some(since) = query.since {
+            sql.push_str(" AND created_at >= ?");
+            values.push(since.timestamp_millis().into());
+        }
+        if let Some(until) = query.until {
+            sql.push_str(" AND created_at <= ?");
+            values.push(until.timestamp_millis().into());
+        }
+        if let Some(session) = &query.session {
+            sql.push_str(" AND session = ?");
+            values.push(session.clone().into());
+        }
+        sql.push_str(" ORDER BY created_at DESC, id DESC");
+
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(values))?;
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            if results.len() >= query.limit {
+                break;
+            }
+            let chunk = output_from_row(row)?;
+            let text: String = row.get(3)?;
+            let mut last_line = None;
+            for found in matcher.find_iter(&text) {
+                let newlines = text[..found.start()].matches('\n').count();
+                if last_line == Some(newlines) {
+                    continue;
+                }
+                last_line = Some(newlines);
+                results.push(SearchResult {
+                    chunk_index: chunk.0,
+                    position: found.start(),
+                    context: context(&text, found.start(), found.end()),
+                    timestamp: chunk.1.timestamp,
+                    command: chunk.1.command.clone(),
+                    session: chunk.1.session.clone(),
+                    line: chunk.1.line.map(|line| line + newlines),
+                });
+                if results.len() >= query.limit {
+                    break;
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Bytes of output text stored
+    pub fn output_bytes(&self) -> Result<u64> {
+        let bytes: i64 =
+            self.reader()
+                .query_row("SELECT COALESCE(SUM(bytes), 0) FROM output", [], |row| {
+                    row.get(0)
+                })?;
+        Ok(bytes as u64)
+    }
+
+    /// Delete the oldest output until at most `max_bytes` of text is stored;
+    /// returns how many chunks were removed
+    pub fn prune_output(&self, max_bytes: u64) -> Result<usize> {
+        let conn = self.conn.lock();
+        // Newest chunk that no longer fits once everything newer is kept
+        let cutoff: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM (SELECT id, SUM(bytes) OVER (ORDER BY id DESC) AS total
+                 FROM output) WHERE total > ?1 ORDER BY id DESC LIMIT 1",
+                params![max_bytes as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(cutoff) = cutoff else {
+            return Ok(0);
+        };
+        let removed = conn.execute("DELETE FROM output WHERE id <= ?1", params![cutoff])?;
+        conn.execute_batch("PRAGMA incremental_vacuum")?;
+        Ok(removed)
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<CommandEntry>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(sql)?;
+        let rows = stmt.query_map(params, entry_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+/// A write waiting for a [`HistoryWriter`]
+enum HistoryWrite {
+    Command(Box<CommandEntry>),
+    Output(Vec<OutputChunk>),
+    PruneOutput(u64),
+    Flush(crossbeam_channel::Sender<()>),
+}
+
+/// Writes to a [`HistoryDb`] on one background thread, in the order they
+/// are sent; the thread ends when the last handle is dropped
+#[derive(Debug, Clone)]
+pub struct HistoryWriter {
+    sender: crossbeam_channel::Sender<HistoryWrite>,
+}
+
+impl HistoryWriter {
+    /// Start the writer thread for `db`
+    pub fn spawn(db: Arc<HistoryDb>) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        std::thread::spawn(move || {
+            for write in receiver {
+                let result = match write {
+                    HistoryWrite::Command(entry) => db.insert(&entry).map(drop),
+                    HistoryWrite::Output(chunks) => db.insert_output(&chunks).map(drop),
+                    HistoryWrite::PruneOutput(max_bytes) => db.prune_output(max_bytes).map(drop),
+                    HistoryWrite::Flush(done) => {
+                        let _ = done.send(());
+                        Ok(())
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::warn!("Failed to write history: {}", e);
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    /// Record a command, with secrets redacted
+    pub fn insert(&self, entry: CommandEntry) {
+        self.send(HistoryWrite::Command(Box::new(entry)));
+    }
+
+    /// Record terminal output, with secrets redacted
+    pub fn insert_output(&self, chunks: Vec<OutputChunk>) {
+        self.send(HistoryWrite::Output(chunks));
+    }
+
+    /// Delete the oldest output until at most `max_bytes` of text is stored
+    pub fn prune_output(&self, max_bytes: u64) {
+        self.send(HistoryWrite::PruneOutput(max_bytes));
+    }
+
+    /// Wait until everything sent so far is written
+    pub fn flush(&self) {
+        let (done, wait) = crossbeam_channel::bounded(1);
+        self.send(HistoryWrite::Flush(done));
+        let _ = wait.recv();
+    }
+
+    fn send(&self, write: HistoryWrite) {
+        if self.sender.send(write).is_err() {
+            tracing::warn!("History writer has stopped");
+        }
+    }
+}
+
+/// Where to resume importing a history file that had `imported` commands,
+/// the last being `last`, when it was read before. Shells trim old commands
+/// from the start of the file, so the last one is looked for at or before
+/// where it was; a file that no longer has it is imported again.
+fn resume_at(commands: &[String], imported: usize, last: &str) -> usize {
+    commands[..imported.min(commands.len())]
+        .iter()
+        .rposition(|command| command == last)
+        .map_or(0, |index| index + 1)
+}
+
+fn insert_entry(conn: &Connection, entry: &CommandEntry) -> rusqlite::Result<()> {
+    let started_at = entry.timestamp.timestamp_millis();
+    conn.prepare_cached(
+        "INSERT INTO commands
+         (command, cwd, session, host, exit_code, duration_ms, started_at, finished_at, project_id, tags, git_branch)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )?
+    .execute(params![
+        crate::redaction::redact(&entry.command),
+        entry.cwd.to_string_lossy(),
+        entry.session,
+        entry.host,
+        entry.exit_code,
+        entry.duration_ms.map(|d| d as i64),
+        started_at,
+        entry.duration_ms.map(|d| started_at + d as i64),
+        entry.project_id,
+        entry.tags.join(","),
+        entry.git_branch,
+    ])?;
+    Ok(())
+}
+
+/// SQL condition (and its parameters) selecting the commands in `scope`
+fn scope_filter(scope: &HistoryScope) -> (String, Vec<rusqlite::types::Value>) {
+    match scope {
+        HistoryScope::Global => ("1".to_string(), Vec::new()),
+        HistoryScope::Session(id) => ("session = ?".to_string(), vec![id.clone().into()]),
+        HistoryScope::Project(id) => ("project_id = ?".to_string(), vec![id.clone().into()]),
+        HistoryScope::Host(Some(host)) => ("host = ?".to_string(), vec![host.clone().into()]),
+        HistoryScope::Host(None) => ("host IS NULL".to_string(), Vec::new()),
+    }
+}
+
+/// Row id and chunk of an `OUTPUT_COLUMNS` row
+fn output_from_row(row: &Row<'_>) -> rusqlite::Result<(usize, OutputChunk)> {
+    Ok((
+        row.get::<_, i64>(0)? as usize,
+        OutputChunk {
+            session: row.get(1)?,
+            command: row.get(2)?,
+            text: row.get(3)?,
+            line: row.get::<_, Option<i64>>(4)?.map(|line| line as usize),
+            timestamp: DateTime::from_timestamp_millis(row.get(5)?).unwrap_or_default(),
+            is_stderr: false,
+        },
+    ))
+}
+
+/// The line of `text` around the match at `start..end`, clipped to
+/// [`CONTEXT_CHARS`] on each side
+fn context(text: &str, start: usize, end: usize) -> String {
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+    let before = text[line_start..start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT_CHARS - 1)
+        .map_or(line_start, |(i, _)| line_start + i);
+    let after = text[end..line_end]
+        .char_indices()
+        .nth(CONTEXT_CHARS)
+        .map_or(line_end, |(i, _)| end + i);
+    text[before..after].trim().to_string()
+}
+
+fn entry_from_row(row: &Row<'_>) -> rusqlite::Result<CommandEntry> {
+    let exit_code: Option<i32> = row.get(4)?;
+    let tags: String = row.get(8)?;
+    Ok(CommandEntry {
+        command: row.get(0)?,
+        cwd: PathBuf::from(row.get::<_, String>(1)?),
+        session: row.get(2)?,
+        host: row.get(3)?,
+        exit_code,
+        duration_ms: row.get::<_, Option<i64>>(5)?.map(|d| d as u64),
+        success: exit_code.map(|code| code == 0),
+        timestamp: DateTime::from_timestamp_millis(row.get(6)?).unwrap_or_default(),
+        project_id: row.get(7)?,
+        tags: tags
+            .split(',')
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect(),
+        git_branch: row.get(9)?,
+    })
+}
+
+/// `query` as a LIKE pattern matching it anywhere
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// `query` as a single FTS5 phrase
+fn fts_phrase(query: &str) -> String {
+    format!("\"{}\"", query.replace('"', "\"\""))
+}
+
+impl SearchableHistory for HistoryDb {
+    fn global_search(&self, query: &str) -> GlobalSearchResults {
+        let commands = self.search(query, 100).unwrap_or_else(|e| {
+            tracing::warn!("Command history search failed: {}", e);
+            Vec::new()
+        });
+        let output_matches = self
+            .search_output(&OutputQuery::new(query))
+            .unwrap_or_else(|e| {
+                tracing::warn!("Output history search failed: {}", e);
+                Vec::new()
+            });
+        GlobalSearchResults {
+            commands,
+            output_matches,
+        }
+    }
+}
+
+/// What [`HistoryDb::search_output`] looks for
+#[derive(Debug, Clone)]
+pub struct OutputQuery {
+    /// Text to find, or a regular expression if `regex` is set
+    pub pattern: String,
+    pub regex: bool,
+    /// Only output recorded at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only output recorded at or before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Only output from this session
+    pub session: Option<String>,
+    /// Maximum number of results
+    pub limit: usize,
+}
+
+impl OutputQuery {
+    /// Plain-text search for `pattern` over all output
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            regex: false,
+            since: None,
+            until: None,
+            session: None,
+            limit: 200,
+        }
+    }
+}
+
+/// The JSON formats written before the database existed
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacyHistory {
+    /// `CommandHistory::save`
+    Core(Vec<CommandEntry>),
+    /// The AI crate's `CommandHistoryStore::save`
+    Store { entries: Vec<LegacyStoreEntry> },
+}
+
+#[derive(Deserialize)]
+struct LegacyStoreEntry {
+    command: String,
+    directory: String,
+    /// Unix seconds
+    timestamp: u64,
+    exit_code: Option<i32>,
+    duration_ms: Option<u64>,
+}
+
+impl LegacyHistory {
+    fn into_entries(self) -> Vec<CommandEntry> {
+        match self {
+            LegacyHistory::Core(entries) => entries,
+            LegacyHistory::Store { entries } => entries
+                .into_iter()
+                .map(|e| {
+                    let mut entry = CommandEntry::new(e.command, PathBuf::from(e.directory));
+                    entry.timestamp =
+                        DateTime::<Utc>::from_timestamp(e.timestamp as i64, 0).unwrap_or_default();
+                    entry.exit_code = e.exit_code;
+                    entry.success = e.exit_code.map(|code| code == 0);
+                    entry.duration_ms = e.duration_ms;
+                    entry
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn entry(command: &str, cwd: &str, seconds_ago: i64) -> CommandEntry {
+        let mut entry = CommandEntry::new(command, PathBuf::from(cwd));
+        entry.timestamp = Utc::now() - Duration::seconds(seconds_ago);
+        entry
+    }
+
+    #[test]
+    fn test_insert_and_search() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        db.insert(&entry("cargo build --release", "/src/app", 30))
+            .unwrap();
+        let mut status = entry("git status", "/src/app", 20);
+        status.git_branch = Some("main".to_string());
+        db.insert(&status).unwrap();
+        let id = db.insert(&entry("Cargo test", "/src/lib", 10)).unwrap();
+        db.complete(id, 101, 2500).unwrap();
+
+        assert_eq!(db.len().unwrap(), 3);
+        let recent = db.recent(2).unwrap();
+        assert_eq!(recent[0].command, "Cargo test");
+        assert_eq!(recent[0].exit_code, Some(101));
+        assert_eq!(recent[0].success, Some(false));
+        assert_eq!(recent[0].duration_ms, Some(2500));
+        assert_eq!(recent[0].git_branch, None);
+        assert_eq!(recent[1].command, "git status");
+        assert_eq!(recent[1].git_branch.as_deref(), Some("main"));
+
+        let found: Vec<_> = db
+            .search("cargo", 10)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(found, ["Cargo test", "cargo build --release"]);
+        // Too short for the trigram index
+        assert_eq!(db.search("gi", 10).unwrap().len(), 1);
+        assert_eq!(db.search("100%", 10).unwrap().len(), 0);
+        assert_eq!(db.search("\"quoted", 10).unwrap().len(), 0);
+
+        assert_eq!(db.in_directory(Path::new("/src/app"), 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_insert_redacts() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        db.insert(&entry("export API_TOKEN=abcdef123456", "/", 0))
+            .unwrap();
+        assert!(db.search("abcdef", 10).unwrap().is_empty());
+        assert!(db.recent(1).unwrap()[0].command.contains("[REDACTED"));
+    }
+
+    #[test]
+    fn test_scoped_search_and_recall() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        let tagged =
+            |command: &str, session: &str, project: Option<&str>, host: Option<&str>, ago| {
+                let mut entry = entry(command, "/src", ago);
+                entry.session = Some(session.to_string());
+                entry.project_id = project.map(str::to_string);
+                entry.host = host.map(str::to_string);
+                entry
+            };
+        db.insert_many(&[
+            tagged("cargo build", "pid 1", Some("app"), None, 50),
+            tagged("cargo test", "pid 2", Some("app"), None, 40),
+            tagged("cargo build", "pid 2", Some("app"), None, 30),
+            tagged("cargo fmt", "pid 3", None, Some("web1"), 20),
+            tagged("echo 'a\nb'", "pid 1", Some("app"), None, 10),
+        ])
+        .unwrap();
+
+        let commands = |found: Vec<CommandEntry>| -> Vec<String> {
+            found.into_iter().map(|e| e.command).collect()
+        };
+        let app = HistoryScope::Project("app".to_string());
+        assert_eq!(db.search_in("cargo", &app, 10).unwrap().len(), 3);
+        assert_eq!(db.search_in("", &app, 10).unwrap().len(), 4);
+        assert_eq!(
+            commands(
+                db.search_in("ca", &HistoryScope::Session("pid 2".to_string()), 10)
+                    .unwrap()
+            ),
+            ["cargo build", "cargo test"]
+        );
+        assert_eq!(
+            commands(
+                db.search_in("cargo", &HistoryScope::Host(Some("web1".to_string())), 10)
+                    .unwrap()
+            ),
+            ["cargo fmt"]
+        );
+        assert_eq!(
+            db.search_in("cargo", &HistoryScope::Host(None), 10)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(db.search("cargo", 10).unwrap().len(), 4);
+
+        // Deduplicated, newest first
+        assert_eq!(
+            db.recall(&app, 10).unwrap(),
+            ["echo 'a\nb'", "cargo build", "cargo test"]
+        );
+    }
+
+    #[test]
+    fn test_prune() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        let entries: Vec<_> = (0..10)
+            .map(|i| entry(&format!("echo {i}"), "/", 100 - i))
+            .collect();
+        assert_eq!(db.insert_many(&entries).unwrap(), 10);
+        assert_eq!(db.prune(4).unwrap(), 6);
+        assert_eq!(db.len().unwrap(), 4);
+        assert_eq!(db.recent(1).unwrap()[0].command, "echo 9");
+        assert_eq!(db.search("echo 2", 10).unwrap().len(), 0);
+        assert_eq!(db.prune(10).unwrap(), 0);
+    }
+
+    fn chunk(text: &str, session: &str, seconds_ago: i64) -> OutputChunk {
+        OutputChunk {
+            text: text.to_string(),
+            timestamp: Utc::now() - Duration::seconds(seconds_ago),
+            command: Some("make".to_string()),
+            is_stderr: false,
+            session: Some(session.to_string()),
+            line: Some(10),
+        }
+    }
+
+    #[test]
+    fn test_output_search() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        db.insert_output(&[
+            chunk(
+                "Compiling app\nerror[E0308]: mismatched types\n",
+                "pid 1",
+                600,
+            ),
+            chunk("all good\n   \nERROR: disk full", "pid 2", 10),
+            chunk("   \n", "pid 2", 5),
+        ])
+        .unwrap();
+
+        let results = db.search_output(&OutputQuery::new("error")).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].session.as_deref(), Some("pid 2"));
+        assert_eq!(results[0].line, Some(12));
+        assert_eq!(results[0].context, "ERROR: disk full");
+        assert_eq!(results[1].line, Some(11));
+        assert_eq!(results[1].command.as_deref(), Some("make"));
+
+        let recent = OutputQuery {
+            since: Some(Utc::now() - Duration::seconds(60)),
+            ..OutputQuery::new("error")
+        };
+        assert_eq!(db.search_output(&recent).unwrap().len(), 1);
+        let in_session = OutputQuery {
+            session: Some("pid 1".to_string()),
+            ..OutputQuery::new("er")
+        };
+        assert_eq!(db.search_output(&in_session).unwrap().len(), 1);
+
+        let regex = OutputQuery {
+            regex: true,
+            ..OutputQuery::new(r"E\d{4}")
+        };
+        let results = db.search_output(&regex).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].position, 20);
+        let invalid = OutputQuery {
+            regex: true,
+            ..OutputQuery::new("(")
+        };
+        assert!(db.search_output(&invalid).is_err());
+
+        let global = db.global_search("mismatched");
+        assert_eq!(global.output_matches.len(), 1);
+    }
+
+    #[test]
+    fn test_output_context_is_clipped() {
+        let line = format!("{}needle{}", "é".repeat(100), "ü".repeat(100));
+        let start = line.find("needle").unwrap();
+        let clipped = context(&line, start, start + 6);
+        assert_eq!(clipped.chars().count(), CONTEXT_CHARS * 2 + 6);
+    }
+
+    #[test]
+    fn test_output_redacted_and_pruned() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        db.insert_output(&[chunk("API_TOKEN=abcdef123456", "pid 1", 30)])
+            .unwrap();
+        assert!(db
+            .search_output(&OutputQuery::new("abcdef"))
+            .unwrap()
+            .is_empty());
+
+        let chunks: Vec<_> = (0..10)
+            .map(|i| chunk(&format!("output {i:04}"), "pid 1", 20 - i))
+            .collect();
+        db.insert_output(&chunks).unwrap();
+        let before = db.output_bytes().unwrap();
+        assert_eq!(db.prune_output(before).unwrap(), 0);
+        // Room for the newest three
+        assert_eq!(db.prune_output(36).unwrap(), 8);
+        assert_eq!(db.output_bytes().unwrap(), 33);
+        assert!(db
+            .search_output(&OutputQuery::new("output 0006"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.search_output(&OutputQuery::new("output 0007"))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_searches_do_not_wait_for_writes() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-history-{}", uuid::Uuid::new_v4()));
+        let db = Arc::new(HistoryDb::open(&dir.join("history.db")).unwrap());
+        let writer = HistoryWriter::spawn(db.clone());
+        writer.insert(entry("cargo build", "/work", 10));
+        writer.insert_output(vec![chunk("error: linker failed", "s1", 5)]);
+        writer.flush();
+
+        // A long write holds the writer connection
+        let writing = db.conn.lock();
+        assert_eq!(db.search("cargo", 10).unwrap().len(), 1);
+        assert_eq!(
+            db.search_output(&OutputQuery::new("linker")).unwrap().len(),
+            1
+        );
+        assert_eq!(db.len().unwrap(), 1);
+        drop(writing);
+
+        writer.prune_output(0);
+        writer.flush();
+        assert_eq!(db.output_bytes().unwrap(), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_migrate_legacy_formats() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-history-{}", uuid::Uuid::new_v4()));
+        let db = HistoryDb::open(&dir.join("history.db")).unwrap();
+
+        let core_path = dir.join("core.json");
+        let core = vec![entry("make check", "/work", 60)];
+        std::fs::write(&core_path, serde_json::to_string(&core).unwrap()).unwrap();
+        assert_eq!(db.migrate_json(&core_path).unwrap(), 1);
+        assert!(!core_path.exists());
+        assert!(dir.join("core.json.migrated").exists());
+
+        let store_path = dir.join("command_history.json");
+        std::fs::write(
+            &store_path,
+            r#"{"entries": [{"command": "npm test", "directory": "/web",
+                "timestamp": 1700000000, "exit_code": 1, "duration_ms": 900}],
+                "max_entries": 10000}"#,
+        )
+        .unwrap();
+        assert_eq!(db.import_json(&store_path).unwrap(), 1);
+
+        let npm = &db.search("npm", 10).unwrap()[0];
+        assert_eq!(npm.cwd, PathBuf::from("/web"));
+        assert_eq!(npm.timestamp.timestamp(), 1_700_000_000);
+        assert_eq!(npm.success, Some(false));
+        assert_eq!(
+            db.search("make", 10).unwrap()[0].cwd,
+            PathBuf::from("/work")
+        );
+
+        // Reopening keeps the data
+        drop(db);
+        let db = HistoryDb::open(&dir.join("history.db")).unwrap();
+        assert_eq!(db.len().unwrap(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,484 @@
+//! Shell history import
+//!
+//! Reads the history other shells and tools keep, so a new install starts
+//! with something to search and learn from:
+//! - bash (`~/.bash_history`, with `HISTTIMEFORMAT` timestamps when present)
+//! - zsh, plain or with `EXTENDED_HISTORY`
+//! - fish (`~/.local/share/fish/fish_history`)
+//! - atuin's SQLite database
+//!
+//! Imports go through [`HistoryDb::import`], which remembers how far each
+//! file was read, so importing twice adds nothing.
+
+use crate::error::{CoreError, Result};
+use crate::history::CommandEntry;
+use crate::history_db::HistoryDb;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+
+/// A shell history format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellHistoryFormat {
+    Bash,
+    Zsh,
+    Fish,
+    Atuin,
+}
+
+impl ShellHistoryFormat {
+    /// All formats, in the order they are detected
+    pub const ALL: [ShellHistoryFormat; 4] = [
+        ShellHistoryFormat::Atuin,
+        ShellHistoryFormat::Zsh,
+        ShellHistoryFormat::Bash,
+        ShellHistoryFormat::Fish,
+    ];
+
+    /// Short name, as used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShellHistoryFormat::Bash => "bash",
+            ShellHistoryFormat::Zsh => "zsh",
+            ShellHistoryFormat::Fish => "fish",
+            ShellHistoryFormat::Atuin => "atuin",
+        }
+    }
+
+    /// Format by short name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Where the shell keeps its history by default
+    pub fn default_path(&self) -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        let data = dirs::data_dir().unwrap_or_else(|| home.join(".local/share"));
+        Some(match self {
+            ShellHistoryFormat::Bash => home.join(".bash_history"),
+            ShellHistoryFormat::Zsh => std::env::var_os("ZDOTDIR")
+                .map(PathBuf::from)
+                .unwrap_or(home)
+                .join(".zsh_history"),
+            ShellHistoryFormat::Fish => data.join("fish").join("fish_history"),
+            ShellHistoryFormat::Atuin => data.join("atuin").join("history.db"),
+        })
+    }
+
+    /// Formats whose default history file exists, with its path
+    pub fn detect() -> Vec<(ShellHistoryFormat, PathBuf)> {
+        Self::ALL
+            .into_iter()
+            .filter_map(|format| Some((format, format.default_path()?)))
+            .filter(|(_, path)| path.exists())
+            .collect()
+    }
+}
+
+/// A command read from another shell's history
+#[derive(Debug, Clone)]
+pub struct ImportedCommand {
+    pub entry: CommandEntry,
+    /// Whether the history recorded when it ran; without one the timestamp
+    /// only preserves the order
+    pub timestamped: bool,
+}
+
+/// Outcome of importing one history file
+#[derive(Debug, Clone)]
+pub struct ImportReport {
+    pub format: ShellHistoryFormat,
+    pub path: PathBuf,
+    /// Commands found in the file
+    pub read: usize,
+    /// Commands that were not imported before
+    pub imported: usize,
+}
+
+/// Read `path` as `format`
+pub fn read(format: ShellHistoryFormat, path: &Path) -> Result<Vec<ImportedCommand>> {
+    if format == ShellHistoryFormat::Atuin {
+        return read_atuin(path);
+    }
+    let content = std::fs::read(path)?;
+    // Untimestamped commands are placed just before the file was last written
+    let fallback = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now());
+    Ok(match format {
+        ShellHistoryFormat::Bash => parse_bash(&String::from_utf8_lossy(&content), fallback),
+        ShellHistoryFormat::Zsh => parse_zsh(&content, fallback),
+        ShellHistoryFormat::Fish => parse_fish(&String::from_utf8_lossy(&content), fallback),
+        ShellHistoryFormat::Atuin => unreachable!(),
+    })
+}
+
+/// Import `path` as `format` into `db`
+pub fn import(db: &HistoryDb, format: ShellHistoryFormat, path: &Path) -> Result<ImportReport> {
+    let commands = read(format, path)?;
+    let imported = db.import(path, &commands)?;
+    Ok(ImportReport {
+        format,
+        path: path.to_path_buf(),
+        read: commands.len(),
+        imported,
+    })
+}
+
+/// Import every detected shell history into `db`
+pub fn import_detected(db: &HistoryDb) -> Vec<Result<ImportReport>> {
+    ShellHistoryFormat::detect()
+        .into_iter()
+        .map(|(format, path)| import(db, format, &path))
+        .collect()
+}
+
+/// Parse bash history; `#<unix time>` lines (written when `HISTTIMEFORMAT`
+/// is set) timestamp the lines that follow them
+pub fn parse_bash(content: &str, fallback: DateTime<Utc>) -> Vec<ImportedCommand> {
+    let mut commands: Vec<RawCommand> = Vec::new();
+    let mut timestamp = None;
+    let mut continues = false;
+    for line in content.lines() {
+        if let Some(time) = line
+            .strip_prefix('#')
+            .and_then(|t| t.parse::<i64>().ok())
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+        {
+            timestamp = Some(time);
+            continues = false;
+            continue;
+        }
+        match commands.last_mut() {
+            // Lines between timestamps belong to one multi-line command
+            Some((command, _, _)) if continues => {
+                command.push('\n');
+                command.push_str(line);
+            }
+            _ => {
+                commands.push((line.to_string(), timestamp, None));
+                continues = timestamp.is_some();
+            }
+        }
+    }
+    finish(commands, fallback)
+}
+
+/// Parse zsh history, plain or `EXTENDED_HISTORY`
+/// (`: <start>:<elapsed>;<command>`)
+pub fn parse_zsh(content: &[u8], fallback: DateTime<Utc>) -> Vec<ImportedCommand> {
+    let content = unmetafy(content);
+    let mut commands: Vec<RawCommand> = Vec::new();
+    let mut continues = false;
+    for line in content.lines() {
+        let (text, more) = match line.strip_suffix('\\') {
+            Some(text) => (text, true),
+            None => (line, false),
+        };
+        match commands.last_mut() {
+            Some((command, _, _)) if continues => {
+                command.push('\n');
+                command.push_str(text);
+            }
+            _ => {
+                let (timestamp, duration_ms, command) = match parse_zsh_extended(text) {
+                    Some((start, elapsed, command)) => (Some(start), Some(elapsed * 1000), command),
+                    None => (None, None, text),
+                };
+                commands.push((command.to_string(), timestamp, duration_ms));
+            }
+        }
+        continues = more;
+    }
+
+    finish(commands, fallback)
+}
+
+/// `: 1700000000:5;git status` → (start, elapsed seconds, command)
+fn parse_zsh_extended(line: &str) -> Option<(DateTime<Utc>, u64, &str)> {
+    let rest = line.strip_prefix(": ")?;
+    let (times, command) = rest.split_once(';')?;
+    let (start, elapsed) = times.split_once(':')?;
+    let start = DateTime::from_timestamp(start.trim().parse().ok()?, 0)?;
+    Some((start, elapsed.trim().parse().ok()?, command))
+}
+
+/// Undo zsh's metafication of special bytes in its history file
+fn unmetafy(content: &[u8]) -> String {
+    const META: u8 = 0x83;
+    let mut bytes = Vec::with_capacity(content.len());
+    let mut iter = content.iter();
+    while let Some(&b) = iter.next() {
+        if b == META {
+            if let Some(&next) = iter.next() {
+                bytes.push(next ^ 0x20);
+            }
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Parse fish's YAML-like history (`- cmd: ...` / `  when: ...`)
+pub fn parse_fish(content: &str, fallback: DateTime<Utc>) -> Vec<ImportedCommand> {
+    let mut commands: Vec<RawCommand> = Vec::new();
+    for line in content.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            commands.push((unescape_fish(command), None, None));
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+            if let Some((_, timestamp, _)) = commands.last_mut() {
+                *timestamp = when
+                    .trim()
+                    .parse()
+                    .ok()
+                    .and_then(|t| DateTime::from_timestamp(t, 0));
+            }
+        }
+    }
+    finish(commands, fallback)
+}
+
+/// Fish writes newlines as `\n` and backslashes as `\\`
+fn unescape_fish(command: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Read atuin's history database
+pub fn read_atuin(path: &Path) -> Result<Vec<ImportedCommand>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, duration, exit, command, cwd, session, hostname FROM history
+         WHERE deleted_at IS NULL ORDER BY timestamp",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+        ))
+    })?;
+
+    let mut commands = Vec::new();
+    for row in rows {
+        let (timestamp_ns, duration_ns, exit, command, cwd, session, hostname) = row?;
+        if command.trim().is_empty() {
+            continue;
+        }
+        let mut entry = CommandEntry::new(command, PathBuf::from(cwd));
+        entry.timestamp = DateTime::from_timestamp_nanos(timestamp_ns);
+        // Atuin records -1 while a command is still running
+        if duration_ns >= 0 {
+            entry.complete(exit as i32, (duration_ns / 1_000_000) as u64);
+        }
+        entry.session = Some(session);
+        // Atuin stores `host:user`
+        let host = hostname.split(':').next().unwrap_or_default();
+        if !crate::shell_integration::is_local_host(Some(host)) {
+            entry.host = Some(host.to_string());
+        }
+        commands.push(ImportedCommand {
+            entry,
+            timestamped: true,
+        });
+    }
+    Ok(commands)
+}
+
+/// A command as read from a history file: text, start time and duration
+type RawCommand = (String, Option<DateTime<Utc>>, Option<u64>);
+
+/// Build entries in file order, dropping blank commands and spacing
+/// untimestamped ones a second apart so they keep their order and end at
+/// `fallback`
+fn finish(commands: Vec<RawCommand>, fallback: DateTime<Utc>) -> Vec<ImportedCommand> {
+    let commands: Vec<_> = commands
+        .into_iter()
+        .filter(|(command, _, _)| !command.trim().is_empty())
+        .collect();
+    let total = commands.len() as i64;
+    commands
+        .into_iter()
+        .enumerate()
+        .map(|(i, (command, timestamp, duration_ms))| {
+            let mut entry = CommandEntry::new(command, PathBuf::new());
+            entry.timestamp =
+                timestamp.unwrap_or_else(|| fallback - Duration::seconds(total - 1 - i as i64));
+            entry.duration_ms = duration_ms;
+            ImportedCommand {
+                entry,
+                timestamped: timestamp.is_some(),
+            }
+        })
+        .collect()
+}
+
+impl std::str::FromStr for ShellHistoryFormat {
+    type Err = CoreError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Self::from_name(name).ok_or_else(|| {
+            CoreError::Config(format!(
+                "unknown history format \"{}\" (expected bash, zsh, fish or atuin)",
+                name
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(imported: &[ImportedCommand]) -> Vec<&str> {
+        imported.iter().map(|c| c.entry.command.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_bash() {
+        let now = Utc::now();
+        let plain = parse_bash("ls\n\ncd /tmp\nls\n", now);
+        assert_eq!(commands(&plain), ["ls", "cd /tmp", "ls"]);
+        assert!(!plain[0].timestamped);
+        assert!(plain[0].entry.timestamp < plain[2].entry.timestamp);
+        assert_eq!(plain[2].entry.timestamp, now);
+
+        let timed = parse_bash(
+            "#1700000000\ngit status\n#1700000060\nfor f in *; do\n  echo $f\ndone\n",
+            now,
+        );
+        assert_eq!(
+            commands(&timed),
+            ["git status", "for f in *; do\n  echo $f\ndone"]
+        );
+        assert!(timed[1].timestamped);
+        assert_eq!(timed[1].entry.timestamp.timestamp(), 1_700_000_060);
+    }
+
+    #[test]
+    fn test_parse_zsh() {
+        let now = Utc::now();
+        let extended = parse_zsh(
+            b": 1700000000:0;git status\n: 1700000010:12;cargo build \\\n--release\n",
+            now,
+        );
+        assert_eq!(
+            commands(&extended),
+            ["git status", "cargo build \n--release"]
+        );
+        assert_eq!(extended[1].entry.timestamp.timestamp(), 1_700_000_010);
+        assert_eq!(extended[1].entry.duration_ms, Some(12_000));
+
+        let plain = parse_zsh(b"ls\nmake\n", now);
+        assert_eq!(commands(&plain), ["ls", "make"]);
+        assert!(!plain[0].timestamped);
+
+        // "ő" is 0xC5 0x91, and zsh metafies 0x91
+        let metafied = parse_zsh(b": 1700000000:0;echo \xc5\x83\xb1\n", now);
+        assert_eq!(commands(&metafied), ["echo \u{151}"]);
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let history = "- cmd: echo hello\\nworld\n  when: 1700000000\n  paths:\n    - world\n\
+                       - cmd: ls C:\\\\temp\n  when: 1700000005\n";
+        let imported = parse_fish(history, Utc::now());
+        assert_eq!(commands(&imported), ["echo hello\nworld", "ls C:\\temp"]);
+        assert_eq!(imported[1].entry.timestamp.timestamp(), 1_700_000_005);
+        assert!(imported[1].timestamped);
+    }
+
+    #[test]
+    fn test_read_atuin() {
+        let path =
+            std::env::temp_dir().join(format!("corgiterm-atuin-{}.db", uuid::Uuid::new_v4()));
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE history (id TEXT PRIMARY KEY, timestamp INTEGER, duration INTEGER,
+                     exit INTEGER, command TEXT, cwd TEXT, session TEXT, hostname TEXT,
+                     deleted_at INTEGER);
+                 INSERT INTO history VALUES
+                     ('a', 1700000000000000000, 2500000000, 1, 'make test', '/src', 's1',
+                      'build-box-9f3a:ci', NULL),
+                     ('b', 1700000001000000000, -1, -1, 'sleep 100', '/src', 's1',
+                      'build-box-9f3a:ci', NULL),
+                     ('c', 1700000002000000000, 1000, 0, 'rm secret', '/', 's1',
+                      'build-box-9f3a:ci', 1700000003000000000);",
+            )
+            .unwrap();
+        }
+        let imported = read_atuin(&path).unwrap();
+        assert_eq!(commands(&imported), ["make test", "sleep 100"]);
+        let make = &imported[0].entry;
+        assert_eq!(make.timestamp.timestamp(), 1_700_000_000);
+        assert_eq!(make.duration_ms, Some(2500));
+        assert_eq!(make.exit_code, Some(1));
+        assert_eq!(make.cwd, PathBuf::from("/src"));
+        assert_eq!(make.host.as_deref(), Some("build-box-9f3a"));
+        assert_eq!(imported[1].entry.exit_code, None);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_import_skips_duplicates() {
+        let db = HistoryDb::open_in_memory().unwrap();
+        let now = Utc::now();
+        let zsh = parse_zsh(b": 1700000000:0;git status\n: 1700000005:0;ls\n", now);
+        let zsh_path = Path::new("/home/me/.zsh_history");
+        assert_eq!(db.import(zsh_path, &zsh).unwrap(), 2);
+        assert_eq!(db.import(zsh_path, &zsh).unwrap(), 0);
+
+        // The same run seen by bash is skipped, the one after it is not
+        let bash = parse_bash("#1700000000\ngit status\n#1700000100\ngit status\n", now);
+        let bash_path = Path::new("/home/me/.bash_history");
+        assert_eq!(db.import(bash_path, &bash).unwrap(), 1);
+
+        // Without timestamps every line counts, repeats included
+        let plain_path = Path::new("/root/.bash_history");
+        let plain = parse_bash("ls\nls\npwd\n", now);
+        assert_eq!(db.import(plain_path, &plain).unwrap(), 3);
+        assert_eq!(db.import(plain_path, &plain).unwrap(), 0);
+        // Lines appended since, after bash trimmed the oldest
+        let later = parse_bash("ls\npwd\nls\nls\n", now + Duration::seconds(60));
+        assert_eq!(db.import(plain_path, &later).unwrap(), 2);
+        assert_eq!(db.len().unwrap(), 8);
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(
+            "zsh".parse::<ShellHistoryFormat>().unwrap(),
+            ShellHistoryFormat::Zsh
+        );
+        assert_eq!(
+            ShellHistoryFormat::from_name("Atuin"),
+            Some(ShellHistoryFormat::Atuin)
+        );
+        assert!("tcsh".parse::<ShellHistoryFormat>().is_err());
+    }
+}
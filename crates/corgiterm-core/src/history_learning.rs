@@ -4,8 +4,11 @@
 //! automatically feeding history into the learning engine.
 
 use crate::history::{CommandEntry, CommandHistory};
+use crate::history_db::HistoryDb;
 use crate::learning::CommandLearning;
+use crate::prediction::git_branch;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Combined history and learning manager
 pub struct HistoryLearningManager {
@@ -33,8 +36,18 @@ impl HistoryLearningManager {
         self
     }
 
+    /// Keep history in a shared history database
+    pub fn with_database(mut self, db: Arc<HistoryDb>) -> Self {
+        self.history = self.history.with_database(db);
+        self
+    }
+
     /// Add a command to both history and learning
-    pub fn add_command(&mut self, entry: CommandEntry) {
+    pub fn add_command(&mut self, mut entry: CommandEntry) {
+        if entry.git_branch.is_none() && entry.host.is_none() {
+            entry.git_branch = git_branch(&entry.cwd);
+        }
+
         // Add to history
         self.history.push(entry.clone());
 
@@ -46,19 +59,10 @@ impl HistoryLearningManager {
 
     /// Complete a command (update exit code and duration)
     pub fn complete_command(&mut self, command: &str, exit_code: i32, duration_ms: u64) {
-        // Find the most recent matching command in history
-        if let Some(recent) = self
-            .history
-            .recent(10)
-            .into_iter()
-            .find(|e| e.command == command)
-        {
-            let mut updated = recent.clone();
-            updated.complete(exit_code, duration_ms);
-
+        if let Some(updated) = self.history.complete(command, exit_code, duration_ms) {
             // Re-add to learning with completion data
             if self.learning_enabled {
-                self.learning.add_command(updated);
+                self.learning.add_command(updated.clone());
             }
         }
     }
@@ -214,6 +218,19 @@ mod tests {
         assert_eq!(manager.history().recent(10).len(), 1);
     }
 
+    #[test]
+    fn test_database_history() {
+        let db = Arc::new(HistoryDb::open_in_memory().unwrap());
+        let mut manager = HistoryLearningManager::new(1000, 100, true).with_database(db.clone());
+        manager.add_command(CommandEntry::new("cargo test", PathBuf::from("/src")));
+        manager.complete_command("cargo test", 0, 1200);
+        assert_eq!(db.recent(1).unwrap()[0].success, Some(true));
+
+        let mut reloaded = HistoryLearningManager::new(1000, 100, true).with_database(db);
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.history().recent(10)[0].duration_ms, Some(1200));
+    }
+
     #[test]
     fn test_disable_learning() {
         let mut manager = HistoryLearningManager::new(1000, 100, true);
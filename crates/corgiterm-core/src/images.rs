@@ -2,8 +2,25 @@
 //!
 //! Provides support for displaying images inline within terminal output.
 //! Implements both the Kitty Graphics Protocol (primary) and Sixel (legacy).
+//!
+//! The engine finds graphics sequences with [`GraphicsScanner`] (alacritty
+//! drops APC strings and unknown DCS sequences) and applies them to an
+//! [`ImageStore`] with the cursor position at the end of the sequence.
+//! Placements are anchored to buffer lines, so images scroll into history
+//! with the text around them.
 
 use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Longest APC/DCS string the scanner keeps; longer ones are dropped
+const MAX_GRAPHICS_LEN: usize = 32 * 1024 * 1024;
+
+/// Largest decoded image (RGBA bytes)
+const MAX_IMAGE_BYTES: usize = 128 * 1024 * 1024;
+
+/// Decoded image data an [`ImageStore`] keeps before dropping the oldest
+const MAX_STORE_BYTES: usize = 320 * 1024 * 1024;
 
 /// Unique identifier for inline images
 pub type ImageId = u32;
@@ -13,6 +30,8 @@ pub type ImageId = u32;
 pub enum ImageFormat {
     /// Raw RGBA pixel data (32-bit)
     Rgba,
+    /// Raw RGB pixel data (24-bit)
+    Rgb,
     /// PNG encoded image
     Png,
     /// Sixel format
@@ -39,7 +58,7 @@ pub struct InlineImage {
     pub data: Vec<u8>,
     /// Image width in pixels
     pub width: u32,
-    /// Image height in u32
+    /// Image height in pixels
     pub height: u32,
     /// Display width in cells (0 = auto)
     pub cell_width: u32,
@@ -58,7 +77,8 @@ pub struct InlineImage {
 pub struct ImagePlacement {
     /// Image ID to display
     pub image_id: ImageId,
-    /// Row position (top)
+    /// Buffer line of the top row (scrollback history plus screen, oldest
+    /// first)
     pub row: usize,
     /// Column position (left)
     pub col: usize,
@@ -130,10 +150,17 @@ impl KittyParser {
             }
         }
 
-        // Decode base64 payload if present
+        // Decode base64 payload if present; padding is optional
         let payload = payload_str.and_then(|p| {
-            use base64::{engine::general_purpose::STANDARD, Engine};
-            STANDARD.decode(p.as_bytes()).ok()
+            use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+            use base64::engine::DecodePaddingMode;
+            use base64::{alphabet, Engine};
+            const LENIENT: GeneralPurpose = GeneralPurpose::new(
+                &alphabet::STANDARD,
+                GeneralPurposeConfig::new()
+                    .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+            );
+            LENIENT.decode(p.as_bytes()).ok()
         });
 
         // Build command
@@ -149,7 +176,7 @@ impl KittyParser {
         };
 
         let format = match self.get_int('f').unwrap_or(32) {
-            24 => ImageFormat::Rgba, // RGB
+            24 => ImageFormat::Rgb,
             32 => ImageFormat::Rgba, // RGBA
             100 => ImageFormat::Png,
             _ => ImageFormat::Rgba,
@@ -169,6 +196,10 @@ impl KittyParser {
             more_data: self.get_int('m') == Some(1),
             placement_id: self.get_int('p'),
             quiet: self.get_int('q'),
+            medium: self.get_char('t').unwrap_or('d'),
+            compressed: self.get_char('o') == Some('z'),
+            delete: self.get_char('d'),
+            hold_cursor: self.get_int('C') == Some(1),
             payload,
         })
     }
@@ -211,6 +242,15 @@ pub struct KittyCommand {
     pub placement_id: Option<u32>,
     /// Quiet mode (0=normal, 1=no OK, 2=no errors)
     pub quiet: Option<u32>,
+    /// Transmission medium: `d` direct, `f` file, `t` temporary file
+    pub medium: char,
+    /// Payload is zlib compressed (`o=z`)
+    pub compressed: bool,
+    /// What `a=d` deletes: `a` all placements, `i` by image ID; uppercase also
+    /// frees the image data
+    pub delete: Option<char>,
+    /// Leave the cursor where it is after placing the image (`C=1`)
+    pub hold_cursor: bool,
     /// Image data payload (decoded from base64)
     pub payload: Option<Vec<u8>>,
 }
@@ -467,6 +507,37 @@ pub struct ImageStore {
     next_id: ImageId,
     /// Partial image data being assembled
     partial_data: HashMap<ImageId, Vec<u8>>,
+    /// Kitty image IDs (`i=`) chosen by programs, to store IDs
+    kitty_ids: HashMap<u32, ImageId>,
+    /// First chunk of a kitty transmission still receiving data (`m=1`)
+    pending_kitty: Option<KittyCommand>,
+}
+
+/// What the terminal should do after a graphics command
+#[derive(Debug, Clone, Default)]
+pub struct GraphicsOutcome {
+    /// The new placement, already added to the store
+    pub placement: Option<ImagePlacement>,
+    /// Move the cursor past the placement
+    pub move_cursor: bool,
+    /// Response to write back to the program
+    pub reply: Option<Vec<u8>>,
+}
+
+/// A failed kitty command, reported as `CODE:message`
+#[derive(Debug)]
+struct KittyError {
+    code: &'static str,
+    message: String,
+}
+
+impl KittyError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
 }
 
 impl ImageStore {
@@ -497,6 +568,11 @@ impl ImageStore {
         self.placements.push(placement);
     }
 
+    /// No images are placed
+    pub fn is_empty(&self) -> bool {
+        self.placements.is_empty()
+    }
+
     /// Get all visible placements
     pub fn visible_placements(&self) -> impl Iterator<Item = &ImagePlacement> {
         self.placements.iter().filter(|p| p.visible)
@@ -538,6 +614,232 @@ impl ImageStore {
         self.images.clear();
         self.placements.clear();
         self.partial_data.clear();
+        self.kitty_ids.clear();
+        self.pending_kitty = None;
+    }
+
+    /// Apply a kitty graphics command with the cursor at buffer `line`, `col`
+    ///
+    /// `cell_size` is the cell size in pixels, used to size placements that
+    /// do not give their size in cells.
+    pub fn apply_kitty(
+        &mut self,
+        cmd: KittyCommand,
+        line: usize,
+        col: usize,
+        cell_size: (u32, u32),
+    ) -> GraphicsOutcome {
+        let Some(cmd) = self.assemble_kitty(cmd) else {
+            return GraphicsOutcome::default();
+        };
+        let result = match cmd.action {
+            KittyAction::Query => decode_kitty(&cmd).map(|_| None),
+            KittyAction::Transmit | KittyAction::TransmitAndDisplay => {
+                decode_kitty(&cmd).map(|(width, height, data)| {
+                    let id = self.next_id();
+                    self.add_image(InlineImage {
+                        id,
+                        data,
+                        width,
+                        height,
+                        cell_width: cmd.cell_width.unwrap_or(0),
+                        cell_height: cmd.cell_height.unwrap_or(0),
+                        x_offset: cmd.x_offset.unwrap_or(0),
+                        y_offset: cmd.y_offset.unwrap_or(0),
+                        z_index: cmd.z_index.unwrap_or(0),
+                    });
+                    // Retransmitting an ID replaces the image
+                    if let Some(replaced) =
+                        cmd.id.and_then(|client| self.kitty_ids.insert(client, id))
+                    {
+                        self.remove_image(replaced);
+                    }
+                    matches!(cmd.action, KittyAction::TransmitAndDisplay).then_some(id)
+                })
+            }
+            KittyAction::Display => cmd
+                .id
+                .and_then(|client| self.kitty_ids.get(&client).copied())
+                .map(Some)
+                .ok_or_else(|| KittyError::new("ENOENT", "no such image")),
+            KittyAction::Delete => {
+                self.delete_kitty(&cmd);
+                return GraphicsOutcome::default();
+            }
+            KittyAction::Animate | KittyAction::Compose => {
+                Err(KittyError::new("EINVAL", "unsupported action"))
+            }
+        };
+
+        let mut outcome = GraphicsOutcome::default();
+        let error = match result {
+            Ok(Some(id)) => {
+                outcome.placement =
+                    self.place(id, line, col, (cmd.cell_width, cmd.cell_height), cell_size);
+                outcome.move_cursor = !cmd.hold_cursor;
+                None
+            }
+            Ok(None) => None,
+            Err(error) => Some(error),
+        };
+        outcome.reply = kitty_reply(&cmd, error.as_ref());
+        self.prune();
+        outcome
+    }
+
+    /// Place a decoded sixel image with its top left corner at buffer `line`,
+    /// `col`
+    pub fn place_sixel(
+        &mut self,
+        image: SixelImage,
+        line: usize,
+        col: usize,
+        cell_size: (u32, u32),
+    ) -> Option<ImagePlacement> {
+        if image.width == 0 || image.height == 0 {
+            return None;
+        }
+        let id = self.next_id();
+        self.add_image(InlineImage {
+            id,
+            data: image.data,
+            width: image.width,
+            height: image.height,
+            cell_width: 0,
+            cell_height: 0,
+            x_offset: 0,
+            y_offset: 0,
+            z_index: 0,
+        });
+        let placement = self.place(id, line, col, (None, None), cell_size);
+        self.prune();
+        placement
+    }
+
+    /// Forget the first `lines` buffer lines, which left the top of the
+    /// history, and move the remaining placements up
+    pub(crate) fn evict(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.placements.retain(|p| p.row >= lines);
+        for placement in &mut self.placements {
+            placement.row -= lines;
+        }
+        self.prune();
+    }
+
+    /// Move every placement's line through `map`, dropping those that are gone
+    pub(crate) fn remap(&mut self, map: impl Fn(usize) -> Option<usize>) {
+        self.placements.retain_mut(|p| match map(p.row) {
+            Some(row) => {
+                p.row = row;
+                true
+            }
+            None => false,
+        });
+        self.prune();
+    }
+
+    /// Join chunked transmissions; `None` while more data is expected
+    fn assemble_kitty(&mut self, cmd: KittyCommand) -> Option<KittyCommand> {
+        let cmd = match self.pending_kitty.take() {
+            Some(mut first) => {
+                if let Some(payload) = cmd.payload {
+                    first.payload.get_or_insert_with(Vec::new).extend(payload);
+                }
+                first.more_data = cmd.more_data;
+                first
+            }
+            None => cmd,
+        };
+        if cmd.more_data {
+            if cmd.payload.as_ref().map_or(0, Vec::len) <= MAX_IMAGE_BYTES {
+                self.pending_kitty = Some(cmd);
+            }
+            return None;
+        }
+        Some(cmd)
+    }
+
+    fn delete_kitty(&mut self, cmd: &KittyCommand) {
+        let target = cmd.delete.unwrap_or('a');
+        let free = target.is_ascii_uppercase();
+        match target.to_ascii_lowercase() {
+            'a' => {
+                self.placements.clear();
+                if free {
+                    self.kitty_ids.clear();
+                }
+            }
+            'i' => {
+                let Some(client) = cmd.id else {
+                    return;
+                };
+                let id = if free {
+                    self.kitty_ids.remove(&client)
+                } else {
+                    self.kitty_ids.get(&client).copied()
+                };
+                if let Some(id) = id {
+                    self.placements.retain(|p| p.image_id != id);
+                }
+            }
+            // Deleting by position, column, row or z-index is not supported
+            _ => {}
+        }
+        self.prune();
+    }
+
+    /// Add a placement of image `id`, sized in cells by `cells` or else by the
+    /// image's pixel size
+    fn place(
+        &mut self,
+        id: ImageId,
+        line: usize,
+        col: usize,
+        cells: (Option<u32>, Option<u32>),
+        cell_size: (u32, u32),
+    ) -> Option<ImagePlacement> {
+        let image = self.images.get(&id)?;
+        let width_cells = cells
+            .0
+            .filter(|&cells| cells > 0)
+            .unwrap_or_else(|| image.width.div_ceil(cell_size.0.max(1)));
+        let height_cells = cells
+            .1
+            .filter(|&cells| cells > 0)
+            .unwrap_or_else(|| image.height.div_ceil(cell_size.1.max(1)));
+        let placement = ImagePlacement {
+            image_id: id,
+            row: line,
+            col,
+            width_cells: width_cells.max(1) as usize,
+            height_cells: height_cells.max(1) as usize,
+            visible: true,
+        };
+        self.add_placement(placement.clone());
+        Some(placement)
+    }
+
+    /// Drop images nothing can show any more, then the oldest images while
+    /// over the memory budget
+    fn prune(&mut self) {
+        let placed: std::collections::HashSet<ImageId> =
+            self.placements.iter().map(|p| p.image_id).collect();
+        let named: std::collections::HashSet<ImageId> = self.kitty_ids.values().copied().collect();
+        self.images
+            .retain(|id, _| placed.contains(id) || named.contains(id));
+
+        let mut total: usize = self.images.values().map(|image| image.data.len()).sum();
+        while total > MAX_STORE_BYTES {
+            let Some(&oldest) = self.images.keys().min() else {
+                break;
+            };
+            total -= self.images[&oldest].data.len();
+            self.remove_image(oldest);
+            self.kitty_ids.retain(|_, id| *id != oldest);
+        }
     }
 
     /// Clear images outside visible area (garbage collection)
@@ -553,6 +855,255 @@ impl ImageStore {
     }
 }
 
+/// Decode a kitty transmission into `(width, height, RGBA pixels)`
+fn decode_kitty(cmd: &KittyCommand) -> Result<(u32, u32, Vec<u8>), KittyError> {
+    let payload = cmd.payload.clone().unwrap_or_default();
+    let data = match cmd.medium {
+        'd' => payload,
+        'f' | 't' => {
+            let path = String::from_utf8(payload)
+                .map_err(|_| KittyError::new("EINVAL", "file name is not UTF-8"))?;
+            read_transmitted_file(Path::new(&path), cmd.medium == 't')?
+        }
+        _ => return Err(KittyError::new("EINVAL", "unsupported transmission medium")),
+    };
+    let data = if cmd.compressed {
+        let mut inflated = Vec::new();
+        flate2::read::ZlibDecoder::new(data.as_slice())
+            .take(MAX_IMAGE_BYTES as u64 + 1)
+            .read_to_end(&mut inflated)
+            .map_err(|e| KittyError::new("EINVAL", format!("bad zlib data: {}", e)))?;
+        inflated
+    } else {
+        data
+    };
+
+    let raw = |bytes_per_pixel: usize| -> Result<(u32, u32, usize), KittyError> {
+        let (Some(width), Some(height)) = (cmd.width, cmd.height) else {
+            return Err(KittyError::new("EINVAL", "missing image size"));
+        };
+        let len = width as usize * height as usize * bytes_per_pixel;
+        if width as usize * height as usize * 4 > MAX_IMAGE_BYTES {
+            return Err(KittyError::new("EFBIG", "image too large"));
+        }
+        if data.len() < len {
+            return Err(KittyError::new("ENODATA", "insufficient image data"));
+        }
+        Ok((width, height, len))
+    };
+    match cmd.format {
+        ImageFormat::Rgba => {
+            let (width, height, len) = raw(4)?;
+            Ok((width, height, data[..len].to_vec()))
+        }
+        ImageFormat::Rgb => {
+            let (width, height, len) = raw(3)?;
+            let rgba = data[..len]
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect();
+            Ok((width, height, rgba))
+        }
+        ImageFormat::Png => {
+            let image = image::load_from_memory_with_format(&data, image::ImageFormat::Png)
+                .map_err(|e| KittyError::new("EBADPNG", e.to_string()))?
+                .to_rgba8();
+            if image.as_raw().len() > MAX_IMAGE_BYTES {
+                return Err(KittyError::new("EFBIG", "image too large"));
+            }
+            Ok((image.width(), image.height(), image.into_raw()))
+        }
+        ImageFormat::Sixel => Err(KittyError::new("EINVAL", "unsupported format")),
+    }
+}
+
+/// Read an image sent by file name (`t=f`) or temporary file (`t=t`)
+fn read_transmitted_file(path: &Path, temporary: bool) -> Result<Vec<u8>, KittyError> {
+    let bad_file = |message: &str| KittyError::new("EBADF", message);
+    let path = path
+        .canonicalize()
+        .map_err(|e| KittyError::new("EBADF", e.to_string()))?;
+    let metadata = std::fs::metadata(&path).map_err(|e| bad_file(&e.to_string()))?;
+    if !metadata.is_file() {
+        return Err(bad_file("not a regular file"));
+    }
+    // Never read kernel or device files
+    if ["/proc", "/sys", "/dev"]
+        .iter()
+        .any(|dir| path.starts_with(dir))
+    {
+        return Err(bad_file("not a regular file"));
+    }
+    // Temporary files are deleted after reading, so they must look like one
+    if temporary {
+        let in_temp_dir = path.parent().is_some_and(|parent| {
+            [std::env::temp_dir(), PathBuf::from("/tmp")]
+                .iter()
+                .filter_map(|dir| dir.canonicalize().ok())
+                .any(|dir| dir == parent)
+        });
+        let named = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().contains("tty-graphics-protocol"));
+        if !in_temp_dir || !named {
+            return Err(bad_file("not a temporary graphics file"));
+        }
+    }
+    if metadata.len() > MAX_IMAGE_BYTES as u64 {
+        return Err(KittyError::new("EFBIG", "file too large"));
+    }
+    let data = std::fs::read(&path).map_err(|e| bad_file(&e.to_string()))?;
+    if temporary {
+        let _ = std::fs::remove_file(&path);
+    }
+    Ok(data)
+}
+
+/// `ESC _ G i=<id> ; OK ESC \` or the error, as `q=` allows; programs that do
+/// not give an image ID get no reply
+fn kitty_reply(cmd: &KittyCommand, error: Option<&KittyError>) -> Option<Vec<u8>> {
+    let id = cmd.id?;
+    let quiet = cmd.quiet.unwrap_or(0);
+    let message = match error {
+        None if quiet >= 1 => return None,
+        None => "OK".to_string(),
+        Some(_) if quiet >= 2 => return None,
+        Some(error) => format!("{}:{}", error.code, error.message),
+    };
+    let mut reply = format!("\x1b_Gi={}", id);
+    if let Some(placement) = cmd.placement_id {
+        reply.push_str(&format!(",p={}", placement));
+    }
+    reply.push_str(&format!(";{}\x1b\\", message));
+    Some(reply.into_bytes())
+}
+
+/// Which graphics protocol a [`GraphicsSequence`] belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    /// `ESC _ G ... ESC \`; the payload starts after the `G`
+    Kitty,
+    /// `ESC P ... q ... ESC \`; the payload starts after the `P`
+    Sixel,
+}
+
+/// A complete graphics sequence found by [`GraphicsScanner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphicsSequence {
+    /// Offset just past the sequence's terminator in the scanned chunk
+    pub end: usize,
+    pub protocol: GraphicsProtocol,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Apc,
+    Dcs,
+    ApcEscape,
+    DcsEscape,
+}
+
+/// Picks kitty graphics (APC) and sixel (DCS) sequences out of a PTY byte
+/// stream
+///
+/// Like [`OscScanner`](crate::shell_integration::OscScanner), sequences split
+/// across chunks are reassembled and the bytes must still be fed to the real
+/// parser.
+#[derive(Debug, Default)]
+pub struct GraphicsScanner {
+    state: ScanState,
+    payload: Vec<u8>,
+    overflowed: bool,
+}
+
+impl GraphicsScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan the next chunk of output, returning the sequences it completes
+    pub fn scan(&mut self, bytes: &[u8]) -> Vec<GraphicsSequence> {
+        let mut found = Vec::new();
+        for (i, &b) in bytes.iter().enumerate() {
+            self.state = match (self.state, b) {
+                (ScanState::Ground, 0x1b) => ScanState::Escape,
+                (ScanState::Ground, _) => ScanState::Ground,
+                (ScanState::Escape, b'_') => self.start(ScanState::Apc),
+                (ScanState::Escape, b'P') => self.start(ScanState::Dcs),
+                (ScanState::Escape, 0x1b) => ScanState::Escape,
+                (ScanState::Escape, _) => ScanState::Ground,
+                (ScanState::Apc, 0x1b) => ScanState::ApcEscape,
+                (ScanState::Dcs, 0x1b) => ScanState::DcsEscape,
+                // CAN / SUB abort the sequence
+                (ScanState::Apc | ScanState::Dcs, 0x18 | 0x1a) => ScanState::Ground,
+                (state @ (ScanState::Apc | ScanState::Dcs), _) => {
+                    if self.payload.len() < MAX_GRAPHICS_LEN {
+                        self.payload.push(b);
+                    } else {
+                        self.overflowed = true;
+                    }
+                    state
+                }
+                // ST terminator
+                (ScanState::ApcEscape, b'\\') => {
+                    self.finish(i + 1, ScanState::Apc, &mut found);
+                    ScanState::Ground
+                }
+                (ScanState::DcsEscape, b'\\') => {
+                    self.finish(i + 1, ScanState::Dcs, &mut found);
+                    ScanState::Ground
+                }
+                // Any other escape abandons the string and starts a new sequence
+                (ScanState::ApcEscape | ScanState::DcsEscape, b'_') => self.start(ScanState::Apc),
+                (ScanState::ApcEscape | ScanState::DcsEscape, b'P') => self.start(ScanState::Dcs),
+                (ScanState::ApcEscape | ScanState::DcsEscape, _) => ScanState::Ground,
+            };
+        }
+        found
+    }
+
+    fn start(&mut self, state: ScanState) -> ScanState {
+        self.payload.clear();
+        self.overflowed = false;
+        state
+    }
+
+    fn finish(&mut self, end: usize, state: ScanState, found: &mut Vec<GraphicsSequence>) {
+        let payload = std::mem::take(&mut self.payload);
+        if self.overflowed {
+            return;
+        }
+        let protocol = match state {
+            ScanState::Apc if payload.first() == Some(&b'G') => GraphicsProtocol::Kitty,
+            ScanState::Dcs if is_sixel(&payload) => GraphicsProtocol::Sixel,
+            _ => return,
+        };
+        let payload = match protocol {
+            GraphicsProtocol::Kitty => payload[1..].to_vec(),
+            GraphicsProtocol::Sixel => payload,
+        };
+        found.push(GraphicsSequence {
+            end,
+            protocol,
+            payload,
+        });
+    }
+}
+
+/// Sixel DCS strings are `P1;P2;P3 q data`; other DCS sequences (DECRQSS,
+/// tmux passthrough) have other introducers
+fn is_sixel(payload: &[u8]) -> bool {
+    payload.iter().position(|&b| b == b'q').is_some_and(|q| {
+        payload[..q]
+            .iter()
+            .all(|&b| b.is_ascii_digit() || b == b';')
+    })
+}
+
 // Helper functions
 
 fn parse_number(data: &[u8]) -> (u32, usize) {
@@ -663,4 +1214,139 @@ mod tests {
         store.remove_image(id);
         assert!(store.get_image(id).is_none());
     }
+
+    fn kitty(store: &mut ImageStore, command: &str) -> GraphicsOutcome {
+        let cmd = KittyParser::new().parse(command.as_bytes()).unwrap();
+        store.apply_kitty(cmd, 5, 3, (10, 20))
+    }
+
+    fn encoded(bytes: &[u8]) -> String {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn test_kitty_transmit_and_display() {
+        let mut store = ImageStore::new();
+        let pixels = encoded(&[255; 20 * 40 * 4]);
+        let outcome = kitty(&mut store, &format!("a=T,f=32,s=20,v=40,i=7;{}", pixels));
+
+        let placement = outcome.placement.unwrap();
+        assert_eq!((placement.row, placement.col), (5, 3));
+        assert_eq!((placement.width_cells, placement.height_cells), (2, 2));
+        assert!(outcome.move_cursor);
+        assert_eq!(outcome.reply.unwrap(), b"\x1b_Gi=7;OK\x1b\\");
+        let image = store.get_image(placement.image_id).unwrap();
+        assert_eq!((image.width, image.height), (20, 40));
+
+        // Display again with an explicit size, quietly, cursor held
+        let outcome = kitty(&mut store, "a=p,i=7,c=4,r=1,q=1,C=1");
+        let again = outcome.placement.unwrap();
+        assert_eq!(again.image_id, placement.image_id);
+        assert_eq!((again.width_cells, again.height_cells), (4, 1));
+        assert!(!outcome.move_cursor);
+        assert!(outcome.reply.is_none());
+        assert_eq!(store.placements_at(5, 6).len(), 1);
+
+        // Deleting placements keeps the image for later display
+        kitty(&mut store, "a=d,d=i,i=7");
+        assert!(store.is_empty());
+        assert!(kitty(&mut store, "a=p,i=7").placement.is_some());
+        kitty(&mut store, "a=d,d=A");
+        assert!(store.get_image(placement.image_id).is_none());
+    }
+
+    #[test]
+    fn test_kitty_chunked_rgb_and_png() {
+        let mut store = ImageStore::new();
+        // RGB sent in two chunks; only the first carries the keys
+        let pixels = encoded(&[10, 20, 30].repeat(4));
+        let (first, rest) = pixels.split_at(8);
+        assert!(
+            kitty(&mut store, &format!("a=T,f=24,s=2,v=2,m=1;{}", first))
+                .placement
+                .is_none()
+        );
+        let outcome = kitty(&mut store, &format!("m=0;{}", rest));
+        let image = store
+            .get_image(outcome.placement.unwrap().image_id)
+            .unwrap();
+        assert_eq!(&image.data[..4], &[10, 20, 30, 255]);
+        // No image ID, no reply
+        assert!(outcome.reply.is_none());
+
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(3, 25, image::Rgba([0, 0, 255, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let outcome = kitty(&mut store, &format!("a=T,f=100,i=2;{}", encoded(&png)));
+        let placement = outcome.placement.unwrap();
+        assert_eq!((placement.width_cells, placement.height_cells), (1, 2));
+    }
+
+    #[test]
+    fn test_kitty_query_and_errors() {
+        let mut store = ImageStore::new();
+        let outcome = kitty(
+            &mut store,
+            &format!("a=q,i=31,s=1,v=1,f=24;{}", encoded(&[0, 0, 0])),
+        );
+        assert_eq!(outcome.reply.unwrap(), b"\x1b_Gi=31;OK\x1b\\");
+        assert!(outcome.placement.is_none());
+        assert!(store.images.is_empty());
+
+        let reply = kitty(&mut store, "a=p,i=99").reply.unwrap();
+        assert!(reply.starts_with(b"\x1b_Gi=99;ENOENT:"));
+        let reply = kitty(&mut store, &format!("a=T,i=3,s=4,v=4;{}", encoded(&[0; 8])))
+            .reply
+            .unwrap();
+        assert!(reply.starts_with(b"\x1b_Gi=3;ENODATA:"));
+        // q=2 silences errors too
+        assert!(kitty(&mut store, "a=p,i=99,q=2").reply.is_none());
+    }
+
+    #[test]
+    fn test_sixel_placement() {
+        let mut store = ImageStore::new();
+        // Two bands of four red columns: 4x12 pixels
+        let image = SixelParser::new()
+            .parse(b"0;1;0q#1;2;100;0;0#1~~~~-~~~~")
+            .unwrap();
+        assert_eq!((image.width, image.height), (4, 12));
+        assert_eq!(&image.data[..4], &[255, 0, 0, 255]);
+
+        let placement = store.place_sixel(image, 8, 2, (3, 10)).unwrap();
+        assert_eq!((placement.row, placement.col), (8, 2));
+        assert_eq!((placement.width_cells, placement.height_cells), (2, 2));
+
+        // Lines leaving the top of the history move placements up
+        store.evict(3);
+        assert_eq!(store.placements_at(5, 2).len(), 1);
+        store.evict(6);
+        assert!(store.is_empty());
+        assert!(store.images.is_empty());
+    }
+
+    #[test]
+    fn test_scanner_finds_graphics_sequences() {
+        let mut scanner = GraphicsScanner::new();
+        let input = b"a\x1b_Ga=T,f=100;AAAA\x1b\\b\x1bP0;1q#0~\x1b\\c";
+        let found = scanner.scan(input);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].protocol, GraphicsProtocol::Kitty);
+        assert_eq!(found[0].payload, b"a=T,f=100;AAAA");
+        assert_eq!(&input[..found[0].end], b"a\x1b_Ga=T,f=100;AAAA\x1b\\");
+        assert_eq!(found[1].protocol, GraphicsProtocol::Sixel);
+        assert_eq!(found[1].payload, b"0;1q#0~");
+        assert_eq!(found[1].end, input.len() - 1);
+
+        // Split across chunks
+        assert!(scanner.scan(b"\x1bPq#0").is_empty());
+        let found = scanner.scan(b"~~\x1b\\");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].payload, b"q#0~~");
+
+        // DECRQSS and other APC strings are not graphics
+        assert!(scanner.scan(b"\x1bP$qm\x1b\\\x1b_other\x1b\\").is_empty());
+    }
 }
@@ -0,0 +1,408 @@
+//! Local IPC control socket
+//!
+//! A running CorgiTerm can listen on a Unix-domain socket (configured by
+//! `advanced.ipc_socket`) and accept JSON-RPC 2.0 requests, one JSON object per
+//! line. Scripts use it to drive the terminal the way `kitty @` drives kitty:
+//!
+//! ```text
+//! $ corgiterm msg new-tab --cwd ~/projects/api
+//! $ corgiterm msg send-text --newline "cargo test"
+//! $ corgiterm msg get-text
+//! ```
+//!
+//! This module owns the wire format and transport only. The socket is serviced
+//! on background threads and every request is handed to the application as an
+//! [`IpcCall`], so the UI can answer it on its own thread (GTK widgets are not
+//! `Send`).
+//!
+//! Methods understood by the UI:
+//!
+//! | Method           | Params                                   | Result              |
+//! |------------------|------------------------------------------|---------------------|
+//! | `ping`           |                                          | `{version}`         |
+//! | `tab.list`       |                                          | `[{index, title, kind, panes, active}]` |
+//! | `tab.new`        | `title?`, `cwd?`                         | `{index}`           |
+//! | `pane.split`     | `tab?`, `direction` (`horizontal`/`vertical`) | `{panes}`      |
+//! | `pane.send_text` | `tab?`, `pane?`, `text`, `newline?`      | `{sent, status}`    |
+//! | `pane.send_raw`  | `tab?`, `pane?`, `text`                  | `{sent}`            |
+//! | `pane.get_text`  | `tab?`, `pane?`                          | `{lines}`           |
+//! | `session.list`   |                                          | `[{id, name, path, sessions}]` |
+//!
+//! `tab` is the 0-based position in the tab bar and `pane` the 0-based pane
+//! index within that tab; both default to the focused one.
+//!
+//! `pane.send_text` takes a single line. Without `newline` it is only typed
+//! at the prompt; with it the line is run, after Safe Mode has judged it like
+//! any other command that was not typed (`status` is `sent`, `held` while it
+//! waits for confirmation, or `cancelled`). `pane.send_raw` writes the text
+//! unchecked, control characters included, and is refused unless
+//! `advanced.ipc_raw_input` is on.
+
+use crate::{CoreError, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Environment variable exported to shells spawned by an app that is listening
+pub const SOCKET_ENV: &str = "CORGITERM_SOCKET";
+
+/// Invalid JSON was received
+pub const PARSE_ERROR: i32 = -32700;
+/// The method does not exist
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// Invalid method parameters
+pub const INVALID_PARAMS: i32 = -32602;
+/// Internal error while handling the request
+pub const INTERNAL_ERROR: i32 = -32603;
+
+/// IPC JSON-RPC request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcRequest {
+    pub jsonrpc: String,
+    pub id: serde_json::Value,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+}
+
+impl IpcRequest {
+    pub fn new(id: u64, method: impl Into<String>, params: Option<serde_json::Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: id.into(),
+            method: method.into(),
+            params,
+        }
+    }
+
+    /// Get a string parameter
+    pub fn param_str(&self, name: &str) -> Option<&str> {
+        self.params.as_ref()?.get(name)?.as_str()
+    }
+
+    /// Get an unsigned integer parameter as an index
+    pub fn param_index(&self, name: &str) -> Option<usize> {
+        self.params
+            .as_ref()?
+            .get(name)?
+            .as_u64()
+            .map(|v| v as usize)
+    }
+
+    /// Get a boolean parameter, false when it is missing
+    pub fn param_bool(&self, name: &str) -> bool {
+        self.params
+            .as_ref()
+            .and_then(|params| params.get(name)?.as_bool())
+            .unwrap_or(false)
+    }
+}
+
+/// IPC JSON-RPC response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcResponse {
+    pub jsonrpc: String,
+    pub id: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<IpcError>,
+}
+
+impl IpcResponse {
+    fn new(
+        id: serde_json::Value,
+        outcome: std::result::Result<serde_json::Value, IpcError>,
+    ) -> Self {
+        let (result, error) = match outcome {
+            Ok(value) => (Some(value), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// IPC error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl IpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+/// A request received on the socket, waiting for the application to answer
+pub struct IpcCall {
+    pub request: IpcRequest,
+    reply: crossbeam_channel::Sender<IpcResponse>,
+}
+
+impl IpcCall {
+    /// Send the result back to the client
+    pub fn respond(self, outcome: std::result::Result<serde_json::Value, IpcError>) {
+        let _ = self
+            .reply
+            .send(IpcResponse::new(self.request.id.clone(), outcome));
+    }
+}
+
+/// Listening side of the control socket
+pub struct IpcServer {
+    path: PathBuf,
+    calls: crossbeam_channel::Receiver<IpcCall>,
+}
+
+impl IpcServer {
+    /// Bind the socket and start accepting connections in the background
+    ///
+    /// A leftover socket file from a crashed instance is replaced; a socket
+    /// that another instance is still listening on is an error.
+    pub fn bind(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let listener = bind_listener(&path)?;
+
+        let (call_tx, call_rx) = crossbeam_channel::unbounded();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let call_tx = call_tx.clone();
+                        std::thread::spawn(move || serve_connection(stream, call_tx));
+                    }
+                    Err(e) => tracing::warn!("IPC accept failed: {}", e),
+                }
+            }
+        });
+
+        tracing::info!("IPC socket listening on {}", path.display());
+        Ok(Self {
+            path,
+            calls: call_rx,
+        })
+    }
+
+    /// Socket path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Requests waiting to be answered
+    pub fn calls(&self) -> &crossbeam_channel::Receiver<IpcCall> {
+        &self.calls
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Bind a private Unix socket at `path`
+///
+/// A leftover socket file from a crashed instance is replaced; a socket
+/// that another instance is still listening on, or a path that is not our
+/// own socket, is an error. Clients can type into the user's shells, so the
+/// socket is bound inside a fresh owner-only directory, made owner-only and
+/// only then moved to `path`; it is never reachable with looser permissions.
+pub fn bind_listener(path: &Path) -> Result<UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    check_owner(path)?;
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(CoreError::Ipc(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(CoreError::Ipc(format!(
+                "Another instance is already listening on {}",
+                path.display()
+            )));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
+
+    let private = parent.join(format!(".corgiterm-bind-{}", uuid::Uuid::new_v4()));
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join("socket");
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    Ok(bound?)
+}
+
+/// Fail if `path` exists and belongs to another user
+///
+/// Sockets in shared directories such as `/tmp` could be planted by someone
+/// else to intercept requests; a missing path is fine.
+pub fn check_owner(path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.uid() != user_id() => Err(CoreError::Ipc(format!(
+            "{} belongs to another user",
+            path.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Effective user id of this process
+pub fn user_id() -> u32 {
+    nix::unistd::geteuid().as_raw()
+}
+
+/// Read newline-delimited requests from one client until it disconnects
+fn serve_connection(stream: UnixStream, calls: crossbeam_channel::Sender<IpcCall>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            tracing::warn!("IPC connection setup failed: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<IpcRequest>(&line) {
+            Ok(request) => {
+                let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+                let id = request.id.clone();
+                if calls
+                    .send(IpcCall {
+                        request,
+                        reply: reply_tx,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+                reply_rx.recv().unwrap_or_else(|_| {
+                    IpcResponse::new(
+                        id,
+                        Err(IpcError::new(INTERNAL_ERROR, "Request was dropped")),
+                    )
+                })
+            }
+            Err(e) => IpcResponse::new(
+                serde_json::Value::Null,
+                Err(IpcError::new(PARSE_ERROR, format!("Parse error: {}", e))),
+            ),
+        };
+
+        let Ok(mut encoded) = serde_json::to_string(&response) else {
+            break;
+        };
+        encoded.push('\n');
+        if writer.write_all(encoded.as_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+/// Send one request to a running instance and wait for its result
+pub fn send_request(
+    path: &Path,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> Result<serde_json::Value> {
+    check_owner(path)?;
+    let stream = UnixStream::connect(path)
+        .map_err(|e| CoreError::Ipc(format!("Cannot connect to {}: {}", path.display(), e)))?;
+
+    let mut encoded = serde_json::to_string(&IpcRequest::new(1, method, params))
+        .map_err(|e| CoreError::Ipc(e.to_string()))?;
+    encoded.push('\n');
+    (&stream).write_all(encoded.as_bytes())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(CoreError::Ipc(
+            "Connection closed without a response".to_string(),
+        ));
+    }
+
+    let response: IpcResponse =
+        serde_json::from_str(&line).map_err(|e| CoreError::Ipc(e.to_string()))?;
+    match (response.result, response.error) {
+        (_, Some(error)) => Err(CoreError::Ipc(format!(
+            "{} (code {})",
+            error.message, error.code
+        ))),
+        (Some(result), None) => Ok(result),
+        (None, None) => Ok(serde_json::Value::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_roundtrip() {
+        let request = IpcRequest::new(
+            7,
+            "pane.send_text",
+            Some(serde_json::json!({ "text": "ls", "pane": 1, "newline": true })),
+        );
+        let encoded = serde_json::to_string(&request).unwrap();
+        let decoded: IpcRequest = serde_json::from_str(&encoded).unwrap();
+
+        assert_eq!(decoded.method, "pane.send_text");
+        assert_eq!(decoded.param_str("text"), Some("ls"));
+        assert_eq!(decoded.param_index("pane"), Some(1));
+        assert_eq!(decoded.param_index("tab"), None);
+        assert!(decoded.param_bool("newline"));
+        assert!(!decoded.param_bool("raw"));
+    }
+
+    #[test]
+    fn test_response_omits_empty_fields() {
+        let ok = IpcResponse::new(1.into(), Ok(serde_json::json!({ "sent": true })));
+        let encoded = serde_json::to_string(&ok).unwrap();
+        assert!(!encoded.contains("error"));
+
+        let err = IpcResponse::new(1.into(), Err(IpcError::method_not_found("nope")));
+        let encoded = serde_json::to_string(&err).unwrap();
+        assert!(!encoded.contains("result"));
+        assert!(encoded.contains("-32601"));
+    }
+}
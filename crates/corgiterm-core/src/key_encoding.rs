@@ -0,0 +1,677 @@
+//! Keyboard input encoding: legacy xterm bytes and the kitty keyboard protocol
+//!
+//! Programs opt into the [kitty keyboard protocol] with `CSI > flags u`
+//! (push), `CSI < n u` (pop) and `CSI = flags ; mode u` (set), and query the
+//! active flags with `CSI ? u`. The engine tracks that negotiation (see
+//! [`TerminalEngine::keyboard_flags`](crate::TerminalEngine::keyboard_flags));
+//! [`encode_key`] turns a key event into the bytes the program expects under
+//! the negotiated [`KeyboardFlags`].
+//!
+//! With no flags, keys use the legacy encoding, where e.g. Ctrl+I and Tab are
+//! the same byte and key releases are not reported at all.
+//!
+//! [kitty keyboard protocol]: https://sw.kovidgoyal.net/kitty/keyboard-protocol/
+
+/// Progressive enhancement flags negotiated by the program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyboardFlags {
+    /// `0b1`: escape codes for keys that are ambiguous in the legacy encoding
+    pub disambiguate: bool,
+    /// `0b10`: report key repeat and release events
+    pub report_event_types: bool,
+    /// `0b100`: report the shifted and base layout keys
+    pub report_alternate_keys: bool,
+    /// `0b1000`: report every key as an escape code, text keys included
+    pub report_all_keys: bool,
+    /// `0b10000`: send the text a key types along with its escape code
+    pub report_associated_text: bool,
+}
+
+impl KeyboardFlags {
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            disambiguate: bits & 0b1 != 0,
+            report_event_types: bits & 0b10 != 0,
+            report_alternate_keys: bits & 0b100 != 0,
+            report_all_keys: bits & 0b1000 != 0,
+            report_associated_text: bits & 0b10000 != 0,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        self.disambiguate as u8
+            | (self.report_event_types as u8) << 1
+            | (self.report_alternate_keys as u8) << 2
+            | (self.report_all_keys as u8) << 3
+            | (self.report_associated_text as u8) << 4
+    }
+
+    /// No enhancement: keys use the legacy xterm encoding
+    pub fn is_legacy(self) -> bool {
+        self.bits() == 0
+    }
+}
+
+/// A key, independent of the toolkit that reported it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    /// A key that types a character, as its unshifted character (`a`, not `A`)
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// F1 to F35
+    F(u8),
+    /// Keypad 0 to 9
+    KeypadDigit(u8),
+    KeypadDecimal,
+    KeypadDivide,
+    KeypadMultiply,
+    KeypadSubtract,
+    KeypadAdd,
+    KeypadEnter,
+    KeypadEqual,
+    LeftShift,
+    LeftControl,
+    LeftAlt,
+    LeftSuper,
+    RightShift,
+    RightControl,
+    RightAlt,
+    RightSuper,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+}
+
+impl KeyCode {
+    /// Modifier and lock keys, which only have codes of their own when every
+    /// key is reported
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            Self::LeftShift
+                | Self::LeftControl
+                | Self::LeftAlt
+                | Self::LeftSuper
+                | Self::RightShift
+                | Self::RightControl
+                | Self::RightAlt
+                | Self::RightSuper
+                | Self::CapsLock
+                | Self::NumLock
+                | Self::ScrollLock
+        )
+    }
+}
+
+/// Modifiers held during a key event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+    pub super_key: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+impl KeyModifiers {
+    fn bits(self) -> u32 {
+        self.shift as u32
+            | (self.alt as u32) << 1
+            | (self.ctrl as u32) << 2
+            | (self.super_key as u32) << 3
+            | (self.caps_lock as u32) << 6
+            | (self.num_lock as u32) << 7
+    }
+
+    /// Ctrl, Alt or Super: the key is a command rather than text
+    fn has_command(self) -> bool {
+        self.alt || self.ctrl || self.super_key
+    }
+
+    fn without_locks(self) -> Self {
+        Self {
+            caps_lock: false,
+            num_lock: false,
+            ..self
+        }
+    }
+}
+
+/// Whether a key went down, auto-repeated or was let go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyEventKind {
+    #[default]
+    Press,
+    Repeat,
+    Release,
+}
+
+/// One key event to encode
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: Option<KeyCode>,
+    pub modifiers: KeyModifiers,
+    pub kind: KeyEventKind,
+    /// Text the key types with Shift and the layout applied, ignoring
+    /// Ctrl/Alt/Super (`"A"` for Shift+A and Ctrl+Shift+A alike)
+    pub text: Option<String>,
+    /// The character with Shift applied, when Shift is held
+    pub shifted: Option<char>,
+    /// The key at the same position on a US layout, when it differs
+    pub base_layout: Option<char>,
+}
+
+impl KeyEvent {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self {
+            code: Some(code),
+            modifiers,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    pub fn with_kind(mut self, kind: KeyEventKind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+/// Bytes for `event` under `flags`; empty when the key sends nothing
+pub fn encode_key(event: &KeyEvent, flags: KeyboardFlags) -> Vec<u8> {
+    let Some(code) = event.code else {
+        return Vec::new();
+    };
+    if flags.is_legacy() {
+        return match event.kind {
+            KeyEventKind::Release => Vec::new(),
+            _ => legacy(code, event),
+        };
+    }
+
+    let kind = if flags.report_event_types {
+        event.kind
+    } else if event.kind == KeyEventKind::Release {
+        return Vec::new();
+    } else {
+        KeyEventKind::Press
+    };
+    // Lock state only matters to programs that asked for every key
+    let modifiers = if flags.report_all_keys {
+        event.modifiers
+    } else {
+        event.modifiers.without_locks()
+    };
+
+    if !flags.report_all_keys {
+        if code.is_modifier() {
+            return Vec::new();
+        }
+        // Typing keeps sending text
+        if let Some(text) = &event.text {
+            if kind != KeyEventKind::Release && !modifiers.has_command() {
+                return text.as_bytes().to_vec();
+            }
+        }
+        // Enter, Tab and Backspace stay usable in a shell that enabled the
+        // protocol and crashed: plain presses keep their legacy bytes
+        if matches!(code, KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace) {
+            if kind == KeyEventKind::Release {
+                return Vec::new();
+            }
+            if modifiers.bits() == 0 {
+                return legacy(code, event);
+            }
+        }
+    }
+
+    csi(code, event, modifiers, kind, flags)
+}
+
+/// How a key is written as an escape code: `CSI number ; modifiers trailer`
+fn functional(code: KeyCode) -> (u32, char) {
+    match code {
+        KeyCode::Char(c) => (c as u32, 'u'),
+        KeyCode::Enter => (13, 'u'),
+        KeyCode::Tab => (9, 'u'),
+        KeyCode::Backspace => (127, 'u'),
+        KeyCode::Escape => (27, 'u'),
+        KeyCode::Up => (1, 'A'),
+        KeyCode::Down => (1, 'B'),
+        KeyCode::Right => (1, 'C'),
+        KeyCode::Left => (1, 'D'),
+        KeyCode::Home => (1, 'H'),
+        KeyCode::End => (1, 'F'),
+        KeyCode::PageUp => (5, '~'),
+        KeyCode::PageDown => (6, '~'),
+        KeyCode::Insert => (2, '~'),
+        KeyCode::Delete => (3, '~'),
+        KeyCode::F(1) => (1, 'P'),
+        KeyCode::F(2) => (1, 'Q'),
+        KeyCode::F(3) => (13, '~'),
+        KeyCode::F(4) => (1, 'S'),
+        KeyCode::F(n @ 5..=12) => (legacy_f_number(n), '~'),
+        KeyCode::F(n) => (57376 + n.saturating_sub(13) as u32, 'u'),
+        KeyCode::KeypadDigit(n) => (57399 + n.min(9) as u32, 'u'),
+        KeyCode::KeypadDecimal => (57409, 'u'),
+        KeyCode::KeypadDivide => (57410, 'u'),
+        KeyCode::KeypadMultiply => (57411, 'u'),
+        KeyCode::KeypadSubtract => (57412, 'u'),
+        KeyCode::KeypadAdd => (57413, 'u'),
+        KeyCode::KeypadEnter => (57414, 'u'),
+        KeyCode::KeypadEqual => (57415, 'u'),
+        KeyCode::CapsLock => (57358, 'u'),
+        KeyCode::ScrollLock => (57359, 'u'),
+        KeyCode::NumLock => (57360, 'u'),
+        KeyCode::LeftShift => (57441, 'u'),
+        KeyCode::LeftControl => (57442, 'u'),
+        KeyCode::LeftAlt => (57443, 'u'),
+        KeyCode::LeftSuper => (57444, 'u'),
+        KeyCode::RightShift => (57447, 'u'),
+        KeyCode::RightControl => (57448, 'u'),
+        KeyCode::RightAlt => (57449, 'u'),
+        KeyCode::RightSuper => (57450, 'u'),
+    }
+}
+
+fn legacy_f_number(n: u8) -> u32 {
+    match n {
+        5 => 15,
+        6 => 17,
+        7 => 18,
+        8 => 19,
+        9 => 20,
+        10 => 21,
+        11 => 23,
+        _ => 24,
+    }
+}
+
+fn csi(
+    code: KeyCode,
+    event: &KeyEvent,
+    modifiers: KeyModifiers,
+    kind: KeyEventKind,
+    flags: KeyboardFlags,
+) -> Vec<u8> {
+    let (number, trailer) = functional(code);
+
+    let mut key = number.to_string();
+    if flags.report_alternate_keys && trailer == 'u' {
+        let shifted = event
+            .shifted
+            .filter(|&c| modifiers.shift && c as u32 != number);
+        let base = event.base_layout.filter(|&c| c as u32 != number);
+        if let Some(shifted) = shifted {
+            key.push_str(&format!(":{}", shifted as u32));
+        }
+        if let Some(base) = base {
+            if shifted.is_none() {
+                key.push(':');
+            }
+            key.push_str(&format!(":{}", base as u32));
+        }
+    }
+
+    let mut params = String::new();
+    let kind_number = match kind {
+        KeyEventKind::Press => None,
+        KeyEventKind::Repeat => Some(2),
+        KeyEventKind::Release => Some(3),
+    };
+    let text = event
+        .text
+        .as_deref()
+        .filter(|_| {
+            flags.report_all_keys
+                && flags.report_associated_text
+                && kind != KeyEventKind::Release
+                && !modifiers.has_command()
+        })
+        .filter(|text| !text.is_empty());
+    if modifiers.bits() != 0 || kind_number.is_some() || text.is_some() {
+        params.push_str(&(modifiers.bits() + 1).to_string());
+        if let Some(kind_number) = kind_number {
+            params.push_str(&format!(":{}", kind_number));
+        }
+    }
+    if let Some(text) = text {
+        let codepoints: Vec<String> = text.chars().map(|c| (c as u32).to_string()).collect();
+        params.push(';');
+        params.push_str(&codepoints.join(":"));
+    }
+
+    // `CSI 1 X` drops the default 1 when nothing follows it
+    let mut out = String::from("\x1b[");
+    if params.is_empty() {
+        if trailer == 'u' || trailer == '~' || number != 1 {
+            out.push_str(&key);
+        }
+    } else {
+        out.push_str(&key);
+        out.push(';');
+        out.push_str(&params);
+    }
+    out.push(trailer);
+    out.into_bytes()
+}
+
+/// The classic xterm encoding
+fn legacy(code: KeyCode, event: &KeyEvent) -> Vec<u8> {
+    let modifiers = event.modifiers;
+    match code {
+        KeyCode::Enter | KeyCode::KeypadEnter => vec![b'\r'],
+        KeyCode::Backspace => vec![0x7f],
+        KeyCode::Tab if modifiers.shift => b"\x1b[Z".to_vec(),
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::Escape => vec![0x1b],
+        KeyCode::Up => b"\x1b[A".to_vec(),
+        KeyCode::Down => b"\x1b[B".to_vec(),
+        KeyCode::Right => b"\x1b[C".to_vec(),
+        KeyCode::Left => b"\x1b[D".to_vec(),
+        KeyCode::Home => b"\x1b[H".to_vec(),
+        KeyCode::End => b"\x1b[F".to_vec(),
+        KeyCode::PageUp => b"\x1b[5~".to_vec(),
+        KeyCode::PageDown => b"\x1b[6~".to_vec(),
+        KeyCode::Insert => b"\x1b[2~".to_vec(),
+        KeyCode::Delete => b"\x1b[3~".to_vec(),
+        KeyCode::F(n @ 1..=4) => vec![0x1b, b'O', b'P' + (n - 1)],
+        KeyCode::F(n @ 5..=12) => format!("\x1b[{}~", legacy_f_number(n)).into_bytes(),
+        KeyCode::Char(c) if modifiers.ctrl && c.is_ascii_alphabetic() => {
+            // Ctrl+letter: the control character
+            vec![c.to_ascii_lowercase() as u8 - b'a' + 1]
+        }
+        _ => event
+            .text
+            .as_deref()
+            .map(|text| text.as_bytes().to_vec())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISAMBIGUATE: u8 = 0b1;
+    const EVENT_TYPES: u8 = 0b10;
+    const ALTERNATE: u8 = 0b100;
+    const ALL_KEYS: u8 = 0b1000;
+    const TEXT: u8 = 0b10000;
+
+    fn ctrl() -> KeyModifiers {
+        KeyModifiers {
+            ctrl: true,
+            ..KeyModifiers::default()
+        }
+    }
+
+    fn shift() -> KeyModifiers {
+        KeyModifiers {
+            shift: true,
+            ..KeyModifiers::default()
+        }
+    }
+
+    fn plain(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::default())
+    }
+
+    fn letter(c: char, modifiers: KeyModifiers) -> KeyEvent {
+        let mut event = KeyEvent::new(KeyCode::Char(c), modifiers);
+        event.text = Some(if modifiers.shift {
+            c.to_ascii_uppercase().to_string()
+        } else {
+            c.to_string()
+        });
+        if modifiers.shift {
+            event.shifted = Some(c.to_ascii_uppercase());
+        }
+        event
+    }
+
+    fn encode(event: &KeyEvent, bits: u8) -> String {
+        String::from_utf8(encode_key(event, KeyboardFlags::from_bits(bits))).unwrap()
+    }
+
+    #[test]
+    fn test_flag_bits_round_trip() {
+        for bits in 0..32 {
+            assert_eq!(KeyboardFlags::from_bits(bits).bits(), bits);
+        }
+        assert!(KeyboardFlags::default().is_legacy());
+    }
+
+    #[test]
+    fn test_every_flag_combination() {
+        let shift_a = letter('a', shift());
+        let release = letter('a', KeyModifiers::default()).with_kind(KeyEventKind::Release);
+        for bits in 0..32 {
+            let legacy = bits == 0;
+            let all = bits & ALL_KEYS != 0;
+            let shift_a_bytes = if all {
+                format!(
+                    "\x1b[97{};2{}u",
+                    if bits & ALTERNATE != 0 { ":65" } else { "" },
+                    if bits & TEXT != 0 { ";65" } else { "" }
+                )
+            } else {
+                "A".to_string()
+            };
+            let cases = [
+                (plain(KeyCode::Tab), if all { "\x1b[9u" } else { "\t" }),
+                (
+                    letter('i', ctrl()),
+                    if legacy { "\t" } else { "\x1b[105;5u" },
+                ),
+                (plain(KeyCode::Enter), if all { "\x1b[13u" } else { "\r" }),
+                (
+                    plain(KeyCode::Escape),
+                    if legacy { "\x1b" } else { "\x1b[27u" },
+                ),
+                (shift_a.clone(), shift_a_bytes.as_str()),
+                (
+                    release.clone(),
+                    if bits & EVENT_TYPES != 0 {
+                        "\x1b[97;1:3u"
+                    } else {
+                        ""
+                    },
+                ),
+            ];
+            for (event, expected) in cases {
+                assert_eq!(
+                    encode(&event, bits),
+                    expected,
+                    "{:?} {:?} under flags {:#07b}",
+                    event.kind,
+                    event.code,
+                    bits
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_legacy() {
+        assert_eq!(encode(&plain(KeyCode::Tab), 0), "\t");
+        assert_eq!(encode(&letter('i', ctrl()), 0), "\t");
+        assert_eq!(encode(&plain(KeyCode::Escape), 0), "\x1b");
+        assert_eq!(encode(&plain(KeyCode::Up), 0), "\x1b[A");
+        assert_eq!(encode(&plain(KeyCode::F(1)), 0), "\x1bOP");
+        assert_eq!(encode(&plain(KeyCode::F(5)), 0), "\x1b[15~");
+        assert_eq!(encode(&KeyEvent::new(KeyCode::Tab, shift()), 0), "\x1b[Z");
+        assert_eq!(encode(&letter('a', shift()), 0), "A");
+        assert_eq!(encode(&plain(KeyCode::LeftShift), 0), "");
+        let release = letter('a', KeyModifiers::default()).with_kind(KeyEventKind::Release);
+        assert_eq!(encode(&release, 0), "");
+    }
+
+    #[test]
+    fn test_disambiguate() {
+        // Ctrl+I and Tab are now different keys
+        assert_eq!(encode(&letter('i', ctrl()), DISAMBIGUATE), "\x1b[105;5u");
+        assert_eq!(encode(&plain(KeyCode::Tab), DISAMBIGUATE), "\t");
+        assert_eq!(encode(&plain(KeyCode::Escape), DISAMBIGUATE), "\x1b[27u");
+        assert_eq!(encode(&plain(KeyCode::Enter), DISAMBIGUATE), "\r");
+        assert_eq!(
+            encode(&KeyEvent::new(KeyCode::Enter, shift()), DISAMBIGUATE),
+            "\x1b[13;2u"
+        );
+        // Text still types text
+        assert_eq!(encode(&letter('a', shift()), DISAMBIGUATE), "A");
+        let alt = KeyModifiers {
+            alt: true,
+            ..KeyModifiers::default()
+        };
+        assert_eq!(encode(&letter('x', alt), DISAMBIGUATE), "\x1b[120;3u");
+        // Functional keys
+        assert_eq!(encode(&plain(KeyCode::Up), DISAMBIGUATE), "\x1b[A");
+        assert_eq!(
+            encode(&KeyEvent::new(KeyCode::Up, ctrl()), DISAMBIGUATE),
+            "\x1b[1;5A"
+        );
+        assert_eq!(encode(&plain(KeyCode::F(1)), DISAMBIGUATE), "\x1b[P");
+        assert_eq!(encode(&plain(KeyCode::F(3)), DISAMBIGUATE), "\x1b[13~");
+        assert_eq!(
+            encode(&KeyEvent::new(KeyCode::Delete, ctrl()), DISAMBIGUATE),
+            "\x1b[3;5~"
+        );
+        assert_eq!(
+            encode(&plain(KeyCode::KeypadEnter), DISAMBIGUATE),
+            "\x1b[57414u"
+        );
+        assert_eq!(encode(&plain(KeyCode::F(13)), DISAMBIGUATE), "\x1b[57376u");
+        // Lock keys are not modifiers here
+        let caps = KeyModifiers {
+            caps_lock: true,
+            ctrl: true,
+            ..KeyModifiers::default()
+        };
+        assert_eq!(encode(&letter('c', caps), DISAMBIGUATE), "\x1b[99;5u");
+        assert_eq!(encode(&plain(KeyCode::LeftControl), DISAMBIGUATE), "");
+    }
+
+    #[test]
+    fn test_event_types() {
+        let flags = DISAMBIGUATE | EVENT_TYPES;
+        let a = letter('a', KeyModifiers::default());
+        assert_eq!(encode(&a, flags), "a");
+        assert_eq!(
+            encode(&a.clone().with_kind(KeyEventKind::Repeat), flags),
+            "a"
+        );
+        assert_eq!(
+            encode(&a.clone().with_kind(KeyEventKind::Release), flags),
+            "\x1b[97;1:3u"
+        );
+        let ctrl_a = letter('a', ctrl());
+        assert_eq!(
+            encode(&ctrl_a.clone().with_kind(KeyEventKind::Repeat), flags),
+            "\x1b[97;5:2u"
+        );
+        assert_eq!(
+            encode(&plain(KeyCode::Up).with_kind(KeyEventKind::Release), flags),
+            "\x1b[1;1:3A"
+        );
+        // Enter, Tab and Backspace never report release without all keys
+        let enter = plain(KeyCode::Enter).with_kind(KeyEventKind::Release);
+        assert_eq!(encode(&enter, flags), "");
+        assert_eq!(encode(&enter, flags | ALL_KEYS), "\x1b[13;1:3u");
+        // Without the flag, repeats are presses and releases are dropped
+        assert_eq!(
+            encode(
+                &ctrl_a.clone().with_kind(KeyEventKind::Repeat),
+                DISAMBIGUATE
+            ),
+            "\x1b[97;5u"
+        );
+        assert_eq!(
+            encode(&ctrl_a.with_kind(KeyEventKind::Release), DISAMBIGUATE),
+            ""
+        );
+        assert_eq!(
+            encode(&a.with_kind(KeyEventKind::Release), EVENT_TYPES),
+            "\x1b[97;1:3u"
+        );
+    }
+
+    #[test]
+    fn test_alternate_keys() {
+        let flags = DISAMBIGUATE | ALTERNATE;
+        let ctrl_shift = KeyModifiers {
+            ctrl: true,
+            shift: true,
+            ..KeyModifiers::default()
+        };
+        assert_eq!(encode(&letter('a', ctrl_shift), flags), "\x1b[97:65;6u");
+        // Shifted key only while Shift is held
+        assert_eq!(encode(&letter('a', ctrl()), flags), "\x1b[97;5u");
+        // Base layout key, e.g. Cyrillic `с` on the US `c` key
+        let mut cyrillic = KeyEvent::new(KeyCode::Char('с'), ctrl());
+        cyrillic.base_layout = Some('c');
+        assert_eq!(encode(&cyrillic, flags), "\x1b[1089::99;5u");
+        assert_eq!(encode(&cyrillic, DISAMBIGUATE), "\x1b[1089;5u");
+        // Functional keys have no alternates
+        assert_eq!(
+            encode(&KeyEvent::new(KeyCode::Up, ctrl_shift), flags),
+            "\x1b[1;6A"
+        );
+    }
+
+    #[test]
+    fn test_all_keys_as_escape_codes() {
+        let flags = ALL_KEYS;
+        assert_eq!(
+            encode(&letter('a', KeyModifiers::default()), flags),
+            "\x1b[97u"
+        );
+        assert_eq!(encode(&letter('a', shift()), flags), "\x1b[97;2u");
+        assert_eq!(encode(&plain(KeyCode::Enter), flags), "\x1b[13u");
+        assert_eq!(encode(&plain(KeyCode::Tab), flags), "\x1b[9u");
+        assert_eq!(encode(&plain(KeyCode::Backspace), flags), "\x1b[127u");
+        assert_eq!(encode(&plain(KeyCode::LeftShift), flags), "\x1b[57441u");
+        let caps = KeyModifiers {
+            caps_lock: true,
+            ..KeyModifiers::default()
+        };
+        assert_eq!(encode(&letter('a', caps), flags), "\x1b[97;65u");
+        assert_eq!(
+            encode(&letter('a', shift()), flags | ALTERNATE),
+            "\x1b[97:65;2u"
+        );
+    }
+
+    #[test]
+    fn test_associated_text() {
+        let flags = ALL_KEYS | TEXT;
+        assert_eq!(
+            encode(&letter('a', KeyModifiers::default()), flags),
+            "\x1b[97;1;97u"
+        );
+        assert_eq!(encode(&letter('a', shift()), flags), "\x1b[97;2;65u");
+        // No text for commands or releases, and only with all keys
+        assert_eq!(encode(&letter('a', ctrl()), flags), "\x1b[97;5u");
+        let release = letter('a', KeyModifiers::default()).with_kind(KeyEventKind::Release);
+        assert_eq!(encode(&release, flags | EVENT_TYPES), "\x1b[97;1:3u");
+        assert_eq!(encode(&letter('a', KeyModifiers::default()), TEXT), "a");
+    }
+}
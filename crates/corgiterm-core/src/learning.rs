@@ -3,15 +3,17 @@
 //! This module learns from user command patterns to provide:
 //! - Frequently used commands
 //! - Command sequences (patterns)
+//! - Next-command prediction (see [`crate::prediction`])
 //! - Directory-specific commands
 //! - Success/failure tracking
 //! - User preferences (alternative commands)
 
 use crate::history::CommandEntry;
+use crate::prediction::{PredictionContext, ReplayReport, SequenceModel};
 use chrono::{DateTime, Timelike, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// A sequence of commands that often occur together
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,6 +124,8 @@ pub struct CommandLearning {
     min_pattern_frequency: usize,
     /// Maximum pattern length
     max_pattern_length: usize,
+    /// Next-command model
+    model: SequenceModel,
 }
 
 impl CommandLearning {
@@ -135,14 +139,40 @@ impl CommandLearning {
             stats: HashMap::new(),
             min_pattern_frequency: 3,
             max_pattern_length: 5,
+            model: SequenceModel::new(),
         }
     }
 
     /// Add a command to learning analysis
     pub fn add_command(&mut self, entry: CommandEntry) {
+        // A completed command comes back with its exit code; it is not a
+        // new command in the sequence, and was counted when it started
+        if let Some(last) = self.recent_commands.back_mut() {
+            if last.exit_code.is_none()
+                && entry.exit_code.is_some()
+                && last.command == entry.command
+                && last.timestamp == entry.timestamp
+            {
+                let success = entry.success;
+                let base_command = extract_base_command(&entry.command);
+                *last = entry;
+                if let Some(stats) = self.stats.get_mut(&base_command) {
+                    match success {
+                        Some(true) => stats.success_count += 1,
+                        Some(false) => stats.failure_count += 1,
+                        None => {}
+                    }
+                }
+                return;
+            }
+        }
+
         // Update command statistics
         self.update_stats(&entry);
 
+        let context = self.context_at(&entry.cwd, entry.git_branch.clone(), entry.timestamp);
+        self.model.observe(&context, &entry.command);
+
         // Add to recent window
         self.recent_commands.push_back(entry);
         if self.recent_commands.len() > self.window_size {
@@ -357,32 +387,72 @@ impl CommandLearning {
 
     /// Get likely next command based on current command
     pub fn predict_next_command(&self, current: &str) -> Option<CommandSuggestion> {
-        let base_current = extract_base_command(current);
-
-        // Look for patterns starting with current command
-        let matching_patterns: Vec<_> = self
-            .patterns
-            .iter()
-            .filter(|p| {
-                p.sequence
-                    .first()
-                    .map(|s| s == &base_current)
-                    .unwrap_or(false)
+        let context = match self.recent_commands.back() {
+            Some(last) if last.command.trim() == current.trim() => {
+                self.context_at(&last.cwd, last.git_branch.clone(), Utc::now())
+            }
+            _ => PredictionContext::at(None, None, Utc::now())
+                .after([&CommandEntry::new(current, PathBuf::new())]),
+        };
+        self.predict(&context, 1).into_iter().next()
+    }
+
+    /// Context for the next command, run in `cwd` on `git_branch` at `time`
+    pub fn context_at(
+        &self,
+        cwd: &Path,
+        git_branch: Option<String>,
+        time: DateTime<Utc>,
+    ) -> PredictionContext {
+        PredictionContext::at(Some(cwd.to_path_buf()), git_branch, time)
+            .after(self.recent_commands.iter().rev())
+    }
+
+    /// Up to `limit` likely next commands in `context`, best first
+    pub fn predict(&self, context: &PredictionContext, limit: usize) -> Vec<CommandSuggestion> {
+        self.model
+            .predict(context, limit)
+            .into_iter()
+            .map(|prediction| {
+                let (reason, source) = if prediction.level.is_sequence() {
+                    let previous = context.previous.last().map_or("", String::as_str);
+                    (
+                        format!("Often follows '{}'", previous),
+                        SuggestionSource::Pattern,
+                    )
+                } else if prediction.level.is_place() {
+                    (
+                        "Often run in this directory".to_string(),
+                        SuggestionSource::Directory,
+                    )
+                } else {
+                    ("Frequently used".to_string(), SuggestionSource::Frequency)
+                };
+                CommandSuggestion {
+                    command: prediction.command,
+                    reason,
+                    confidence: prediction.score as f32,
+                    source,
+                }
             })
-            .collect();
+            .collect()
+    }
 
-        if let Some(pattern) = matching_patterns.iter().max_by_key(|p| p.frequency) {
-            if let Some(next_cmd) = pattern.sequence.get(1) {
-                return Some(CommandSuggestion {
-                    command: next_cmd.clone(),
-                    reason: format!("Often follows '{}'", base_current),
-                    confidence: pattern.confidence,
-                    source: SuggestionSource::Pattern,
-                });
-            }
+    /// Replay `entries` (oldest first), predicting each command before
+    /// learning it, and report how often the prediction was right
+    pub fn replay(&mut self, entries: impl IntoIterator<Item = CommandEntry>) -> ReplayReport {
+        let mut report = ReplayReport::default();
+        for entry in entries {
+            let context = self.context_at(&entry.cwd, entry.git_branch.clone(), entry.timestamp);
+            let command = entry.command.trim();
+            let rank = self
+                .predict(&context, 3)
+                .iter()
+                .position(|suggestion| suggestion.command == command);
+            report.record(rank);
+            self.add_command(entry);
         }
-
-        None
+        report
     }
 
     /// Get command patterns
@@ -446,6 +516,7 @@ impl CommandLearning {
             patterns: self.patterns.clone(),
             preferences: self.preferences.clone(),
             stats: self.stats.clone(),
+            model: self.model.clone(),
         };
 
         let json = serde_json::to_string_pretty(&data)?;
@@ -465,6 +536,7 @@ impl CommandLearning {
         self.patterns = data.patterns;
         self.preferences = data.preferences;
         self.stats = data.stats;
+        self.model = data.model;
 
         Ok(())
     }
@@ -476,6 +548,8 @@ struct LearningData {
     patterns: Vec<CommandPattern>,
     preferences: Vec<UserPreference>,
     stats: HashMap<String, CommandStats>,
+    #[serde(default)]
+    model: SequenceModel,
 }
 
 /// A suggested command
@@ -528,6 +602,40 @@ mod tests {
         assert_eq!(stats.total_count, 1);
         assert_eq!(stats.success_count, 1);
         assert_eq!(stats.success_rate(), 1.0);
+
+        // The same run completing counts its outcome, not another run
+        let mut started = CommandEntry::new("make", PathBuf::from("/src"));
+        learning.add_command(started.clone());
+        started.complete(2, 50);
+        learning.add_command(started);
+        let stats = learning.stats.get("make").unwrap();
+        assert_eq!(stats.total_count, 1);
+        assert_eq!(stats.failure_count, 1);
+    }
+
+    #[test]
+    fn test_predict_next_command() {
+        let mut learning = CommandLearning::new(100);
+        for _ in 0..3 {
+            for command in ["git pull", "cargo build", "cargo test"] {
+                let entry = CommandEntry::new(command, PathBuf::from("/src"));
+                learning.add_command(entry.clone());
+                // Completion is not another command in the sequence
+                let mut done = entry;
+                done.complete(0, 100);
+                learning.add_command(done);
+            }
+        }
+        assert_eq!(learning.recent_commands.len(), 9);
+        assert_eq!(learning.recent_commands[8].exit_code, Some(0));
+
+        let next = learning.predict_next_command("git pull").unwrap();
+        assert_eq!(next.command, "cargo build");
+        assert_eq!(next.source, SuggestionSource::Pattern);
+        assert_eq!(next.reason, "Often follows 'git pull'");
+
+        let context = learning.context_at(Path::new("/src"), None, Utc::now());
+        assert_eq!(learning.predict(&context, 1)[0].command, "git pull");
     }
 
     #[test]
@@ -17,43 +17,83 @@
 //! ```
 
 pub mod ascii_art;
+pub mod blocks;
+pub mod clipboard_audit;
+pub mod command_reference;
 pub mod engine;
 pub mod error;
+pub mod headless;
 pub mod hints;
 pub mod history;
+pub mod history_db;
+pub mod history_import;
 pub mod history_learning;
+pub mod images;
+#[cfg(unix)]
+pub mod ipc;
+pub mod key_encoding;
 pub mod learning;
+pub mod output_index;
+pub mod paste;
+pub mod prediction;
 pub mod pty;
 pub mod recording;
+pub mod redaction;
 pub mod safe_mode;
+pub mod safe_mode_impact;
+pub mod safe_mode_profiles;
+pub mod safe_mode_rules;
+pub mod safe_mode_trash;
 pub mod session;
+pub mod shell_integration;
+pub mod shell_syntax;
 pub mod terminal;
 
 pub use ascii_art::{
     all_fonts, AsciiArtConfig, AsciiArtGenerator, AsciiFont, CharacterSet, CorgiArt, ImageFilter,
     FONT_BANNER, FONT_BLOCK, FONT_MINI, FONT_SHADOW, FONT_SMALL, FONT_STANDARD,
 };
+pub use blocks::{CommandBlock, CommandBlocks};
+pub use command_reference::{CommandExplanation, CommandReference, ExplainedWord, WordKind};
 pub use engine::{
-    AlacrittyEngine, CellColor, EngineCursor, EngineCursorShape, RenderCell, RenderFlags,
-    TerminalEngine,
+    hyperlink_spans, AlacrittyEngine, CellColor, CellHyperlink, EngineCursor, EngineCursorShape,
+    HyperlinkSpan, RenderCell, RenderFlags, TerminalEngine, VisibleImage,
 };
 pub use error::{CoreError, Result};
+pub use headless::{HeadlessOptions, HeadlessOutput, HeadlessResult};
 pub use hints::{Hint, HintDetector, HintModeState, HintType};
-pub use history::{CommandHistory, OutputHistory, SearchableHistory};
+pub use history::{
+    CommandEntry, CommandHistory, GlobalSearchResults, HistoryScope, OutputChunk, OutputHistory,
+    SearchResult, SearchableHistory,
+};
+pub use history_db::{HistoryDb, HistoryWriter, OutputQuery};
+pub use history_import::{ImportReport, ImportedCommand, ShellHistoryFormat};
 pub use history_learning::{
     FrequentCommandData, HistoryLearningManager, LearningContextData, PatternData, PreferenceData,
 };
+pub use images::{ImageId, ImagePlacement, ImageStore, InlineImage};
+pub use key_encoding::{encode_key, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardFlags};
 pub use learning::{
     CommandLearning, CommandPattern, CommandStats, CommandSuggestion, SuggestionSource,
     UserPreference,
 };
+pub use output_index::OutputIndexer;
+pub use paste::{PasteInspection, PasteIssue, PasteLine};
+pub use prediction::{Prediction, PredictionContext, ReplayReport, SequenceModel};
 pub use pty::{Pty, PtySize};
 pub use recording::{
     EventType, PlaybackState, Recording, RecordingEvent, RecordingId, RecordingMeta, RecordingStore,
 };
-pub use safe_mode::{CommandPreview, RiskLevel, SafeMode};
+pub use redaction::{Redactor, SecretKind, SecretMatch};
+pub use safe_mode::{CommandPreview, CommandRisk, RiskLevel, SafeMode};
+pub use safe_mode_impact::{FileEffect, PathImpact};
+pub use safe_mode_profiles::{Profile, ProfileSet, DEFAULT_PROFILE};
+pub use safe_mode_rules::{LoadedRule, Rule, RuleLayer, RuleSet};
+pub use safe_mode_trash::{Trash, TrashEntry};
 pub use session::{Session, SessionId, SessionManager};
-pub use terminal::{Terminal, TerminalEvent, TerminalHealth, TerminalSize};
+pub use terminal::{
+    ClipboardAction, ClipboardReply, Terminal, TerminalEvent, TerminalHealth, TerminalSize,
+};
 
 /// Core version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
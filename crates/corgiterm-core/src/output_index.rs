@@ -0,0 +1,175 @@
+//! Output indexing
+//!
+//! Turns the lines a terminal has finished writing into [`OutputChunk`]s for
+//! the history database, tagged with the command that printed them when
+//! shell integration reports commands. Text comes from the grid, so escape
+//! sequences are already gone.
+//!
+//! Chunks carry a line serial, the buffer line plus
+//! [`TerminalEngine::lines_evicted`], which keeps naming the same line as
+//! scrollback scrolls away; a search hit can be scrolled back into view for
+//! as long as it is still in the buffer.
+
+use crate::engine::TerminalEngine;
+use crate::history::OutputChunk;
+use chrono::Utc;
+
+/// Collects a terminal's output for indexing
+#[derive(Debug, Clone)]
+pub struct OutputIndexer {
+    session: String,
+    /// Serial of the first line not indexed yet
+    next: usize,
+}
+
+impl OutputIndexer {
+    /// Index output of the terminal session `session`
+    pub fn new(session: impl Into<String>) -> Self {
+        Self {
+            session: session.into(),
+            next: 0,
+        }
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Chunks for the lines completed since the last call, one per command
+    ///
+    /// Prompts and command lines are skipped (commands are in the command
+    /// history), and nothing is taken from the alternate screen.
+    pub fn collect<E: TerminalEngine + ?Sized>(&mut self, engine: &E) -> Vec<OutputChunk> {
+        if engine.alternate_screen() {
+            return Vec::new();
+        }
+        let evicted = engine.lines_evicted();
+        let end = engine.cursor_buffer_line();
+        // Lines that scrolled away before they were indexed are lost
+        let start = self.next.saturating_sub(evicted);
+        if start >= end {
+            return Vec::new();
+        }
+        self.next = evicted + end;
+
+        let blocks = engine.command_blocks();
+        let mut chunks: Vec<(Option<usize>, usize, Vec<String>)> = Vec::new();
+        for (line, text) in (start..end).zip(engine.lines_text(start..end)) {
+            let owner = match blocks.block_at(line) {
+                Some(index) => {
+                    let block = &blocks.blocks()[index];
+                    match block.output_line {
+                        Some(output) if line >= output => Some(index),
+                        // Prompt and command line
+                        _ => continue,
+                    }
+                }
+                None => None,
+            };
+            match chunks.last_mut() {
+                Some((last_owner, _, lines)) if *last_owner == owner => lines.push(text),
+                _ => chunks.push((owner, line, vec![text])),
+            }
+        }
+
+        let timestamp = Utc::now();
+        chunks
+            .into_iter()
+            .filter(|(_, _, lines)| lines.iter().any(|line| !line.trim().is_empty()))
+            .map(|(owner, first, lines)| OutputChunk {
+                text: lines.join("\n"),
+                timestamp,
+                command: owner.and_then(|index| blocks.blocks()[index].command.clone()),
+                is_stderr: false,
+                session: Some(self.session.clone()),
+                line: Some(evicted + first),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::AlacrittyEngine;
+    use crate::terminal::TerminalSize;
+
+    fn engine(scrollback: usize) -> AlacrittyEngine {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        AlacrittyEngine::new(TerminalSize { rows: 5, cols: 40 }, tx, scrollback)
+    }
+
+    fn run_command(e: &mut AlacrittyEngine, command: &str, lines: usize) {
+        e.feed(format!("\x1b]133;A\x07$ \x1b]133;B\x07{command}\r\n").as_bytes());
+        e.feed(format!("\x1b]133;C;cmdline_url={command}\x07").as_bytes());
+        for i in 0..lines {
+            e.feed(format!("{command} {i}\r\n").as_bytes());
+        }
+        e.feed(b"\x1b]133;D;0\x07");
+    }
+
+    #[test]
+    fn test_collects_command_output() {
+        let mut e = engine(100);
+        let mut indexer = OutputIndexer::new("pid 1");
+        run_command(&mut e, "make", 2);
+        run_command(&mut e, "ls", 1);
+        e.feed(b"\x1b]133;A\x07$ ");
+
+        let chunks = indexer.collect(&e);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "make 0\nmake 1");
+        assert_eq!(chunks[0].command.as_deref(), Some("make"));
+        assert_eq!(chunks[0].session.as_deref(), Some("pid 1"));
+        assert_eq!(chunks[0].line, Some(1));
+        assert_eq!(chunks[1].text, "ls 0");
+        assert_eq!(chunks[1].line, Some(4));
+
+        // Nothing new
+        assert!(indexer.collect(&e).is_empty());
+        run_command(&mut e, "pwd", 1);
+        e.feed(b"\x1b]133;A\x07$ ");
+        let chunks = indexer.collect(&e);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, "pwd 0");
+    }
+
+    #[test]
+    fn test_line_serials_survive_eviction() {
+        let mut e = engine(10);
+        let mut indexer = OutputIndexer::new("pid 1");
+        e.feed(b"first\r\n");
+        assert_eq!(indexer.collect(&e)[0].line, Some(0));
+
+        for i in 0..40 {
+            e.feed(format!("line {i}\r\n").as_bytes());
+        }
+        let chunks = indexer.collect(&e);
+        // Without shell integration all output is one chunk with no command
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].command, None);
+        let line = chunks[0].line.unwrap();
+        let first = chunks[0].text.lines().next().unwrap();
+        let index = first
+            .strip_prefix("line ")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        // "line N" is serial N + 1, wherever it is in the buffer now
+        assert_eq!(line, index + 1);
+        assert_eq!(
+            e.all_text()[line - e.lines_evicted()],
+            format!("line {}", index)
+        );
+    }
+
+    #[test]
+    fn test_skips_alternate_screen() {
+        let mut e = engine(100);
+        let mut indexer = OutputIndexer::new("pid 1");
+        e.feed(b"\x1b[?1049hvim stuff\r\nmore\r\n");
+        assert!(indexer.collect(&e).is_empty());
+        e.feed(b"\x1b[?1049lback\r\n");
+        assert_eq!(indexer.collect(&e)[0].text, "back");
+    }
+}
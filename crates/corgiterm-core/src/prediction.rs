@@ -0,0 +1,519 @@
+//! Next-command prediction
+//!
+//! A back-off n-gram model over command lines. Every command is counted
+//! under a chain of contexts, from "after these two commands, with this exit
+//! code, in this directory on this git branch" down to no context at all.
+//! A candidate is scored by the most specific context that has seen it,
+//! discounted by [`BACKOFF`] for each level it had to back off, so a long,
+//! specific history wins while unfamiliar situations still get the user's
+//! general habits.
+//!
+//! Previous commands are reduced to their [`command_key`] so `git commit -m
+//! "..."` is one state whatever the message; predictions are whole command
+//! lines. Counts decay with a half-life of [`HALF_LIFE`] commands, so new
+//! habits take over from old ones.
+
+use crate::history::CommandEntry;
+use crate::learning::TimeCategory;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Previous commands a context looks at
+pub const ORDER: usize = 2;
+
+/// Discount for each context level backed off
+pub const BACKOFF: f64 = 0.4;
+
+/// Commands after which a count has lost half its weight
+pub const HALF_LIFE: f64 = 1000.0;
+
+/// Observations between prunes of decayed counts
+const PRUNE_INTERVAL: u64 = 1000;
+
+/// Counts lighter than this are pruned
+const MIN_WEIGHT: f64 = 0.05;
+
+/// Separates the parts of a context key
+const SEPARATOR: &str = "\u{1f}";
+
+/// What a prediction is conditioned on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredictionContext {
+    /// Keys of the previous commands, most recent last
+    pub previous: Vec<String>,
+    /// Exit code of the previous command
+    pub exit_code: Option<i32>,
+    /// Working directory
+    pub directory: Option<PathBuf>,
+    /// Checked out git branch (see [`git_branch`])
+    pub git_branch: Option<String>,
+    /// Time of day
+    pub time_category: TimeCategory,
+}
+
+impl PredictionContext {
+    /// Context for a command run in `directory` at `time`, with no history
+    pub fn at(directory: Option<PathBuf>, git_branch: Option<String>, time: DateTime<Utc>) -> Self {
+        Self {
+            previous: Vec::new(),
+            exit_code: None,
+            directory,
+            git_branch,
+            time_category: TimeCategory::from_datetime(&time),
+        }
+    }
+
+    /// The same context following `recent` commands, newest first
+    pub fn after<'a>(mut self, recent: impl IntoIterator<Item = &'a CommandEntry>) -> Self {
+        let recent: Vec<_> = recent.into_iter().take(ORDER).collect();
+        self.exit_code = recent.first().and_then(|entry| entry.exit_code);
+        self.previous = recent
+            .iter()
+            .rev()
+            .map(|entry| command_key(&entry.command))
+            .collect();
+        self
+    }
+
+    /// Keys of the contexts this one backs off through, most specific first
+    fn levels(&self) -> Vec<(Level, String)> {
+        let exit = self
+            .exit_code
+            .map_or("?".to_string(), |code| code.to_string());
+        let directory = self.directory.as_ref().map(|dir| dir.display().to_string());
+        let place = directory
+            .as_ref()
+            .map(|dir| key(&[dir, self.git_branch.as_deref().unwrap_or("")]));
+        let time = format!("{:?}", self.time_category);
+        let last = self.previous.last();
+        let pair = match self.previous.as_slice() {
+            [.., before, last] => Some(key(&[before, last])),
+            _ => None,
+        };
+
+        let levels = [
+            (
+                Level::SequenceHere,
+                pair.as_ref()
+                    .zip(place.as_ref())
+                    .map(|(pair, place)| key(&[pair, &exit, place])),
+            ),
+            (
+                Level::Sequence,
+                pair.as_ref().map(|pair| key(&[pair, &exit])),
+            ),
+            (
+                Level::FollowsHere,
+                last.zip(place.as_ref())
+                    .map(|(last, place)| key(&[last, &exit, place])),
+            ),
+            (Level::FollowsExit, last.map(|last| key(&[last, &exit]))),
+            (Level::Follows, last.cloned()),
+            (
+                Level::PlaceAndTime,
+                place.as_ref().map(|place| key(&[place, &time])),
+            ),
+            (Level::Place, place),
+            (Level::Directory, directory),
+            (Level::Time, Some(time)),
+            (Level::Any, Some(String::new())),
+        ];
+        levels
+            .into_iter()
+            .filter_map(|(level, context)| {
+                context.map(|context| (level, key(&[&(level as u8).to_string(), &context])))
+            })
+            .collect()
+    }
+}
+
+/// Joins the parts of a context key
+fn key(parts: &[&str]) -> String {
+    parts.join(SEPARATOR)
+}
+
+/// Kinds of context, most specific first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Last two commands, exit code, directory and branch
+    SequenceHere,
+    /// Last two commands and exit code
+    Sequence,
+    /// Last command, exit code, directory and branch
+    FollowsHere,
+    /// Last command and exit code
+    FollowsExit,
+    /// Last command
+    Follows,
+    /// Directory, branch and time of day
+    PlaceAndTime,
+    /// Directory and branch
+    Place,
+    /// Directory
+    Directory,
+    /// Time of day
+    Time,
+    /// No context
+    Any,
+}
+
+impl Level {
+    /// Whether the prediction came from previous commands
+    pub fn is_sequence(self) -> bool {
+        matches!(
+            self,
+            Level::SequenceHere
+                | Level::Sequence
+                | Level::FollowsHere
+                | Level::FollowsExit
+                | Level::Follows
+        )
+    }
+
+    /// Whether the prediction came from where the command runs
+    pub fn is_place(self) -> bool {
+        matches!(self, Level::PlaceAndTime | Level::Place | Level::Directory)
+    }
+}
+
+/// A predicted command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub command: String,
+    /// Backed-off probability (0.0 - 1.0)
+    pub score: f64,
+    /// Most specific context that predicted it
+    pub level: Level,
+}
+
+/// A count that decays as commands are observed
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Weight {
+    value: f64,
+    /// Clock of the last update
+    at: u64,
+}
+
+impl Weight {
+    fn get(self, clock: u64) -> f64 {
+        self.value * 0.5f64.powf(clock.saturating_sub(self.at) as f64 / HALF_LIFE)
+    }
+
+    fn add(&mut self, clock: u64, amount: f64) {
+        self.value = self.get(clock) + amount;
+        self.at = clock;
+    }
+}
+
+/// Commands seen in one context
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ContextCounts {
+    total: Weight,
+    next: HashMap<String, Weight>,
+}
+
+/// Back-off n-gram model of which command comes next
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SequenceModel {
+    contexts: HashMap<String, ContextCounts>,
+    /// Commands observed
+    clock: u64,
+}
+
+impl SequenceModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Commands observed so far
+    pub fn observed(&self) -> u64 {
+        self.clock
+    }
+
+    /// Count `command` as run in `context`
+    pub fn observe(&mut self, context: &PredictionContext, command: &str) {
+        let command = command.trim();
+        if command.is_empty() {
+            return;
+        }
+        self.clock += 1;
+        for (_, key) in context.levels() {
+            let counts = self.contexts.entry(key).or_default();
+            counts.total.add(self.clock, 1.0);
+            counts
+                .next
+                .entry(command.to_string())
+                .or_default()
+                .add(self.clock, 1.0);
+        }
+        if self.clock.is_multiple_of(PRUNE_INTERVAL) {
+            self.prune();
+        }
+    }
+
+    /// Up to `limit` likely commands in `context`, best first
+    pub fn predict(&self, context: &PredictionContext, limit: usize) -> Vec<Prediction> {
+        let mut found: HashMap<&str, Prediction> = HashMap::new();
+        let mut discount = 1.0;
+        for (level, key) in context.levels() {
+            if let Some(counts) = self.contexts.get(&key) {
+                let total = counts.total.get(self.clock);
+                if total > 0.0 {
+                    for (command, weight) in &counts.next {
+                        found.entry(command).or_insert_with(|| Prediction {
+                            command: command.clone(),
+                            score: discount * weight.get(self.clock) / total,
+                            level,
+                        });
+                    }
+                }
+            }
+            discount *= BACKOFF;
+        }
+
+        let mut predictions: Vec<_> = found.into_values().collect();
+        predictions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.command.cmp(&b.command))
+        });
+        predictions.truncate(limit);
+        predictions
+    }
+
+    /// Drop counts that have decayed away
+    fn prune(&mut self) {
+        let clock = self.clock;
+        self.contexts.retain(|_, counts| {
+            counts
+                .next
+                .retain(|_, weight| weight.get(clock) >= MIN_WEIGHT);
+            !counts.next.is_empty()
+        });
+    }
+}
+
+/// How well predictions matched a replayed history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Commands replayed
+    pub commands: usize,
+    /// Commands that were the top prediction
+    pub top1: usize,
+    /// Commands among the top three predictions
+    pub top3: usize,
+}
+
+impl ReplayReport {
+    /// Count a command predicted at `rank` (0 is the top prediction)
+    pub fn record(&mut self, rank: Option<usize>) {
+        self.commands += 1;
+        if rank == Some(0) {
+            self.top1 += 1;
+        }
+        if rank.is_some_and(|rank| rank < 3) {
+            self.top3 += 1;
+        }
+    }
+
+    pub fn top1_accuracy(&self) -> f64 {
+        self.top1 as f64 / self.commands.max(1) as f64
+    }
+
+    pub fn top3_accuracy(&self) -> f64 {
+        self.top3 as f64 / self.commands.max(1) as f64
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "top-1 {:.1}%, top-3 {:.1}% over {} commands",
+            self.top1_accuracy() * 100.0,
+            self.top3_accuracy() * 100.0,
+            self.commands
+        )
+    }
+}
+
+/// A command reduced to the program and its subcommand, if it has one
+///
+/// `git commit -m "fix"` becomes `git commit`, `ls -la` becomes `ls`;
+/// paths and other arguments are dropped.
+pub fn command_key(command: &str) -> String {
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return String::new();
+    };
+    match words.next() {
+        Some(sub)
+            if sub.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !sub.starts_with('-') =>
+        {
+            format!("{program} {sub}")
+        }
+        _ => program.to_string(),
+    }
+}
+
+/// The git branch checked out in `dir` or a parent directory
+///
+/// `Some("HEAD")` when the HEAD is detached, `None` outside a repository.
+pub fn git_branch(dir: &Path) -> Option<String> {
+    for dir in dir.ancestors() {
+        let dot_git = dir.join(".git");
+        let git_dir = if dot_git.is_dir() {
+            dot_git
+        } else if dot_git.is_file() {
+            // Worktrees and submodules: "gitdir: <path>"
+            let link = std::fs::read_to_string(&dot_git).ok()?;
+            dir.join(link.strip_prefix("gitdir:")?.trim())
+        } else {
+            continue;
+        };
+        let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+        let branch = head
+            .trim()
+            .strip_prefix("ref: refs/heads/")
+            .unwrap_or("HEAD");
+        return Some(branch.to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    fn entry(command: &str, exit_code: i32) -> CommandEntry {
+        let mut entry = CommandEntry::new(command, PathBuf::from("/src/app"));
+        entry.complete(exit_code, 10);
+        entry
+    }
+
+    fn context(recent: &[CommandEntry]) -> PredictionContext {
+        let noon = Utc::now().with_hour(12).unwrap();
+        PredictionContext::at(Some(PathBuf::from("/src/app")), Some("main".into()), noon)
+            .after(recent.iter().rev())
+    }
+
+    fn observe_all(model: &mut SequenceModel, commands: &[(&str, i32)]) {
+        let mut recent = Vec::new();
+        for (command, exit_code) in commands {
+            model.observe(&context(&recent), command);
+            recent.push(entry(command, *exit_code));
+        }
+    }
+
+    #[test]
+    fn test_command_key() {
+        assert_eq!(command_key("git commit -m \"fix it\""), "git commit");
+        assert_eq!(command_key("ls -la /tmp"), "ls");
+        assert_eq!(command_key("vim src/main.rs"), "vim");
+        assert_eq!(command_key("cargo build --release"), "cargo build");
+        assert_eq!(command_key("  "), "");
+    }
+
+    #[test]
+    fn test_context_after() {
+        let recent = [entry("cd app", 0), entry("git status", 0), entry("make", 2)];
+        let context = context(&recent);
+        assert_eq!(context.previous, vec!["git status", "make"]);
+        assert_eq!(context.exit_code, Some(2));
+        assert_eq!(context.time_category, TimeCategory::Afternoon);
+    }
+
+    #[test]
+    fn test_exit_code_conditions_prediction() {
+        let mut model = SequenceModel::new();
+        for _ in 0..5 {
+            observe_all(
+                &mut model,
+                &[
+                    ("cargo build", 101),
+                    ("vim src/lib.rs", 0),
+                    ("cargo build", 0),
+                    ("cargo test", 0),
+                ],
+            );
+        }
+        let failed = model.predict(&context(&[entry("cargo build", 101)]), 3);
+        assert_eq!(failed[0].command, "vim src/lib.rs");
+        assert_eq!(failed[0].level, Level::FollowsHere);
+        let passed = model.predict(&context(&[entry("cargo build", 0)]), 3);
+        assert_eq!(passed[0].command, "cargo test");
+
+        // Unseen sequences back off to what is common here
+        let unseen = model.predict(&context(&[entry("htop", 0)]), 3);
+        assert!(!unseen.is_empty());
+        assert!(unseen[0].level.is_place());
+        assert!(unseen[0].score < failed[0].score);
+    }
+
+    #[test]
+    fn test_recent_habits_win() {
+        let mut model = SequenceModel::new();
+        for _ in 0..20 {
+            observe_all(&mut model, &[("git pull", 0), ("make", 0)]);
+        }
+        for _ in 0..2000 {
+            observe_all(&mut model, &[("git pull", 0), ("cargo build", 0)]);
+        }
+        let predicted = model.predict(&context(&[entry("git pull", 0)]), 10);
+        assert_eq!(predicted[0].command, "cargo build");
+        let make = predicted.iter().find(|p| p.command == "make").unwrap();
+        assert_eq!(make.level, Level::FollowsHere);
+        assert!(make.score < 0.01);
+    }
+
+    #[test]
+    fn test_replay_report() {
+        let mut report = ReplayReport::default();
+        report.record(Some(0));
+        report.record(Some(2));
+        report.record(Some(5));
+        report.record(None);
+        assert_eq!(report.top1, 1);
+        assert_eq!(report.top3, 2);
+        assert_eq!(report.top3_accuracy(), 0.5);
+        assert_eq!(
+            report.to_string(),
+            "top-1 25.0%, top-3 50.0% over 4 commands"
+        );
+    }
+
+    #[test]
+    fn test_git_branch() {
+        let root = std::env::temp_dir().join(format!("corgiterm-branch-{}", uuid::Uuid::new_v4()));
+        let nested = root.join("src/deep");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(git_branch(&nested), None);
+
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".git/HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+        assert_eq!(git_branch(&nested).as_deref(), Some("feature/x"));
+
+        std::fs::write(root.join(".git/HEAD"), "0123456789abcdef\n").unwrap();
+        assert_eq!(git_branch(&root).as_deref(), Some("HEAD"));
+
+        // A worktree points at its git directory
+        let worktree = root.join("worktree");
+        let worktree_git = root.join(".git/worktrees/wt");
+        std::fs::create_dir_all(&worktree).unwrap();
+        std::fs::create_dir_all(&worktree_git).unwrap();
+        std::fs::write(worktree_git.join("HEAD"), "ref: refs/heads/wt\n").unwrap();
+        std::fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", worktree_git.display()),
+        )
+        .unwrap();
+        assert_eq!(git_branch(&worktree).as_deref(), Some("wt"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -72,11 +72,64 @@ impl Pty {
     /// - `size`: Initial terminal size.
     /// - `working_dir`: Optional starting directory.
     /// - `term`: Optional TERM environment variable (Unix only).
+    ///
+    /// bash, zsh and fish are started with CorgiTerm's shell integration
+    /// loaded; see [`crate::shell_integration`].
     pub fn spawn(
         shell: Option<&str>,
         size: PtySize,
         working_dir: Option<&std::path::Path>,
         term: Option<&str>,
+    ) -> Result<Self> {
+        Self::spawn_shell(
+            shell,
+            size,
+            working_dir,
+            term,
+            crate::shell_integration::Level::Marks,
+        )
+    }
+
+    /// Like [`Pty::spawn`], choosing how much shell integration to load
+    pub fn spawn_shell(
+        shell: Option<&str>,
+        size: PtySize,
+        working_dir: Option<&std::path::Path>,
+        term: Option<&str>,
+        shell_integration: crate::shell_integration::Level,
+    ) -> Result<Self> {
+        let shell_path = resolve_shell(shell);
+        let mut cmd = CommandBuilder::new(&shell_path);
+        crate::shell_integration::inject(&mut cmd, &shell_path, shell_integration);
+        Self::spawn_builder(cmd, size, working_dir, term)
+    }
+
+    /// Create a new PTY and run a single command line through the shell
+    ///
+    /// The command is passed to `shell -c` (or `cmd.exe /C` on Windows), so
+    /// pipes, redirections and builtins behave exactly as they would when typed.
+    /// The child exits when the command finishes; use [`Pty::wait`] for its status.
+    pub fn spawn_command(
+        command: &str,
+        shell: Option<&str>,
+        size: PtySize,
+        working_dir: Option<&std::path::Path>,
+        term: Option<&str>,
+    ) -> Result<Self> {
+        let shell_path = resolve_shell(shell);
+        let mut cmd = CommandBuilder::new(&shell_path);
+        #[cfg(unix)]
+        cmd.args(["-c", command]);
+        #[cfg(windows)]
+        cmd.args(["/C", command]);
+        Self::spawn_builder(cmd, size, working_dir, term)
+    }
+
+    fn spawn_builder(
+        mut cmd: CommandBuilder,
+        size: PtySize,
+        working_dir: Option<&std::path::Path>,
+        term: Option<&str>,
     ) -> Result<Self> {
         // Get native PTY system (works on all platforms)
         let pty_system = native_pty_system();
@@ -86,25 +139,6 @@ impl Pty {
             .openpty(size.into())
             .map_err(|e| CoreError::Pty(format!("Failed to open PTY: {}", e)))?;
 
-        // Determine shell to use
-        let shell_path = shell
-            .map(String::from)
-            .or_else(|| std::env::var("SHELL").ok())
-            .unwrap_or_else(|| {
-                #[cfg(unix)]
-                {
-                    "/bin/bash".to_string()
-                }
-                #[cfg(windows)]
-                {
-                    // Prefer PowerShell if available, fall back to cmd
-                    std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string())
-                }
-            });
-
-        // Build command
-        let mut cmd = CommandBuilder::new(&shell_path);
-
         // Set working directory if specified
         if let Some(dir) = working_dir {
             cmd.cwd(dir);
@@ -117,6 +151,8 @@ impl Pty {
             cmd.env("TERM", term_value);
             apply_utf8_locale_env(&mut cmd);
         }
+        #[cfg(not(unix))]
+        let _ = term;
 
         // Spawn child process
         let child = pair
@@ -208,10 +244,7 @@ impl Pty {
     pub fn is_alive(&self) -> bool {
         if let Ok(mut child) = self.child.lock() {
             // try_wait returns None if still running
-            match child.try_wait() {
-                Ok(None) => true,
-                _ => false,
-            }
+            matches!(child.try_wait(), Ok(None))
         } else {
             false
         }
@@ -278,6 +311,24 @@ impl Drop for Pty {
     }
 }
 
+/// Pick the shell to run: the explicit choice, then $SHELL, then a platform default.
+fn resolve_shell(shell: Option<&str>) -> String {
+    shell
+        .map(String::from)
+        .or_else(|| std::env::var("SHELL").ok())
+        .unwrap_or_else(|| {
+            #[cfg(unix)]
+            {
+                "/bin/bash".to_string()
+            }
+            #[cfg(windows)]
+            {
+                // Prefer PowerShell if available, fall back to cmd
+                std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string())
+            }
+        })
+}
+
 #[cfg(unix)]
 fn apply_utf8_locale_env(cmd: &mut CommandBuilder) {
     let locale = preferred_utf8_locale();
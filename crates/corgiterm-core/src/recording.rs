@@ -9,6 +9,7 @@
 //! Recording format is designed to be compact and easy to replay at
 //! different speeds (1x, 2x, 0.5x, etc.)
 
+use crate::redaction::Redactor;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -169,6 +170,29 @@ impl Recording {
         self.meta.duration_ms = self.elapsed_ms();
     }
 
+    /// Copy with secrets in output, input and markers redacted. Each event
+    /// is checked on its own, so typed input is only caught once it arrives
+    /// as a single chunk (a paste, or shell-integration markers).
+    pub fn redacted(&self, redactor: &Redactor) -> Self {
+        let events = self
+            .events
+            .iter()
+            .map(|e| RecordingEvent {
+                timestamp_ms: e.timestamp_ms,
+                event: match &e.event {
+                    EventType::Output(data) => EventType::Output(redactor.redact_bytes(data)),
+                    EventType::Input(data) => EventType::Input(redactor.redact_bytes(data)),
+                    EventType::Marker(label) => EventType::Marker(redactor.redact(label)),
+                    other => other.clone(),
+                },
+            })
+            .collect();
+        Self {
+            meta: self.meta.clone(),
+            events,
+        }
+    }
+
     /// Get elapsed time since recording started (in milliseconds)
     fn elapsed_ms(&self) -> u64 {
         let elapsed = Utc::now() - self.meta.started_at;
@@ -261,7 +285,7 @@ impl PlaybackState {
 
     /// Set playback speed
     pub fn set_speed(&mut self, speed: f64) {
-        self.speed = speed.max(0.1).min(10.0);
+        self.speed = speed.clamp(0.1, 10.0);
     }
 
     /// Check if playback is complete
@@ -323,19 +347,24 @@ impl RecordingStore {
 
         // Sort by date, newest first
         self.recordings
-            .sort_by(|a, b| b.started_at.cmp(&a.started_at));
+            .sort_by_key(|b| std::cmp::Reverse(b.started_at));
 
         Ok(())
     }
 
-    /// Save a recording to disk
+    /// Save a recording to disk, with secrets redacted
     pub fn save(&self, recording: &Recording) -> Result<PathBuf, std::io::Error> {
         std::fs::create_dir_all(&self.storage_path)?;
 
         let filename = format!("{}.json", recording.meta.id);
         let path = self.storage_path.join(&filename);
 
-        let content = serde_json::to_string_pretty(recording)?;
+        let redactor = crate::redaction::global();
+        let content = if redactor.is_enabled() {
+            serde_json::to_string_pretty(&recording.redacted(&redactor))?
+        } else {
+            serde_json::to_string_pretty(recording)?
+        };
         std::fs::write(&path, content)?;
 
         Ok(path)
@@ -420,6 +449,26 @@ mod tests {
         assert!(recording.meta.ended_at.is_some());
     }
 
+    #[test]
+    fn test_redacted_recording() {
+        let mut recording = Recording::new("Secrets", PathBuf::from("/"), 24, 80);
+        recording.add_input(b"export API_TOKEN=abc123\r");
+        recording.add_output(&[0xff, 0x00]);
+        recording.add_marker("ran psql postgres://me:pw@db/app");
+
+        let redacted = recording.redacted(&Redactor::new());
+        let events: Vec<_> = redacted.events.iter().map(|e| &e.event).collect();
+        assert!(matches!(
+            events[0],
+            EventType::Input(data) if data == b"export API_TOKEN=[REDACTED:secret]\r"
+        ));
+        assert!(matches!(events[1], EventType::Output(data) if data == &[0xff, 0x00]));
+        assert!(matches!(
+            events[2],
+            EventType::Marker(label) if label == "ran psql postgres://me:[REDACTED:url-password]@db/app"
+        ));
+    }
+
     #[test]
     fn test_playback_state() {
         let mut recording = Recording::new("Test", PathBuf::from("/"), 24, 80);
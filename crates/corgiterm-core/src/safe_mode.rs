@@ -4,7 +4,8 @@
 //! - Preview of what a command will do before execution
 //! - Risk level assessment (safe, caution, danger)
 //! - Undo suggestions for dangerous operations
-//! - Natural language explanations
+//! - Natural language explanations, with a per-flag breakdown from the
+//!   built-in [`CommandReference`] when no AI provider is available
 //!
 //! ```text
 //! ┌──────────────────────────────────────────────────────────────────┐
@@ -28,19 +29,37 @@
 //! └──────────────────────────────────────────────────────────────────┘
 //! ```
 
+use crate::command_reference::{CommandExplanation, CommandReference};
+use crate::safe_mode_impact::{self, Effect, Operand, PathImpact};
+use crate::safe_mode_profiles::ProfileSet;
+use crate::safe_mode_rules::{
+    find_project_rules, skip_options, split_options, subcommand, Rule, RuleAlternative, RuleLayer,
+    RuleSet,
+};
+use crate::safe_mode_trash;
+use crate::shell_syntax::{
+    self, Command, CommandList, Connector, RedirectOp, Redirection, SimpleCommand,
+};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 /// Risk level for a command
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RiskLevel {
     /// Safe - read-only or easily reversible
+    #[serde(alias = "safe")]
     Safe,
     /// Caution - makes changes but can be undone
+    #[serde(alias = "caution")]
     Caution,
     /// Danger - destructive or hard to reverse
+    #[serde(alias = "danger")]
     Danger,
     /// Unknown - can't determine risk
+    #[serde(alias = "unknown")]
     Unknown,
 }
 
@@ -62,6 +81,26 @@ impl RiskLevel {
             Self::Unknown => "UNKNOWN",
         }
     }
+
+    /// Ranks levels from `Safe` to `Danger`; an unknown command is riskier
+    /// than a safe one but not than one known to make changes
+    fn severity(self) -> u8 {
+        match self {
+            Self::Safe => 0,
+            Self::Unknown => 1,
+            Self::Caution => 2,
+            Self::Danger => 3,
+        }
+    }
+
+    /// The more serious of two levels
+    pub fn worst(self, other: Self) -> Self {
+        if other.severity() > self.severity() {
+            other
+        } else {
+            self
+        }
+    }
 }
 
 /// Preview information for a command
@@ -73,11 +112,11 @@ pub struct CommandPreview {
     pub risk: RiskLevel,
     /// Human-readable explanation
     pub explanation: Vec<String>,
-    /// Files that will be affected
+    /// Files that will be affected, the largest first
     pub affected_files: Vec<PathBuf>,
-    /// Estimated count of affected items
+    /// Files and directories affected in total
     pub affected_count: Option<usize>,
-    /// Estimated size of affected data
+    /// Size of the affected files in total
     pub affected_size: Option<u64>,
     /// Suggested undo command
     pub undo_hint: Option<String>,
@@ -87,8 +126,31 @@ pub struct CommandPreview {
     pub network_access: bool,
     /// Similar safe alternatives
     pub alternatives: Vec<CommandAlternative>,
+    /// Risk of each command in the line, in the order they were parsed
+    #[serde(default)]
+    pub commands: Vec<CommandRisk>,
+    /// What a dry run found for the paths in `affected_files`
+    #[serde(default)]
+    pub impact: Vec<PathImpact>,
+    /// The dry run stopped early, so the totals are lower bounds
+    #[serde(default)]
+    pub impact_truncated: bool,
+    /// The line to run instead when approved, with `rm` moving files into
+    /// the trash (see [`SafeMode::set_trash_program`])
+    #[serde(default)]
+    pub trash_rewrite: Option<String>,
+    /// The Safe Mode profile the line was judged under
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Each command's program, subcommands, flags and operands with what
+    /// they mean; the summaries also end `explanation`
+    #[serde(default)]
+    pub breakdown: Vec<CommandExplanation>,
 }
 
+/// Paths listed in a preview
+pub const MAX_LISTED_PATHS: usize = 10;
+
 /// A safer alternative to a command
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandAlternative {
@@ -97,37 +159,174 @@ pub struct CommandAlternative {
     pub risk: RiskLevel,
 }
 
+/// Risk of one simple command in a previewed line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRisk {
+    /// The command's words
+    pub command: String,
+    pub risk: RiskLevel,
+    pub explanation: Vec<String>,
+    /// Names of the rules and checks that fired
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
 /// Safe Mode analyzer
+///
+/// Commands are parsed with [`shell_syntax`], so each command in a list,
+/// pipeline or substitution is judged on its own, quoted text is never
+/// mistaken for a command, and wrappers such as `sudo`, `env`, `xargs` and
+/// `sh -c` are looked through. What each command does comes from the
+/// layered [`RuleSet`]; the line is as risky as its riskiest command.
 pub struct SafeMode {
-    /// Dangerous command patterns
-    dangerous_patterns: Vec<DangerPattern>,
-    /// Safe command patterns
-    safe_patterns: Vec<&'static str>,
+    /// Built-in and user rules
+    rules: Arc<RuleSet>,
+    /// Rules with the last project's layer added, until its file changes
+    project_rules: Mutex<Option<ProjectRules>>,
+    /// Profiles that sessions can add on top
+    profiles: Arc<ProfileSet>,
     /// Is Safe Mode enabled?
     pub enabled: bool,
     /// AI integration for smart explanations
     ai_enabled: bool,
+    /// Offline explanations of common commands
+    reference: Arc<CommandReference>,
+    /// CorgiTerm executable that approved `rm` commands are rewritten to
+    trash_program: Option<String>,
 }
 
-#[derive(Debug)]
-struct DangerPattern {
-    pattern: regex::Regex,
+#[derive(Clone)]
+struct ProjectRules {
+    file: PathBuf,
+    modified: Option<SystemTime>,
+    rules: Arc<RuleSet>,
+    warnings: Vec<String>,
+}
+
+/// One thing a command would do
+#[derive(Debug, Clone)]
+struct Verdict {
     risk: RiskLevel,
-    explanation: &'static str,
-    undo_hint: Option<&'static str>,
+    explanation: Option<String>,
+    undo_hint: Option<String>,
+    /// The rule or check that produced it
+    rule: Option<String>,
+    /// A safer command, with the words it replaces
+    alternative: Option<(String, RuleAlternative)>,
 }
 
+impl Verdict {
+    fn new(rule: &str, risk: RiskLevel, explanation: impl Into<String>) -> Self {
+        Self {
+            risk,
+            explanation: Some(explanation.into()),
+            undo_hint: None,
+            rule: Some(rule.to_string()),
+            alternative: None,
+        }
+    }
+
+    /// A check that found nothing risky
+    fn safe(rule: &str) -> Self {
+        Self {
+            risk: RiskLevel::Safe,
+            explanation: None,
+            undo_hint: None,
+            rule: Some(rule.to_string()),
+            alternative: None,
+        }
+    }
+
+    fn unknown() -> Self {
+        Self {
+            risk: RiskLevel::Unknown,
+            explanation: None,
+            undo_hint: None,
+            rule: None,
+            alternative: None,
+        }
+    }
+
+    /// A rule's verdict on `words`, the command and its arguments
+    fn from_rule(rule: &Rule, words: &[&str]) -> Self {
+        Self {
+            risk: rule.risk,
+            explanation: rule.explanation.clone(),
+            undo_hint: rule.undo.clone(),
+            rule: Some(rule.name.clone()),
+            alternative: rule
+                .alternative_for(&words[1..])
+                .map(|alternative| (words.join(" "), alternative)),
+        }
+    }
+}
+
+/// Where a command sits in its pipeline
+#[derive(Debug, Clone, Copy, Default)]
+struct Stage {
+    /// Its input comes from the previous command
+    piped: bool,
+    /// An earlier command in the pipeline downloads something
+    after_download: bool,
+}
+
+/// Nested `sh -c`, `eval` and wrappers beyond this are not followed
+const MAX_NESTING: usize = 16;
+
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "ash", "fish"];
+const INTERPRETERS: &[&str] = &["python", "python3", "perl", "ruby", "node", "php"];
+const DOWNLOADERS: &[&str] = &["curl", "wget", "fetch", "aria2c"];
+const NETWORK_COMMANDS: &[&str] = &[
+    "curl", "wget", "fetch", "aria2c", "ssh", "scp", "sftp", "rsync", "ftp", "nc", "ncat",
+    "telnet", "ping", "npm", "npx", "yarn", "pnpm", "pip", "pip3", "gem", "brew", "apt", "apt-get",
+    "dnf", "yum", "pacman", "zypper", "snap", "flatpak",
+];
+const GIT_NETWORK_SUBCOMMANDS: &[&str] =
+    &["push", "pull", "fetch", "clone", "ls-remote", "submodule"];
+/// Devices that are safe to write to
+const HARMLESS_DEVICES: &[&str] = &[
+    "null", "zero", "full", "random", "urandom", "stdin", "stdout", "stderr", "tty",
+];
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    "/etc/", "/boot/", "/usr/", "/bin/", "/sbin/", "/lib", "/System/",
+];
+
 impl SafeMode {
-    /// Create a new SafeMode analyzer
+    /// Create a new SafeMode analyzer with the built-in rules
     pub fn new() -> Self {
+        Self::with_rules(RuleSet::builtin())
+    }
+
+    /// Create an analyzer with the given built-in and user rules; project
+    /// rules are added per working directory
+    pub fn with_rules(rules: RuleSet) -> Self {
         Self {
-            dangerous_patterns: Self::default_dangerous_patterns(),
-            safe_patterns: Self::default_safe_patterns(),
+            rules: Arc::new(rules),
+            project_rules: Mutex::new(None),
+            profiles: Arc::new(ProfileSet::builtin()),
             enabled: false,
             ai_enabled: false,
+            reference: Arc::new(CommandReference::builtin()),
+            trash_program: None,
         }
     }
 
+    /// Replace the built-in and user rules
+    pub fn set_rules(&mut self, rules: RuleSet) {
+        self.rules = Arc::new(rules);
+        *self.project_rules.lock() = None;
+    }
+
+    /// Replace the profiles sessions can choose from
+    pub fn set_profiles(&mut self, profiles: ProfileSet) {
+        self.profiles = Arc::new(profiles);
+    }
+
+    /// The profiles sessions can choose from
+    pub fn profiles(&self) -> &ProfileSet {
+        &self.profiles
+    }
+
     /// Enable or disable Safe Mode
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
@@ -138,292 +337,880 @@ impl SafeMode {
         self.ai_enabled = enabled;
     }
 
+    /// Explain each command in a line flag by flag, without AI
+    pub fn explain(&self, command: &str) -> Vec<CommandExplanation> {
+        self.reference.explain(command)
+    }
+
+    /// Rewrite approved `rm` commands to `program trash put`, so deleted
+    /// files can be restored; `None` leaves them to `rm`
+    pub fn set_trash_program(&mut self, program: Option<String>) {
+        self.trash_program = program;
+    }
+
+    /// The rules that apply in `cwd`, with problems loading its project's
+    /// rule file
+    pub fn rules_for(&self, cwd: &Path) -> (Arc<RuleSet>, Vec<String>) {
+        let Some(file) = find_project_rules(cwd) else {
+            return (self.rules.clone(), Vec::new());
+        };
+        let modified = std::fs::metadata(&file).and_then(|m| m.modified()).ok();
+        let mut cache = self.project_rules.lock();
+        if let Some(cached) = cache
+            .as_ref()
+            .filter(|cached| cached.file == file && cached.modified == modified)
+        {
+            return (cached.rules.clone(), cached.warnings.clone());
+        }
+
+        let mut rules = (*self.rules).clone();
+        let warnings = match rules.load_file(&file, RuleLayer::Project) {
+            Ok(warnings) => warnings,
+            Err(e) => vec![e.to_string()],
+        };
+        let warnings: Vec<String> = warnings
+            .into_iter()
+            .map(|warning| format!("{}: {}", file.display(), warning))
+            .collect();
+        for warning in &warnings {
+            tracing::warn!("Safe Mode rules: {}", warning);
+        }
+        let rules = Arc::new(rules);
+        *cache = Some(ProjectRules {
+            file,
+            modified,
+            rules: rules.clone(),
+            warnings: warnings.clone(),
+        });
+        (rules, warnings)
+    }
+
+    /// The rules that apply in `cwd` under `profile`, with problems
+    /// loading them
+    pub fn rules_with_profile(
+        &self,
+        cwd: &Path,
+        profile: Option<&str>,
+    ) -> (Arc<RuleSet>, Vec<String>) {
+        let (rules, warnings) = self.rules_for(cwd);
+        self.add_profile(rules, warnings, profile)
+    }
+
+    /// `rules` with `profile`'s rules on top
+    fn add_profile(
+        &self,
+        rules: Arc<RuleSet>,
+        mut warnings: Vec<String>,
+        profile: Option<&str>,
+    ) -> (Arc<RuleSet>, Vec<String>) {
+        let Some(name) = profile else {
+            return (rules, warnings);
+        };
+        let (rules, profile_warnings) = match self.profiles.get(name) {
+            Some(profile) if profile.rules.is_empty() => (rules, Vec::new()),
+            Some(profile) => {
+                let (rules, profile_warnings) = profile.apply(&rules);
+                let profile_warnings = profile_warnings
+                    .into_iter()
+                    .map(|warning| format!("profile {}: {}", name, warning))
+                    .collect();
+                (Arc::new(rules), profile_warnings)
+            }
+            None => (
+                rules,
+                vec![format!("unknown Safe Mode profile \"{}\"", name)],
+            ),
+        };
+        for warning in &profile_warnings {
+            tracing::warn!("Safe Mode rules: {}", warning);
+        }
+        warnings.extend(profile_warnings);
+        (rules, warnings)
+    }
+
     /// Analyze a command and generate preview
-    pub fn analyze(&self, command: &str, cwd: &PathBuf) -> CommandPreview {
+    pub fn analyze(&self, command: &str, cwd: &Path) -> CommandPreview {
+        self.analyze_with_profile(command, cwd, None)
+    }
+
+    /// Analyze a command with a profile's rules added, such as the one
+    /// chosen for the session's project or SSH host
+    pub fn analyze_with_profile(
+        &self,
+        command: &str,
+        cwd: &Path,
+        profile: Option<&str>,
+    ) -> CommandPreview {
+        let (rules, _) = self.rules_with_profile(cwd, profile);
+        self.preview(command, &rules, profile, Some(cwd), true)
+    }
+
+    /// Analyze a command like [`SafeMode::analyze_with_profile`], but
+    /// without the dry run, which walks the filesystem and asks git; quick
+    /// enough to hold a line while its full preview is worked out
+    pub fn judge_with_profile(
+        &self,
+        command: &str,
+        cwd: &Path,
+        profile: Option<&str>,
+    ) -> CommandPreview {
+        let (rules, _) = self.rules_with_profile(cwd, profile);
+        self.preview(command, &rules, profile, Some(cwd), false)
+    }
+
+    /// Judge a command by its rules alone, without dry-running it or
+    /// rewriting it for the trash, as for a line that runs on another
+    /// machine; a local `cwd` adds its project's rules
+    pub fn classify_with_profile(
+        &self,
+        command: &str,
+        cwd: Option<&Path>,
+        profile: Option<&str>,
+    ) -> CommandPreview {
+        let (rules, _) = match cwd {
+            Some(cwd) => self.rules_with_profile(cwd, profile),
+            None => self.add_profile(self.rules.clone(), Vec::new(), profile),
+        };
+        self.preview(command, &rules, profile, None, false)
+    }
+
+    /// Judge `command` under `rules`; with a `cwd` the preview also holds
+    /// the trash rewrite and, with `dry_run`, what it would do to files there
+    fn preview(
+        &self,
+        command: &str,
+        rules: &RuleSet,
+        profile: Option<&str>,
+        cwd: Option<&Path>,
+        dry_run: bool,
+    ) -> CommandPreview {
+        let mut analysis = Analysis {
+            rules,
+            commands: Vec::new(),
+            undo_hint: None,
+            needs_sudo: false,
+            network_access: false,
+            effects: Vec::new(),
+            quoted: Vec::new(),
+            input: None,
+            functions: Vec::new(),
+            alternatives: Vec::new(),
+        };
+        analysis.list(&shell_syntax::parse(command), 0);
+
+        let risk = analysis
+            .commands
+            .iter()
+            .map(|command| command.risk)
+            .reduce(RiskLevel::worst)
+            .unwrap_or(RiskLevel::Unknown);
+        let mut explanation = if risk == RiskLevel::Safe {
+            vec!["This is a safe, read-only command".to_string()]
+        } else {
+            let mut explanation: Vec<String> = Vec::new();
+            for text in analysis.commands.iter().flat_map(|c| &c.explanation) {
+                if !explanation.contains(text) {
+                    explanation.push(text.clone());
+                }
+            }
+            explanation
+        };
+        let breakdown = self.reference.explain(command);
+        for headline in breakdown.iter().filter_map(CommandExplanation::headline) {
+            if !explanation.contains(&headline) {
+                explanation.push(headline);
+            }
+        }
+
         let mut preview = CommandPreview {
             command: command.to_string(),
-            risk: RiskLevel::Unknown,
-            explanation: Vec::new(),
+            risk,
+            explanation,
             affected_files: Vec::new(),
             affected_count: None,
             affected_size: None,
-            undo_hint: None,
-            needs_sudo: command.starts_with("sudo "),
-            network_access: false,
+            undo_hint: analysis.undo_hint.map(|(_, hint)| hint),
+            needs_sudo: analysis.needs_sudo,
+            network_access: analysis.network_access,
             alternatives: Vec::new(),
+            commands: Vec::new(),
+            impact: Vec::new(),
+            impact_truncated: false,
+            trash_rewrite: None,
+            profile: profile.map(str::to_string),
+            breakdown,
         };
 
-        // Check for dangerous patterns (break on first match - patterns ordered by severity)
-        for pattern in &self.dangerous_patterns {
-            if pattern.pattern.is_match(command) {
-                // Only override if new risk is higher
-                if pattern.risk as u8 > preview.risk as u8 || preview.risk == RiskLevel::Unknown {
-                    preview.risk = pattern.risk;
-                }
-                preview.explanation.push(pattern.explanation.to_string());
-                if let Some(undo) = pattern.undo_hint {
-                    preview.undo_hint = Some(undo.to_string());
+        if let Some(cwd) = cwd {
+            if let Some(program) = &self.trash_program {
+                preview.trash_rewrite = safe_mode_trash::rewrite_removals(command, program);
+                if preview.trash_rewrite.is_some() {
+                    preview.undo_hint = Some(
+                        "Deleted files go to the CorgiTerm trash - restore them with \
+                         Undo Last Deletion or `corgiterm trash undo`"
+                            .to_string(),
+                    );
                 }
             }
-        }
 
-        // Check for safe patterns
-        for safe in &self.safe_patterns {
-            if command.starts_with(safe) {
-                preview.risk = RiskLevel::Safe;
-                preview.explanation.clear();
-                preview
-                    .explanation
-                    .push("This is a safe, read-only command".to_string());
-                break;
+            // Dry-run what the line would delete, overwrite or discard
+            let impact = dry_run.then(|| safe_mode_impact::dry_run(&analysis.effects, cwd));
+            if let Some(impact) = impact.filter(|impact| !impact.paths.is_empty()) {
+                preview.affected_count = Some(impact.entries);
+                preview.affected_size = (impact.bytes > 0).then_some(impact.bytes);
+                preview.impact = impact.paths.into_iter().take(MAX_LISTED_PATHS).collect();
+                preview.affected_files = preview.impact.iter().map(|p| p.path.clone()).collect();
+                preview.impact_truncated = impact.truncated;
             }
         }
 
-        // If still unknown, try to infer
-        if preview.risk == RiskLevel::Unknown {
-            preview.risk = self.infer_risk(command);
+        preview.alternatives = alternatives(command, risk, analysis.alternatives);
+        preview.commands = analysis.commands;
+        preview
+    }
+}
+
+impl Clone for SafeMode {
+    /// A copy sharing the same rules, for analysis on another thread
+    fn clone(&self) -> Self {
+        Self {
+            rules: self.rules.clone(),
+            project_rules: Mutex::new(self.project_rules.lock().clone()),
+            profiles: self.profiles.clone(),
+            enabled: self.enabled,
+            ai_enabled: self.ai_enabled,
+            reference: self.reference.clone(),
+            trash_program: self.trash_program.clone(),
         }
+    }
+}
+
+impl Default for SafeMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        // Try to count affected files for rm/mv/cp
-        if let Some(files) = self.estimate_affected_files(command, cwd) {
-            preview.affected_files = files.clone();
-            preview.affected_count = Some(files.len());
-            preview.affected_size = self.estimate_size(&files);
+/// Safer commands for a risky (Danger or Caution) line, substituted into the
+/// whole line where possible
+fn alternatives(
+    command: &str,
+    risk: RiskLevel,
+    found: Vec<(String, RuleAlternative)>,
+) -> Vec<CommandAlternative> {
+    let mut alternatives: Vec<CommandAlternative> = Vec::new();
+    if !matches!(risk, RiskLevel::Danger | RiskLevel::Caution) {
+        return alternatives;
+    }
+    for (original, alternative) in found {
+        let command = if command.contains(&original) {
+            command.replacen(&original, &alternative.command, 1)
+        } else {
+            alternative.command
+        };
+        if alternatives.iter().all(|a| a.command != command) {
+            alternatives.push(CommandAlternative {
+                command,
+                description: alternative.description,
+                risk: alternative.risk,
+            });
         }
+    }
+    alternatives
+}
 
-        // Network commands
-        if command.contains("curl")
-            || command.contains("wget")
-            || command.contains("ssh")
-            || command.contains("scp")
-            || command.contains("npm")
-            || command.contains("pip")
-        {
-            preview.network_access = true;
+/// Everything found in one line so far
+struct Analysis<'a> {
+    rules: &'a RuleSet,
+    commands: Vec<CommandRisk>,
+    undo_hint: Option<(RiskLevel, String)>,
+    needs_sudo: bool,
+    network_access: bool,
+    /// What commands would do to files, for the dry run
+    effects: Vec<Effect>,
+    /// Quoted words of the command being classified
+    quoted: Vec<String>,
+    /// Text the line feeds to the input of the command being classified
+    input: Option<String>,
+    /// Functions defined in the line, judged by their bodies
+    functions: Vec<String>,
+    /// Safer commands, with the command text they replace
+    alternatives: Vec<(String, RuleAlternative)>,
+}
+
+impl Analysis<'_> {
+    /// Words of the command being classified, with whether they were quoted
+    fn operands(&self, words: &[&str]) -> Vec<Operand> {
+        words
+            .iter()
+            .map(|word| Operand {
+                text: word.to_string(),
+                quoted: self.quoted.iter().any(|quoted| quoted == word),
+            })
+            .collect()
+    }
+
+    /// A `>` redirection empties its target before the command runs
+    fn note_truncation(&mut self, redirection: &Redirection) {
+        let truncates = matches!(
+            redirection.op,
+            RedirectOp::Output
+                | RedirectOp::Clobber
+                | RedirectOp::OutputAll
+                | RedirectOp::DupOutput
+        );
+        if truncates && redirection.writes_file() {
+            self.effects.push(Effect::Truncate {
+                target: Operand {
+                    text: redirection.target.text.clone(),
+                    quoted: redirection.target.quoted,
+                },
+            });
         }
+    }
 
-        // Generate alternatives for risky commands (Danger or Caution)
-        if preview.risk == RiskLevel::Danger || preview.risk == RiskLevel::Caution {
-            preview.alternatives = self.suggest_alternatives(command);
+    fn record(&mut self, command: String, verdicts: Vec<Verdict>) {
+        let mut risk = RiskLevel::Safe;
+        let mut explanation: Vec<String> = Vec::new();
+        let mut rules: Vec<String> = Vec::new();
+        for verdict in verdicts {
+            risk = risk.worst(verdict.risk);
+            if let Some(text) = verdict.explanation {
+                if !explanation.contains(&text) {
+                    explanation.push(text);
+                }
+            }
+            if let Some(rule) = verdict.rule {
+                if !rules.contains(&rule) {
+                    rules.push(rule);
+                }
+            }
+            if let Some(hint) = verdict.undo_hint {
+                let replace = self
+                    .undo_hint
+                    .as_ref()
+                    .is_none_or(|(held, _)| verdict.risk.severity() > held.severity());
+                if replace {
+                    self.undo_hint = Some((verdict.risk, hint));
+                }
+            }
+            if let Some(alternative) = verdict.alternative {
+                self.alternatives.push(alternative);
+            }
         }
+        self.commands.push(CommandRisk {
+            command,
+            risk,
+            explanation,
+            rules,
+        });
+    }
 
-        preview
+    fn list(&mut self, list: &CommandList, depth: usize) {
+        for item in &list.items {
+            let mut after_download = false;
+            let mut echoed = None;
+            for (index, command) in item.pipeline.commands.iter().enumerate() {
+                let stage = Stage {
+                    piped: index > 0,
+                    after_download,
+                };
+                self.input = echoed;
+                if let Some(program) = self.command(command, stage, depth) {
+                    after_download |= DOWNLOADERS.contains(&program.as_str());
+                }
+                echoed = echoed_text(command);
+            }
+        }
     }
 
-    fn default_dangerous_patterns() -> Vec<DangerPattern> {
-        vec![
-            DangerPattern {
-                pattern: regex::Regex::new(r"rm\s+-rf?\s+/").unwrap(),
-                risk: RiskLevel::Danger,
-                explanation:
-                    "Recursively removes files from the root directory - EXTREMELY DANGEROUS",
-                undo_hint: None,
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"rm\s+-rf").unwrap(),
-                risk: RiskLevel::Caution,
-                explanation: "Recursively removes files and directories without confirmation",
-                undo_hint: Some("Files cannot be recovered - consider using trash-cli instead"),
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"rm\s").unwrap(),
-                risk: RiskLevel::Caution,
-                explanation: "Removes files - they will be permanently deleted",
-                undo_hint: Some("Consider: trash-put <file> (recoverable)"),
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"chmod\s+-R\s+777").unwrap(),
-                risk: RiskLevel::Danger,
-                explanation: "Makes all files world-readable/writable - security risk",
-                undo_hint: None,
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r":\(\)\s*\{\s*:\|:\s*&\s*\};:").unwrap(),
-                risk: RiskLevel::Danger,
-                explanation: "Fork bomb - will crash your system",
-                undo_hint: None,
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"dd\s+if=").unwrap(),
-                risk: RiskLevel::Danger,
-                explanation: "Low-level disk write - can overwrite entire drives",
-                undo_hint: None,
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"mkfs").unwrap(),
-                risk: RiskLevel::Danger,
-                explanation: "Formats a filesystem - all data will be lost",
-                undo_hint: None,
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r">\s*/dev/").unwrap(),
-                risk: RiskLevel::Danger,
-                explanation: "Writing directly to device - can corrupt data",
-                undo_hint: None,
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"git\s+push\s+(-f|--force)").unwrap(),
-                risk: RiskLevel::Caution,
-                explanation: "Force pushing can overwrite remote history",
-                undo_hint: Some("git reflog to find lost commits"),
-            },
-            DangerPattern {
-                pattern: regex::Regex::new(r"git\s+reset\s+--hard").unwrap(),
-                risk: RiskLevel::Caution,
-                explanation: "Discards all uncommitted changes",
-                undo_hint: Some("git reflog to recover"),
-            },
-        ]
-    }
-
-    fn default_safe_patterns() -> Vec<&'static str> {
-        vec![
-            "ls",
-            "pwd",
-            "echo",
-            "cat",
-            "less",
-            "head",
-            "tail",
-            "grep",
-            "find",
-            "which",
-            "whereis",
-            "man",
-            "help",
-            "type",
-            "file",
-            "stat",
-            "wc",
-            "diff",
-            "git status",
-            "git log",
-            "git diff",
-            "git branch",
-            "git show",
-            "ps",
-            "top",
-            "htop",
-            "df",
-            "du",
-            "free",
-            "uptime",
-            "whoami",
-            "date",
-            "cal",
-            "env",
-            "printenv",
-        ]
-    }
-
-    fn infer_risk(&self, command: &str) -> RiskLevel {
-        // Commands that modify things
-        if command.contains("mv ") || command.contains("cp ") {
-            return RiskLevel::Caution;
-        }
-
-        // Install commands
-        if command.contains("install")
-            || command.contains("apt")
-            || command.contains("dnf")
-            || command.contains("yum")
-        {
-            return RiskLevel::Caution;
+    /// Record the risk of a command, returning the program it runs
+    fn command(&mut self, command: &Command, stage: Stage, depth: usize) -> Option<String> {
+        match command {
+            Command::Simple(simple) => self.simple(simple, stage, depth),
+            Command::Subshell { body, redirections } | Command::Group { body, redirections } => {
+                self.list(body, depth);
+                for redirection in redirections {
+                    for substitution in &redirection.target.substitutions {
+                        self.list(substitution, depth + 1);
+                    }
+                    self.note_truncation(redirection);
+                    if let Some(verdict) = redirection_verdict(redirection) {
+                        let target = format!("> {}", redirection.target.text);
+                        self.record(target, vec![verdict]);
+                    }
+                }
+                None
+            }
+            Command::Function { name, body } => {
+                self.functions.push(name.clone());
+                if forks_itself(name, body) {
+                    self.record(
+                        format!("{name}() {{ ... }}"),
+                        vec![Verdict::new(
+                            "fork-bomb",
+                            RiskLevel::Danger,
+                            "Fork bomb - will crash your system",
+                        )],
+                    );
+                }
+                self.command(body, Stage::default(), depth);
+                None
+            }
+        }
+    }
+
+    fn simple(&mut self, simple: &SimpleCommand, stage: Stage, depth: usize) -> Option<String> {
+        let piped = self.input.take();
+        // Substitutions run before the command itself
+        let words = simple
+            .assignments
+            .iter()
+            .chain(&simple.words)
+            .chain(simple.redirections.iter().map(|r| &r.target));
+        for word in words {
+            for substitution in &word.substitutions {
+                self.list(substitution, depth + 1);
+            }
         }
 
-        // Anything with sudo
-        if command.starts_with("sudo") {
-            return RiskLevel::Caution;
+        // The last redirection of standard input replaces the pipe
+        let redirected = simple.redirections.iter().rev().find(|redirection| {
+            redirection.fd.unwrap_or(0) == 0
+                && matches!(
+                    redirection.op,
+                    RedirectOp::Input | RedirectOp::HereDoc | RedirectOp::HereString
+                )
+        });
+        self.input = match redirected {
+            Some(redirection) => redirection.input_text().map(str::to_string),
+            None => piped,
+        };
+
+        let mut verdicts: Vec<Verdict> = simple
+            .redirections
+            .iter()
+            .filter_map(redirection_verdict)
+            .collect();
+        for redirection in &simple.redirections {
+            self.note_truncation(redirection);
+        }
+        let words: Vec<&str> = simple.words.iter().map(|w| w.text.as_str()).collect();
+        self.quoted = simple
+            .words
+            .iter()
+            .filter(|word| word.quoted)
+            .map(|word| word.text.clone())
+            .collect();
+        let program = self.classify(&words, stage, &mut verdicts, depth);
+        if words.is_empty() && verdicts.is_empty() {
+            verdicts.push(Verdict::safe("assignment"));
         }
 
-        RiskLevel::Unknown
+        let text = if words.is_empty() {
+            simple
+                .assignments
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            words.join(" ")
+        };
+        self.record(text, verdicts);
+        program
     }
 
-    fn estimate_affected_files(&self, command: &str, cwd: &PathBuf) -> Option<Vec<PathBuf>> {
-        // Simple parser for rm, mv, cp targets
-        // In a real implementation, this would do proper shell expansion
-        let parts: Vec<&str> = command.split_whitespace().collect();
+    /// Judge a command's words, looking through wrappers, and return the
+    /// program that ends up running
+    fn classify(
+        &mut self,
+        words: &[&str],
+        stage: Stage,
+        verdicts: &mut Vec<Verdict>,
+        depth: usize,
+    ) -> Option<String> {
+        let (&first, args) = words.split_first()?;
+        let name = first.rsplit('/').next().unwrap_or(first);
+        // Scripts judged below classify their own commands
+        let input = self.input.clone();
+        if depth > MAX_NESTING {
+            verdicts.push(Verdict::new(
+                "nesting",
+                RiskLevel::Unknown,
+                "Too deeply nested to analyze",
+            ));
+            return Some(name.to_string());
+        }
+        if NETWORK_COMMANDS.contains(&name)
+            || (name == "git"
+                && skip_options(args, &["-C", "-c"])
+                    .first()
+                    .is_some_and(|sub| GIT_NETWORK_SUBCOMMANDS.contains(sub)))
+        {
+            self.network_access = true;
+        }
+
+        // Wrappers run the command that follows their own options
+        let wrapped = match name {
+            "sudo" | "doas" | "run0" | "pkexec" => {
+                self.needs_sudo = true;
+                verdicts.push(Verdict::new(
+                    "sudo",
+                    RiskLevel::Caution,
+                    "Runs with administrator privileges",
+                ));
+                Some(skip_options(
+                    args,
+                    &[
+                        "-u",
+                        "-g",
+                        "-h",
+                        "-p",
+                        "-C",
+                        "-D",
+                        "-r",
+                        "-t",
+                        "-U",
+                        "-T",
+                        "-R",
+                        "--user",
+                        "--group",
+                        "--host",
+                        "--prompt",
+                        "--chdir",
+                        "--role",
+                        "--type",
+                        "--other-user",
+                        "--close-from",
+                        "--command-timeout",
+                    ],
+                ))
+            }
+            "env" => {
+                let rest = skip_options(args, &["-u", "-C", "-S", "--unset", "--chdir"]);
+                let skip = rest.iter().take_while(|arg| arg.contains('=')).count();
+                Some(&rest[skip..])
+            }
+            "nohup" | "builtin" | "time" | "chronic" | "unbuffer" => Some(skip_options(args, &[])),
+            "busybox" | "toybox" => Some(skip_options(args, &[])),
+            "exec" => Some(skip_options(args, &["-a"])),
+            "command" if !matches!(args.first(), Some(&("-v" | "-V"))) => {
+                Some(skip_options(args, &[]))
+            }
+            "nice" => Some(skip_options(args, &["-n", "--adjustment"])),
+            "ionice" => Some(skip_options(args, &["-c", "-n", "-p", "-P", "-u"])),
+            "stdbuf" => Some(skip_options(args, &["-i", "-o", "-e"])),
+            "timeout" => {
+                let rest = skip_options(args, &["-s", "-k", "--signal", "--kill-after"]);
+                Some(rest.get(1..).unwrap_or_default())
+            }
+            "xargs" => Some(skip_options(
+                args,
+                &[
+                    "-I",
+                    "-n",
+                    "-P",
+                    "-L",
+                    "-s",
+                    "-E",
+                    "-d",
+                    "-a",
+                    "--max-args",
+                    "--max-procs",
+                    "--max-lines",
+                    "--delimiter",
+                    "--arg-file",
+                ],
+            )),
+            _ => None,
+        };
+        if let Some(rest) = wrapped {
+            if rest.is_empty() {
+                // `env` and `xargs` on their own just print
+                if verdicts.is_empty() {
+                    verdicts.push(Verdict::safe(name));
+                }
+                return Some(name.to_string());
+            }
+            return self.classify(rest, stage, verdicts, depth + 1);
+        }
 
-        if parts.is_empty() {
-            return None;
+        if self.functions.iter().any(|f| f == name) {
+            verdicts.push(Verdict::safe("function"));
+            return Some(name.to_string());
         }
 
-        match parts[0] {
-            "rm" | "mv" | "cp" => {
-                let mut files = Vec::new();
-                for part in parts.iter().skip(1) {
-                    if !part.starts_with('-') {
-                        let path = if part.starts_with('/') {
-                            PathBuf::from(part)
-                        } else {
-                            cwd.join(part)
-                        };
-                        if path.exists() {
-                            files.push(path);
-                        }
+        let mut own = Vec::new();
+        match name {
+            // `command -v` only looks a name up
+            "command" => own.push(Verdict::safe("command")),
+            "eval" => {
+                self.list(&shell_syntax::parse(&args.join(" ")), depth + 1);
+                own.push(Verdict::safe("eval"));
+            }
+            "watch" => {
+                let rest = skip_options(args, &["-n", "--interval"]);
+                if !rest.is_empty() {
+                    self.list(&shell_syntax::parse(&rest.join(" ")), depth + 1);
+                }
+                own.push(Verdict::safe("watch"));
+            }
+            _ if SHELLS.contains(&name) => match shell_script(args) {
+                ShellInput::Inline(script) => {
+                    self.list(&shell_syntax::parse(script), depth + 1);
+                    own.push(Verdict::safe("shell-script"));
+                }
+                ShellInput::File(file) => own.push(Verdict::new(
+                    "shell-script",
+                    RiskLevel::Unknown,
+                    format!("Runs the script {file}"),
+                )),
+                ShellInput::Stdin => own.extend(stdin_script_verdict(stage)),
+            },
+            "su" => {
+                self.needs_sudo = true;
+                verdicts.push(Verdict::new(
+                    "sudo",
+                    RiskLevel::Caution,
+                    "Runs as another user",
+                ));
+                if let Some(script) = su_script(args) {
+                    self.list(&shell_syntax::parse(script), depth + 1);
+                }
+                own.push(Verdict::safe("su"));
+            }
+            // With no script argument an interpreter runs its input
+            _ if INTERPRETERS.contains(&name)
+                && args
+                    .iter()
+                    .all(|arg| arg.starts_with('-') && *arg != "-c" && *arg != "-e") =>
+            {
+                own.extend(stdin_script_verdict(stage));
+            }
+            "find" => {
+                // Commands run by -exec are judged along with find itself
+                let mut index = 0;
+                while index < args.len() {
+                    if matches!(args[index], "-exec" | "-execdir" | "-ok" | "-okdir") {
+                        let start = index + 1;
+                        let end = args[start..]
+                            .iter()
+                            .position(|arg| matches!(*arg, ";" | "+"))
+                            .map_or(args.len(), |offset| start + offset);
+                        self.classify(&args[start..end], Stage::default(), &mut own, depth + 1);
+                        index = end;
                     }
+                    index += 1;
                 }
-                if files.is_empty() {
-                    None
-                } else {
-                    Some(files)
+                if args.contains(&"-delete") {
+                    let args = self.operands(args);
+                    self.effects.push(Effect::FindDelete { args });
                 }
             }
-            _ => None,
+            "rm" | "unlink" => {
+                let (options, operands) = split_options(args);
+                let recursive = options.iter().any(|option| {
+                    *option == "--recursive"
+                        || (!option.starts_with("--") && option.contains(['r', 'R']))
+                });
+                let operands = self.operands(&operands);
+                self.effects.push(Effect::Remove {
+                    operands,
+                    recursive,
+                });
+            }
+            "mv" | "cp" => {
+                let args = self.operands(args);
+                self.effects.push(Effect::Transfer { args });
+            }
+            "git" if matches!(subcommand(name, args), Some(("clean" | "reset", _))) => {
+                let args = args.iter().map(|arg| arg.to_string()).collect();
+                self.effects.push(Effect::Git { args });
+            }
+            _ => {}
         }
-    }
 
-    fn estimate_size(&self, files: &[PathBuf]) -> Option<u64> {
-        let mut total = 0u64;
-        for file in files {
-            if let Ok(metadata) = file.metadata() {
-                if metadata.is_dir() {
-                    // Would need to walk directory for accurate size
-                    total += 1024 * 1024; // Estimate 1MB per directory
-                } else {
-                    total += metadata.len();
-                }
-            }
+        for loaded in self.rules.matching(name, args, input.as_deref()) {
+            self.network_access |= loaded.rule.network;
+            own.push(Verdict::from_rule(&loaded.rule, words));
         }
-        if total > 0 {
-            Some(total)
-        } else {
-            None
+        if own.is_empty() {
+            own.push(Verdict::unknown());
         }
+        verdicts.extend(own);
+        Some(name.to_string())
     }
+}
 
-    fn suggest_alternatives(&self, command: &str) -> Vec<CommandAlternative> {
-        let mut alternatives = Vec::new();
+/// The words `echo` or `printf` would write into a pipe
+fn echoed_text(command: &Command) -> Option<String> {
+    let Command::Simple(simple) = command else {
+        return None;
+    };
+    matches!(simple.name(), Some("echo" | "printf")).then(|| {
+        simple
+            .args()
+            .iter()
+            .map(|word| word.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    })
+}
 
-        if command.contains("rm ") {
-            alternatives.push(CommandAlternative {
-                command: command.replace("rm ", "trash-put "),
-                description: "Move to trash instead (recoverable)".to_string(),
-                risk: RiskLevel::Safe,
-            });
+/// The command `su` runs with `-c`, if any
+fn su_script<'a>(args: &[&'a str]) -> Option<&'a str> {
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if let Some(script) = arg.strip_prefix("--command=") {
+            return Some(script);
+        }
+        if arg == "--command" {
+            return args.next().copied();
+        }
+        if arg == "--" || !arg.starts_with('-') {
+            continue;
+        }
+        if let Some(flags) = arg.strip_prefix('-').filter(|f| !f.starts_with('-')) {
+            if flags.contains('c') {
+                return args.next().copied();
+            }
+            if flags.ends_with(['s', 'g', 'G', 'w']) {
+                args.next();
+            }
+        } else if matches!(
+            arg,
+            "--shell" | "--group" | "--supp-group" | "--whitelist-environment"
+        ) {
+            args.next();
         }
+    }
+    None
+}
 
-        if command.contains("rm -rf") {
-            alternatives.push(CommandAlternative {
-                command: command.replace("rm -rf", "rm -ri"),
-                description: "Interactive mode - confirm each file".to_string(),
-                risk: RiskLevel::Caution,
-            });
+/// What a shell invocation runs
+enum ShellInput<'a> {
+    /// `-c script`
+    Inline(&'a str),
+    /// A script file
+    File(&'a str),
+    /// Commands read from standard input
+    Stdin,
+}
+
+fn shell_script<'a>(args: &[&'a str]) -> ShellInput<'a> {
+    let mut inline = false;
+    let mut stdin = false;
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index];
+        index += 1;
+        if arg == "--" {
+            break;
+        }
+        if matches!(arg, "-o" | "+o" | "-O" | "+O") {
+            index += 1;
+        } else if let Some(flags) = arg.strip_prefix('-').filter(|f| !f.starts_with('-')) {
+            inline |= flags.contains('c');
+            stdin |= flags.contains('s');
+        } else if !arg.starts_with('-') && !arg.starts_with('+') {
+            index -= 1;
+            break;
         }
+    }
+    match args.get(index) {
+        Some(script) if inline => ShellInput::Inline(script),
+        Some(file) if !stdin => ShellInput::File(file),
+        _ => ShellInput::Stdin,
+    }
+}
 
-        alternatives
+/// A shell or interpreter reading its program from a pipe
+fn stdin_script_verdict(stage: Stage) -> Option<Verdict> {
+    if stage.after_download {
+        Some(Verdict::new(
+            "download-to-shell",
+            RiskLevel::Danger,
+            "Runs a script downloaded from the internet without showing it first",
+        ))
+    } else if stage.piped {
+        Some(Verdict::new(
+            "pipe-to-shell",
+            RiskLevel::Caution,
+            "Runs commands read from its input",
+        ))
+    } else {
+        None
     }
 }
 
-impl Default for SafeMode {
-    fn default() -> Self {
-        Self::new()
+fn redirection_verdict(redirection: &Redirection) -> Option<Verdict> {
+    if !redirection.writes_file() {
+        return None;
+    }
+    let target = redirection.target.text.as_str();
+    let appends = matches!(redirection.op, RedirectOp::Append | RedirectOp::AppendAll);
+    if let Some(device) = target.strip_prefix("/dev/") {
+        let harmless = HARMLESS_DEVICES.contains(&device)
+            || ["fd/", "pts/", "shm/"]
+                .iter()
+                .any(|dir| device.starts_with(dir));
+        return (!harmless).then(|| {
+            Verdict::new(
+                "redirect-device",
+                RiskLevel::Danger,
+                "Writing directly to device - can corrupt data",
+            )
+        });
+    }
+    if SYSTEM_DIRECTORIES.iter().any(|dir| target.starts_with(dir)) {
+        return Some(if appends {
+            Verdict::new(
+                "redirect-system-file",
+                RiskLevel::Caution,
+                "Appends to a system file",
+            )
+        } else {
+            Verdict::new(
+                "redirect-system-file",
+                RiskLevel::Danger,
+                "Overwrites a system file",
+            )
+        });
     }
+    Some(if appends {
+        Verdict::new(
+            "redirect-file",
+            RiskLevel::Caution,
+            "Appends to the file it redirects to",
+        )
+    } else {
+        Verdict::new(
+            "redirect-file",
+            RiskLevel::Caution,
+            "Overwrites the file it redirects to",
+        )
+    })
+}
+
+/// A function that starts itself more than once at a time never stops
+fn forks_itself(name: &str, body: &Command) -> bool {
+    let (Command::Group { body, .. } | Command::Subshell { body, .. }) = body else {
+        return false;
+    };
+    body.items.iter().any(|item| {
+        let calls_itself = item.pipeline.commands.iter().any(
+            |command| matches!(command, Command::Simple(simple) if simple.name() == Some(name)),
+        );
+        calls_itself
+            && (item.pipeline.commands.len() > 1 || item.connector == Connector::Background)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::safe_mode_impact::FileEffect;
 
     #[test]
     fn test_safe_command() {
@@ -446,4 +1233,479 @@ mod tests {
         assert_eq!(preview.risk, RiskLevel::Caution);
         assert!(!preview.alternatives.is_empty());
     }
+
+    /// Tricky command lines and the risk Safe Mode must give them
+    const CORPUS: &[(&str, RiskLevel)] = &[
+        // Lists: the riskiest command decides
+        ("ls; rm -rf ~", RiskLevel::Danger),
+        ("ls && rm -rf /", RiskLevel::Danger),
+        ("false || rm -rf ~/", RiskLevel::Danger),
+        ("ls -la\nrm -rf /*", RiskLevel::Danger),
+        ("sleep 1 & rm -rf $HOME", RiskLevel::Danger),
+        ("ls; pwd; whoami", RiskLevel::Safe),
+        ("cd /tmp && ls", RiskLevel::Safe),
+        ("ls; make", RiskLevel::Unknown),
+        ("make; rm notes.txt", RiskLevel::Caution),
+        // Quoting: text is not a command
+        ("echo \"rm -rf /\"", RiskLevel::Safe),
+        ("echo 'rm -rf ~; mkfs /dev/sda'", RiskLevel::Safe),
+        ("grep -r \"dd if=\" .", RiskLevel::Safe),
+        ("echo rm -rf /", RiskLevel::Safe),
+        ("git log --grep='reset --hard'", RiskLevel::Safe),
+        ("printf '%s\\n' '> /dev/sda'", RiskLevel::Safe),
+        ("cat <<EOF\nrm -rf /\nEOF", RiskLevel::Safe),
+        ("ls # ; rm -rf /", RiskLevel::Safe),
+        ("\"rm\" -rf /", RiskLevel::Danger),
+        ("\\rm -rf /", RiskLevel::Danger),
+        ("/bin/rm -rf /", RiskLevel::Danger),
+        // Pipelines and pipes into shells
+        ("cat log | grep error | wc -l", RiskLevel::Safe),
+        ("ps aux | grep x | sort", RiskLevel::Safe),
+        (
+            "curl -fsSL https://example.com/install.sh | sh",
+            RiskLevel::Danger,
+        ),
+        (
+            "wget -qO- https://example.com/x | sudo bash",
+            RiskLevel::Danger,
+        ),
+        ("curl -s https://example.com/x | python3", RiskLevel::Danger),
+        (
+            "curl -s https://example.com/x | bash -s -- --yes",
+            RiskLevel::Danger,
+        ),
+        ("echo ls | sh", RiskLevel::Caution),
+        (
+            "curl -s https://example.com/data.json | jq .",
+            RiskLevel::Unknown,
+        ),
+        ("find . -name '*.o' | xargs rm", RiskLevel::Caution),
+        ("ls | xargs", RiskLevel::Safe),
+        // Subshells, groups and substitutions
+        ("(cd build && rm -rf ~)", RiskLevel::Danger),
+        ("{ ls; rm -rf /; }", RiskLevel::Danger),
+        ("echo $(rm -rf ~)", RiskLevel::Danger),
+        ("echo \"$(rm -rf ~)\"", RiskLevel::Danger),
+        ("echo `rm -rf /`", RiskLevel::Danger),
+        ("echo '$(rm -rf ~)'", RiskLevel::Safe),
+        ("diff <(ls a) <(ls b)", RiskLevel::Safe),
+        ("echo \"today is $(date)\"", RiskLevel::Safe),
+        ("x=$(rm -rf /)", RiskLevel::Danger),
+        ("FOO=bar", RiskLevel::Safe),
+        ("if [ -d ~ ]; then rm -rf ~; fi", RiskLevel::Danger),
+        ("for f in *.tmp; do rm \"$f\"; done", RiskLevel::Caution),
+        ("while true; do ls; done", RiskLevel::Safe),
+        ("case $x in a) rm -rf / ;; esac", RiskLevel::Danger),
+        // Wrappers
+        ("sudo ls", RiskLevel::Caution),
+        ("sudo rm -rf /", RiskLevel::Danger),
+        ("sudo -u root -- rm -rf /", RiskLevel::Danger),
+        ("env FOO=1 rm -rf ~", RiskLevel::Danger),
+        ("env", RiskLevel::Safe),
+        ("nohup rm -rf / &", RiskLevel::Danger),
+        ("nice -n 10 rm -rf ~", RiskLevel::Danger),
+        ("timeout 5 rm -rf /", RiskLevel::Danger),
+        ("time ls", RiskLevel::Safe),
+        ("command -v rm", RiskLevel::Safe),
+        ("exec rm -rf ~", RiskLevel::Danger),
+        ("su -c 'rm -rf /'", RiskLevel::Danger),
+        ("su - root -c \"ls; rm -rf ~\"", RiskLevel::Danger),
+        ("su --command='rm -rf /'", RiskLevel::Danger),
+        ("su -lc 'echo hi'", RiskLevel::Caution),
+        ("su", RiskLevel::Caution),
+        ("busybox rm -rf /", RiskLevel::Danger),
+        ("toybox rm -rf ~", RiskLevel::Danger),
+        ("busybox ls", RiskLevel::Safe),
+        ("doas rm -rf /", RiskLevel::Danger),
+        ("doas -u backup rm -rf ~", RiskLevel::Danger),
+        ("nohup nice rm -rf ~ &", RiskLevel::Danger),
+        ("env -i PATH=/bin rm -rf /", RiskLevel::Danger),
+        ("env -i -- rm -rf ~", RiskLevel::Danger),
+        ("sh -c 'rm -rf /'", RiskLevel::Danger),
+        ("bash -lc \"ls; rm -rf ~\"", RiskLevel::Danger),
+        ("bash -c 'echo hi'", RiskLevel::Safe),
+        ("zsh -c \"echo 'rm -rf /'\"", RiskLevel::Safe),
+        ("eval \"rm -rf /\"", RiskLevel::Danger),
+        ("eval echo hi", RiskLevel::Safe),
+        ("watch -n 1 'rm -rf ~'", RiskLevel::Danger),
+        ("find / -exec rm -rf {} \\;", RiskLevel::Danger),
+        ("find ~ -maxdepth 0 -exec rm -rf {} +", RiskLevel::Danger),
+        (
+            "find /home/alex -type f -execdir /bin/rm {} +",
+            RiskLevel::Danger,
+        ),
+        ("find / -name '*.log' -delete", RiskLevel::Danger),
+        ("find ~ -delete", RiskLevel::Danger),
+        ("find . -name '*.pyc' -delete", RiskLevel::Caution),
+        ("find build -exec rm {} \\;", RiskLevel::Caution),
+        ("find / -name '*.conf' -ok rm {} \\;", RiskLevel::Caution),
+        ("find . -name '*.rs'", RiskLevel::Safe),
+        ("find . -exec ls -l {} \\;", RiskLevel::Safe),
+        ("xargs -n 1 rm -rf / < list", RiskLevel::Danger),
+        ("bash script.sh", RiskLevel::Unknown),
+        // rm
+        ("rm notes.txt", RiskLevel::Caution),
+        ("rm -rf node_modules", RiskLevel::Caution),
+        ("rm -r -f build/", RiskLevel::Caution),
+        ("rm -rf /tmp/build", RiskLevel::Caution),
+        ("rm -rf ./", RiskLevel::Caution),
+        ("rm -fr /", RiskLevel::Danger),
+        ("rm -Rf /usr", RiskLevel::Danger),
+        ("rm -rf /etc/", RiskLevel::Danger),
+        ("rm --recursive --force ~", RiskLevel::Danger),
+        ("rm -rf ~/*", RiskLevel::Danger),
+        ("rm -rf \"$HOME\"", RiskLevel::Danger),
+        ("rm -rf ${HOME}/", RiskLevel::Danger),
+        ("rm -rf /home/alex", RiskLevel::Danger),
+        ("rm -rf /home/alex/project", RiskLevel::Caution),
+        ("rm -rf --no-preserve-root /", RiskLevel::Danger),
+        ("rm -rf \"$BUILD_DIR/\"*", RiskLevel::Caution),
+        ("rm -- -rf", RiskLevel::Caution),
+        // Permissions
+        ("chmod -R 777 /var/www", RiskLevel::Danger),
+        ("chmod -R o+w .", RiskLevel::Danger),
+        ("chmod 777 script.sh", RiskLevel::Caution),
+        ("chmod +x script.sh", RiskLevel::Caution),
+        ("chmod -R 755 /", RiskLevel::Danger),
+        ("chmod -x script.sh", RiskLevel::Caution),
+        ("sudo chown -R alex /", RiskLevel::Danger),
+        ("chown alex file", RiskLevel::Caution),
+        // Disks and devices
+        ("dd if=/dev/zero of=/dev/sda bs=1M", RiskLevel::Danger),
+        ("mkfs.ext4 /dev/sdb1", RiskLevel::Danger),
+        ("sudo wipefs -a /dev/sdb", RiskLevel::Danger),
+        ("fdisk -l", RiskLevel::Safe),
+        ("shred -u secrets.txt", RiskLevel::Danger),
+        ("echo hi > /dev/sda", RiskLevel::Danger),
+        ("cat image.iso >/dev/disk2", RiskLevel::Danger),
+        ("ls > /dev/null 2>&1", RiskLevel::Safe),
+        ("make 2>/dev/null", RiskLevel::Unknown),
+        ("echo hi >&2", RiskLevel::Safe),
+        ("ls > listing.txt", RiskLevel::Caution),
+        ("echo line >> notes.txt", RiskLevel::Caution),
+        ("echo 127.0.0.1 > /etc/hosts", RiskLevel::Danger),
+        ("cat < input.txt", RiskLevel::Safe),
+        ("[[ $a > 1 ]] && ls", RiskLevel::Safe),
+        // Fork bombs
+        (":(){ :|:& };:", RiskLevel::Danger),
+        (":() { : | : & } ; :", RiskLevel::Danger),
+        ("bomb() { bomb | bomb & }; bomb", RiskLevel::Danger),
+        ("greet() { echo hi; }; greet", RiskLevel::Safe),
+        // git
+        ("git status", RiskLevel::Safe),
+        ("git log --oneline | head", RiskLevel::Safe),
+        ("git -C repo diff", RiskLevel::Safe),
+        ("git push --force origin main", RiskLevel::Caution),
+        ("git push -f", RiskLevel::Caution),
+        ("git push origin +main", RiskLevel::Caution),
+        ("git reset --hard HEAD~1", RiskLevel::Caution),
+        ("git clean -fdx", RiskLevel::Caution),
+        ("git checkout -- .", RiskLevel::Caution),
+        ("git branch -D feature", RiskLevel::Caution),
+        ("git branch", RiskLevel::Safe),
+        ("git commit -m 'rm -rf /'", RiskLevel::Unknown),
+        // Packages and other changes
+        ("sudo apt install htop", RiskLevel::Caution),
+        ("apt search htop", RiskLevel::Safe),
+        ("npm install", RiskLevel::Caution),
+        ("pip install requests", RiskLevel::Caution),
+        ("cargo install ripgrep", RiskLevel::Caution),
+        ("cargo build", RiskLevel::Unknown),
+        ("mv a.txt b.txt", RiskLevel::Caution),
+        ("cp -r src backup", RiskLevel::Caution),
+        ("sed -i 's/a/b/' file", RiskLevel::Caution),
+        ("sed 's/a/b/' file", RiskLevel::Unknown),
+        ("echo x | sudo tee /etc/motd", RiskLevel::Caution),
+        ("pkill firefox", RiskLevel::Caution),
+        ("crontab -r", RiskLevel::Danger),
+        ("crontab -l", RiskLevel::Safe),
+        ("systemctl status sshd", RiskLevel::Safe),
+        ("sudo systemctl stop sshd", RiskLevel::Caution),
+        ("rsync -a --delete src/ dst/", RiskLevel::Caution),
+        ("mkdir -p build && touch build/.keep", RiskLevel::Safe),
+        // Malformed or odd input never panics
+        ("", RiskLevel::Unknown),
+        ("   ", RiskLevel::Unknown),
+        ("rm -rf 'unterminated", RiskLevel::Caution),
+        ("echo $(rm -rf ~", RiskLevel::Danger),
+        (";;", RiskLevel::Unknown),
+        ("))) rm -rf /", RiskLevel::Danger),
+    ];
+
+    #[test]
+    fn test_corpus() {
+        let safe_mode = SafeMode::new();
+        let mismatches: Vec<String> = CORPUS
+            .iter()
+            .filter_map(|&(command, expected)| {
+                let preview = safe_mode.analyze(command, Path::new("/nonexistent"));
+                (preview.risk != expected)
+                    .then(|| format!("{command:?}: expected {expected:?}, got {:?}", preview.risk))
+            })
+            .collect();
+        assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    }
+
+    #[test]
+    fn test_each_command_is_reported() {
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("ls; sudo rm -rf ~", Path::new("/nonexistent"));
+        let risks: Vec<_> = preview
+            .commands
+            .iter()
+            .map(|c| (c.command.as_str(), c.risk))
+            .collect();
+        assert_eq!(
+            risks,
+            [
+                ("ls", RiskLevel::Safe),
+                ("sudo rm -rf ~", RiskLevel::Danger)
+            ]
+        );
+        assert!(preview.needs_sudo);
+        assert!(preview
+            .explanation
+            .iter()
+            .any(|e| e.contains("home directory")));
+        assert!(!preview.explanation.iter().any(|e| e.contains("read-only")));
+    }
+
+    #[test]
+    fn test_quoted_commands_are_not_flagged() {
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("echo \"rm -rf /\"", Path::new("/"));
+        assert_eq!(
+            preview.explanation,
+            ["This is a safe, read-only command", "echo: Print text"]
+        );
+        assert!(preview.alternatives.is_empty());
+        assert!(preview.affected_files.is_empty());
+    }
+
+    #[test]
+    fn test_undo_hints_and_network() {
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("git push --force", Path::new("/"));
+        assert_eq!(
+            preview.undo_hint.as_deref(),
+            Some("git reflog to find lost commits")
+        );
+        assert!(preview.network_access);
+        assert!(!safe_mode.analyze("ls", Path::new("/")).network_access);
+        assert!(
+            safe_mode
+                .analyze("ls | curl -T - https://example.com", Path::new("/"))
+                .network_access
+        );
+    }
+
+    #[test]
+    fn test_trash_rewrite_replaces_rm_undo_hint() {
+        let mut safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("rm -rf build", Path::new("/"));
+        assert_eq!(preview.trash_rewrite, None);
+        assert!(!preview.undo_hint.unwrap().contains("CorgiTerm trash"));
+
+        safe_mode.set_trash_program(Some("corgiterm".to_string()));
+        let preview = safe_mode.analyze("rm -rf build", Path::new("/"));
+        assert_eq!(
+            preview.trash_rewrite.as_deref(),
+            Some("'corgiterm' trash put -rf build")
+        );
+        assert!(preview.undo_hint.unwrap().contains("corgiterm trash undo"));
+        assert_eq!(
+            safe_mode
+                .analyze("sudo rm -rf build", Path::new("/"))
+                .trash_rewrite,
+            None
+        );
+    }
+
+    #[test]
+    fn test_affected_files_come_from_parsed_operands() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-safe-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a b.txt"), "hello").unwrap();
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("ls && rm -f 'a b.txt' missing", &dir);
+        assert_eq!(preview.affected_files, [dir.join("a b.txt")]);
+        assert_eq!(preview.affected_size, Some(5));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fired_rules_and_alternatives() {
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("cd build && rm -rf dist", Path::new("/"));
+        assert_eq!(preview.commands[1].rules, ["rm", "rm-recursive-force"]);
+        let alternatives: Vec<_> = preview.alternatives.iter().map(|a| &a.command).collect();
+        assert_eq!(
+            alternatives,
+            ["cd build && trash-put dist", "cd build && rm -ri dist"]
+        );
+        let preview = safe_mode.analyze("sudo rm -r ~/old", Path::new("/"));
+        assert_eq!(preview.alternatives[0].command, "sudo trash-put ~/old");
+        let preview = safe_mode.analyze("sudo mkfs.ext4 /dev/sdb1", Path::new("/"));
+        assert_eq!(preview.commands[0].rules, ["sudo", "mkfs"]);
+    }
+
+    #[test]
+    fn test_project_rules_apply_below_their_directory() {
+        let root = std::env::temp_dir().join(format!("corgiterm-safe-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join(".corgiterm")).unwrap();
+        std::fs::write(
+            root.join(crate::safe_mode_rules::PROJECT_RULES_FILE),
+            "[[rule]]\nname = \"deploy\"\ncommand = \"deploy.sh\"\nrisk = \"danger\"\n\n\
+             [[rule]]\nname = \"rm\"\ncommand = \"rm\"\nrisk = \"safe\"\n",
+        )
+        .unwrap();
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("./deploy.sh", &root);
+        assert_eq!(preview.risk, RiskLevel::Danger);
+        assert_eq!(
+            safe_mode.analyze("./deploy.sh", Path::new("/")).risk,
+            RiskLevel::Unknown
+        );
+        // Project rules cannot loosen built-in ones
+        assert_eq!(
+            safe_mode.analyze("rm notes.txt", &root).risk,
+            RiskLevel::Caution
+        );
+        let (_, warnings) = safe_mode.rules_for(&root);
+        assert_eq!(warnings.len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_production_checks_sql_fed_to_clients() {
+        let safe_mode = SafeMode::new();
+        let risk = |command: &str| {
+            safe_mode
+                .classify_with_profile(command, None, Some("production"))
+                .risk
+        };
+        for command in [
+            "echo 'DROP TABLE users' | psql",
+            "psql <<< 'drop table x'",
+            "sudo -u postgres psql app <<SQL\nBEGIN;\nDELETE FROM users;\nSQL",
+            "printf 'truncate table logs;' | mysql -u root",
+            "echo FLUSHALL | redis-cli",
+        ] {
+            assert_eq!(risk(command), RiskLevel::Danger, "{command}");
+        }
+        for command in [
+            "echo 'SELECT 1' | psql",
+            "echo 'drop table x' > f; psql < f",
+            "echo 'drop table x' | grep -v drop | psql",
+            "psql <<< 'drop table x' < queries.sql",
+            "cat <<EOF\ndrop table x\nEOF",
+        ] {
+            assert_ne!(risk(command), RiskLevel::Danger, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_profile_escalates_commands() {
+        let safe_mode = SafeMode::new();
+        let cwd = Path::new("/");
+        let command = "kubectl delete deployment web && terraform apply";
+        assert_eq!(safe_mode.analyze(command, cwd).risk, RiskLevel::Unknown);
+        let preview = safe_mode.analyze_with_profile(command, cwd, Some("production"));
+        assert_eq!(preview.risk, RiskLevel::Danger);
+        assert_eq!(preview.profile.as_deref(), Some("production"));
+        assert_eq!(
+            safe_mode
+                .analyze_with_profile("psql -c 'DROP TABLE users'", cwd, Some("production"))
+                .risk,
+            RiskLevel::Danger
+        );
+
+        let (_, warnings) = safe_mode.rules_with_profile(cwd, Some("missing"));
+        assert_eq!(warnings, ["unknown Safe Mode profile \"missing\""]);
+        assert_eq!(
+            safe_mode
+                .analyze_with_profile(command, cwd, Some("missing"))
+                .risk,
+            RiskLevel::Unknown
+        );
+    }
+
+    #[test]
+    fn test_preview_lists_dry_run_impact() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-safe-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("cache/nested")).unwrap();
+        std::fs::write(dir.join("cache/nested/blob"), "0123456789").unwrap();
+        std::fs::write(dir.join("notes.txt"), "keep me").unwrap();
+        let safe_mode = SafeMode::new();
+
+        let preview = safe_mode.analyze("rm -r ./cac* && echo done > notes.txt", &dir);
+        assert_eq!(preview.affected_count, Some(4));
+        assert_eq!(preview.affected_size, Some(17));
+        assert_eq!(
+            preview.affected_files,
+            [dir.join("cache"), dir.join("notes.txt")]
+        );
+        assert_eq!(preview.impact[0].effect, FileEffect::Delete);
+        assert_eq!(preview.impact[1].effect, FileEffect::Overwrite);
+        assert!(!preview.impact_truncated);
+
+        // Appending and quoted globs leave existing files alone
+        let preview = safe_mode.analyze("echo more >> notes.txt; rm -r 'cac*'", &dir);
+        assert!(preview.impact.is_empty());
+        assert_eq!(preview.affected_count, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_classify_skips_dry_run_and_trash() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-safe-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("cache")).unwrap();
+        std::fs::write(dir.join("cache/blob"), "0123456789").unwrap();
+        let mut safe_mode = SafeMode::new();
+        safe_mode.set_trash_program(Some("corgiterm".to_string()));
+
+        let preview = safe_mode.classify_with_profile("rm -rf cache", Some(&dir), None);
+        assert_eq!(preview.risk, RiskLevel::Caution);
+        assert!(preview.impact.is_empty());
+        assert_eq!(preview.affected_count, None);
+        assert_eq!(preview.trash_rewrite, None);
+        assert!(!preview.alternatives.is_empty());
+
+        let preview = safe_mode.classify_with_profile("rm -rf ~", None, Some("production"));
+        assert_eq!(preview.risk, RiskLevel::Danger);
+        assert_eq!(preview.profile.as_deref(), Some("production"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_judge_keeps_trash_rewrite_without_dry_run() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-safe-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("cache")).unwrap();
+        std::fs::write(dir.join("cache/blob"), "0123456789").unwrap();
+        let mut safe_mode = SafeMode::new();
+        safe_mode.set_trash_program(Some("corgiterm".to_string()));
+
+        let judged = safe_mode.judge_with_profile("rm -rf cache", &dir, None);
+        let analyzed = safe_mode.analyze_with_profile("rm -rf cache", &dir, None);
+        assert_eq!(judged.risk, analyzed.risk);
+        assert_eq!(judged.trash_rewrite, analyzed.trash_rewrite);
+        assert!(judged.trash_rewrite.is_some());
+        assert!(judged.impact.is_empty());
+        assert_eq!(analyzed.affected_count, Some(2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preview_explains_flags_offline() {
+        let safe_mode = SafeMode::new();
+        let preview = safe_mode.analyze("git push --force origin main", Path::new("/"));
+        assert_eq!(preview.breakdown.len(), 1);
+        assert_eq!(preview.breakdown[0].name, "git push");
+        assert!(preview
+            .explanation
+            .contains(&"git push: Upload local commits to a remote repository".to_string()));
+        let force = &preview.breakdown[0].words[2];
+        assert_eq!(force.text, "--force");
+        assert!(force.meaning.as_deref().unwrap().contains("Overwrite"));
+    }
 }
@@ -0,0 +1,1034 @@
+//! Dry runs for Safe Mode previews
+//!
+//! Before a destructive command runs, Safe Mode works out what it would
+//! touch. Operands are expanded the way the shell would (`~`, `{a,b}`,
+//! `*.log`) against the real filesystem, directories given to `rm -r` are
+//! walked for their size, existing `mv`/`cp`/`>` targets are reported as
+//! overwritten, `git clean` and `git reset --hard` ask git what they would
+//! remove or discard, and `find ... -delete` is evaluated without deleting.
+//!
+//! Nothing here changes the filesystem. Walks stop after [`MAX_ENTRIES`]
+//! entries or [`MAX_DURATION`], in which case the totals are lower bounds,
+//! and git is given up on after [`GIT_TIMEOUT`].
+
+use crate::safe_mode_rules::{glob, split_options, subcommand};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Entries looked at before a dry run stops counting
+pub const MAX_ENTRIES: usize = 20_000;
+
+/// Time spent walking before a dry run stops counting
+pub const MAX_DURATION: Duration = Duration::from_millis(200);
+
+/// Time a git command may take before the dry run goes on without it
+pub const GIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Expansions of one word beyond this are not followed
+const MAX_EXPANSIONS: usize = 1_000;
+
+/// What a command would do to a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileEffect {
+    /// Removed
+    Delete,
+    /// Replaced by another file, or emptied by a `>` redirection
+    Overwrite,
+    /// Uncommitted changes are thrown away
+    DiscardChanges,
+}
+
+impl FileEffect {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Delete => "deleted",
+            Self::Overwrite => "overwritten",
+            Self::DiscardChanges => "changes discarded",
+        }
+    }
+}
+
+/// One path a command would affect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathImpact {
+    pub path: PathBuf,
+    pub effect: FileEffect,
+    /// Files and directories at or below the path
+    pub entries: usize,
+    /// Size of those files
+    pub bytes: u64,
+}
+
+/// A word from the command line
+#[derive(Debug, Clone)]
+pub(crate) struct Operand {
+    pub text: String,
+    /// Quoted words are taken literally
+    pub quoted: bool,
+}
+
+/// Something a command would do to files, noted while classifying it
+#[derive(Debug, Clone)]
+pub(crate) enum Effect {
+    /// `rm` and `unlink`
+    Remove {
+        operands: Vec<Operand>,
+        recursive: bool,
+    },
+    /// `mv` and `cp`, with all of their arguments
+    Transfer { args: Vec<Operand> },
+    /// A `>` redirection
+    Truncate { target: Operand },
+    /// `find ... -delete`
+    FindDelete { args: Vec<Operand> },
+    /// `git clean` and `git reset --hard`, with all of git's arguments
+    Git { args: Vec<String> },
+}
+
+/// What a dry run found
+#[derive(Debug, Default)]
+pub(crate) struct Impact {
+    /// Affected paths, largest first
+    pub paths: Vec<PathImpact>,
+    /// Entries across all paths
+    pub entries: usize,
+    pub bytes: u64,
+    /// The run stopped early, so the totals are lower bounds
+    pub truncated: bool,
+}
+
+/// How much of a path to count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Everything below a directory
+    Tree,
+    /// The entry itself, even if it is a directory
+    Entry,
+    /// Only if it is not a directory
+    File,
+    /// Only if it is a regular file
+    RegularFile,
+}
+
+/// Work out what `effects` would do, relative to `cwd`
+pub(crate) fn dry_run(effects: &[Effect], cwd: &Path) -> Impact {
+    let mut run = DryRun {
+        cwd,
+        budget: MAX_ENTRIES,
+        deadline: Instant::now() + MAX_DURATION,
+        truncated: false,
+        seen: HashSet::new(),
+        paths: Vec::new(),
+    };
+    for effect in effects {
+        match effect {
+            Effect::Remove {
+                operands,
+                recursive,
+            } => {
+                let scope = if *recursive { Scope::Tree } else { Scope::File };
+                for operand in operands {
+                    for path in run.expand(operand) {
+                        run.record(path, FileEffect::Delete, scope);
+                    }
+                }
+            }
+            Effect::Transfer { args } => run.transfer(args),
+            Effect::Truncate { target } => {
+                for path in run.expand(target) {
+                    run.record(path, FileEffect::Overwrite, Scope::RegularFile);
+                }
+            }
+            Effect::FindDelete { args } => run.find(args),
+            Effect::Git { args } => run.git(args),
+        }
+    }
+
+    let mut paths = run.paths;
+    paths.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then(b.entries.cmp(&a.entries))
+            .then(a.path.cmp(&b.path))
+    });
+    Impact {
+        entries: paths.iter().map(|p| p.entries).sum(),
+        bytes: paths.iter().map(|p| p.bytes).sum(),
+        paths,
+        truncated: run.truncated,
+    }
+}
+
+struct DryRun<'a> {
+    cwd: &'a Path,
+    /// Entries left to look at
+    budget: usize,
+    deadline: Instant,
+    truncated: bool,
+    seen: HashSet<PathBuf>,
+    paths: Vec<PathImpact>,
+}
+
+impl DryRun<'_> {
+    fn record(&mut self, path: PathBuf, effect: FileEffect, scope: Scope) {
+        let Ok(metadata) = path.symlink_metadata() else {
+            return;
+        };
+        let counted = match scope {
+            Scope::Tree | Scope::Entry => true,
+            Scope::File => !metadata.is_dir(),
+            Scope::RegularFile => metadata.is_file(),
+        };
+        if !counted || !self.seen.insert(path.clone()) {
+            return;
+        }
+        let (entries, bytes) = if scope == Scope::Tree && metadata.is_dir() {
+            self.measure(&path)
+        } else {
+            (
+                1,
+                if metadata.is_file() {
+                    metadata.len()
+                } else {
+                    0
+                },
+            )
+        };
+        self.paths.push(PathImpact {
+            path,
+            effect,
+            entries,
+            bytes,
+        });
+    }
+
+    /// Count an entry against the budget; false once it is used up
+    fn spend(&mut self) -> bool {
+        // Checking the clock on every entry would cost more than the entry
+        if self.budget == 0 || (self.budget.is_multiple_of(256) && Instant::now() > self.deadline) {
+            self.budget = 0;
+            self.truncated = true;
+            return false;
+        }
+        self.budget -= 1;
+        true
+    }
+
+    /// Entries and bytes in a directory tree, without following symlinks
+    fn measure(&mut self, root: &Path) -> (usize, u64) {
+        let mut entries = 0;
+        let mut bytes = 0;
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            if !self.spend() {
+                break;
+            }
+            entries += 1;
+            let Ok(children) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for child in children.flatten() {
+                let Ok(metadata) = child.path().symlink_metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    pending.push(child.path());
+                } else if self.spend() {
+                    entries += 1;
+                    if metadata.is_file() {
+                        bytes += metadata.len();
+                    }
+                } else {
+                    return (entries, bytes);
+                }
+            }
+        }
+        (entries, bytes)
+    }
+
+    /// Existing files that `mv` or `cp` would replace
+    fn transfer(&mut self, args: &[Operand]) {
+        let mut target_dir: Option<Operand> = None;
+        let mut no_target_dir = false;
+        let mut operands = Vec::new();
+        let mut options_ended = false;
+        let mut index = 0;
+        while index < args.len() {
+            let arg = &args[index];
+            index += 1;
+            let text = arg.text.as_str();
+            if options_ended || !text.starts_with('-') || text == "-" {
+                operands.push(arg);
+                continue;
+            }
+            let value = |text: &str| Operand {
+                text: text.to_string(),
+                quoted: arg.quoted,
+            };
+            match text {
+                "--" => options_ended = true,
+                "-t" | "--target-directory" => {
+                    target_dir = args.get(index).cloned();
+                    index += 1;
+                }
+                "-S" | "--suffix" => index += 1,
+                "-T" | "--no-target-directory" => no_target_dir = true,
+                // Nothing is lost when existing files are kept or backed up
+                "-n" | "--no-clobber" | "--update=none" => return,
+                _ if text.starts_with("--backup") => return,
+                _ if text.starts_with("--target-directory=") => {
+                    target_dir = Some(value(&text["--target-directory=".len()..]));
+                }
+                _ if !text.starts_with("--") => {
+                    let flags = &text[1..];
+                    if flags.contains(['n', 'b']) {
+                        return;
+                    }
+                    no_target_dir |= flags.contains('T');
+                    // A value option ends its bundle; the value may follow
+                    if let Some(at) = flags.find(['t', 'S']) {
+                        let attached = &flags[at + 1..];
+                        let value = if attached.is_empty() {
+                            index += 1;
+                            args.get(index - 1).cloned()
+                        } else {
+                            Some(value(attached))
+                        };
+                        if flags.as_bytes()[at] == b't' {
+                            target_dir = value;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (dir, sources) = match &target_dir {
+            Some(dir) => (self.expand(dir), &operands[..]),
+            None => {
+                let Some((destination, sources)) = operands.split_last() else {
+                    return;
+                };
+                let destinations = self.expand(destination);
+                match destinations.as_slice() {
+                    [destination] if !sources.is_empty() => {
+                        if no_target_dir || !destination.is_dir() {
+                            self.record(destination.clone(), FileEffect::Overwrite, Scope::File);
+                            return;
+                        }
+                    }
+                    _ => return,
+                }
+                (destinations, sources)
+            }
+        };
+        let [dir] = dir.as_slice() else {
+            return;
+        };
+        for source in sources {
+            for path in self.expand(source) {
+                if let Some(name) = path.file_name() {
+                    self.record(dir.join(name), FileEffect::Overwrite, Scope::File);
+                }
+            }
+        }
+    }
+
+    /// What `find` would delete, by evaluating its expression
+    fn find(&mut self, args: &[Operand]) {
+        let split = args
+            .iter()
+            .position(|arg| arg.text.starts_with('-') || arg.text == "(" || arg.text == "!")
+            .unwrap_or(args.len());
+        let Some(expression) = FindExpression::parse(
+            &args[split..]
+                .iter()
+                .map(|a| a.text.as_str())
+                .collect::<Vec<_>>(),
+        ) else {
+            tracing::debug!("Safe Mode cannot simulate this find expression");
+            return;
+        };
+        let start_operands = if split == 0 {
+            vec![Operand {
+                text: ".".to_string(),
+                quoted: true,
+            }]
+        } else {
+            args[..split].to_vec()
+        };
+        let now = SystemTime::now();
+        for start in &start_operands {
+            for root in self.expand(start) {
+                let display =
+                    if start.quoted || !start.text.contains(['*', '?', '[', '{', '~', '$']) {
+                        start.text.trim_end_matches('/').to_string()
+                    } else {
+                        root.to_string_lossy().into_owned()
+                    };
+                let display = if display.is_empty() {
+                    "/".to_string()
+                } else {
+                    display
+                };
+                let mut pending = vec![(root, display, 0)];
+                while let Some((path, display, depth)) = pending.pop() {
+                    if !self.spend() {
+                        return;
+                    }
+                    let Ok(metadata) = path.symlink_metadata() else {
+                        continue;
+                    };
+                    if depth >= expression.min_depth
+                        && expression.matches(&path, &display, &metadata, now)
+                    {
+                        self.record(path.clone(), FileEffect::Delete, Scope::Entry);
+                    }
+                    if metadata.is_dir() && expression.max_depth.is_none_or(|max| depth < max) {
+                        let Ok(children) = std::fs::read_dir(&path) else {
+                            continue;
+                        };
+                        for child in children.flatten() {
+                            let name = child.file_name().to_string_lossy().into_owned();
+                            let child_display = if display.ends_with('/') {
+                                format!("{display}{name}")
+                            } else {
+                                format!("{display}/{name}")
+                            };
+                            pending.push((child.path(), child_display, depth + 1));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// What `git clean` would remove or `git reset --hard` would discard,
+    /// from git itself
+    fn git(&mut self, args: &[String]) {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let Some((command, rest)) = subcommand("git", &args) else {
+            return;
+        };
+        // Only `-C` is passed on; other global options could run programs
+        let mut dir = self.cwd.to_path_buf();
+        let globals = &args[..args.len() - rest.len() - 1];
+        for pair in globals.windows(2) {
+            if pair[0] == "-C" {
+                dir = dir.join(pair[1]);
+            }
+        }
+
+        match command {
+            "clean" => {
+                let (options, _) = split_options(rest);
+                let dry_run_already = options
+                    .iter()
+                    .any(|o| *o == "--dry-run" || (!o.starts_with("--") && o.contains('n')));
+                let forced = options
+                    .iter()
+                    .any(|o| *o == "--force" || (!o.starts_with("--") && o.contains('f')));
+                if dry_run_already || !forced {
+                    return;
+                }
+                let mut clean_args = vec!["clean".to_string(), "-n".to_string()];
+                for arg in rest {
+                    if matches!(*arg, "--force" | "--interactive") {
+                        continue;
+                    }
+                    if let Some(flags) = arg.strip_prefix('-').filter(|f| !f.starts_with('-')) {
+                        let flags: String = flags.chars().filter(|c| !"fi".contains(*c)).collect();
+                        if !flags.is_empty() {
+                            clean_args.push(format!("-{flags}"));
+                        }
+                        continue;
+                    }
+                    clean_args.push(arg.to_string());
+                }
+                let Some(output) = run_git(&dir, &clean_args) else {
+                    return;
+                };
+                for line in output.lines() {
+                    if let Some(path) = line.strip_prefix("Would remove ") {
+                        let path = path.trim_end_matches('/');
+                        self.record(dir.join(path), FileEffect::Delete, Scope::Tree);
+                    }
+                }
+            }
+            "reset" if rest.contains(&"--hard") => {
+                let (_, operands) = split_options(rest);
+                let commit = operands.first().copied().unwrap_or("HEAD");
+                let Some(top) = run_git(&dir, &["rev-parse", "--show-toplevel"]) else {
+                    return;
+                };
+                let top = PathBuf::from(top.trim_end());
+                let Some(changed) = run_git(&dir, &["diff", "--name-only", "-z", commit, "--"])
+                else {
+                    return;
+                };
+                for name in changed.split('\0').filter(|name| !name.is_empty()) {
+                    self.record(top.join(name), FileEffect::DiscardChanges, Scope::File);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Paths an operand names, expanded like the shell would; words whose
+    /// value cannot be known here (`$DIR`, `$(...)`) name nothing
+    fn expand(&self, operand: &Operand) -> Vec<PathBuf> {
+        let Some(text) = expand_home(&operand.text) else {
+            return Vec::new();
+        };
+        if operand.quoted {
+            return vec![resolve(self.cwd, &text)];
+        }
+        let mut paths = Vec::new();
+        for word in expand_braces(&text) {
+            if word.contains(['*', '?', '[']) {
+                paths.extend(expand_glob(self.cwd, &word));
+            } else {
+                paths.push(resolve(self.cwd, &word));
+            }
+            if paths.len() >= MAX_EXPANSIONS {
+                break;
+            }
+        }
+        paths
+    }
+}
+
+/// Output of a successful git command; `None` when it fails or takes longer
+/// than [`GIT_TIMEOUT`]
+fn run_git(dir: &Path, args: &[impl AsRef<std::ffi::OsStr>]) -> Option<String> {
+    let mut child = Command::new("git")
+        // A repository's own config must not start programs during a preview
+        .args(["-c", "core.fsmonitor=false"])
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    // Read while waiting, so a full pipe cannot stall git
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + GIT_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            _ => {
+                tracing::debug!("Safe Mode dry run: git did not finish in time");
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    let output = reader.join().ok()?.ok()?;
+    status
+        .success()
+        .then(|| String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Replace a leading `~`, `$HOME` or `${HOME}`; `None` if another
+/// parameter or substitution is left
+fn expand_home(text: &str) -> Option<String> {
+    let home = || dirs::home_dir().map(|home| home.to_string_lossy().into_owned());
+    let mut expanded = text.to_string();
+    for prefix in ["~", "$HOME", "${HOME}"] {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            if rest.is_empty() || rest.starts_with('/') {
+                expanded = format!("{}{}", home()?, rest);
+                break;
+            }
+        }
+    }
+    (!expanded.contains('$') && !expanded.contains('`')).then_some(expanded)
+}
+
+/// `dir` joined with `text`, without `.` components
+fn resolve(cwd: &Path, text: &str) -> PathBuf {
+    cwd.join(text)
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Expand `{a,b}` lists and `{1..3}` ranges, left to right
+fn expand_braces(word: &str) -> Vec<String> {
+    let mut done = Vec::new();
+    let mut pending = vec![word.to_string()];
+    while let Some(word) = pending.pop() {
+        if done.len() + pending.len() >= MAX_EXPANSIONS {
+            done.push(word);
+            continue;
+        }
+        match brace_alternatives(&word) {
+            Some((prefix, alternatives, suffix)) => {
+                for alternative in alternatives.iter().rev() {
+                    pending.push(format!("{prefix}{alternative}{suffix}"));
+                }
+            }
+            None => done.push(word),
+        }
+    }
+    done
+}
+
+/// The first brace expression in `word`: the text before it, its
+/// alternatives and the text after it
+fn brace_alternatives(word: &str) -> Option<(&str, Vec<String>, &str)> {
+    let bytes = word.as_bytes();
+    for (open, _) in word.match_indices('{') {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for (offset, &byte) in bytes[open..].iter().enumerate() {
+            match byte {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(open + offset);
+                        break;
+                    }
+                }
+                b',' if depth == 1 => commas.push(open + offset),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+        let (prefix, inner, suffix) = (&word[..open], &word[open + 1..close], &word[close + 1..]);
+        if !commas.is_empty() {
+            let mut alternatives = Vec::new();
+            let mut start = open + 1;
+            for comma in commas.into_iter().chain([close]) {
+                alternatives.push(word[start..comma].to_string());
+                start = comma + 1;
+            }
+            return Some((prefix, alternatives, suffix));
+        }
+        if let Some((from, to)) = inner.split_once("..") {
+            if let (Ok(from), Ok(to)) = (from.parse::<i64>(), to.parse::<i64>()) {
+                let range: Vec<String> = if from <= to {
+                    (from..=to)
+                        .take(MAX_EXPANSIONS)
+                        .map(|n| n.to_string())
+                        .collect()
+                } else {
+                    (to..=from)
+                        .rev()
+                        .take(MAX_EXPANSIONS)
+                        .map(|n| n.to_string())
+                        .collect()
+                };
+                return Some((prefix, range, suffix));
+            }
+        }
+    }
+    None
+}
+
+/// Existing paths matching a glob; hidden names only match patterns that
+/// start with `.`
+fn expand_glob(cwd: &Path, pattern: &str) -> Vec<PathBuf> {
+    let (mut matches, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec![PathBuf::from("/")], rest),
+        None => (vec![cwd.to_path_buf()], pattern),
+    };
+    for component in rest.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        if !component.contains(['*', '?', '[']) {
+            for path in &mut matches {
+                path.push(component);
+            }
+            continue;
+        }
+        let mut next = Vec::new();
+        for dir in &matches {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| glob(component, name, true))
+                .collect();
+            names.sort();
+            next.extend(names.into_iter().map(|name| dir.join(name)));
+            if next.len() >= MAX_EXPANSIONS {
+                break;
+            }
+        }
+        matches = next;
+    }
+    matches.retain(|path| path.symlink_metadata().is_ok());
+    matches
+}
+
+/// The tests in a `find` expression that Safe Mode can evaluate
+#[derive(Debug, Default)]
+struct FindExpression {
+    tests: Vec<(bool, FindTest)>,
+    min_depth: usize,
+    max_depth: Option<usize>,
+}
+
+#[derive(Debug)]
+enum FindTest {
+    Name {
+        pattern: String,
+        ignore_case: bool,
+    },
+    Path {
+        pattern: String,
+        ignore_case: bool,
+    },
+    Type(Vec<char>),
+    Empty,
+    /// Age in whole units of `seconds`, compared with `+n`, `-n` or `n`
+    Age {
+        seconds: u64,
+        comparison: char,
+        value: u64,
+    },
+}
+
+impl FindExpression {
+    /// `None` for expressions with operators or tests it does not know
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut expression = Self::default();
+        let mut negated = false;
+        let mut index = 0;
+        while index < args.len() {
+            let arg = args[index];
+            let value = args.get(index + 1).copied();
+            index += 1;
+            let test = match arg {
+                "!" | "-not" => {
+                    negated = !negated;
+                    continue;
+                }
+                "-delete" | "-print" | "-depth" | "-xdev" | "-mount" | "-a" | "-and" => continue,
+                "-maxdepth" | "-mindepth" => {
+                    let depth = value?.parse().ok()?;
+                    index += 1;
+                    if arg == "-maxdepth" {
+                        expression.max_depth = Some(depth);
+                    } else {
+                        expression.min_depth = depth;
+                    }
+                    continue;
+                }
+                "-name" | "-iname" => FindTest::Name {
+                    pattern: value?.to_string(),
+                    ignore_case: arg == "-iname",
+                },
+                "-path" | "-ipath" | "-wholename" | "-iwholename" => FindTest::Path {
+                    pattern: value?.to_string(),
+                    ignore_case: arg.starts_with("-i"),
+                },
+                "-type" => {
+                    FindTest::Type(value?.split(',').filter_map(|t| t.chars().next()).collect())
+                }
+                "-empty" => FindTest::Empty,
+                "-mtime" | "-mmin" => {
+                    let value = value?;
+                    let (comparison, number) = match value.chars().next()? {
+                        sign @ ('+' | '-') => (sign, &value[1..]),
+                        _ => ('=', value),
+                    };
+                    FindTest::Age {
+                        seconds: if arg == "-mtime" { 86_400 } else { 60 },
+                        comparison,
+                        value: number.parse().ok()?,
+                    }
+                }
+                _ => return None,
+            };
+            if !matches!(test, FindTest::Empty) {
+                index += 1;
+            }
+            expression.tests.push((negated, test));
+            negated = false;
+        }
+        Some(expression)
+    }
+
+    fn matches(
+        &self,
+        path: &Path,
+        display: &str,
+        metadata: &std::fs::Metadata,
+        now: SystemTime,
+    ) -> bool {
+        self.tests.iter().all(|(negated, test)| {
+            let result = match test {
+                FindTest::Name {
+                    pattern,
+                    ignore_case,
+                } => {
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| display.to_string());
+                    matches_pattern(pattern, &name, *ignore_case)
+                }
+                FindTest::Path {
+                    pattern,
+                    ignore_case,
+                } => matches_pattern(pattern, display, *ignore_case),
+                FindTest::Type(types) => {
+                    let file_type = metadata.file_type();
+                    types.iter().any(|t| match t {
+                        'f' => file_type.is_file(),
+                        'd' => file_type.is_dir(),
+                        'l' => file_type.is_symlink(),
+                        _ => false,
+                    })
+                }
+                FindTest::Empty => {
+                    if metadata.is_dir() {
+                        std::fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+                    } else {
+                        metadata.is_file() && metadata.len() == 0
+                    }
+                }
+                FindTest::Age {
+                    seconds,
+                    comparison,
+                    value,
+                } => {
+                    let age = metadata
+                        .modified()
+                        .ok()
+                        .and_then(|modified| now.duration_since(modified).ok())
+                        .map_or(0, |age| age.as_secs() / seconds);
+                    match comparison {
+                        '+' => age > *value,
+                        '-' => age < *value,
+                        _ => age == *value,
+                    }
+                }
+            };
+            result != *negated
+        })
+    }
+}
+
+fn matches_pattern(pattern: &str, text: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        glob(&pattern.to_lowercase(), &text.to_lowercase(), false)
+    } else {
+        glob(pattern, text, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Operand {
+        fn plain(text: &str) -> Self {
+            Self {
+                text: text.to_string(),
+                quoted: false,
+            }
+        }
+    }
+
+    fn temp_tree() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("corgiterm-impact-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("build/deep")).unwrap();
+        let root = root.canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("dest")).unwrap();
+        std::fs::write(root.join("build/a.o"), "12345").unwrap();
+        std::fs::write(root.join("build/deep/b.o"), "123").unwrap();
+        std::fs::write(root.join("one.log"), "1").unwrap();
+        std::fs::write(root.join("two.log"), "22").unwrap();
+        std::fs::write(root.join(".hidden.log"), "333").unwrap();
+        std::fs::write(root.join("dest/one.log"), "old").unwrap();
+        root
+    }
+
+    fn paths(impact: &Impact, root: &Path) -> Vec<String> {
+        let mut paths: Vec<String> = impact
+            .paths
+            .iter()
+            .map(|p| p.path.strip_prefix(root).unwrap().display().to_string())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_braces() {
+        assert_eq!(expand_braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_braces("{x,y}{1..2}"), ["x1", "x2", "y1", "y2"]);
+        assert_eq!(expand_braces("{a,{b,c}}"), ["a", "b", "c"]);
+        assert_eq!(expand_braces("${HOME}"), ["${HOME}"]);
+        assert_eq!(expand_braces("{solo}"), ["{solo}"]);
+        assert_eq!(expand_braces("f{3..1}"), ["f3", "f2", "f1"]);
+    }
+
+    #[test]
+    fn test_recursive_removal_is_measured() {
+        let root = temp_tree();
+        let impact = dry_run(
+            &[Effect::Remove {
+                operands: vec![Operand::plain("build/"), Operand::plain("*.log")],
+                recursive: true,
+            }],
+            &root,
+        );
+        assert_eq!(paths(&impact, &root), ["build", "one.log", "two.log"]);
+        let build = &impact.paths[0];
+        assert_eq!((build.entries, build.bytes), (4, 8));
+        assert_eq!((impact.entries, impact.bytes), (6, 11));
+        assert!(!impact.truncated);
+
+        // Without -r a directory is not removed, and quoted globs are literal
+        let impact = dry_run(
+            &[Effect::Remove {
+                operands: vec![
+                    Operand::plain("build"),
+                    Operand {
+                        text: "*.log".to_string(),
+                        quoted: true,
+                    },
+                    Operand::plain("{one,missing}.log"),
+                    Operand::plain("$DIR/one.log"),
+                ],
+                recursive: false,
+            }],
+            &root,
+        );
+        assert_eq!(paths(&impact, &root), ["one.log"]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_overwrites() {
+        let root = temp_tree();
+        let args = |words: &[&str]| Effect::Transfer {
+            args: words.iter().map(|w| Operand::plain(w)).collect(),
+        };
+        let impact = dry_run(&[args(&["*.log", "dest"])], &root);
+        assert_eq!(paths(&impact, &root), ["dest/one.log"]);
+        let impact = dry_run(&[args(&["-t", "dest", "one.log"])], &root);
+        assert_eq!(paths(&impact, &root), ["dest/one.log"]);
+        let impact = dry_run(&[args(&["one.log", "two.log"])], &root);
+        assert_eq!(paths(&impact, &root), ["two.log"]);
+        assert_eq!(impact.paths[0].effect, FileEffect::Overwrite);
+        assert!(dry_run(&[args(&["-n", "one.log", "two.log"])], &root)
+            .paths
+            .is_empty());
+        assert!(dry_run(&[args(&["one.log", "new.log"])], &root)
+            .paths
+            .is_empty());
+
+        let impact = dry_run(
+            &[
+                Effect::Truncate {
+                    target: Operand::plain("two.log"),
+                },
+                Effect::Truncate {
+                    target: Operand::plain("/dev/null"),
+                },
+            ],
+            &root,
+        );
+        assert_eq!(paths(&impact, &root), ["two.log"]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_delete_is_simulated() {
+        let root = temp_tree();
+        let find = |words: &[&str]| Effect::FindDelete {
+            args: words.iter().map(|w| Operand::plain(w)).collect(),
+        };
+        let impact = dry_run(&[find(&[".", "-name", "*.o", "-delete"])], &root);
+        assert_eq!(paths(&impact, &root), ["build/a.o", "build/deep/b.o"]);
+
+        let impact = dry_run(
+            &[find(&[
+                "-maxdepth",
+                "1",
+                "-type",
+                "f",
+                "!",
+                "-name",
+                "t*",
+                "-delete",
+            ])],
+            &root,
+        );
+        assert_eq!(paths(&impact, &root), [".hidden.log", "one.log"]);
+
+        let impact = dry_run(
+            &[find(&["build", "-path", "build/deep*", "-delete"])],
+            &root,
+        );
+        assert_eq!(paths(&impact, &root), ["build/deep", "build/deep/b.o"]);
+
+        // Expressions it cannot evaluate affect nothing rather than guessing
+        assert!(dry_run(
+            &[find(&[".", "-name", "*.o", "-o", "-empty", "-delete"])],
+            &root
+        )
+        .paths
+        .is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_git_clean_and_reset_ask_git() {
+        let root = temp_tree();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&root)
+                .env("GIT_AUTHOR_NAME", "t")
+                .env("GIT_AUTHOR_EMAIL", "t@example.com")
+                .env("GIT_COMMITTER_NAME", "t")
+                .env("GIT_COMMITTER_EMAIL", "t@example.com")
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        if !git(&["init", "-q"]) {
+            // git is not installed
+            std::fs::remove_dir_all(&root).unwrap();
+            return;
+        }
+        assert!(git(&["add", "one.log", "two.log"]));
+        assert!(git(&["commit", "-q", "-m", "init"]));
+        std::fs::write(root.join("one.log"), "changed").unwrap();
+
+        let effect = |words: &[&str]| Effect::Git {
+            args: words.iter().map(|w| w.to_string()).collect(),
+        };
+        let impact = dry_run(&[effect(&["reset", "--hard"])], &root);
+        assert_eq!(paths(&impact, &root), ["one.log"]);
+        assert_eq!(impact.paths[0].effect, FileEffect::DiscardChanges);
+
+        let impact = dry_run(&[effect(&["clean", "-fd"])], &root);
+        assert_eq!(paths(&impact, &root), [".hidden.log", "build", "dest"]);
+        assert!(dry_run(&[effect(&["clean", "-d"])], &root).paths.is_empty());
+        assert!(root.join("build/a.o").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! Safe Mode profiles
+//!
+//! A profile is a named rule layer for a kind of session: `beginner` holds
+//! back more while learning, `standard` is the rules as they are and
+//! `production` makes changes to clusters, infrastructure and databases
+//! dangerous. Projects and SSH hosts choose one; the session's profile is
+//! added on top of the built-in, user and project rules (see
+//! [`crate::safe_mode_rules`]).
+//!
+//! Profiles are rule files with a description, shipped in
+//! `safe-mode/profiles/`. Users add their own, or replace a built-in one,
+//! with `<name>.toml` files in `safe-mode-profiles/` next to `config.toml`.
+
+use crate::error::{CoreError, Result};
+use crate::safe_mode_rules::{Rule, RuleLayer, RuleSet};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Profiles shipped with CorgiTerm
+const BUILTIN_PROFILES: &[(&str, &str)] = &[
+    (
+        "beginner",
+        include_str!("../safe-mode/profiles/beginner.toml"),
+    ),
+    (
+        "standard",
+        include_str!("../safe-mode/profiles/standard.toml"),
+    ),
+    (
+        "production",
+        include_str!("../safe-mode/profiles/production.toml"),
+    ),
+];
+
+/// The profile used when nothing else is chosen
+pub const DEFAULT_PROFILE: &str = "standard";
+
+/// User profile directory, next to `config.toml`
+pub const USER_PROFILES_DIR: &str = "safe-mode-profiles";
+
+/// `ssh` options that take a value
+const SSH_VALUE_OPTIONS: &str = "BbcDEeFIiJLlmOopQRSWw";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+/// A named set of rules layered over the others
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub description: String,
+    pub rules: Vec<Rule>,
+    /// The file the profile was read from, `None` for built-in ones
+    pub file: Option<PathBuf>,
+}
+
+impl Profile {
+    /// Parse a profile from TOML text
+    pub fn from_toml(name: &str, text: &str, file: Option<PathBuf>) -> Result<Self> {
+        let parsed: ProfileFile =
+            toml::from_str(text).map_err(|e| CoreError::Config(e.to_string()))?;
+        Ok(Self {
+            name: name.to_string(),
+            description: parsed.description,
+            rules: parsed.rule,
+            file,
+        })
+    }
+
+    /// `rules` with this profile's layer added, and warnings for rules
+    /// that could not be added
+    pub fn apply(&self, rules: &RuleSet) -> (RuleSet, Vec<String>) {
+        let mut rules = rules.clone();
+        let warnings = rules.add_rules(
+            self.rules.iter().cloned(),
+            RuleLayer::Profile,
+            self.file.clone(),
+        );
+        (rules, warnings)
+    }
+}
+
+/// The profiles that can be chosen
+#[derive(Debug, Clone)]
+pub struct ProfileSet {
+    profiles: Vec<Profile>,
+}
+
+impl Default for ProfileSet {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ProfileSet {
+    /// The built-in profiles
+    pub fn builtin() -> Self {
+        let profiles = BUILTIN_PROFILES
+            .iter()
+            .map(|(name, text)| {
+                Profile::from_toml(name, text, None).expect("built-in Safe Mode profiles are valid")
+            })
+            .collect();
+        Self { profiles }
+    }
+
+    /// Built-in profiles plus the `*.toml` files in `dir`, which replace
+    /// built-in ones of the same name
+    ///
+    /// Files that fail to load are returned as warnings rather than
+    /// failing, like the rule files.
+    pub fn with_user_dir(dir: &Path) -> (Self, Vec<String>) {
+        let mut profiles = Self::builtin();
+        let mut warnings = Vec::new();
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (profiles, warnings),
+            Err(e) => {
+                warnings.push(format!("{}: {}", dir.display(), e));
+                return (profiles, warnings);
+            }
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        files.sort();
+        for file in files {
+            let Some(name) = file.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let loaded = std::fs::read_to_string(&file)
+                .map_err(CoreError::from)
+                .and_then(|text| Profile::from_toml(name, &text, Some(file.clone())));
+            match loaded {
+                Ok(profile) => profiles.insert(profile),
+                Err(e) => warnings.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+        (profiles, warnings)
+    }
+
+    /// Add a profile, replacing one with the same name
+    pub fn insert(&mut self, profile: Profile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// Find a profile by name
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// All profiles, built-in ones first
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// Profile names, built-in ones first
+    pub fn names(&self) -> Vec<&str> {
+        self.profiles.iter().map(|p| p.name.as_str()).collect()
+    }
+}
+
+/// The destination of an `ssh` command line (`host` or `user@host`),
+/// used to find the profile of the host a session is connected to
+pub fn ssh_destination<'a>(args: &[&'a str]) -> Option<&'a str> {
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if arg == "--" {
+            return args.next().copied();
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            return Some(arg.strip_prefix("ssh://").unwrap_or(arg));
+        };
+        // A value option takes the rest of the word, or the next one
+        if let Some(at) = flags.find(|c| SSH_VALUE_OPTIONS.contains(c)) {
+            if at + 1 == flags.len() {
+                args.next();
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safe_mode::RiskLevel;
+
+    fn risk(rules: &RuleSet, command: &str, args: &[&str]) -> Option<RiskLevel> {
+        rules
+            .matching(command, args, None)
+            .map(|loaded| loaded.rule.risk)
+            .reduce(RiskLevel::worst)
+    }
+
+    #[test]
+    fn test_builtin_profiles() {
+        let profiles = ProfileSet::builtin();
+        assert_eq!(profiles.names(), ["beginner", "standard", "production"]);
+        assert!(profiles.get(DEFAULT_PROFILE).unwrap().rules.is_empty());
+        assert!(profiles
+            .profiles()
+            .iter()
+            .all(|p| !p.description.is_empty()));
+    }
+
+    #[test]
+    fn test_production_escalates_changes() {
+        let base = RuleSet::builtin();
+        let profiles = ProfileSet::builtin();
+        let (production, warnings) = profiles.get("production").unwrap().apply(&base);
+        assert!(warnings.is_empty());
+
+        assert_eq!(risk(&base, "terraform", &["apply"]), None);
+        assert_eq!(
+            risk(&production, "terraform", &["apply"]),
+            Some(RiskLevel::Danger)
+        );
+        assert_eq!(risk(&production, "terraform", &["plan"]), None);
+        assert_eq!(
+            risk(
+                &production,
+                "kubectl",
+                &["-n", "prod", "delete", "pod", "web"]
+            ),
+            Some(RiskLevel::Danger)
+        );
+        assert_eq!(risk(&production, "kubectl", &["get", "pods"]), None);
+        assert_eq!(
+            risk(&production, "psql", &["-c", "drop table users"]),
+            Some(RiskLevel::Danger)
+        );
+        assert_eq!(risk(&production, "psql", &["-c", "SELECT 1"]), None);
+        assert_eq!(
+            risk(&production, "systemctl", &["restart", "nginx"]),
+            Some(RiskLevel::Danger)
+        );
+    }
+
+    #[test]
+    fn test_beginner_replaces_builtin_rules() {
+        let base = RuleSet::builtin();
+        let (beginner, _) = ProfileSet::builtin().get("beginner").unwrap().apply(&base);
+        assert_eq!(
+            risk(&base, "git", &["reset", "--hard"]),
+            Some(RiskLevel::Caution)
+        );
+        assert_eq!(
+            risk(&beginner, "git", &["reset", "--hard"]),
+            Some(RiskLevel::Danger)
+        );
+        let rule = beginner.get("git-reset-hard").unwrap();
+        assert_eq!(rule.layer, RuleLayer::Profile);
+        assert_eq!(beginner.rules().len(), base.rules().len());
+    }
+
+    #[test]
+    fn test_ssh_destination() {
+        assert_eq!(ssh_destination(&["db"]), Some("db"));
+        assert_eq!(
+            ssh_destination(&["-p", "2222", "-i", "key", "admin@db", "uptime"]),
+            Some("admin@db")
+        );
+        assert_eq!(ssh_destination(&["-tAp2222", "web"]), Some("web"));
+        assert_eq!(ssh_destination(&["-o", "User=x", "--", "web"]), Some("web"));
+        assert_eq!(
+            ssh_destination(&["ssh://web.example.com"]),
+            Some("web.example.com")
+        );
+        assert_eq!(ssh_destination(&["-v"]), None);
+    }
+
+    #[test]
+    fn test_user_profiles() {
+        let dir = std::env::temp_dir().join(format!(
+            "corgiterm-safe-mode-profiles-{}",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("staging.toml"),
+            r#"
+description = "Staging cluster"
+
+[[rule]]
+name = "staging: deploy"
+command = "deploy"
+risk = "caution"
+"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("production.toml"), "description = \"Mine\"\n").unwrap();
+        std::fs::write(dir.join("broken.toml"), "rule = 3\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a profile").unwrap();
+
+        let (profiles, warnings) = ProfileSet::with_user_dir(&dir);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("broken.toml"));
+        assert_eq!(
+            profiles.names(),
+            ["beginner", "standard", "production", "staging"]
+        );
+        let production = profiles.get("production").unwrap();
+        assert_eq!(production.description, "Mine");
+        assert!(production.rules.is_empty());
+        let staging = profiles.get("staging").unwrap();
+        assert_eq!(
+            staging.file.as_deref(),
+            Some(dir.join("staging.toml").as_path())
+        );
+        assert_eq!(staging.rules.len(), 1);
+
+        let (missing, warnings) = ProfileSet::with_user_dir(&dir.join("missing"));
+        assert!(warnings.is_empty());
+        assert_eq!(missing.profiles().len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
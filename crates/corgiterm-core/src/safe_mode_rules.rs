@@ -0,0 +1,815 @@
+//! Declarative Safe Mode rules
+//!
+//! Rules say what a command does from its name, subcommand, flags and
+//! operands. They are written in TOML (see `safe-mode/rules.toml` for the
+//! format and the built-in set) and layered:
+//!
+//! 1. built-in rules shipped with CorgiTerm
+//! 2. the user's `safe-mode.toml` next to `config.toml`, plus the
+//!    `safe_mode.dangerous_patterns` and `safe_mode.safe_patterns` settings
+//! 3. the project's `.corgiterm/safe-mode.toml`, found from the working
+//!    directory upwards
+//! 4. the session's profile, such as `production` (see
+//!    [`crate::safe_mode_profiles`])
+//!
+//! A user or profile rule with the name of an earlier one replaces or (with
+//! `enabled = false`) disables it. Project rules come with whatever
+//! repository was cloned, so they can only add rules, never replace or
+//! disable earlier ones.
+
+use crate::error::{CoreError, Result};
+use crate::safe_mode::RiskLevel;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Rules shipped with CorgiTerm
+const BUILTIN_RULES: &str = include_str!("../safe-mode/rules.toml");
+
+/// User rule file name, next to `config.toml`
+pub const USER_RULES_FILE: &str = "safe-mode.toml";
+
+/// Project rule file, relative to the project root
+pub const PROJECT_RULES_FILE: &str = ".corgiterm/safe-mode.toml";
+
+/// Options that take a value before a command's subcommand
+const VALUE_OPTIONS: &[(&str, &[&str])] = &[
+    (
+        "git",
+        &[
+            "-C",
+            "-c",
+            "--git-dir",
+            "--work-tree",
+            "--namespace",
+            "--exec-path",
+        ],
+    ),
+    ("systemctl", &["-H", "-M", "--host", "--machine"]),
+    ("cargo", &["-C", "--config"]),
+    (
+        "kubectl",
+        &[
+            "-n",
+            "--namespace",
+            "--context",
+            "--cluster",
+            "--user",
+            "--kubeconfig",
+            "-s",
+            "--server",
+        ],
+    ),
+];
+
+/// One rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Unique name
+    pub name: String,
+    /// Command names, globs allowed
+    #[serde(deserialize_with = "one_or_many")]
+    pub command: Vec<String>,
+    /// First operand, such as `push` in `git push`
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub subcommand: Vec<String>,
+    /// Flags that must all be present; `|` separates alternatives
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Flags that keep the rule from matching
+    #[serde(default)]
+    pub unless_flags: Vec<String>,
+    /// Globs that must each match some argument
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// `args` globs may also match the text the line feeds to the command's
+    /// input: a here-document, a here-string, or `echo`/`printf` piped in
+    #[serde(default)]
+    pub input: bool,
+    /// Globs of which one must match some normalized operand
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub paths: Vec<String>,
+    pub risk: RiskLevel,
+    #[serde(default)]
+    pub explanation: Option<String>,
+    /// How to recover afterwards
+    #[serde(default)]
+    pub undo: Option<String>,
+    #[serde(default)]
+    pub alternative: Option<RuleAlternative>,
+    /// The command reaches the network
+    #[serde(default)]
+    pub network: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// A safer command suggested by a rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleAlternative {
+    /// Command template; `{args}` and `{operands}` are filled in
+    pub command: String,
+    pub description: String,
+    pub risk: RiskLevel,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub(crate) fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+impl Rule {
+    /// Whether the rule applies to a command (by its base name), its
+    /// arguments and the `input` the line feeds it, when known
+    pub fn matches(&self, command: &str, args: &[&str], input: Option<&str>) -> bool {
+        if !self.enabled || !self.command.iter().any(|c| glob(c, command, false)) {
+            return false;
+        }
+        if !self.subcommand.is_empty() {
+            let Some((subcommand, _)) = subcommand(command, args) else {
+                return false;
+            };
+            if !self.subcommand.iter().any(|s| s == subcommand) {
+                return false;
+            }
+        }
+        let options: Vec<&str> = args
+            .iter()
+            .copied()
+            .take_while(|arg| *arg != "--")
+            .filter(|arg| arg.starts_with('-') && arg.len() > 1)
+            .collect();
+        let has_any =
+            |alternatives: &str| alternatives.split('|').any(|flag| has_flag(&options, flag));
+        if !self.flags.iter().all(|flags| has_any(flags)) {
+            return false;
+        }
+        if self.unless_flags.iter().any(|flags| has_any(flags)) {
+            return false;
+        }
+        let input = input.filter(|_| self.input).map(str::trim);
+        let arg_matches = |pattern: &str| {
+            args.iter()
+                .chain(&input)
+                .any(|arg| glob_any(pattern, arg, false))
+        };
+        if !self.args.iter().all(|pattern| arg_matches(pattern)) {
+            return false;
+        }
+        if !self.paths.is_empty() {
+            let (_, operands) = split_options(args);
+            let matched = operands.iter().any(|operand| {
+                let path = normalize_path(operand);
+                self.paths
+                    .iter()
+                    .any(|pattern| glob_any(pattern, &path, true))
+            });
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The rule's alternative, filled in for a command's arguments
+    pub fn alternative_for(&self, args: &[&str]) -> Option<RuleAlternative> {
+        let alternative = self.alternative.as_ref()?;
+        let (_, operands) = split_options(args);
+        let command = alternative
+            .command
+            .replace("{args}", &args.join(" "))
+            .replace("{operands}", &operands.join(" "));
+        Some(RuleAlternative {
+            command: command.trim_end().to_string(),
+            ..alternative.clone()
+        })
+    }
+}
+
+/// Where a rule came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleLayer {
+    BuiltIn,
+    /// The user's rule file or Safe Mode settings
+    User,
+    Project,
+    /// A Safe Mode profile chosen for the project or SSH host
+    Profile,
+}
+
+/// A rule and where it was loaded from
+#[derive(Debug, Clone)]
+pub struct LoadedRule {
+    pub rule: Rule,
+    pub layer: RuleLayer,
+    /// The file the rule was read from
+    pub file: Option<PathBuf>,
+}
+
+impl fmt::Display for LoadedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layer = match self.layer {
+            RuleLayer::BuiltIn => "built-in",
+            RuleLayer::User => "user",
+            RuleLayer::Project => "project",
+            RuleLayer::Profile => "profile",
+        };
+        match &self.file {
+            Some(file) => write!(f, "{} ({}, {})", self.rule.name, layer, file.display()),
+            None => write!(f, "{} ({})", self.rule.name, layer),
+        }
+    }
+}
+
+/// Layered Safe Mode rules
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<LoadedRule>,
+}
+
+impl RuleSet {
+    /// The built-in rules
+    pub fn builtin() -> Self {
+        let mut rules = Self::default();
+        rules
+            .add_toml(BUILTIN_RULES, RuleLayer::BuiltIn, None)
+            .expect("built-in Safe Mode rules are valid");
+        rules
+    }
+
+    /// Built-in rules, then the user layer: `user_file` if it exists and
+    /// the configured patterns
+    ///
+    /// Problems with the user file are returned rather than failing, so
+    /// Safe Mode keeps working with the rules that did load.
+    pub fn with_user_layer(
+        user_file: Option<&Path>,
+        dangerous_patterns: &[String],
+        safe_patterns: &[String],
+    ) -> (Self, Vec<String>) {
+        let mut rules = Self::builtin();
+        let mut warnings = Vec::new();
+        if let Some(file) = user_file {
+            match rules.load_file(file, RuleLayer::User) {
+                Ok(file_warnings) => warnings.extend(file_warnings),
+                Err(e) => warnings.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+        for pattern in dangerous_patterns {
+            rules.add_pattern(pattern, RiskLevel::Danger);
+        }
+        for pattern in safe_patterns {
+            rules.add_pattern(pattern, RiskLevel::Safe);
+        }
+        (rules, warnings)
+    }
+
+    /// Add the rules in a TOML file; a missing file adds nothing
+    pub fn load_file(&mut self, path: &Path, layer: RuleLayer) -> Result<Vec<String>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => self.add_toml(&text, layer, Some(path.to_path_buf())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add rules from TOML text, returning warnings for rules that were
+    /// not allowed to replace existing ones
+    pub fn add_toml(
+        &mut self,
+        text: &str,
+        layer: RuleLayer,
+        file: Option<PathBuf>,
+    ) -> Result<Vec<String>> {
+        let parsed: RuleFile =
+            toml::from_str(text).map_err(|e| CoreError::Config(e.to_string()))?;
+        Ok(self.add_rules(parsed.rule, layer, file))
+    }
+
+    /// Add parsed rules, returning warnings for rules that were not
+    /// allowed to replace existing ones
+    pub fn add_rules(
+        &mut self,
+        rules: impl IntoIterator<Item = Rule>,
+        layer: RuleLayer,
+        file: Option<PathBuf>,
+    ) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for rule in rules {
+            if let Err(warning) = self.add(LoadedRule {
+                rule,
+                layer: layer.clone(),
+                file: file.clone(),
+            }) {
+                warnings.push(warning);
+            }
+        }
+        warnings
+    }
+
+    /// Add a rule from a plain pattern such as `terraform destroy`: the
+    /// first word is the command and the rest must appear as arguments
+    pub fn add_pattern(&mut self, pattern: &str, risk: RiskLevel) {
+        let mut words = pattern.split_whitespace();
+        let Some(command) = words.next() else {
+            return;
+        };
+        let explanation = (risk != RiskLevel::Safe)
+            .then(|| format!("Matches your Safe Mode pattern \"{}\"", pattern.trim()));
+        let rule = Rule {
+            name: format!("pattern: {}", pattern.trim()),
+            command: vec![command.to_string()],
+            subcommand: Vec::new(),
+            flags: Vec::new(),
+            unless_flags: Vec::new(),
+            args: words.map(str::to_string).collect(),
+            input: false,
+            paths: Vec::new(),
+            risk,
+            explanation,
+            undo: None,
+            alternative: None,
+            network: false,
+            enabled: true,
+        };
+        // Pattern names cannot clash with another layer's
+        let _ = self.add(LoadedRule {
+            rule,
+            layer: RuleLayer::User,
+            file: None,
+        });
+    }
+
+    fn add(&mut self, loaded: LoadedRule) -> std::result::Result<(), String> {
+        match self
+            .rules
+            .iter_mut()
+            .find(|r| r.rule.name == loaded.rule.name)
+        {
+            Some(existing)
+                if loaded.layer == RuleLayer::Project && existing.layer != RuleLayer::Project =>
+            {
+                Err(format!(
+                    "project rule \"{}\" cannot replace a {} rule",
+                    loaded.rule.name,
+                    match existing.layer {
+                        RuleLayer::BuiltIn => "built-in",
+                        RuleLayer::Profile => "profile",
+                        _ => "user",
+                    }
+                ))
+            }
+            Some(existing) => {
+                *existing = loaded;
+                Ok(())
+            }
+            None => {
+                self.rules.push(loaded);
+                Ok(())
+            }
+        }
+    }
+
+    /// All rules, in the order they were added
+    pub fn rules(&self) -> &[LoadedRule] {
+        &self.rules
+    }
+
+    /// Find a rule by name
+    pub fn get(&self, name: &str) -> Option<&LoadedRule> {
+        self.rules.iter().find(|r| r.rule.name == name)
+    }
+
+    /// Rules that apply to a command and the `input` the line feeds it
+    pub fn matching<'a>(
+        &'a self,
+        command: &'a str,
+        args: &'a [&'a str],
+        input: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a LoadedRule> + 'a {
+        self.rules
+            .iter()
+            .filter(move |loaded| loaded.rule.matches(command, args, input))
+    }
+}
+
+/// The nearest project rule file at or above `dir`
+pub fn find_project_rules(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|ancestor| ancestor.join(PROJECT_RULES_FILE))
+        .find(|path| path.is_file())
+}
+
+/// The first operand, skipping options and the values of known ones, and
+/// the arguments after it
+pub(crate) fn subcommand<'a, 'b>(
+    command: &str,
+    args: &'a [&'b str],
+) -> Option<(&'b str, &'a [&'b str])> {
+    let with_value = VALUE_OPTIONS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map_or(&[][..], |(_, options)| *options);
+    let (&first, rest) = skip_options(args, with_value).split_first()?;
+    Some((first, rest))
+}
+
+/// Skip leading options, and the values of those in `with_value`
+pub(crate) fn skip_options<'a, 'b>(args: &'a [&'b str], with_value: &[&str]) -> &'a [&'b str] {
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if *arg == "--" {
+            return &args[index + 1..];
+        }
+        if !arg.starts_with('-') || *arg == "-" {
+            break;
+        }
+        index += 1;
+        if with_value.contains(arg) {
+            index += 1;
+        }
+    }
+    &args[index.min(args.len())..]
+}
+
+/// Split arguments into options and operands; `--` ends the options
+pub(crate) fn split_options<'a>(args: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut ended = false;
+    for &arg in args {
+        if ended || !arg.starts_with('-') || arg == "-" {
+            operands.push(arg);
+        } else if arg == "--" {
+            ended = true;
+        } else {
+            options.push(arg);
+        }
+    }
+    (options, operands)
+}
+
+/// `--long` matches `--long` and `--long=value`, `-x` matches any short
+/// bundle containing `x`, and anything else must match exactly
+fn has_flag(options: &[&str], flag: &str) -> bool {
+    let mut chars = flag.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('-'), Some('-'), Some(_)) => options.iter().any(|option| {
+            option
+                .strip_prefix(flag)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+        }),
+        (Some('-'), Some(short), None) => options.iter().any(|option| {
+            option
+                .strip_prefix('-')
+                .is_some_and(|bundle| !bundle.starts_with('-') && bundle.contains(short))
+        }),
+        _ => options.contains(&flag),
+    }
+}
+
+/// Spell the root and home directories one way, without trailing `/`,
+/// `/.` or `/*`
+fn normalize_path(path: &str) -> String {
+    let mut trimmed = path;
+    while let Some(shorter) = trimmed
+        .strip_suffix("/*")
+        .or_else(|| trimmed.strip_suffix("/."))
+        .or_else(|| trimmed.strip_suffix('/'))
+    {
+        trimmed = shorter;
+    }
+    if trimmed.is_empty() && path.starts_with('/') {
+        return "/".to_string();
+    }
+    for home in ["$HOME", "${HOME}"] {
+        if let Some(rest) = trimmed.strip_prefix(home) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return format!("~{rest}");
+            }
+        }
+    }
+    trimmed.to_string()
+}
+
+/// Match any of the `|`-separated globs
+fn glob_any(alternatives: &str, text: &str, path: bool) -> bool {
+    alternatives
+        .split('|')
+        .any(|pattern| glob(pattern, text, path))
+}
+
+/// Match a glob with `*`, `?` and `[...]`; for paths, `*` and `?` stay
+/// within one component and `**` crosses them
+pub(crate) fn glob(pattern: &str, text: &str, path: bool) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_chars(&pattern, &text, path)
+}
+
+fn glob_chars(pattern: &[char], text: &[char], path: bool) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let crosses = !path || pattern.get(1) == Some(&'*');
+            let rest = if path && crosses {
+                &pattern[2..]
+            } else {
+                &pattern[1..]
+            };
+            (0..=text.len())
+                .take_while(|&taken| crosses || taken == 0 || text[taken - 1] != '/')
+                .any(|taken| glob_chars(rest, &text[taken..], path))
+        }
+        Some('?') => {
+            text.first().is_some_and(|&c| !(path && c == '/'))
+                && glob_chars(&pattern[1..], &text[1..], path)
+        }
+        Some('[') => {
+            let Some(close) = pattern
+                .iter()
+                .skip(2)
+                .position(|&c| c == ']')
+                .map(|i| i + 2)
+            else {
+                return text.first() == Some(&'[') && glob_chars(&pattern[1..], &text[1..], path);
+            };
+            let Some(&c) = text.first() else {
+                return false;
+            };
+            let mut class = &pattern[1..close];
+            let negated = matches!(class.first(), Some('!' | '^'));
+            if negated {
+                class = &class[1..];
+            }
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if class.get(i + 1) == Some(&'-') && i + 2 < class.len() {
+                    matched |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+            matched != negated && glob_chars(&pattern[close + 1..], &text[1..], path)
+        }
+        Some(&c) => text.first() == Some(&c) && glob_chars(&pattern[1..], &text[1..], path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(toml: &str) -> Rule {
+        let file: RuleFile = toml::from_str(toml).unwrap();
+        file.rule.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn test_builtin_rules_parse() {
+        let rules = RuleSet::builtin();
+        assert!(rules.get("rm-root").is_some());
+        assert!(rules.rules().iter().all(|r| r.layer == RuleLayer::BuiltIn));
+        let mut names: Vec<_> = rules.rules().iter().map(|r| &r.rule.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), rules.rules().len(), "rule names are unique");
+    }
+
+    #[test]
+    fn test_globs() {
+        assert!(glob("mkfs*", "mkfs.ext4", false));
+        assert!(glob("if=*", "if=/dev/zero", false));
+        assert!(glob("[0-7][0-7][2367]", "777", false));
+        assert!(!glob("[0-7][0-7][2367]", "755", false));
+        assert!(glob("[!a]b", "xb", false));
+        assert!(glob("/*", "/usr", true));
+        assert!(!glob("/*", "/usr/lib", true));
+        assert!(glob("/home/*", "/home/alex", true));
+        assert!(glob("**", "/etc/motd", true));
+        assert!(glob("?", "a", true));
+        assert!(!glob("?", "/", true));
+        assert!(glob("a[", "a[", false));
+        assert!(glob_any("drop|clear", "clear", false));
+    }
+
+    #[test]
+    fn test_paths_are_normalized() {
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("/*"), "/");
+        assert_eq!(normalize_path("//."), "/");
+        assert_eq!(normalize_path("/usr/"), "/usr");
+        assert_eq!(normalize_path("~/*"), "~");
+        assert_eq!(normalize_path("$HOME"), "~");
+        assert_eq!(normalize_path("${HOME}/docs"), "~/docs");
+        assert_eq!(normalize_path("$HOMEDIR"), "$HOMEDIR");
+        assert_eq!(normalize_path("build/"), "build");
+    }
+
+    #[test]
+    fn test_flags() {
+        let options = ["-rf", "--force-with-lease=main", "-delete"];
+        assert!(has_flag(&options, "-r"));
+        assert!(has_flag(&options, "-f"));
+        assert!(!has_flag(&options, "-R"));
+        assert!(has_flag(&options, "--force-with-lease"));
+        assert!(!has_flag(&options, "--force"));
+        assert!(has_flag(&options, "-delete"));
+        assert!(!has_flag(&options, "-dele"));
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rm_root = rule(
+            r#"
+            [[rule]]
+            name = "x"
+            command = "rm"
+            flags = ["-r|-R|--recursive"]
+            paths = ["/", "/*"]
+            risk = "danger"
+            "#,
+        );
+        assert!(rm_root.matches("rm", &["-rf", "/"], None));
+        assert!(rm_root.matches("rm", &["--recursive", "/usr/"], None));
+        assert!(!rm_root.matches("rm", &["-f", "/"], None));
+        assert!(!rm_root.matches("rm", &["-rf", "/usr/lib"], None));
+        assert!(!rm_root.matches("rm", &["--", "-r", "/tmp/x"], None));
+        assert!(!rm_root.matches("ls", &["-r", "/"], None));
+
+        let push = rule(
+            r#"
+            [[rule]]
+            name = "x"
+            command = "git"
+            subcommand = "push"
+            flags = ["-f|--force"]
+            unless_flags = ["--dry-run|-n"]
+            risk = "caution"
+            "#,
+        );
+        assert!(push.matches("git", &["-C", "repo", "push", "-f"], None));
+        assert!(!push.matches("git", &["commit", "-f"], None));
+        assert!(!push.matches("git", &["push", "--force", "--dry-run"], None));
+        assert!(!push.matches("git", &["push"], None));
+    }
+
+    #[test]
+    fn test_alternatives_are_filled_in() {
+        let rules = RuleSet::builtin();
+        let rm = &rules.get("rm").unwrap().rule;
+        let alternative = rm
+            .alternative_for(&["-rf", "node_modules", "dist"])
+            .unwrap();
+        assert_eq!(alternative.command, "trash-put node_modules dist");
+        assert_eq!(alternative.risk, RiskLevel::Safe);
+    }
+
+    #[test]
+    fn test_user_rules_replace_and_disable() {
+        let mut rules = RuleSet::builtin();
+        let warnings = rules
+            .add_toml(
+                r#"
+                [[rule]]
+                name = "rm"
+                command = "rm"
+                risk = "danger"
+                explanation = "We never delete here"
+
+                [[rule]]
+                name = "mv"
+                command = "mv"
+                risk = "caution"
+                enabled = false
+
+                [[rule]]
+                name = "terraform-destroy"
+                command = "terraform"
+                subcommand = "destroy"
+                risk = "danger"
+                "#,
+                RuleLayer::User,
+                None,
+            )
+            .unwrap();
+        assert!(warnings.is_empty());
+        let fired: Vec<_> = rules
+            .matching("rm", &["notes.txt"], None)
+            .map(|r| (r.rule.name.as_str(), r.rule.risk))
+            .collect();
+        assert_eq!(fired, [("rm", RiskLevel::Danger)]);
+        assert_eq!(rules.matching("mv", &["a", "b"], None).count(), 0);
+        assert_eq!(rules.matching("terraform", &["destroy"], None).count(), 1);
+    }
+
+    #[test]
+    fn test_project_rules_cannot_replace_earlier_layers() {
+        let mut rules = RuleSet::builtin();
+        let warnings = rules
+            .add_toml(
+                r#"
+                [[rule]]
+                name = "rm-root"
+                command = "rm"
+                risk = "safe"
+                enabled = false
+
+                [[rule]]
+                name = "deploy"
+                command = "./deploy.sh"
+                risk = "danger"
+                "#,
+                RuleLayer::Project,
+                Some(PathBuf::from("/repo/.corgiterm/safe-mode.toml")),
+            )
+            .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("rm-root"));
+        assert!(rules.get("rm-root").unwrap().rule.enabled);
+        let deploy = rules.get("deploy").unwrap();
+        assert_eq!(
+            deploy.to_string(),
+            "deploy (project, /repo/.corgiterm/safe-mode.toml)"
+        );
+    }
+
+    #[test]
+    fn test_patterns_and_bad_files() {
+        let (rules, warnings) = RuleSet::with_user_layer(
+            Some(Path::new("/nonexistent/safe-mode.toml")),
+            &["terraform destroy".to_string()],
+            &["make test".to_string()],
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(
+            rules
+                .matching("terraform", &["destroy", "-auto-approve"], None)
+                .count(),
+            1
+        );
+        assert_eq!(rules.matching("terraform", &["plan"], None).count(), 0);
+        assert_eq!(
+            rules
+                .matching("make", &["test"], None)
+                .next()
+                .unwrap()
+                .rule
+                .risk,
+            RiskLevel::Safe
+        );
+
+        let mut rules = RuleSet::default();
+        assert!(rules
+            .add_toml("[[rule]]\nname = 1", RuleLayer::User, None)
+            .is_err());
+        assert!(rules
+            .add_toml(
+                "[[rule]]\nname = \"x\"\ncommand = \"x\"\nrisk = \"safe\"\ncolour = \"red\"",
+                RuleLayer::User,
+                None
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_project_rules_are_found_upwards() {
+        let root = std::env::temp_dir().join(format!("corgiterm-rules-{}", uuid::Uuid::new_v4()));
+        let nested = root.join("src/deep");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_rules(&nested), None);
+        std::fs::create_dir_all(root.join(".corgiterm")).unwrap();
+        std::fs::write(root.join(PROJECT_RULES_FILE), "").unwrap();
+        assert_eq!(
+            find_project_rules(&nested),
+            Some(root.join(PROJECT_RULES_FILE))
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
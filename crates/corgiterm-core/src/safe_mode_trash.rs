@@ -0,0 +1,611 @@
+//! Trash for files Safe Mode would otherwise let `rm` delete
+//!
+//! With `safe_mode.trash_deletions` on, an approved `rm` is rewritten to run
+//! `corgiterm trash put` instead, which moves its operands into a trash
+//! directory laid out as the freedesktop.org Trash specification describes:
+//! the files go in `files/` and a `.trashinfo` file in `info/` records where
+//! each came from and when. Each `put` is also appended to a journal, so
+//! "undo last deletion" can put back everything one command removed.
+//! Journal entries expire by age and by the trash's total size; an expired
+//! entry's files are deleted for good.
+
+use crate::shell_syntax::{self, Command, CommandList, SimpleCommand};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// One `put`: everything a single command moved into the trash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: uuid::Uuid,
+    pub timestamp: DateTime<Utc>,
+    /// The command that was rewritten, e.g. `rm -r build`
+    pub command: String,
+    pub items: Vec<TrashedItem>,
+}
+
+impl TrashEntry {
+    /// Bytes held in the trash for this entry
+    pub fn bytes(&self) -> u64 {
+        self.items.iter().map(|item| item.bytes).sum()
+    }
+}
+
+/// A file or directory moved into the trash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashedItem {
+    /// Absolute path it was moved from
+    pub original: PathBuf,
+    /// Its name under `files/` (and `info/<name>.trashinfo`)
+    pub name: String,
+    pub bytes: u64,
+}
+
+/// Result of a `put`
+#[derive(Debug, Default)]
+pub struct PutOutcome {
+    /// The journal entry, unless nothing was moved
+    pub entry: Option<TrashEntry>,
+    /// Paths that could not be moved, and why
+    pub failed: Vec<(PathBuf, io::Error)>,
+}
+
+/// Result of undoing an entry
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Restored {
+    pub command: String,
+    /// Paths moved back to where they were
+    pub restored: Vec<PathBuf>,
+    /// Paths left in the trash because something now exists there; the
+    /// entry stays in the journal with just these
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// A trash directory and its journal
+#[derive(Debug, Clone)]
+pub struct Trash {
+    root: PathBuf,
+}
+
+impl Trash {
+    /// Default location: `<data dir>/corgiterm/trash`
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("corgiterm").join("trash"))
+    }
+
+    /// Use the trash at `root`; nothing is created until the first `put`
+    pub fn open(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn files_dir(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    fn info_dir(&self) -> PathBuf {
+        self.root.join("info")
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.root.join("journal.jsonl")
+    }
+
+    /// Move `paths` into the trash as one journal entry; relative paths are
+    /// taken from the current directory
+    pub fn put(&self, paths: &[PathBuf], command: &str) -> PutOutcome {
+        let mut outcome = PutOutcome::default();
+        if let Err(e) =
+            fs::create_dir_all(self.files_dir()).and_then(|_| fs::create_dir_all(self.info_dir()))
+        {
+            let kind = e.kind();
+            outcome.failed = paths
+                .iter()
+                .map(|path| (path.clone(), io::Error::new(kind, e.to_string())))
+                .collect();
+            return outcome;
+        }
+
+        let mut items = Vec::new();
+        for path in paths {
+            match self.move_in(path) {
+                Ok(item) => items.push(item),
+                Err(e) => outcome.failed.push((path.clone(), e)),
+            }
+        }
+        if items.is_empty() {
+            return outcome;
+        }
+
+        let entry = TrashEntry {
+            id: uuid::Uuid::new_v4(),
+            timestamp: Utc::now(),
+            command: command.to_string(),
+            items,
+        };
+        if let Err(e) = self.append(&entry) {
+            tracing::warn!("Failed to write trash journal: {}", e);
+        }
+        outcome.entry = Some(entry);
+        outcome
+    }
+
+    fn move_in(&self, path: &Path) -> io::Result<TrashedItem> {
+        let original = absolute(path)?;
+        let file_name = original
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "refusing to trash this path")
+            })?;
+        let metadata = fs::symlink_metadata(&original)?;
+        if original.starts_with(&self.root) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path is inside the trash",
+            ));
+        }
+
+        // Claim a name by creating its info file, as the specification asks
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(&original),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+        let mut counter = 1;
+        let (name, info_path) = loop {
+            let name = if counter == 1 {
+                file_name.clone()
+            } else {
+                format!("{}.{}", file_name, counter)
+            };
+            counter += 1;
+            if fs::symlink_metadata(self.files_dir().join(&name)).is_ok() {
+                continue;
+            }
+            let info_path = self.info_dir().join(format!("{}.trashinfo", name));
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(mut file) => {
+                    file.write_all(info.as_bytes())?;
+                    break (name, info_path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        };
+
+        let bytes = tree_size(&original, &metadata);
+        if let Err(e) = move_path(&original, &self.files_dir().join(&name)) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        Ok(TrashedItem {
+            original,
+            name,
+            bytes,
+        })
+    }
+
+    fn append(&self, entry: &TrashEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.journal_path())?
+            .write_all(line.as_bytes())
+    }
+
+    /// Journal entries, oldest first; lines that do not parse are skipped
+    pub fn entries(&self) -> Vec<TrashEntry> {
+        fs::read_to_string(self.journal_path())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn write_entries(&self, entries: &[TrashEntry]) -> io::Result<()> {
+        let mut contents = String::new();
+        for entry in entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        let temp = self.root.join(format!(".journal-{}", uuid::Uuid::new_v4()));
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, self.journal_path())
+    }
+
+    /// Put back the files of the newest entry; `None` when the journal is
+    /// empty
+    pub fn undo_last(&self) -> io::Result<Option<Restored>> {
+        let mut entries = self.entries();
+        let Some(mut entry) = entries.pop() else {
+            return Ok(None);
+        };
+
+        let mut restored = Restored {
+            command: entry.command.clone(),
+            ..Restored::default()
+        };
+        let mut remaining = Vec::new();
+        for item in entry.items {
+            let trashed = self.files_dir().join(&item.name);
+            if fs::symlink_metadata(&item.original).is_ok() {
+                restored.conflicts.push(item.original.clone());
+                remaining.push(item);
+                continue;
+            }
+            if let Some(parent) = item.original.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match move_path(&trashed, &item.original) {
+                Ok(()) => {
+                    let _ =
+                        fs::remove_file(self.info_dir().join(format!("{}.trashinfo", item.name)));
+                    restored.restored.push(item.original);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    // Emptied from the trash by something else
+                    tracing::warn!("{} is no longer in the trash", trashed.display());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if !remaining.is_empty() {
+            entry.items = remaining;
+            entries.push(entry);
+        }
+        self.write_entries(&entries)?;
+        Ok(Some(restored))
+    }
+
+    /// Delete entries older than `max_age`, then the oldest entries until
+    /// the trash holds at most `max_bytes`; returns how many were deleted
+    pub fn expire(&self, max_age: std::time::Duration, max_bytes: u64) -> io::Result<usize> {
+        let entries = self.entries();
+        let cutoff = chrono::Duration::from_std(max_age)
+            .ok()
+            .and_then(|age| Utc::now().checked_sub_signed(age));
+        let mut total: u64 = entries.iter().map(TrashEntry::bytes).sum();
+        let mut kept = Vec::new();
+        let mut expired = Vec::new();
+        for entry in entries {
+            if cutoff.is_some_and(|cutoff| entry.timestamp < cutoff) || total > max_bytes {
+                total -= entry.bytes();
+                expired.push(entry);
+            } else {
+                kept.push(entry);
+            }
+        }
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        for item in expired.iter().flat_map(|entry| &entry.items) {
+            let path = self.files_dir().join(&item.name);
+            let removed = match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path),
+                Ok(_) => fs::remove_file(&path),
+                Err(_) => Ok(()),
+            };
+            if let Err(e) = removed {
+                tracing::warn!("Failed to expire {}: {}", path.display(), e);
+                continue;
+            }
+            let _ = fs::remove_file(self.info_dir().join(format!("{}.trashinfo", item.name)));
+        }
+        self.write_entries(&kept)?;
+        Ok(expired.len())
+    }
+}
+
+/// `path` made absolute against the current directory, with `.` and `..`
+/// resolved by name (a symlink operand is trashed, not its target)
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let joined = std::env::current_dir()?.join(path);
+    let mut result = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other),
+        }
+    }
+    Ok(result)
+}
+
+/// Rename, or copy and delete when `to` is on another filesystem
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            if let Err(e) = copy_tree(from, to) {
+                let _ = remove_tree(to);
+                return Err(e);
+            }
+            remove_tree(from)
+        }
+        result => result,
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(from)?;
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(target, to);
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("cannot copy symlink to {}", target.display()),
+        ));
+    }
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for child in fs::read_dir(from)? {
+            let child = child?;
+            copy_tree(&child.path(), &to.join(child.file_name()))?;
+        }
+        fs::set_permissions(to, metadata.permissions())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn remove_tree(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Bytes under `path`, not following symlinks
+fn tree_size(path: &Path, metadata: &fs::Metadata) -> u64 {
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|children| {
+            children
+                .flatten()
+                .filter_map(|child| {
+                    let metadata = fs::symlink_metadata(child.path()).ok()?;
+                    Some(tree_size(&child.path(), &metadata))
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Percent-encode a path for a `.trashinfo` `Path=` key
+fn encode_path(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().into_owned().into_bytes();
+    let mut encoded = String::new();
+    for byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// `rm` options `corgiterm trash put` understands; an `rm` with any other
+/// option is left alone
+const PUT_SHORT_OPTIONS: &str = "frRdv";
+const PUT_LONG_OPTIONS: &[&str] = &[
+    "--force",
+    "--recursive",
+    "--dir",
+    "--verbose",
+    "--preserve-root",
+    "--no-preserve-root",
+    "--one-file-system",
+];
+
+/// `command` with every `rm` it runs directly replaced by `program trash
+/// put`, or `None` when there is nothing to rewrite
+///
+/// Only `rm` at the top of the line, in subshells and in groups is
+/// rewritten: not in function bodies, substitutions, or under `sudo`,
+/// `xargs` and the like. Interactive removals (`-i`, `-I`) stay with `rm`.
+pub fn rewrite_removals(command: &str, program: &str) -> Option<String> {
+    let mut spans = Vec::new();
+    collect_removals(&shell_syntax::parse(command), &mut spans);
+    if spans.is_empty() {
+        return None;
+    }
+
+    let replacement: Vec<char> = format!("{} trash put", shell_quote(program))
+        .chars()
+        .collect();
+    let mut chars: Vec<char> = command.chars().collect();
+    for span in spans.into_iter().rev() {
+        chars.splice(span, replacement.iter().copied());
+    }
+    Some(chars.into_iter().collect())
+}
+
+fn collect_removals(list: &CommandList, spans: &mut Vec<std::ops::Range<usize>>) {
+    for command in list.items.iter().flat_map(|item| &item.pipeline.commands) {
+        match command {
+            Command::Simple(simple) if is_rewritable_rm(simple) => {
+                spans.push(simple.words[0].span.clone());
+            }
+            Command::Subshell { body, .. } | Command::Group { body, .. } => {
+                collect_removals(body, spans)
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_rewritable_rm(command: &SimpleCommand) -> bool {
+    let Some(name) = command.words.first() else {
+        return false;
+    };
+    if !matches!(name.text.as_str(), "rm" | "/bin/rm" | "/usr/bin/rm")
+        || !name.substitutions.is_empty()
+    {
+        return false;
+    }
+    for arg in command.args() {
+        if arg.text == "--" {
+            break;
+        }
+        if arg.text.starts_with("--") {
+            if !PUT_LONG_OPTIONS.contains(&arg.text.as_str()) {
+                return false;
+            }
+        } else if let Some(flags) = arg.text.strip_prefix('-').filter(|f| !f.is_empty()) {
+            if !flags.chars().all(|flag| PUT_SHORT_OPTIONS.contains(flag)) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Single-quote `text` for the shell
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("corgiterm-trash-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_put_and_undo() {
+        let dir = temp_dir();
+        let trash = Trash::open(dir.join("trash"));
+        fs::create_dir_all(dir.join("work/build")).unwrap();
+        fs::write(dir.join("work/build/out.o"), "12345").unwrap();
+        fs::write(dir.join("work/a file"), "abc").unwrap();
+
+        let outcome = trash.put(
+            &[
+                dir.join("work/build"),
+                dir.join("work/a file"),
+                dir.join("work/missing"),
+            ],
+            "rm -r build 'a file' missing",
+        );
+        let entry = outcome.entry.unwrap();
+        assert_eq!(entry.items.len(), 2);
+        assert_eq!(entry.bytes(), 8);
+        assert_eq!(outcome.failed.len(), 1);
+        assert!(!dir.join("work/build").exists());
+        assert!(dir.join("trash/files/build/out.o").exists());
+        let info = fs::read_to_string(dir.join("trash/info/a file.trashinfo")).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"));
+        assert!(info.contains("/work/a%20file\nDeletionDate="));
+
+        // A second file with the same name gets its own slot
+        fs::write(dir.join("work/a file"), "again").unwrap();
+        let second = trash.put(&[dir.join("work/a file")], "rm 'a file'");
+        assert_eq!(second.entry.unwrap().items[0].name, "a file.2");
+        assert_eq!(trash.entries().len(), 2);
+
+        let restored = trash.undo_last().unwrap().unwrap();
+        assert_eq!(restored.restored, [dir.join("work/a file")]);
+        assert_eq!(
+            fs::read_to_string(dir.join("work/a file")).unwrap(),
+            "again"
+        );
+
+        // The first file's original path is taken now, so it stays trashed
+        let restored = trash.undo_last().unwrap().unwrap();
+        assert_eq!(restored.restored, [dir.join("work/build")]);
+        assert_eq!(restored.conflicts, [dir.join("work/a file")]);
+        assert_eq!(
+            fs::read_to_string(dir.join("work/build/out.o")).unwrap(),
+            "12345"
+        );
+        assert!(!dir.join("trash/info/build.trashinfo").exists());
+        assert_eq!(trash.entries().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expire_by_age_and_size() {
+        let dir = temp_dir();
+        let trash = Trash::open(dir.join("trash"));
+        for (name, size) in [("old", 10), ("big", 100), ("new", 5)] {
+            fs::write(dir.join(name), "x".repeat(size)).unwrap();
+            trash.put(&[dir.join(name)], &format!("rm {}", name));
+        }
+        let mut entries = trash.entries();
+        entries[0].timestamp = Utc::now() - chrono::Duration::days(40);
+        trash.write_entries(&entries).unwrap();
+
+        let expired = trash
+            .expire(std::time::Duration::from_secs(30 * 24 * 60 * 60), 50)
+            .unwrap();
+        assert_eq!(expired, 2);
+        let left = trash.entries();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].command, "rm new");
+        assert!(!dir.join("trash/files/big").exists());
+        assert!(!dir.join("trash/info/old.trashinfo").exists());
+        assert!(dir.join("trash/files/new").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_removals() {
+        let program = "/opt/corgi term/corgiterm";
+        assert_eq!(
+            rewrite_removals("rm -rf build && make", program).as_deref(),
+            Some("'/opt/corgi term/corgiterm' trash put -rf build && make")
+        );
+        assert_eq!(
+            rewrite_removals("cd x; (rm *.log) | cat; { rm -- -f; }", "corgiterm").as_deref(),
+            Some("cd x; ('corgiterm' trash put *.log) | cat; { 'corgiterm' trash put -- -f; }")
+        );
+        assert_eq!(
+            rewrite_removals("FOO=1 rm \"my file\" 2>/dev/null", "c").as_deref(),
+            Some("FOO=1 'c' trash put \"my file\" 2>/dev/null")
+        );
+        // Left to rm itself
+        for line in [
+            "rm -i a",
+            "rm --interactive=once a",
+            "sudo rm -rf /opt/x",
+            "echo $(rm a)",
+            "f() { rm a; }",
+            "find . -name x -exec rm {} +",
+            "ls",
+        ] {
+            assert_eq!(rewrite_removals(line, "c"), None, "{}", line);
+        }
+    }
+}
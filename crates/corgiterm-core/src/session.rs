@@ -29,7 +29,7 @@ use crate::{CoreError, Pty, Result, Terminal, TerminalSize};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 /// Unique identifier for a session
@@ -70,6 +70,12 @@ impl Default for ProjectId {
     }
 }
 
+impl std::fmt::Display for ProjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// A terminal session (a single tab within a project)
 pub struct Session {
     /// Unique session ID
@@ -80,7 +86,7 @@ pub struct Session {
     pty: Option<Pty>,
     /// Terminal emulator state
     terminal: Option<Terminal>,
-    /// Working directory
+    /// Working directory, kept current from the shell's OSC 7 reports
     pub cwd: PathBuf,
     /// Shell command (if custom)
     pub shell: Option<String>,
@@ -156,6 +162,11 @@ impl Session {
             if n > 0 {
                 if let Some(ref mut terminal) = self.terminal {
                     terminal.process(&buf[..n]);
+                    if let Some(cwd) = terminal.cwd() {
+                        if cwd != self.cwd {
+                            self.cwd = cwd.to_path_buf();
+                        }
+                    }
                 }
                 self.last_activity = Utc::now();
             }
@@ -240,6 +251,9 @@ pub struct ProjectSettings {
     pub startup_commands: Vec<String>,
     /// Default terminal size
     pub default_size: Option<(usize, usize)>,
+    /// Safe Mode profile for terminals in this folder
+    #[serde(default)]
+    pub safe_mode_profile: Option<String>,
 }
 
 impl Project {
@@ -408,6 +422,15 @@ impl SessionManager {
         &self.projects
     }
 
+    /// The project containing `dir`, the innermost one when projects are
+    /// nested
+    pub fn project_for(&self, dir: &Path) -> Option<&Project> {
+        self.projects
+            .iter()
+            .filter(|p| dir.starts_with(&p.path))
+            .max_by_key(|p| p.path.components().count())
+    }
+
     /// Get mutable projects
     pub fn projects_mut(&mut self) -> &mut [Project] {
         &mut self.projects
@@ -476,7 +499,7 @@ impl SessionManager {
         }
 
         // Sort by last activity
-        items.sort_by(|a, b| b.last_activity.cmp(&a.last_activity));
+        items.sort_by_key(|b| std::cmp::Reverse(b.last_activity));
         items
     }
 
@@ -533,6 +556,17 @@ mod tests {
         assert_eq!(project.sessions[1].name, "git");
     }
 
+    #[test]
+    fn test_project_for_nested_folders() {
+        let mut manager = SessionManager::new(PathBuf::from("/tmp/corgiterm-unused"));
+        manager.open_project(PathBuf::from("/work"));
+        manager.open_project(PathBuf::from("/work/infra"));
+        let project = |dir: &str| manager.project_for(Path::new(dir)).map(|p| p.name.clone());
+        assert_eq!(project("/work/infra/prod").as_deref(), Some("infra"));
+        assert_eq!(project("/work/app").as_deref(), Some("work"));
+        assert_eq!(project("/workshop"), None);
+    }
+
     #[test]
     fn test_session_id() {
         let id1 = SessionId::new();
@@ -0,0 +1,592 @@
+//! Shell integration via OSC 133 semantic prompt marks and OSC 7 directories
+//!
+//! Bundled bash, zsh and fish scripts make the shell announce where its prompt,
+//! the typed command and the command's output begin, and how each command
+//! exited:
+//!
+//! | Sequence                                   | Event                              |
+//! |--------------------------------------------|------------------------------------|
+//! | `OSC 133 ; A`                              | [`TerminalEvent::PromptStart`]     |
+//! | `OSC 133 ; B`                              | [`TerminalEvent::CommandStart`]    |
+//! | `OSC 133 ; C [; cmdline_url=<escaped>]`    | [`TerminalEvent::OutputStart`]     |
+//! | `OSC 133 ; D [; <exit status>]`            | [`TerminalEvent::CommandFinished`] |
+//! | `OSC 7 ; file://<host>/<escaped path>`     | [`TerminalEvent::CwdChanged`]      |
+//! | `OSC 6977 ; check ; id=<n> ; cmdline_url=<escaped>` | [`TerminalEvent::CommandCheck`] |
+//! | `OSC 6977 ; keymap ; vi\|emacs`            | [`TerminalEvent::EditingMode`]     |
+//!
+//! The scripts report the working directory and the line editor's key
+//! bindings with every prompt, so the directory stays right inside `ssh` and
+//! other programs that hide the shell from `/proc`, as long as the remote
+//! shell reports OSC 7 too.
+//!
+//! At [`Level::CheckCommands`] the shell gets [`CHECK_ENV`] and binds the keys
+//! that run a line by default (Enter, Ctrl-J, and Ctrl-O in bash and zsh) to
+//! send the edited line as a `check` instead of running it, with a fresh `id`.
+//! The line stays in the editor until the terminal writes [`accept_line`] for
+//! that id to the PTY. The scripts bind only the current id's key, and run the
+//! line only if it is still the text that was checked; an edited line is
+//! checked again. A refused line can be edited or cleared. Keys the user binds
+//! to run a line themselves are not checked.
+//!
+//! [`Pty::spawn`](crate::Pty::spawn) installs the scripts under the user data
+//! directory and starts the shell so it loads them on top of the user's own
+//! startup files:
+//! - bash: `--rcfile` bootstrap that sources `~/.bashrc` first
+//! - zsh: `ZDOTDIR` bootstrap whose `.zshenv` restores the user's `ZDOTDIR`
+//! - fish: a `vendor_conf.d` snippet found through `XDG_DATA_DIRS`
+//!
+//! Other shells run unchanged and simply produce no marks.
+
+use crate::terminal::TerminalEvent;
+use portable_pty::CommandBuilder;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variable pointing the bootstrap files at the installed scripts
+pub const DIR_ENV: &str = "CORGITERM_SHELL_INTEGRATION_DIR";
+
+/// Environment variable asking the scripts to have typed commands checked
+pub const CHECK_ENV: &str = "CORGITERM_CHECK_COMMANDS";
+
+/// Longest check id the scripts send
+const MAX_CHECK_ID_LEN: usize = 20;
+
+/// Key sequence the scripts bind for check `id`, written to the PTY to let
+/// the checked command run
+pub fn accept_line(id: &str) -> Vec<u8> {
+    format!("\x1b[6977;{}~", id).into_bytes()
+}
+
+/// Longest OSC payload the scanner keeps; longer sequences are skipped
+const MAX_OSC_LEN: usize = 16 * 1024;
+
+/// Scripts as `(path relative to the install dir, contents)`
+const FILES: &[(&str, &str)] = &[
+    ("bashrc", include_str!("../shell-integration/bashrc")),
+    (
+        "corgiterm.bash",
+        include_str!("../shell-integration/corgiterm.bash"),
+    ),
+    (
+        "corgiterm.zsh",
+        include_str!("../shell-integration/corgiterm.zsh"),
+    ),
+    (
+        "zsh/.zshenv",
+        include_str!("../shell-integration/zsh/.zshenv"),
+    ),
+    (
+        "fish/vendor_conf.d/corgiterm.fish",
+        include_str!("../shell-integration/fish/vendor_conf.d/corgiterm.fish"),
+    ),
+];
+
+/// Shells with bundled integration scripts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellKind {
+    /// Recognize a shell from its program path, e.g. `/usr/bin/zsh`
+    pub fn detect(program: &str) -> Option<Self> {
+        let name = Path::new(program).file_name()?.to_str()?;
+        match name.trim_start_matches('-') {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// How much shell integration a spawned shell loads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Level {
+    /// Run the shell unchanged
+    Off,
+    /// Prompt marks, command lines and working directory reports
+    #[default]
+    Marks,
+    /// Marks, plus holding each typed command until the terminal accepts it
+    CheckCommands,
+}
+
+/// Default install location: `<data dir>/corgiterm/shell-integration`
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("corgiterm").join("shell-integration"))
+}
+
+/// Write the integration scripts into `dir`, leaving unchanged files alone
+pub fn install(dir: &Path) -> std::io::Result<()> {
+    for (name, contents) in FILES {
+        let path = dir.join(name);
+        if std::fs::read_to_string(&path).is_ok_and(|existing| existing == *contents) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+    }
+    Ok(())
+}
+
+/// Install the scripts once per process, returning where they live
+fn installed_dir() -> Option<&'static Path> {
+    static DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = default_dir()?;
+        match install(&dir) {
+            Ok(()) => Some(dir),
+            Err(e) => {
+                tracing::warn!(
+                    "Shell integration disabled: cannot install scripts in {}: {}",
+                    dir.display(),
+                    e
+                );
+                None
+            }
+        }
+    })
+    .as_deref()
+}
+
+/// Make an interactive shell started by `cmd` load the integration
+///
+/// `cmd` must not have arguments yet; unknown shells are left untouched.
+pub(crate) fn inject(cmd: &mut CommandBuilder, shell: &str, level: Level) {
+    if level == Level::Off {
+        return;
+    }
+    let Some(kind) = ShellKind::detect(shell) else {
+        return;
+    };
+    let Some(dir) = installed_dir() else {
+        return;
+    };
+
+    cmd.env(DIR_ENV, dir);
+    if level == Level::CheckCommands {
+        cmd.env(CHECK_ENV, "1");
+    }
+    match kind {
+        ShellKind::Bash => {
+            cmd.arg("--rcfile");
+            cmd.arg(dir.join("bashrc"));
+        }
+        ShellKind::Zsh => {
+            if let Some(original) = std::env::var_os("ZDOTDIR") {
+                cmd.env("CORGITERM_ZDOTDIR", original);
+            }
+            cmd.env("ZDOTDIR", dir.join("zsh"));
+        }
+        ShellKind::Fish => {
+            let mut data_dirs = dir.as_os_str().to_owned();
+            match std::env::var_os("XDG_DATA_DIRS") {
+                Some(original) => {
+                    data_dirs.push(":");
+                    data_dirs.push(&original);
+                    cmd.env("CORGITERM_XDG_DATA_DIRS", original);
+                }
+                // The XDG default, so fish still finds system-wide snippets
+                None => data_dirs.push(":/usr/local/share:/usr/share"),
+            }
+            cmd.env("XDG_DATA_DIRS", data_dirs);
+        }
+    }
+}
+
+/// Interpret the fields of an OSC 133 sequence (everything after `133`)
+pub fn parse_semantic_prompt(fields: &[&[u8]]) -> Option<TerminalEvent> {
+    let (kind, rest) = fields.split_first()?;
+    match *kind {
+        b"A" => Some(TerminalEvent::PromptStart),
+        b"B" => Some(TerminalEvent::CommandStart),
+        b"C" => Some(TerminalEvent::OutputStart {
+            command: rest.iter().find_map(|field| command_line(field)),
+        }),
+        b"D" => Some(TerminalEvent::CommandFinished {
+            exit_code: rest.iter().find_map(|field| {
+                std::str::from_utf8(field)
+                    .ok()
+                    .and_then(|s| s.trim().parse().ok())
+            }),
+        }),
+        _ => None,
+    }
+}
+
+/// Interpret a CorgiTerm OSC 6977 sequence (everything after `6977`)
+pub fn parse_command_check(fields: &[&[u8]]) -> Option<TerminalEvent> {
+    let (kind, rest) = fields.split_first()?;
+    match *kind {
+        // vte splits on ';', which is escaped in the command line. The id is
+        // written back as keys, so only digits are taken.
+        b"check" => Some(TerminalEvent::CommandCheck {
+            id: rest
+                .iter()
+                .find_map(|field| field.strip_prefix(b"id="))
+                .filter(|id| {
+                    (1..=MAX_CHECK_ID_LEN).contains(&id.len()) && id.iter().all(u8::is_ascii_digit)
+                })
+                .map(|id| String::from_utf8_lossy(id).into_owned())?,
+            command: rest
+                .iter()
+                .find_map(|field| command_line(field))
+                .unwrap_or_default(),
+        }),
+        b"keymap" => match rest.first().copied()? {
+            b"vi" => Some(TerminalEvent::EditingMode { vi: true }),
+            b"emacs" => Some(TerminalEvent::EditingMode { vi: false }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Interpret an OSC 7 working directory report (everything after `7;`)
+///
+/// Only `file://` URLs are understood; the path is percent-decoded.
+pub fn parse_cwd_report(url: &[u8]) -> Option<TerminalEvent> {
+    let rest = url.strip_prefix(b"file://")?;
+    let slash = rest.iter().position(|&b| b == b'/')?;
+    let (host, path) = rest.split_at(slash);
+    let host = std::str::from_utf8(host)
+        .ok()
+        .filter(|host| !host.is_empty())
+        .map(str::to_string);
+    Some(TerminalEvent::CwdChanged {
+        host,
+        path: PathBuf::from(OsString::from_vec(percent_decode(path))),
+    })
+}
+
+/// Whether an OSC 7 host names this machine
+///
+/// An empty host and `localhost` are local. Hostnames are compared without
+/// their domain, since shells disagree on whether `$HOSTNAME` is qualified.
+pub fn is_local_host(host: Option<&str>) -> bool {
+    static LOCAL: OnceLock<Option<String>> = OnceLock::new();
+    let Some(host) = host else {
+        return true;
+    };
+    let short = |name: &str| name.split('.').next().unwrap_or_default().to_lowercase();
+    if host.is_empty() || host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    LOCAL
+        .get_or_init(|| {
+            nix::unistd::gethostname()
+                .ok()
+                .and_then(|name| name.into_string().ok())
+        })
+        .as_deref()
+        .is_some_and(|local| short(local) == short(host))
+}
+
+/// Shell integration event for a complete OSC payload, if it is one
+pub fn event_for_osc(payload: &[u8]) -> Option<TerminalEvent> {
+    if let Some(url) = payload.strip_prefix(b"7;") {
+        return parse_cwd_report(url);
+    }
+    let fields: Vec<&[u8]> = payload.split(|&b| b == b';').collect();
+    match fields.split_first()? {
+        (&b"133", rest) => parse_semantic_prompt(rest),
+        (&b"6977", rest) => parse_command_check(rest),
+        _ => None,
+    }
+}
+
+/// The command line carried by an OSC 133 C field, if any
+fn command_line(field: &[u8]) -> Option<String> {
+    if let Some(encoded) = field.strip_prefix(b"cmdline_url=") {
+        Some(String::from_utf8_lossy(&percent_decode(encoded)).into_owned())
+    } else {
+        field
+            .strip_prefix(b"cmdline=")
+            .map(|raw| String::from_utf8_lossy(raw).into_owned())
+    }
+}
+
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() {
+            if let (Some(hi), Some(lo)) = (hex(input[i + 1]), hex(input[i + 2])) {
+                out.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(input[i]);
+        i += 1;
+    }
+    out
+}
+
+/// A complete OSC sequence found by [`OscScanner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OscSequence {
+    /// Offset just past the sequence's terminator in the scanned chunk
+    pub end: usize,
+    /// Bytes between `ESC ]` and the terminator
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Picks OSC sequences out of a PTY byte stream
+///
+/// Used alongside parsers that drop OSC numbers they do not know. Sequences
+/// split across chunks are reassembled; the bytes themselves are not consumed
+/// and must still be fed to the real parser.
+#[derive(Debug, Default)]
+pub struct OscScanner {
+    state: ScanState,
+    payload: Vec<u8>,
+    overflowed: bool,
+}
+
+impl OscScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan the next chunk of output, returning the sequences it completes
+    pub fn scan(&mut self, bytes: &[u8]) -> Vec<OscSequence> {
+        let mut found = Vec::new();
+        for (i, &b) in bytes.iter().enumerate() {
+            self.state = match (self.state, b) {
+                (ScanState::Ground, 0x1b) => ScanState::Escape,
+                (ScanState::Ground, _) => ScanState::Ground,
+                (ScanState::Escape, b']') => {
+                    self.payload.clear();
+                    self.overflowed = false;
+                    ScanState::Osc
+                }
+                (ScanState::Escape, 0x1b) => ScanState::Escape,
+                (ScanState::Escape, _) => ScanState::Ground,
+                // BEL terminator
+                (ScanState::Osc, 0x07) => {
+                    self.finish(i + 1, &mut found);
+                    ScanState::Ground
+                }
+                (ScanState::Osc, 0x1b) => ScanState::OscEscape,
+                // CAN / SUB abort the sequence
+                (ScanState::Osc, 0x18 | 0x1a) => ScanState::Ground,
+                (ScanState::Osc, _) => {
+                    if self.payload.len() < MAX_OSC_LEN {
+                        self.payload.push(b);
+                    } else {
+                        self.overflowed = true;
+                    }
+                    ScanState::Osc
+                }
+                // ST terminator
+                (ScanState::OscEscape, b'\\') => {
+                    self.finish(i + 1, &mut found);
+                    ScanState::Ground
+                }
+                // Any other escape ends the string and starts a new sequence
+                (ScanState::OscEscape, _) => {
+                    self.finish(i, &mut found);
+                    if b == b']' {
+                        self.payload.clear();
+                        self.overflowed = false;
+                        ScanState::Osc
+                    } else {
+                        ScanState::Ground
+                    }
+                }
+            };
+        }
+        found
+    }
+
+    fn finish(&mut self, end: usize, found: &mut Vec<OscSequence>) {
+        if !self.overflowed {
+            found.push(OscSequence {
+                end,
+                payload: std::mem::take(&mut self.payload),
+            });
+        }
+        self.payload.clear();
+        self.overflowed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_shell() {
+        assert_eq!(ShellKind::detect("/bin/bash"), Some(ShellKind::Bash));
+        assert_eq!(ShellKind::detect("-zsh"), Some(ShellKind::Zsh));
+        assert_eq!(
+            ShellKind::detect("/opt/homebrew/bin/fish"),
+            Some(ShellKind::Fish)
+        );
+        assert_eq!(ShellKind::detect("/bin/sh"), None);
+    }
+
+    #[test]
+    fn test_parse_marks() {
+        assert!(matches!(
+            event_for_osc(b"133;A"),
+            Some(TerminalEvent::PromptStart)
+        ));
+        assert!(matches!(
+            event_for_osc(b"133;B"),
+            Some(TerminalEvent::CommandStart)
+        ));
+        assert!(matches!(
+            event_for_osc(b"133;D;127"),
+            Some(TerminalEvent::CommandFinished {
+                exit_code: Some(127)
+            })
+        ));
+        assert!(matches!(
+            event_for_osc(b"133;D"),
+            Some(TerminalEvent::CommandFinished { exit_code: None })
+        ));
+        assert!(event_for_osc(b"133;Z").is_none());
+        assert!(event_for_osc(b"0;title").is_none());
+    }
+
+    #[test]
+    fn test_parse_command_line() {
+        match event_for_osc(b"133;C;cmdline_url=git%20commit%20-m%20%22wip%3B%20ok%22") {
+            Some(TerminalEvent::OutputStart { command }) => {
+                assert_eq!(command.as_deref(), Some("git commit -m \"wip; ok\""))
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match event_for_osc(b"133;C") {
+            Some(TerminalEvent::OutputStart { command }) => assert!(command.is_none()),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_command_check() {
+        match event_for_osc(b"6977;check;id=4242;cmdline_url=rm%20-rf%20%2A%3B%20ls") {
+            Some(TerminalEvent::CommandCheck { id, command }) => {
+                assert_eq!(id, "4242");
+                assert_eq!(command, "rm -rf *; ls");
+                assert_eq!(accept_line(&id), b"\x1b[6977;4242~");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match event_for_osc(b"6977;check;id=7;cmdline_url=") {
+            Some(TerminalEvent::CommandCheck { command, .. }) => assert!(command.is_empty()),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        // The id is typed back into the shell, so it must be plain digits
+        assert!(event_for_osc(b"6977;check;cmdline_url=ls").is_none());
+        assert!(event_for_osc(b"6977;check;id=1~\x1b[6977;2;cmdline_url=ls").is_none());
+        assert!(event_for_osc(b"6977;check;id=123456789012345678901;cmdline_url=ls").is_none());
+        assert!(event_for_osc(b"6977;other").is_none());
+    }
+
+    #[test]
+    fn test_parse_editing_mode() {
+        assert!(matches!(
+            event_for_osc(b"6977;keymap;vi"),
+            Some(TerminalEvent::EditingMode { vi: true })
+        ));
+        assert!(matches!(
+            event_for_osc(b"6977;keymap;emacs"),
+            Some(TerminalEvent::EditingMode { vi: false })
+        ));
+        assert!(event_for_osc(b"6977;keymap;vim").is_none());
+        assert!(event_for_osc(b"6977;keymap").is_none());
+    }
+
+    #[test]
+    fn test_parse_cwd_report() {
+        match event_for_osc(b"7;file://corgi.example.com/home/me/My%20Docs;x") {
+            Some(TerminalEvent::CwdChanged { host, path }) => {
+                assert_eq!(host.as_deref(), Some("corgi.example.com"));
+                assert_eq!(path, PathBuf::from("/home/me/My Docs;x"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match event_for_osc(b"7;file:///tmp") {
+            Some(TerminalEvent::CwdChanged { host, path }) => {
+                assert!(host.is_none());
+                assert_eq!(path, PathBuf::from("/tmp"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(event_for_osc(b"7;https://example.com/").is_none());
+        assert!(event_for_osc(b"7;file://hostonly").is_none());
+    }
+
+    #[test]
+    fn test_local_host() {
+        assert!(is_local_host(None));
+        assert!(is_local_host(Some("")));
+        assert!(is_local_host(Some("LocalHost")));
+        let name = nix::unistd::gethostname().unwrap().into_string().unwrap();
+        assert!(is_local_host(Some(&name)));
+        assert!(is_local_host(Some(&format!("{}.example.invalid", name))));
+        assert!(!is_local_host(Some("corgiterm-remote-host.invalid")));
+    }
+
+    #[test]
+    fn test_scanner_finds_both_terminators() {
+        let mut scanner = OscScanner::new();
+        let input = b"ls\x1b]133;C\x07out\x1b]133;D;0\x1b\\$ ";
+        let found = scanner.scan(input);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].payload, b"133;C");
+        assert_eq!(&input[..found[0].end], b"ls\x1b]133;C\x07");
+        assert_eq!(found[1].payload, b"133;D;0");
+        assert_eq!(found[1].end, input.len() - 2);
+    }
+
+    #[test]
+    fn test_scanner_reassembles_split_sequences() {
+        let mut scanner = OscScanner::new();
+        assert!(scanner.scan(b"abc\x1b").is_empty());
+        assert!(scanner.scan(b"]133;").is_empty());
+        let found = scanner.scan(b"D;2\x07");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].payload, b"133;D;2");
+        assert_eq!(found[0].end, 4);
+    }
+
+    #[test]
+    fn test_scanner_ignores_csi_and_aborted_sequences() {
+        let mut scanner = OscScanner::new();
+        assert!(scanner.scan(b"\x1b[31mred\x1b[0m").is_empty());
+        assert!(scanner.scan(b"\x1b]133;A\x18\x07").is_empty());
+    }
+
+    #[test]
+    fn test_install_writes_scripts() {
+        let dir = std::env::temp_dir().join(format!("corgiterm-si-{}", uuid::Uuid::new_v4()));
+        install(&dir).unwrap();
+        for (name, contents) in FILES {
+            assert_eq!(std::fs::read_to_string(dir.join(name)).unwrap(), *contents);
+        }
+        // Reinstalling over identical files is a no-op
+        install(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1200 @@
+//! Shell command syntax for Safe Mode
+//!
+//! Parses a command line the way bash or zsh would split it: lists (`;`,
+//! `&`, `&&`, `||`, newlines), pipelines (`|`, `|&`), subshells and brace
+//! groups, `if`/`while`/`for`/`case` bodies, command substitutions (`$(...)`,
+//! backticks, `<(...)`), quoting, assignments, redirections and here
+//! documents. Nothing is expanded or run; the tree only tells Safe Mode which
+//! commands a line would start and with which words.
+//!
+//! Parsing is lenient: an unterminated quote or substitution runs to the end
+//! of the input, which is how the shell sees it once the user finishes typing.
+
+/// How a pipeline is joined to the one after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `;` or a newline
+    Sequence,
+    /// `&`
+    Background,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+}
+
+/// Pipelines joined by connectors
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandList {
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub pipeline: Pipeline,
+    /// How this pipeline is joined to the next one
+    pub connector: Connector,
+}
+
+/// Commands joined by `|`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    /// Starts with `!`
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// `( list )`
+    Subshell {
+        body: CommandList,
+        redirections: Vec<Redirection>,
+    },
+    /// `{ list; }`, or the branches of a `case`
+    Group {
+        body: CommandList,
+        redirections: Vec<Redirection>,
+    },
+    /// `name() body` or `function name body`
+    Function {
+        name: String,
+        body: Box<Command>,
+    },
+}
+
+/// Words and redirections that start one program or builtin
+///
+/// `for` and `case` headers and `[[ ... ]]` tests are kept as simple commands
+/// named after their keyword, so substitutions in them are not lost.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleCommand {
+    /// Leading `NAME=value` words
+    pub assignments: Vec<Word>,
+    /// The command name and its arguments
+    pub words: Vec<Word>,
+    pub redirections: Vec<Redirection>,
+}
+
+impl SimpleCommand {
+    /// The command name, without quotes
+    pub fn name(&self) -> Option<&str> {
+        self.words.first().map(|word| word.text.as_str())
+    }
+
+    /// The words after the command name
+    pub fn args(&self) -> &[Word] {
+        self.words.get(1..).unwrap_or_default()
+    }
+}
+
+/// One shell word
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Word {
+    /// The word with quotes removed; parameters and substitutions are left
+    /// as written (`$HOME`, `$(date)`)
+    pub text: String,
+    /// Some part of the word was quoted or escaped
+    pub quoted: bool,
+    /// Commands run by `$(...)`, backticks and `<(...)` in the word
+    pub substitutions: Vec<CommandList>,
+    /// Where the word was written, in characters from the start of the
+    /// parsed text (for words inside backticks, of the backtick body)
+    pub span: std::ops::Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `>|`
+    Clobber,
+    /// `<>`
+    ReadWrite,
+    /// `<<` and `<<-`
+    HereDoc,
+    /// `<<<`
+    HereString,
+    /// `<&`
+    DupInput,
+    /// `>&`
+    DupOutput,
+    /// `&>`
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+}
+
+impl RedirectOp {
+    /// How the operator is written
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Input => "<",
+            Self::Output => ">",
+            Self::Append => ">>",
+            Self::Clobber => ">|",
+            Self::ReadWrite => "<>",
+            Self::HereDoc => "<<",
+            Self::HereString => "<<<",
+            Self::DupInput => "<&",
+            Self::DupOutput => ">&",
+            Self::OutputAll => "&>",
+            Self::AppendAll => "&>>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirection {
+    /// Explicit file descriptor (`2>`)
+    pub fd: Option<u32>,
+    pub op: RedirectOp,
+    /// The file, or for a here-document its delimiter
+    pub target: Word,
+    /// A here-document's lines, each ending in a newline; `None` until the
+    /// line with the redirection ends
+    pub body: Option<String>,
+}
+
+impl Redirection {
+    /// Text a here-document or here-string feeds to the command, as written
+    pub fn input_text(&self) -> Option<&str> {
+        match self.op {
+            RedirectOp::HereDoc => self.body.as_deref(),
+            RedirectOp::HereString => Some(&self.target.text),
+            _ => None,
+        }
+    }
+
+    /// The redirection writes to the file named by its target
+    pub fn writes_file(&self) -> bool {
+        match self.op {
+            RedirectOp::Output
+            | RedirectOp::Append
+            | RedirectOp::Clobber
+            | RedirectOp::ReadWrite
+            | RedirectOp::OutputAll
+            | RedirectOp::AppendAll => true,
+            // `>&2` duplicates a descriptor; `>& file` is bash for `&> file`
+            RedirectOp::DupOutput => !self
+                .target
+                .text
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '-'),
+            _ => false,
+        }
+    }
+}
+
+impl CommandList {
+    /// Every simple command in the list, including those in subshells,
+    /// groups, function bodies and substitutions
+    pub fn simple_commands(&self) -> Vec<&SimpleCommand> {
+        let mut found = Vec::new();
+        collect_list(self, &mut found);
+        found
+    }
+}
+
+fn collect_list<'a>(list: &'a CommandList, found: &mut Vec<&'a SimpleCommand>) {
+    for item in &list.items {
+        for command in &item.pipeline.commands {
+            collect_command(command, found);
+        }
+    }
+}
+
+fn collect_command<'a>(command: &'a Command, found: &mut Vec<&'a SimpleCommand>) {
+    match command {
+        Command::Simple(simple) => {
+            let words = simple
+                .assignments
+                .iter()
+                .chain(&simple.words)
+                .chain(simple.redirections.iter().map(|r| &r.target));
+            for word in words {
+                for substitution in &word.substitutions {
+                    collect_list(substitution, found);
+                }
+            }
+            found.push(simple);
+        }
+        Command::Subshell { body, redirections } | Command::Group { body, redirections } => {
+            collect_list(body, found);
+            for redirection in redirections {
+                for substitution in &redirection.target.substitutions {
+                    collect_list(substitution, found);
+                }
+            }
+        }
+        Command::Function { body, .. } => collect_command(body, found),
+    }
+}
+
+/// Parse a command line
+pub fn parse(input: &str) -> CommandList {
+    parse_at_depth(input, 0)
+}
+
+fn parse_at_depth(input: &str, depth: usize) -> CommandList {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+        depth,
+        heredocs: Vec::new(),
+        bodies: Vec::new(),
+    };
+    let mut list = CommandList::default();
+    // Stray closers at the top level are skipped rather than ending the list
+    while parser.peek().is_some() {
+        let start = parser.pos;
+        list.items.extend(parser.parse_list(&[]).items);
+        if parser.pos == start {
+            parser.pos += 1;
+        }
+    }
+    fill_list_bodies(&mut list, &mut parser.bodies.into_iter());
+    list
+}
+
+/// Hand here-document bodies, which are read after the whole line, to their
+/// redirections in the order they were written
+fn fill_list_bodies(list: &mut CommandList, bodies: &mut impl Iterator<Item = String>) {
+    for item in &mut list.items {
+        for command in &mut item.pipeline.commands {
+            fill_command_bodies(command, bodies);
+        }
+    }
+}
+
+fn fill_command_bodies(command: &mut Command, bodies: &mut impl Iterator<Item = String>) {
+    let redirections = match command {
+        Command::Simple(simple) => &mut simple.redirections,
+        Command::Subshell { body, redirections } | Command::Group { body, redirections } => {
+            fill_list_bodies(body, bodies);
+            redirections
+        }
+        Command::Function { body, .. } => return fill_command_bodies(body, bodies),
+    };
+    for redirection in redirections {
+        if redirection.op == RedirectOp::HereDoc {
+            redirection.body = bodies.next();
+        }
+    }
+}
+
+/// Nesting beyond this is read as plain text
+const MAX_DEPTH: usize = 64;
+
+/// Keywords that only frame the commands inside them
+const FRAMING_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "while", "until", "do", "done", "time", "}", "esac",
+];
+
+const REDIRECTIONS: &[(&str, RedirectOp)] = &[
+    ("&>>", RedirectOp::AppendAll),
+    ("&>", RedirectOp::OutputAll),
+    ("<<<", RedirectOp::HereString),
+    ("<<-", RedirectOp::HereDoc),
+    ("<<", RedirectOp::HereDoc),
+    ("<>", RedirectOp::ReadWrite),
+    ("<&", RedirectOp::DupInput),
+    (">>", RedirectOp::Append),
+    (">&", RedirectOp::DupOutput),
+    (">|", RedirectOp::Clobber),
+    ("<", RedirectOp::Input),
+    (">", RedirectOp::Output),
+];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Substitution and grouping depth
+    depth: usize,
+    /// Here-document delimiters whose bodies start after the next newline,
+    /// and whether leading tabs are stripped (`<<-`)
+    heredocs: Vec<(String, bool)>,
+    /// Here-document bodies read so far, in order
+    bodies: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn eat(&mut self, c: char) {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip spaces, line continuations and comments, but not newlines
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.pos += 1,
+                Some('\\') if self.peek_at(1) == Some('\n') => self.pos += 2,
+                Some('#') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Skip blanks and newlines, and any here-document bodies they start
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_blanks();
+            if self.peek() != Some('\n') {
+                break;
+            }
+            self.pos += 1;
+            self.read_heredoc_bodies();
+        }
+    }
+
+    fn read_heredoc_bodies(&mut self) {
+        for (delimiter, strip_tabs) in std::mem::take(&mut self.heredocs) {
+            let mut body = String::new();
+            while self.pos < self.chars.len() {
+                let end = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(self.chars.len(), |offset| self.pos + offset);
+                let line: String = self.chars[self.pos..end].iter().collect();
+                self.pos = (end + 1).min(self.chars.len());
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if line == delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            self.bodies.push(body);
+        }
+    }
+
+    /// The unquoted word at the cursor, if it is plain text
+    fn peek_bare_word(&self) -> Option<String> {
+        let mut word = String::new();
+        for &c in &self.chars[self.pos..] {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | ';' | '&' | '|' | '(' | ')' | '<' | '>' => break,
+                '\'' | '"' | '\\' | '$' | '`' => return None,
+                _ => word.push(c),
+            }
+        }
+        (!word.is_empty()).then_some(word)
+    }
+
+    fn eat_bare_word(&mut self, word: &str) -> bool {
+        self.skip_blanks();
+        if self.peek_bare_word().as_deref() == Some(word) {
+            self.pos += word.chars().count();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_closer(&self, closers: &[&str]) -> bool {
+        closers.iter().any(|&closer| match closer {
+            ")" | ";;" | ";&" | ";;&" => self.starts_with(closer),
+            keyword => self.peek_bare_word().as_deref() == Some(keyword),
+        })
+    }
+
+    fn parse_list(&mut self, closers: &[&str]) -> CommandList {
+        self.depth += 1;
+        let mut list = CommandList::default();
+        loop {
+            self.skip_blank_lines();
+            if self.peek().is_none() || self.at_closer(closers) {
+                break;
+            }
+            // Empty commands between separators
+            if self.peek() == Some(';') && !self.starts_with(";;") {
+                self.pos += 1;
+                continue;
+            }
+            let start = self.pos;
+            let pipeline = self.parse_pipeline(closers);
+            self.skip_blanks();
+            let connector = if self.starts_with("&&") {
+                self.pos += 2;
+                Connector::And
+            } else if self.starts_with("||") {
+                self.pos += 2;
+                Connector::Or
+            } else if self.peek() == Some('&') {
+                self.pos += 1;
+                Connector::Background
+            } else {
+                if self.peek() == Some(';') && !self.at_closer(closers) {
+                    self.pos += 1;
+                }
+                Connector::Sequence
+            };
+            if !pipeline.commands.is_empty() {
+                list.items.push(ListItem {
+                    pipeline,
+                    connector,
+                });
+            }
+            if self.pos == start {
+                break;
+            }
+        }
+        self.depth -= 1;
+        list
+    }
+
+    fn parse_pipeline(&mut self, closers: &[&str]) -> Pipeline {
+        let mut pipeline = Pipeline::default();
+        self.skip_blanks();
+        if self.peek_bare_word().as_deref() == Some("!") {
+            self.pos += 1;
+            pipeline.negated = true;
+        }
+        loop {
+            self.skip_blanks();
+            if self.at_closer(closers) {
+                break;
+            }
+            if let Some(command) = self.parse_command(closers) {
+                pipeline.commands.push(command);
+            }
+            self.skip_blanks();
+            if self.starts_with("||") {
+                break;
+            } else if self.starts_with("|&") {
+                self.pos += 2;
+            } else if self.peek() == Some('|') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+            self.skip_blank_lines();
+        }
+        pipeline
+    }
+
+    fn parse_command(&mut self, closers: &[&str]) -> Option<Command> {
+        loop {
+            self.skip_blanks();
+            match self.peek_bare_word() {
+                Some(word)
+                    if FRAMING_KEYWORDS.contains(&word.as_str())
+                        && !closers.contains(&word.as_str()) =>
+                {
+                    self.pos += word.chars().count();
+                }
+                _ => break,
+            }
+        }
+        if self.at_closer(closers) {
+            return None;
+        }
+        match self.peek() {
+            Some('(') if self.peek_at(1) == Some('(') => {
+                // Arithmetic `(( ... ))` runs nothing
+                self.skip_parens();
+                return None;
+            }
+            Some('(') => {
+                self.pos += 1;
+                let body = self.parse_nested(&[")"]);
+                self.eat(')');
+                let redirections = self.parse_redirections();
+                return Some(Command::Subshell { body, redirections });
+            }
+            _ => {}
+        }
+        match self.peek_bare_word().as_deref() {
+            Some("{") => {
+                self.pos += 1;
+                let body = self.parse_nested(&["}"]);
+                self.eat_bare_word("}");
+                let redirections = self.parse_redirections();
+                Some(Command::Group { body, redirections })
+            }
+            Some("case") => Some(self.parse_case()),
+            Some(keyword @ ("for" | "select")) => Some(self.parse_for(keyword)),
+            Some("[[") => Some(self.parse_conditional()),
+            Some("function") => {
+                self.pos += "function".len();
+                self.skip_blanks();
+                let name = self.read_word()?.text;
+                self.skip_blanks();
+                if self.peek() == Some('(') {
+                    self.skip_parens();
+                }
+                Some(self.parse_function_body(name))
+            }
+            _ => self.parse_simple(),
+        }
+    }
+
+    /// Parse a list nested inside the current one, unless nesting is absurd
+    fn parse_nested(&mut self, closers: &[&str]) -> CommandList {
+        if self.depth >= MAX_DEPTH {
+            self.pos = self.chars.len();
+            return CommandList::default();
+        }
+        self.parse_list(closers)
+    }
+
+    fn parse_simple(&mut self) -> Option<Command> {
+        let mut command = SimpleCommand::default();
+        loop {
+            self.skip_blanks();
+            if let Some(redirection) = self.parse_redirection() {
+                command.redirections.push(redirection);
+                continue;
+            }
+            match self.peek() {
+                None | Some('\n' | ';' | '&' | '|' | ')') => break,
+                Some('(') => {
+                    // `name() body` defines a function
+                    if command.words.len() == 1 && command.assignments.is_empty() {
+                        self.skip_parens();
+                        let name = command.words.remove(0).text;
+                        return Some(self.parse_function_body(name));
+                    }
+                    break;
+                }
+                _ => {}
+            }
+            let Some(word) = self.read_word() else {
+                break;
+            };
+            if command.words.is_empty() && is_assignment(&word.text) {
+                command.assignments.push(word);
+            } else {
+                command.words.push(word);
+            }
+        }
+        let empty = command.words.is_empty()
+            && command.assignments.is_empty()
+            && command.redirections.is_empty();
+        (!empty).then_some(Command::Simple(command))
+    }
+
+    fn parse_function_body(&mut self, name: String) -> Command {
+        self.skip_blank_lines();
+        let body = self.parse_command(&[]).unwrap_or(Command::Group {
+            body: CommandList::default(),
+            redirections: Vec::new(),
+        });
+        Command::Function {
+            name,
+            body: Box::new(body),
+        }
+    }
+
+    /// `for NAME in words`, kept as a simple command; the body follows as
+    /// ordinary commands framed by `do` and `done`
+    fn parse_for(&mut self, keyword: &str) -> Command {
+        self.pos += keyword.len();
+        let mut command = SimpleCommand {
+            words: vec![Word {
+                text: keyword.to_string(),
+                ..Word::default()
+            }],
+            ..SimpleCommand::default()
+        };
+        self.skip_blanks();
+        if self.starts_with("((") {
+            self.skip_parens();
+            return Command::Simple(command);
+        }
+        while let Some(word) = {
+            self.skip_blanks();
+            self.read_word()
+        } {
+            command.words.push(word);
+        }
+        Command::Simple(command)
+    }
+
+    /// `case word in pattern) list ;; ... esac`, as a group of every branch
+    fn parse_case(&mut self) -> Command {
+        self.pos += "case".len();
+        let mut header = SimpleCommand {
+            words: vec![Word {
+                text: "case".to_string(),
+                ..Word::default()
+            }],
+            ..SimpleCommand::default()
+        };
+        self.skip_blanks();
+        header.words.extend(self.read_word());
+        let mut body = CommandList {
+            items: vec![ListItem {
+                pipeline: Pipeline {
+                    negated: false,
+                    commands: vec![Command::Simple(header)],
+                },
+                connector: Connector::Sequence,
+            }],
+        };
+        self.eat_bare_word("in");
+        loop {
+            self.skip_blank_lines();
+            let start = self.pos;
+            if self.peek().is_none() || self.eat_bare_word("esac") {
+                break;
+            }
+            self.eat('(');
+            // Patterns, up to the `)`
+            loop {
+                self.skip_blanks();
+                match self.peek() {
+                    None => break,
+                    Some(')') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    Some('|') => self.pos += 1,
+                    _ => {
+                        if self.read_word().is_none() {
+                            self.pos += 1;
+                        }
+                    }
+                }
+            }
+            let branch = self.parse_nested(&[";;&", ";;", ";&", "esac"]);
+            body.items.extend(branch.items);
+            for terminator in [";;&", ";;", ";&"] {
+                if self.starts_with(terminator) {
+                    self.pos += terminator.len();
+                    break;
+                }
+            }
+            if self.pos == start {
+                self.pos += 1;
+            }
+        }
+        let redirections = self.parse_redirections();
+        Command::Group { body, redirections }
+    }
+
+    /// `[[ ... ]]`, whose `<`, `>`, `&&` and `||` are comparisons, not syntax
+    fn parse_conditional(&mut self) -> Command {
+        let mut command = SimpleCommand::default();
+        loop {
+            self.skip_blanks();
+            let operator = ["&&", "||", "<", ">", "(", ")", "|", "&"]
+                .into_iter()
+                .find(|op| self.starts_with(op));
+            if let Some(operator) = operator {
+                self.pos += operator.len();
+                command.words.push(Word {
+                    text: operator.to_string(),
+                    ..Word::default()
+                });
+                continue;
+            }
+            let Some(word) = self.read_word() else {
+                break;
+            };
+            let done = word.text == "]]" && !word.quoted;
+            command.words.push(word);
+            if done {
+                break;
+            }
+        }
+        command.redirections = self.parse_redirections();
+        Command::Simple(command)
+    }
+
+    fn parse_redirections(&mut self) -> Vec<Redirection> {
+        let mut redirections = Vec::new();
+        loop {
+            self.skip_blanks();
+            match self.parse_redirection() {
+                Some(redirection) => redirections.push(redirection),
+                None => return redirections,
+            }
+        }
+    }
+
+    fn parse_redirection(&mut self) -> Option<Redirection> {
+        let digits = self.chars[self.pos..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        let after = self.pos + digits;
+        let (text, op) = REDIRECTIONS.iter().copied().find(|(text, _)| {
+            text.chars()
+                .enumerate()
+                .all(|(i, c)| self.chars.get(after + i) == Some(&c))
+        })?;
+        if digits > 0 && text.starts_with('&') {
+            return None;
+        }
+        // `<(...)` and `>(...)` are process substitutions, read as words
+        if matches!(text, "<" | ">") && self.chars.get(after + 1) == Some(&'(') {
+            return None;
+        }
+        let fd = self.chars[self.pos..after]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok();
+        self.pos = after + text.len();
+        self.skip_blanks();
+        let target = self.read_word().unwrap_or_default();
+        if op == RedirectOp::HereDoc {
+            self.heredocs.push((target.text.clone(), text == "<<-"));
+        }
+        Some(Redirection {
+            fd,
+            op,
+            target,
+            body: None,
+        })
+    }
+
+    /// Skip a balanced `( ... )` or `(( ... ))`
+    fn skip_parens(&mut self) {
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn read_word(&mut self) -> Option<Word> {
+        let mut word = Word::default();
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | ';' | '&' | '|' | '(' | ')' => break,
+                '<' | '>' => {
+                    if self.peek_at(1) != Some('(') {
+                        break;
+                    }
+                    let from = self.pos;
+                    self.pos += 2;
+                    let body = self.parse_nested(&[")"]);
+                    self.eat(')');
+                    word.substitutions.push(body);
+                    word.text.extend(&self.chars[from..self.pos]);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    if let Some(next) = self.peek() {
+                        if next != '\n' {
+                            word.text.push(next);
+                            word.quoted = true;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                '\'' => {
+                    self.pos += 1;
+                    word.quoted = true;
+                    while let Some(c) = self.peek() {
+                        self.pos += 1;
+                        if c == '\'' {
+                            break;
+                        }
+                        word.text.push(c);
+                    }
+                }
+                '"' => {
+                    self.pos += 1;
+                    word.quoted = true;
+                    self.read_double_quoted(&mut word);
+                }
+                '$' => self.read_dollar(&mut word, false),
+                '`' => self.read_backticks(&mut word),
+                _ => {
+                    word.text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        word.span = start..self.pos;
+        (self.pos > start).then_some(word)
+    }
+
+    fn read_double_quoted(&mut self, word: &mut Word) {
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return;
+                }
+                '\\' => match self.peek_at(1) {
+                    Some(next @ ('$' | '`' | '"' | '\\')) => {
+                        word.text.push(next);
+                        self.pos += 2;
+                    }
+                    Some('\n') => self.pos += 2,
+                    _ => {
+                        word.text.push('\\');
+                        self.pos += 1;
+                    }
+                },
+                '$' => self.read_dollar(word, true),
+                '`' => self.read_backticks(word),
+                _ => {
+                    word.text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn read_dollar(&mut self, word: &mut Word, in_double_quotes: bool) {
+        let start = self.pos;
+        match self.peek_at(1) {
+            Some('(') if self.peek_at(2) == Some('(') => {
+                // Arithmetic expansion
+                self.pos += 1;
+                self.skip_parens();
+            }
+            Some('(') => {
+                self.pos += 2;
+                let body = self.parse_nested(&[")"]);
+                self.eat(')');
+                word.substitutions.push(body);
+            }
+            Some('{') => {
+                self.pos += 2;
+                let mut depth = 1usize;
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    match c {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Some('\'') if !in_double_quotes => {
+                // ANSI-C quoting: `$'...'`
+                self.pos += 2;
+                word.quoted = true;
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    match c {
+                        '\'' => return,
+                        '\\' => {
+                            if let Some(next) = self.peek() {
+                                word.text.push(next);
+                                self.pos += 1;
+                            }
+                        }
+                        _ => word.text.push(c),
+                    }
+                }
+                return;
+            }
+            Some('"') if !in_double_quotes => {
+                // Locale translation: `$"..."`
+                self.pos += 2;
+                word.quoted = true;
+                self.read_double_quoted(word);
+                return;
+            }
+            _ => self.pos += 1,
+        }
+        word.text.extend(&self.chars[start..self.pos]);
+    }
+
+    fn read_backticks(&mut self, word: &mut Word) {
+        let start = self.pos;
+        self.pos += 1;
+        let mut inner = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '`' => break,
+                '\\' => {
+                    if let Some(next) = self.peek() {
+                        if !matches!(next, '`' | '\\' | '$') {
+                            inner.push('\\');
+                        }
+                        inner.push(next);
+                        self.pos += 1;
+                    }
+                }
+                _ => inner.push(c),
+            }
+        }
+        if self.depth < MAX_DEPTH {
+            word.substitutions
+                .push(parse_at_depth(&inner, self.depth + 1));
+        }
+        word.text.extend(&self.chars[start..self.pos]);
+    }
+}
+
+/// `NAME=value`, `NAME+=value` or `NAME[index]=value`
+fn is_assignment(text: &str) -> bool {
+    let Some(eq) = text.find('=') else {
+        return false;
+    };
+    let name = text[..eq].trim_end_matches('+');
+    let name = match name.find('[') {
+        Some(bracket) if name.ends_with(']') => &name[..bracket],
+        _ => name,
+    };
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(input: &str) -> Vec<String> {
+        parse(input)
+            .simple_commands()
+            .iter()
+            .filter_map(|command| command.name().map(str::to_string))
+            .collect()
+    }
+
+    fn words(command: &SimpleCommand) -> Vec<&str> {
+        command
+            .words
+            .iter()
+            .map(|word| word.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_lists_and_pipelines() {
+        let list = parse("ls -la; cat a | grep b && echo ok || echo fail & sleep 1");
+        let connectors: Vec<_> = list.items.iter().map(|item| item.connector).collect();
+        assert_eq!(
+            connectors,
+            [
+                Connector::Sequence,
+                Connector::And,
+                Connector::Or,
+                Connector::Background,
+                Connector::Sequence
+            ]
+        );
+        assert_eq!(list.items[1].pipeline.commands.len(), 2);
+        assert_eq!(
+            names("ls -la; cat a | grep b && echo ok || echo fail & sleep 1"),
+            ["ls", "cat", "grep", "echo", "echo", "sleep"]
+        );
+        assert_eq!(names("a\nb\n\nc |& d"), ["a", "b", "c", "d"]);
+        assert!(parse("! grep -q x f").items[0].pipeline.negated);
+    }
+
+    #[test]
+    fn test_quoting() {
+        let list = parse(r#"echo "rm -rf /" 'a b' c\ d $'e\'f' "x$HOME" '' "#);
+        let commands = list.simple_commands();
+        assert_eq!(
+            words(commands[0]),
+            ["echo", "rm -rf /", "a b", "c d", "e'f", "x$HOME", ""]
+        );
+        assert!(commands[0].words[1].quoted);
+        assert_eq!(commands[0].words[1].span, 5..15);
+        assert_eq!(commands[0].words[2].span, 16..21);
+        assert!(!commands[0].words[0].quoted);
+        // Operators inside quotes are text
+        assert_eq!(names("echo 'a; rm -rf ~' \"| sh\""), ["echo"]);
+        // Unterminated quotes run to the end
+        assert_eq!(
+            words(parse("echo 'oops; rm x").simple_commands()[0]),
+            ["echo", "oops; rm x"]
+        );
+    }
+
+    #[test]
+    fn test_substitutions() {
+        assert_eq!(names("echo $(rm -rf ~)"), ["rm", "echo"]);
+        assert_eq!(
+            names("echo \"today is $(date | tr a b)\""),
+            ["date", "tr", "echo"]
+        );
+        assert_eq!(names("echo `whoami`"), ["whoami", "echo"]);
+        assert_eq!(names("diff <(ls a) <(ls b)"), ["ls", "ls", "diff"]);
+        assert_eq!(names("echo $(echo $(id))"), ["id", "echo", "echo"]);
+        assert_eq!(names("x=$(curl -s url) ls"), ["curl", "ls"]);
+        assert_eq!(names("echo $((1 + 2)) ${HOME:-/tmp}"), ["echo"]);
+        let list = parse("echo $(ls)");
+        assert_eq!(list.simple_commands()[1].words[1].text, "$(ls)");
+    }
+
+    #[test]
+    fn test_redirections() {
+        let list = parse("cmd <in >out 2>>err &>all 2>&1 >|force 3<>rw <<<word");
+        let command = list.simple_commands()[0];
+        let ops: Vec<_> = command
+            .redirections
+            .iter()
+            .map(|r| (r.fd, r.op, r.target.text.as_str()))
+            .collect();
+        assert_eq!(
+            ops,
+            [
+                (None, RedirectOp::Input, "in"),
+                (None, RedirectOp::Output, "out"),
+                (Some(2), RedirectOp::Append, "err"),
+                (None, RedirectOp::OutputAll, "all"),
+                (Some(2), RedirectOp::DupOutput, "1"),
+                (None, RedirectOp::Clobber, "force"),
+                (Some(3), RedirectOp::ReadWrite, "rw"),
+                (None, RedirectOp::HereString, "word"),
+            ]
+        );
+        let writes: Vec<_> = command
+            .redirections
+            .iter()
+            .map(Redirection::writes_file)
+            .collect();
+        assert_eq!(writes, [false, true, true, true, false, true, true, false]);
+        assert_eq!(words(command), ["cmd"]);
+    }
+
+    #[test]
+    fn test_heredoc_bodies_are_not_commands() {
+        let input = "cat <<EOF > notes\nrm -rf /\nEOF\nls\ncat <<-'END'\n\trm x\n\tEND\npwd";
+        assert_eq!(names(input), ["cat", "ls", "cat", "pwd"]);
+    }
+
+    #[test]
+    fn test_input_text() {
+        let input = "(psql <<A) <<-B; mysql <<< 'drop table x'\n1\nA\n\t2\n\tB\ncat <<C";
+        let list = parse(input);
+        let texts: Vec<_> = list
+            .items
+            .iter()
+            .flat_map(|item| &item.pipeline.commands)
+            .flat_map(|command| match command {
+                Command::Simple(simple) => simple.redirections.clone(),
+                Command::Subshell { body, redirections } => {
+                    let mut all = body.simple_commands()[0].redirections.clone();
+                    all.extend(redirections.iter().cloned());
+                    all
+                }
+                _ => Vec::new(),
+            })
+            .map(|redirection| redirection.input_text().map(str::to_string))
+            .collect();
+        // The last body never started, since its line did not end
+        assert_eq!(
+            texts,
+            [
+                Some("1\n".to_string()),
+                Some("2\n".to_string()),
+                Some("drop table x".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compound_commands() {
+        assert_eq!(names("(cd /tmp && rm -rf x)"), ["cd", "rm"]);
+        assert_eq!(names("{ echo a; rm b; } > log"), ["echo", "rm"]);
+        assert_eq!(
+            names("if test -f x; then rm x; elif true; then :; else echo no; fi"),
+            ["test", "rm", "true", ":", "echo"]
+        );
+        assert_eq!(
+            names("for f in *.log $(ls); do rm \"$f\"; done"),
+            ["ls", "for", "rm"]
+        );
+        assert_eq!(
+            names("while read l; do echo $l; done < file"),
+            ["read", "echo"]
+        );
+        assert_eq!(
+            names("case $x in a|b) rm a ;; (c) ls ;& *) pwd ;; esac; echo done"),
+            ["case", "rm", "ls", "pwd", "echo"]
+        );
+        assert_eq!(names("[[ $a > 1 && -f b ]] && rm b"), ["[[", "rm"]);
+        assert_eq!(names("(( i++ )) && ls"), ["ls"]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let list = parse(":(){ :|:& };:");
+        match &list.items[0].pipeline.commands[0] {
+            Command::Function { name, body } => {
+                assert_eq!(name, ":");
+                assert!(matches!(**body, Command::Group { .. }));
+            }
+            other => panic!("expected a function, got {other:?}"),
+        }
+        assert_eq!(names(":(){ :|:& };:"), [":", ":", ":"]);
+        assert_eq!(
+            names("function clean { rm -rf build; }; clean"),
+            ["rm", "clean"]
+        );
+    }
+
+    #[test]
+    fn test_assignments_and_comments() {
+        let list = parse("A=1 B+=2 C[0]=3 make install # rm -rf /");
+        let command = list.simple_commands()[0];
+        assert_eq!(command.assignments.len(), 3);
+        assert_eq!(words(command), ["make", "install"]);
+        assert_eq!(names("# just a comment"), Vec::<String>::new());
+        assert_eq!(names("echo a#b"), ["echo"]);
+        assert_eq!(parse("echo a#b").simple_commands()[0].words[1].text, "a#b");
+    }
+
+    #[test]
+    fn test_malformed_input_terminates() {
+        for input in [
+            ")", "))) ;; }", "$(", "`", "a | | b", "((", "case", "<", "&&", "{", "((((",
+        ] {
+            let _ = parse(input);
+        }
+        let deep = "$(".repeat(500);
+        let _ = parse(&deep);
+    }
+}
@@ -50,13 +50,62 @@ pub enum TerminalEvent {
     /// acknowledgements, DA responses, etc.). The UI must forward these bytes to
     /// the child process or interactive programs will hang waiting for the reply.
     PtyWrite(Vec<u8>),
+    /// Shell integration (OSC 133 A): the shell is about to draw its prompt
+    PromptStart,
+    /// Shell integration (OSC 133 B): the prompt ended, user input follows
+    CommandStart,
+    /// Shell integration (OSC 133 C): the command was submitted and its
+    /// output follows. `command` is the command line when the shell sends it.
+    OutputStart { command: Option<String> },
+    /// Shell integration (OSC 133 D): the command finished
+    CommandFinished { exit_code: Option<i32> },
+    /// Shell integration (OSC 6977 check): the user pressed Enter on
+    /// `command`, which waits in the shell's line editor until the terminal
+    /// writes [`accept_line`](crate::shell_integration::accept_line) for `id`
+    CommandCheck { id: String, command: String },
+    /// Shell integration (OSC 6977 keymap): whether the shell's line editor
+    /// uses vi key bindings, reported with every prompt
+    EditingMode { vi: bool },
+    /// Shell integration (OSC 7): the shell's working directory changed.
+    /// `host` is the machine the path belongs to, when the shell names one.
+    CwdChanged {
+        host: Option<String>,
+        path: std::path::PathBuf,
+    },
 }
 
 /// Clipboard actions from terminal
 #[derive(Debug, Clone)]
 pub enum ClipboardAction {
     Copy(String),
-    Paste,
+    /// A program asked to read the clipboard (OSC 52). If the user allows it,
+    /// write `reply.format(text)` back to the PTY.
+    Paste {
+        /// The primary selection rather than the clipboard
+        primary: bool,
+        reply: ClipboardReply,
+    },
+}
+
+/// Builds the OSC 52 response to a clipboard read request
+#[derive(Clone)]
+pub struct ClipboardReply(std::sync::Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl ClipboardReply {
+    pub fn new(format: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self(std::sync::Arc::new(format))
+    }
+
+    /// Bytes to write to the PTY to hand `text` to the program
+    pub fn format(&self, text: &str) -> Vec<u8> {
+        (self.0)(text).into_bytes()
+    }
+}
+
+impl std::fmt::Debug for ClipboardReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ClipboardReply")
+    }
 }
 
 /// Default foreground color (warm white)
@@ -131,6 +180,8 @@ struct TerminalState {
     cursor: (usize, usize),
     /// Terminal title
     title: String,
+    /// Last local working directory reported through OSC 7
+    cwd: Option<std::path::PathBuf>,
     /// Scrollback buffer
     scrollback: Vec<Vec<Cell>>,
     /// Maximum scrollback lines
@@ -159,6 +210,7 @@ impl TerminalState {
             size,
             cursor: (0, 0),
             title: String::new(),
+            cwd: None,
             scrollback: Vec::new(),
             max_scrollback: 10000,
             pending_events: Vec::new(),
@@ -370,10 +422,8 @@ impl Perform for TerminalState {
 
     fn execute(&mut self, byte: u8) {
         match byte {
-            0x08 => {
-                if self.cursor.1 > 0 {
-                    self.cursor.1 -= 1;
-                }
+            0x08 if self.cursor.1 > 0 => {
+                self.cursor.1 -= 1;
             }
             0x09 => {
                 let next_tab = (self.cursor.1 / 8 + 1) * 8;
@@ -406,6 +456,31 @@ impl Perform for TerminalState {
                             .push(TerminalEvent::TitleChanged(title.to_string()));
                     }
                 }
+                b"133" => {
+                    if let Some(event) =
+                        crate::shell_integration::parse_semantic_prompt(&params[1..])
+                    {
+                        self.pending_events.push(event);
+                    }
+                }
+                b"6977" => {
+                    if let Some(event) = crate::shell_integration::parse_command_check(&params[1..])
+                    {
+                        self.pending_events.push(event);
+                    }
+                }
+                b"7" => {
+                    // vte splits on ';', which may appear in the path
+                    let url = params[1..].join(&b';');
+                    if let Some(event) = crate::shell_integration::parse_cwd_report(&url) {
+                        if let TerminalEvent::CwdChanged { host, path } = &event {
+                            if crate::shell_integration::is_local_host(host.as_deref()) {
+                                self.cwd = Some(path.clone());
+                            }
+                        }
+                        self.pending_events.push(event);
+                    }
+                }
                 _ => {}
             }
         }
@@ -709,6 +784,12 @@ impl Terminal {
         &self.state.title
     }
 
+    /// Working directory last reported by the shell (OSC 7), if it is on
+    /// this machine
+    pub fn cwd(&self) -> Option<&std::path::Path> {
+        self.state.cwd.as_deref()
+    }
+
     /// Get grid for rendering
     pub fn grid(&self) -> &[Vec<Cell>] {
         &self.state.grid
@@ -828,6 +909,21 @@ mod tests {
         assert_eq!(term.state.grid[0][4].content, "o");
     }
 
+    #[test]
+    fn test_cwd_reports() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut term = Terminal::new(TerminalSize::default(), tx);
+        term.process(b"\x1b]7;file:///tmp/a;b\x07");
+        assert_eq!(term.cwd(), Some(std::path::Path::new("/tmp/a;b")));
+        assert!(rx
+            .try_iter()
+            .any(|event| matches!(event, TerminalEvent::CwdChanged { .. })));
+
+        // Directories on other machines are reported but not tracked
+        term.process(b"\x1b]7;file://corgiterm-remote-host.invalid/srv\x07");
+        assert_eq!(term.cwd(), Some(std::path::Path::new("/tmp/a;b")));
+    }
+
     #[test]
     fn test_health_monitoring() {
         let (tx, _rx) = crossbeam_channel::unbounded();
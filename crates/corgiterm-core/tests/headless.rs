@@ -0,0 +1,81 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+
+use corgiterm_core::headless::{self, EXIT_BLOCKED, EXIT_TIMED_OUT};
+use corgiterm_core::{HeadlessOptions, HeadlessOutput, TerminalSize};
+
+fn options(command: &str) -> HeadlessOptions {
+    let mut options = HeadlessOptions::new(command);
+    options.shell = Some("/bin/sh".to_string());
+    options.size = TerminalSize { rows: 10, cols: 40 };
+    options.timeout = Some(Duration::from_secs(10));
+    options
+}
+
+#[test]
+fn exit_code_and_screen_come_from_the_child() {
+    let mut options = options("printf 'CORGI_HEADLESS\\n'; exit 3");
+    options.output = HeadlessOutput::Screen;
+
+    let mut out = Vec::new();
+    let result = headless::run(&options, &mut out).expect("headless run should succeed");
+
+    assert_eq!(result.exit_code, 3);
+    assert!(!result.timed_out);
+    assert_eq!(result.screen, vec!["CORGI_HEADLESS".to_string()]);
+    assert_eq!(String::from_utf8_lossy(&out), "CORGI_HEADLESS\n");
+}
+
+#[test]
+fn stream_mode_passes_raw_bytes_through() {
+    let options = options("printf '\\033[31mred\\033[0m'");
+
+    let mut out = Vec::new();
+    let result = headless::run(&options, &mut out).expect("headless run should succeed");
+
+    assert_eq!(result.exit_code, 0);
+    assert!(
+        String::from_utf8_lossy(&out).contains("\x1b[31mred"),
+        "escape sequences should be forwarded verbatim: {:?}",
+        String::from_utf8_lossy(&out)
+    );
+    assert_eq!(result.screen, vec!["red".to_string()]);
+}
+
+#[test]
+fn screen_honors_requested_size() {
+    let mut options = options("printf '%s' 0123456789");
+    options.size = TerminalSize { rows: 4, cols: 6 };
+
+    let result = headless::run(&options, &mut Vec::new()).expect("headless run should succeed");
+
+    assert_eq!(
+        result.screen,
+        vec!["012345".to_string(), "6789".to_string()]
+    );
+}
+
+#[test]
+fn long_running_command_is_killed_at_timeout() {
+    let mut options = options("sleep 30");
+    options.timeout = Some(Duration::from_millis(200));
+
+    let result = headless::run(&options, &mut Vec::new()).expect("headless run should succeed");
+
+    assert!(result.timed_out);
+    assert_eq!(result.exit_code, EXIT_TIMED_OUT);
+}
+
+#[test]
+fn safe_mode_blocks_dangerous_commands_before_spawning() {
+    let mut options = options("dd if=/dev/zero of=/dev/null count=0");
+    options.safe_mode = true;
+
+    let mut out = Vec::new();
+    let result = headless::run(&options, &mut out).expect("headless run should succeed");
+
+    assert_eq!(result.exit_code, EXIT_BLOCKED);
+    assert!(result.blocked.is_some());
+    assert!(out.is_empty(), "nothing should run when blocked");
+}
@@ -0,0 +1,106 @@
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use corgiterm_core::ipc::{self, IpcError, IpcServer};
+
+fn socket_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "corgiterm-ipc-test-{}-{}.sock",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ))
+}
+
+/// Answer calls the way the UI does: on one thread, in arrival order
+fn serve(server: &IpcServer, count: usize) -> std::thread::JoinHandle<()> {
+    let calls = server.calls().clone();
+    std::thread::spawn(move || {
+        for call in calls.iter().take(count) {
+            let outcome = match call.request.method.as_str() {
+                "echo" => match call.request.param_str("text") {
+                    Some(text) => Ok(serde_json::json!({ "text": text })),
+                    None => Err(IpcError::invalid_params("Missing required parameter: text")),
+                },
+                method => Err(IpcError::method_not_found(method)),
+            };
+            call.respond(outcome);
+        }
+    })
+}
+
+#[test]
+fn request_reaches_handler_and_result_comes_back() {
+    let server = IpcServer::bind(socket_path()).expect("bind should succeed");
+    let handler = serve(&server, 1);
+
+    let result = ipc::send_request(
+        server.path(),
+        "echo",
+        Some(serde_json::json!({ "text": "woof" })),
+    )
+    .expect("request should succeed");
+
+    assert_eq!(result, serde_json::json!({ "text": "woof" }));
+    handler.join().unwrap();
+}
+
+#[test]
+fn handler_errors_are_reported_to_the_client() {
+    let server = IpcServer::bind(socket_path()).expect("bind should succeed");
+    let handler = serve(&server, 2);
+
+    let err = ipc::send_request(server.path(), "tab.explode", None).unwrap_err();
+    assert!(err.to_string().contains("-32601"), "{}", err);
+
+    let err = ipc::send_request(server.path(), "echo", None).unwrap_err();
+    assert!(err.to_string().contains("text"), "{}", err);
+    handler.join().unwrap();
+}
+
+#[test]
+fn socket_is_private_and_removed_on_drop() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = socket_path();
+    let server = IpcServer::bind(&path).expect("bind should succeed");
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    drop(server);
+    assert!(!path.exists());
+}
+
+#[test]
+fn stale_socket_is_replaced_but_live_one_is_not() {
+    let path = socket_path();
+
+    // A socket file nobody listens on, as left behind by a crash
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let server = IpcServer::bind(&path).expect("stale socket should be replaced");
+
+    assert!(IpcServer::bind(&path).is_err());
+    drop(server);
+}
+
+#[test]
+fn other_files_are_not_replaced() {
+    let path = socket_path();
+    std::fs::write(&path, "notes").unwrap();
+
+    let err = IpcServer::bind(&path)
+        .err()
+        .expect("a regular file is not a socket");
+    assert!(err.to_string().contains("not a socket"), "{}", err);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn connecting_to_missing_socket_fails() {
+    let err = ipc::send_request(&socket_path(), "ping", None).unwrap_err();
+    assert!(err.to_string().contains("Cannot connect"), "{}", err);
+}
@@ -0,0 +1,80 @@
+//! Offline evaluation of next-command prediction
+//!
+//! Replays a recorded history, predicting each command from the ones before
+//! it, and reports top-1/top-3 accuracy next to a most-frequent-command
+//! baseline. Run with `--nocapture` to see the report.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use corgiterm_core::{CommandEntry, CommandLearning, ReplayReport};
+
+const FIXTURE: &str = include_str!("fixtures/command_history.tsv");
+
+/// Fixture lines: time, directory, git branch (`-` for none), exit code, command
+fn recorded_history() -> Vec<CommandEntry> {
+    FIXTURE
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.splitn(5, '\t').collect();
+            let [time, directory, branch, exit_code, command] = fields[..] else {
+                panic!("malformed fixture line: {line:?}");
+            };
+            let mut entry = CommandEntry::new(command, PathBuf::from(directory));
+            entry.timestamp = DateTime::parse_from_rfc3339(time)
+                .expect("fixture time")
+                .with_timezone(&Utc);
+            entry.git_branch = (branch != "-").then(|| branch.to_string());
+            entry.complete(exit_code.parse().expect("fixture exit code"), 0);
+            entry
+        })
+        .collect()
+}
+
+/// Predict the commands run most often so far
+fn frequency_baseline(history: &[CommandEntry]) -> ReplayReport {
+    let mut report = ReplayReport::default();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for entry in history {
+        let mut ranked: Vec<_> = counts.iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        report.record(
+            ranked
+                .iter()
+                .take(3)
+                .position(|(command, _)| **command == entry.command),
+        );
+        *counts.entry(&entry.command).or_default() += 1;
+    }
+    report
+}
+
+#[test]
+fn replay_recorded_history() {
+    let history = recorded_history();
+    assert!(
+        history.len() > 500,
+        "fixture has {} commands",
+        history.len()
+    );
+
+    let baseline = frequency_baseline(&history);
+    let report = CommandLearning::new(100).replay(history);
+    println!("sequence model: {report}");
+    println!("most frequent:  {baseline}");
+
+    assert_eq!(report.commands, baseline.commands);
+    assert!(report.top1 <= report.top3);
+    assert!(
+        report.top1_accuracy() > baseline.top1_accuracy() * 2.0,
+        "{report} vs baseline {baseline}"
+    );
+    assert!(
+        report.top3_accuracy() > baseline.top3_accuracy() * 1.5,
+        "{report} vs baseline {baseline}"
+    );
+    assert!(report.top1_accuracy() >= 0.5, "{report}");
+    assert!(report.top3_accuracy() >= 0.7, "{report}");
+}
@@ -0,0 +1,282 @@
+#![cfg(unix)]
+
+use std::io::Read;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use corgiterm_core::shell_integration::{accept_line, Level};
+use corgiterm_core::{AlacrittyEngine, Pty, PtySize, TerminalEngine, TerminalEvent, TerminalSize};
+
+const SIZE: PtySize = PtySize {
+    rows: 24,
+    cols: 80,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+/// Forward everything the PTY prints to a channel
+fn read_output(pty: &Pty) -> mpsc::Receiver<Vec<u8>> {
+    let reader = pty.reader_clone();
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = reader
+                .lock()
+                .expect("PTY reader lock should not be poisoned")
+                .read(&mut buf);
+            match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Keep the user's startup files and data dir out of the test
+///
+/// The scripts are installed once per process, so the data dir is shared by
+/// all tests and outlives them.
+fn isolated_home(name: &str) -> std::path::PathBuf {
+    static DATA: std::sync::Once = std::sync::Once::new();
+    DATA.call_once(|| {
+        let data = std::env::temp_dir().join(format!("corgiterm-si-data-{}", std::process::id()));
+        std::env::set_var("XDG_DATA_HOME", data);
+    });
+    let home = std::env::temp_dir().join(format!("corgiterm-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    std::env::set_var("HOME", &home);
+    home
+}
+
+#[test]
+fn bash_reports_commands_exit_codes_and_cwd() {
+    if !std::path::Path::new("/bin/bash").exists() {
+        eprintln!("skipping: /bin/bash not available");
+        return;
+    }
+
+    let home = isolated_home("si-home");
+    let pty = Pty::spawn(Some("/bin/bash"), SIZE, Some(&home), Some("xterm-256color"))
+        .expect("bash should spawn inside a PTY");
+    let rx = read_output(&pty);
+
+    pty.write(b"echo corgi; (exit 3)\n")
+        .expect("test command should be written to PTY");
+
+    let (events_tx, events_rx) = crossbeam_channel::unbounded();
+    let mut engine = AlacrittyEngine::new(TerminalSize { rows: 24, cols: 80 }, events_tx, 100);
+    let mut command = None;
+    let mut exit_code = None;
+    let mut cwd = None;
+    let mut vi_keys = None;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while exit_code.is_none() && Instant::now() < deadline {
+        if let Ok(chunk) = rx.recv_timeout(Duration::from_millis(100)) {
+            engine.feed(&chunk);
+        }
+        for event in events_rx.try_iter() {
+            match event {
+                TerminalEvent::OutputStart { command: c } => command = c,
+                TerminalEvent::CommandFinished { exit_code: code } if command.is_some() => {
+                    exit_code = code
+                }
+                TerminalEvent::CwdChanged { path, .. } => cwd = Some(path),
+                TerminalEvent::EditingMode { vi } => vi_keys = Some(vi),
+                _ => {}
+            }
+        }
+    }
+
+    let _ = pty.kill();
+    let _ = std::fs::remove_dir_all(&home);
+    assert_eq!(command.as_deref(), Some("echo corgi; (exit 3)"));
+    assert_eq!(exit_code, Some(3));
+    assert_eq!(cwd.as_deref(), Some(home.as_path()));
+    assert_eq!(vi_keys, Some(false));
+}
+
+#[test]
+fn bash_holds_typed_commands_until_accepted() {
+    if !std::path::Path::new("/bin/bash").exists() {
+        eprintln!("skipping: /bin/bash not available");
+        return;
+    }
+
+    let home = isolated_home("si-check-home");
+    let pty = Pty::spawn_shell(
+        Some("/bin/bash"),
+        SIZE,
+        Some(&home),
+        Some("xterm-256color"),
+        Level::CheckCommands,
+    )
+    .expect("bash should spawn inside a PTY");
+    let rx = read_output(&pty);
+
+    let (events_tx, events_rx) = crossbeam_channel::unbounded();
+    let mut engine = AlacrittyEngine::new(TerminalSize { rows: 24, cols: 80 }, events_tx, 100);
+    let mut prompted = false;
+    let mut checked = None;
+    let mut ran = None;
+    let mut accepted = false;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while ran.is_none() && Instant::now() < deadline {
+        if let Ok(chunk) = rx.recv_timeout(Duration::from_millis(100)) {
+            engine.feed(&chunk);
+        }
+        for event in events_rx.try_iter() {
+            match event {
+                // Type only once readline owns the terminal, or the line
+                // discipline turns the carriage return into a newline
+                TerminalEvent::CommandStart if !prompted => {
+                    prompted = true;
+                    pty.write(b"echo 'checked; ok'\r")
+                        .expect("test command should be written to PTY");
+                }
+                TerminalEvent::CommandCheck { id, command } => checked = Some((id, command)),
+                TerminalEvent::OutputStart { command } => ran = Some((command, accepted)),
+                _ => {}
+            }
+        }
+        if let Some((id, _)) = checked.as_ref().filter(|_| !accepted) {
+            // Give bash a moment to prove it is waiting
+            std::thread::sleep(Duration::from_millis(300));
+            for chunk in rx.try_iter() {
+                engine.feed(&chunk);
+            }
+            for event in events_rx.try_iter() {
+                if let TerminalEvent::OutputStart { command } = event {
+                    ran = Some((command, accepted));
+                }
+            }
+            accepted = true;
+            pty.write(&accept_line(id))
+                .expect("accept key should be written");
+        }
+    }
+
+    let _ = pty.kill();
+    let _ = std::fs::remove_dir_all(&home);
+    let (_, checked) = checked.expect("the line should be checked");
+    assert_eq!(checked, "echo 'checked; ok'");
+    let (command, after_accept) = ran.expect("the command should run once accepted");
+    assert!(after_accept, "the command ran before it was accepted");
+    assert_eq!(command.as_deref(), Some("echo 'checked; ok'"));
+}
+
+#[test]
+fn bash_checks_edited_lines_again() {
+    if !std::path::Path::new("/bin/bash").exists() {
+        eprintln!("skipping: /bin/bash not available");
+        return;
+    }
+
+    let home = isolated_home("si-recheck-home");
+    let pty = Pty::spawn_shell(
+        Some("/bin/bash"),
+        SIZE,
+        Some(&home),
+        Some("xterm-256color"),
+        Level::CheckCommands,
+    )
+    .expect("bash should spawn inside a PTY");
+    let rx = read_output(&pty);
+
+    let (events_tx, events_rx) = crossbeam_channel::unbounded();
+    let mut engine = AlacrittyEngine::new(TerminalSize { rows: 24, cols: 80 }, events_tx, 100);
+    let mut checks = Vec::new();
+    let mut ran = None;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while ran.is_none() && Instant::now() < deadline {
+        if let Ok(chunk) = rx.recv_timeout(Duration::from_millis(100)) {
+            engine.feed(&chunk);
+        }
+        for event in events_rx.try_iter() {
+            match event {
+                TerminalEvent::CommandStart if checks.is_empty() => {
+                    pty.write(b"echo first\r")
+                        .expect("test command should be written to PTY");
+                }
+                TerminalEvent::CommandCheck { id, command } => {
+                    let mut keys = Vec::new();
+                    if checks.is_empty() {
+                        // Edit the line before the answer arrives; a stale
+                        // answer must not run the new text
+                        keys.extend_from_slice(b" second");
+                    }
+                    keys.extend_from_slice(&accept_line(&id));
+                    pty.write(&keys).expect("keys should be written");
+                    checks.push(command);
+                }
+                TerminalEvent::OutputStart { command } => ran = Some(command),
+                _ => {}
+            }
+        }
+    }
+
+    let _ = pty.kill();
+    let _ = std::fs::remove_dir_all(&home);
+    assert_eq!(checks, ["echo first", "echo first second"]);
+    assert_eq!(ran.flatten().as_deref(), Some("echo first second"));
+}
+
+#[test]
+fn bash_checks_lines_run_with_ctrl_j_and_ctrl_o() {
+    if !std::path::Path::new("/bin/bash").exists() {
+        eprintln!("skipping: /bin/bash not available");
+        return;
+    }
+
+    let home = isolated_home("si-keys-home");
+    let pty = Pty::spawn_shell(
+        Some("/bin/bash"),
+        SIZE,
+        Some(&home),
+        Some("xterm-256color"),
+        Level::CheckCommands,
+    )
+    .expect("bash should spawn inside a PTY");
+    let rx = read_output(&pty);
+
+    let (events_tx, events_rx) = crossbeam_channel::unbounded();
+    let mut engine = AlacrittyEngine::new(TerminalSize { rows: 24, cols: 80 }, events_tx, 100);
+    let mut lines = [&b"echo ctrl-j\n"[..], b"echo ctrl-o\x0f"].into_iter();
+    let mut checks = Vec::new();
+    let mut ran = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while ran.len() < 2 && Instant::now() < deadline {
+        if let Ok(chunk) = rx.recv_timeout(Duration::from_millis(100)) {
+            engine.feed(&chunk);
+        }
+        for event in events_rx.try_iter() {
+            match event {
+                TerminalEvent::CommandStart => {
+                    if let Some(line) = lines.next() {
+                        pty.write(line)
+                            .expect("test command should be written to PTY");
+                    }
+                }
+                TerminalEvent::CommandCheck { id, command } => {
+                    // Only checked lines may run
+                    assert_eq!(ran.len(), checks.len());
+                    checks.push(command);
+                    pty.write(&accept_line(&id))
+                        .expect("accept key should be written");
+                }
+                TerminalEvent::OutputStart { command } => ran.extend(command),
+                _ => {}
+            }
+        }
+    }
+
+    let _ = pty.kill();
+    let _ = std::fs::remove_dir_all(&home);
+    assert_eq!(checks, ["echo ctrl-j", "echo ctrl-o"]);
+    assert_eq!(ran, ["echo ctrl-j", "echo ctrl-o"]);
+}
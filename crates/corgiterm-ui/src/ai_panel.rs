@@ -764,6 +764,30 @@ impl AiPanel {
     }
 
     fn explain_command(command: &str, buffer: TextBuffer, is_processing: Rc<RefCell<bool>>) {
+        // Without a provider, explain from the built-in command reference
+        let ai = ai_manager();
+        let provider_available = ai
+            .as_ref()
+            .is_some_and(|am| am.read().default_provider().is_some());
+        if !provider_available {
+            let explanations = corgiterm_core::CommandReference::builtin().explain(command);
+            let text = if explanations.is_empty() {
+                "No AI provider is configured, and there is no command here to explain \
+                 offline."
+                    .to_string()
+            } else {
+                let breakdown: Vec<String> = explanations.iter().map(|e| e.to_string()).collect();
+                format!(
+                    "{}\n\nExplained offline from the built-in command reference. \
+                     Configure an AI provider for risks, alternatives and error messages.",
+                    breakdown.join("\n\n")
+                )
+            };
+            buffer.set_text(&text);
+            *is_processing.borrow_mut() = false;
+            return;
+        }
+
         if let Some(am) = ai_manager() {
             let system = "You are a shell command expert. Explain the given command in detail:\n\
                          1. What does this command do overall?\n\
@@ -36,6 +36,26 @@ static CONVERSATION_STORE: std::sync::OnceLock<
     Arc<RwLock<corgiterm_ai::conversation::ConversationStore>>,
 > = std::sync::OnceLock::new();
 
+/// Options CorgiTerm was launched with
+static RUN_OPTIONS: std::sync::OnceLock<RunOptions> = std::sync::OnceLock::new();
+
+/// Command-line options that apply to the whole session
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// `--safe-mode`: check typed commands even if `safe_mode.check_typed`
+    /// is off
+    pub safe_mode: bool,
+}
+
+/// Get the options CorgiTerm was launched with
+pub fn run_options() -> RunOptions {
+    RUN_OPTIONS.get().cloned().unwrap_or_default()
+}
+
+pub(crate) fn set_run_options(options: RunOptions) {
+    let _ = RUN_OPTIONS.set(options);
+}
+
 /// Get the global config manager
 pub fn config_manager() -> Option<Arc<RwLock<corgiterm_config::ConfigManager>>> {
     CONFIG_MANAGER.get().cloned()
@@ -71,12 +91,48 @@ pub fn conversation_store() -> Option<Arc<RwLock<corgiterm_ai::conversation::Con
     CONVERSATION_STORE.get().cloned()
 }
 
-/// Record a command execution for AI learning
-pub fn record_command(command: String, directory: String, exit_code: Option<i32>) {
+/// Id and name of the innermost sidebar project containing `dir`
+pub fn project_for(dir: &std::path::Path) -> Option<(String, String)> {
+    let session_manager = session_manager()?;
+    let session_manager = session_manager.read();
+    let project = session_manager.project_for(dir)?;
+    Some((project.id.to_string(), project.name.clone()))
+}
+
+/// Record a command execution in the shared history, tagged with its project
+/// and git branch when it ran on this machine
+pub fn record_command(
+    command: String,
+    directory: String,
+    session: Option<String>,
+    host: Option<String>,
+    exit_code: Option<i32>,
+    duration_ms: Option<u64>,
+) {
     if let Some(store) = history_store() {
+        // Timestamped when the command started
+        let started = std::time::SystemTime::now()
+            - std::time::Duration::from_millis(duration_ms.unwrap_or(0));
+        let timestamp = started
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let local_dir = host.is_none().then(|| std::path::Path::new(&directory));
+        let project_id = local_dir.and_then(project_for).map(|(id, _)| id);
+        let git_branch = local_dir.and_then(corgiterm_core::prediction::git_branch);
         let mut store = store.write();
-        store.record(command, directory, exit_code, None);
-        // Save periodically (every 10 commands)
+        store.record_entry(corgiterm_ai::history::HistoryEntry {
+            command,
+            directory,
+            timestamp,
+            exit_code,
+            duration_ms,
+            session,
+            host,
+            project_id,
+            git_branch,
+        });
+        // Without the history database, save periodically (every 10 commands)
         if store.len() % 10 == 0 {
             if let Err(e) = store.save() {
                 tracing::warn!("Failed to save command history: {}", e);
@@ -85,6 +141,30 @@ pub fn record_command(command: String, directory: String, exit_code: Option<i32>
     }
 }
 
+/// Get the shared history database, if it could be opened
+pub fn history_db() -> Option<Arc<corgiterm_core::HistoryDb>> {
+    history_store().and_then(|store| store.read().database().cloned())
+}
+
+/// Index terminal output for global search
+///
+/// Written on the history writer thread; every 64th write also trims the
+/// output kept to `history.output_max_mb`.
+pub fn record_output(chunks: Vec<corgiterm_core::OutputChunk>) {
+    static WRITES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let Some(writer) = history_store().and_then(|store| store.read().writer().cloned()) else {
+        return;
+    };
+    writer.insert_output(chunks);
+    if WRITES.fetch_add(1, std::sync::atomic::Ordering::Relaxed) % 64 == 0 {
+        let max_mb = config_manager()
+            .map(|cm| cm.read().config().history.output_max_mb)
+            .unwrap_or(256);
+        writer.prune_output(max_mb * 1024 * 1024);
+    }
+}
+
 /// Get learning context for AI prompts
 pub fn get_learning_context() -> corgiterm_ai::learning::LearningContext {
     if let Some(store) = history_store() {
@@ -115,6 +195,9 @@ fn load_css() {
 fn init_config() {
     match corgiterm_config::ConfigManager::new() {
         Ok(config_manager) => {
+            if let Err(e) = corgiterm_ai::redaction::configure(&config_manager.config().privacy) {
+                tracing::warn!("Secret redaction: {}", e);
+            }
             let config_arc = Arc::new(RwLock::new(config_manager));
             let _ = CONFIG_MANAGER.set(config_arc.clone());
             dialogs::init_config(config_arc);
@@ -434,10 +517,22 @@ pub fn build_ui(app: &Application) {
     let style_manager = libadwaita::StyleManager::default();
     style_manager.set_color_scheme(libadwaita::ColorScheme::PreferDark);
 
+    // Bind the remote-control socket before any shell is spawned so the
+    // shells inherit CORGITERM_SOCKET
+    #[cfg(unix)]
+    let ipc_server = crate::ipc::bind_configured();
+
     // Create and show main window IMMEDIATELY
     let window = MainWindow::new(app);
     window.present();
 
+    #[cfg(unix)]
+    if let Some(server) = ipc_server {
+        crate::ipc::attach(server, window.command_gateway());
+    }
+    #[cfg(unix)]
+    crate::mcp_backend::start(window.command_gateway());
+
     // LAZY INITIALIZATION - happens after window is visible
     // Use idle_add to run after GTK main loop processes the window
     gtk4::glib::idle_add_local_once(|| {
@@ -0,0 +1,547 @@
+//! Gateway for commands that were not typed by the user
+//!
+//! AI suggestions, snippets, SSH connections, plugins, MCP agents, history
+//! search, `corgiterm msg` and CorgiTerm's own tools all send their command
+//! lines through [`CommandGateway`], tagged with a [`CommandOrigin`]. The
+//! gateway runs Safe Mode on the line and, when the risk reaches the
+//! confirmation threshold configured for that origin (`safe_mode.confirm`),
+//! holds it in the Safe Mode preview until the user approves or cancels it.
+//! A command sent to a tab in broadcast mode goes to every broadcast pane
+//! and must also clear the `broadcast` threshold.
+//!
+//! Lines the user types reach it too when `safe_mode.check_typed` (or
+//! `--safe-mode`) is on: the shell holds the line when Enter is pressed and
+//! the terminal view passes it to [`CommandGateway::check_typed`], which lets
+//! the shell run it, now or once the user approves it.
+//!
+//! Each line is judged under the Safe Mode profile of the terminal it goes
+//! to (see [`profile_for`]), so a tab on a production host or in a
+//! production project holds back more. Lines are held or let through by
+//! their rules alone; the dry run of what a held line would do to files runs
+//! on another thread and fills in the preview when it is done.
+//!
+//! Only one command waits at a time; a new one replaces it and the old one
+//! is cancelled.
+
+use corgiterm_config::{CommandConfirmConfig, ConfirmAt};
+use corgiterm_core::{
+    CommandPreview, PasteInspection, ProfileSet, RiskLevel, SafeMode, DEFAULT_PROFILE,
+};
+use gtk4::glib;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+use crate::tab_bar::TerminalTabs;
+use crate::widgets::safe_mode_preview::SafeModePreviewWidget;
+
+/// Where a command came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOrigin {
+    Ai,
+    Snippet,
+    Ssh,
+    Plugin,
+    Mcp,
+    Broadcast,
+    History,
+    Tool,
+    Typed,
+    Ipc,
+}
+
+thread_local! {
+    /// The main window's gateway, for terminal views to check typed lines
+    static GATEWAY: RefCell<Weak<CommandGateway>> = RefCell::new(Weak::new());
+}
+
+/// The main window's command gateway, once the window exists
+pub fn gateway() -> Option<Rc<CommandGateway>> {
+    GATEWAY.with(|gateway| gateway.borrow().upgrade())
+}
+
+impl CommandOrigin {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ai => "AI",
+            Self::Snippet => "snippet",
+            Self::Ssh => "SSH manager",
+            Self::Plugin => "plugin",
+            Self::Mcp => "MCP agent",
+            Self::Broadcast => "broadcast",
+            Self::History => "history",
+            Self::Tool => "tool",
+            Self::Typed => "typed",
+            Self::Ipc => "IPC",
+        }
+    }
+
+    fn threshold(self, config: &CommandConfirmConfig) -> ConfirmAt {
+        match self {
+            Self::Ai => config.ai,
+            Self::Snippet => config.snippet,
+            Self::Ssh => config.ssh,
+            Self::Plugin => config.plugin,
+            Self::Mcp => config.mcp,
+            Self::Broadcast => config.broadcast,
+            Self::History => config.history,
+            Self::Tool => config.tool,
+            Self::Typed => config.typed,
+            Self::Ipc => config.ipc,
+        }
+    }
+}
+
+/// The Safe Mode profile for a session on another machine (`host`, as
+/// reported by its shell or given to `ssh`) or in `cwd`: the saved SSH
+/// host's or the innermost project's, falling back to the default profile
+pub fn profile_for(host: Option<&str>, cwd: Option<&Path>) -> String {
+    let config = crate::app::config_manager().map(|cm| cm.read().config());
+    let chosen = match host {
+        Some(host) => config
+            .as_ref()
+            .and_then(|config| config.ssh.host_for(host))
+            .and_then(|host| host.safe_mode_profile.clone()),
+        None => cwd.and_then(|cwd| {
+            let session_manager = crate::app::session_manager()?;
+            let session_manager = session_manager.read();
+            session_manager
+                .project_for(cwd)?
+                .settings
+                .safe_mode_profile
+                .clone()
+        }),
+    };
+    chosen
+        .or_else(|| config.map(|config| config.safe_mode.profile))
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+/// Names of the Safe Mode profiles that can be chosen, built-in ones first
+pub fn profile_names() -> Vec<String> {
+    let names = |profiles: &ProfileSet| -> Vec<String> {
+        profiles.names().into_iter().map(str::to_string).collect()
+    };
+    match gateway() {
+        Some(gateway) => names(gateway.safe_mode.borrow().profiles()),
+        None => {
+            let config = crate::app::config_manager()
+                .map(|cm| cm.read().config().safe_mode)
+                .unwrap_or_default();
+            names(&ProfileSet::with_user_dir(&config.profiles_dir()).0)
+        }
+    }
+}
+
+/// Whether a command at `risk` waits for confirmation under `threshold`
+pub fn needs_confirmation(threshold: ConfirmAt, risk: RiskLevel) -> bool {
+    match threshold {
+        ConfirmAt::Always => true,
+        ConfirmAt::Caution => risk != RiskLevel::Safe,
+        ConfirmAt::Danger => risk == RiskLevel::Danger,
+        ConfirmAt::Never => false,
+    }
+}
+
+/// The pane a command goes to: tab bar position and pane index, `None`
+/// meaning the current tab and its focused pane
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandTarget {
+    pub tab: Option<usize>,
+    pub pane: Option<usize>,
+}
+
+/// What happened to a submitted command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Written to the terminal
+    Sent,
+    /// Waiting in the Safe Mode preview
+    Held,
+    /// Turned down in the preview, or replaced by another command
+    Cancelled,
+    /// The target is not a terminal
+    NoTerminal,
+}
+
+/// Called once a held command is sent or cancelled
+pub type DecisionCallback = Box<dyn FnOnce(Delivery)>;
+
+/// What approving a held command does
+enum Approval {
+    /// Send the (possibly rewritten) command to a pane
+    Send(CommandTarget),
+    /// Let the shell run the line it is holding
+    Accept(Box<dyn FnOnce()>),
+}
+
+/// A command waiting in the preview
+struct Pending {
+    origin: CommandOrigin,
+    approval: Approval,
+    on_decided: Option<DecisionCallback>,
+}
+
+/// Routes non-keyboard commands through Safe Mode to the terminals
+pub struct CommandGateway {
+    tabs: Rc<TerminalTabs>,
+    safe_mode: Rc<RefCell<SafeMode>>,
+    preview: Rc<SafeModePreviewWidget>,
+    pending: RefCell<Option<Pending>>,
+    /// Counts held commands, so a dry run only fills in its own preview
+    held: Rc<Cell<u64>>,
+}
+
+impl CommandGateway {
+    /// Create the gateway; it takes over the preview's execute and cancel
+    /// callbacks
+    pub fn new(
+        tabs: Rc<TerminalTabs>,
+        safe_mode: Rc<RefCell<SafeMode>>,
+        preview: Rc<SafeModePreviewWidget>,
+    ) -> Rc<Self> {
+        let gateway = Rc::new(Self {
+            tabs,
+            safe_mode,
+            preview: preview.clone(),
+            pending: RefCell::new(None),
+            held: Rc::new(Cell::new(0)),
+        });
+
+        let weak: Weak<Self> = Rc::downgrade(&gateway);
+        preview.set_on_execute(move |command| {
+            if let Some(gateway) = weak.upgrade() {
+                gateway.approve(&command);
+            }
+        });
+        let weak = Rc::downgrade(&gateway);
+        preview.set_on_cancel(move || {
+            if let Some(gateway) = weak.upgrade() {
+                gateway.cancel();
+            }
+        });
+        GATEWAY.with(|current| *current.borrow_mut() = Rc::downgrade(&gateway));
+        gateway
+    }
+
+    pub fn tabs(&self) -> &Rc<TerminalTabs> {
+        &self.tabs
+    }
+
+    /// Submit a command for the current tab; returns false when there is
+    /// no terminal to run it in
+    pub fn submit(&self, origin: CommandOrigin, command: &str) -> bool {
+        self.submit_to(origin, command, CommandTarget::default(), None) != Delivery::NoTerminal
+    }
+
+    /// Submit a command for `target`; when it is held, `on_decided` is
+    /// called once the user sends or cancels it
+    pub fn submit_to(
+        &self,
+        origin: CommandOrigin,
+        command: &str,
+        target: CommandTarget,
+        on_decided: Option<DecisionCallback>,
+    ) -> Delivery {
+        let Some((session, broadcasting)) = self.tabs.with_split_pane_at(target.tab, |sp| {
+            let session = sp.with_terminal(target.pane, |tv| tv.safe_mode_session());
+            (session, target.pane.is_none() && sp.is_broadcast_enabled())
+        }) else {
+            return Delivery::NoTerminal;
+        };
+        let (host, cwd, profile) = session.map_or((None, None, None), |(host, cwd, profile)| {
+            (host, cwd, Some(profile))
+        });
+
+        let screened = self.screen(
+            origin,
+            command,
+            host.as_deref(),
+            cwd,
+            profile.as_deref(),
+            broadcasting,
+        );
+        match screened {
+            Some((preview, dry_run_in)) => {
+                self.hold(
+                    &preview,
+                    dry_run_in,
+                    Pending {
+                        origin,
+                        approval: Approval::Send(target),
+                        on_decided,
+                    },
+                );
+                Delivery::Held
+            }
+            None => self.deliver(origin, command, target),
+        }
+    }
+
+    /// Check a line the user typed under the terminal's `profile`; `accept`
+    /// lets the shell run it, right away or once the user approves it in
+    /// the preview
+    pub fn check_typed(
+        &self,
+        command: &str,
+        host: Option<&str>,
+        cwd: Option<PathBuf>,
+        profile: &str,
+        accept: Box<dyn FnOnce()>,
+    ) -> Delivery {
+        let screened = self.screen(
+            CommandOrigin::Typed,
+            command,
+            host,
+            cwd,
+            Some(profile),
+            false,
+        );
+        match screened {
+            Some((preview, dry_run_in)) => {
+                self.hold(
+                    &preview,
+                    dry_run_in,
+                    Pending {
+                        origin: CommandOrigin::Typed,
+                        approval: Approval::Accept(accept),
+                        on_decided: None,
+                    },
+                );
+                Delivery::Held
+            }
+            None => {
+                accept();
+                Delivery::Sent
+            }
+        }
+    }
+
+    /// Judge each line of a paste by its rules under the terminal's
+    /// `profile`; project rules apply unless it goes to another `host`
+    pub fn analyze_paste(
+        &self,
+        inspection: &mut PasteInspection,
+        host: Option<&str>,
+        cwd: Option<PathBuf>,
+        profile: &str,
+    ) {
+        let safe_mode = self.safe_mode.borrow();
+        if !safe_mode.enabled {
+            return;
+        }
+        let cwd = host.is_none().then(|| local_cwd(cwd));
+        inspection.analyze(&safe_mode, cwd.as_deref(), Some(profile));
+    }
+
+    /// Dry-run the risky lines of an analyzed paste on another thread,
+    /// sending each line's index and full preview as it is done; `None` when
+    /// there is nothing to run or the paste goes to another `host`
+    pub fn paste_impact(
+        &self,
+        inspection: &PasteInspection,
+        host: Option<&str>,
+        cwd: Option<PathBuf>,
+        profile: &str,
+    ) -> Option<crossbeam_channel::Receiver<(usize, CommandPreview)>> {
+        let safe_mode = self.safe_mode.borrow();
+        if !safe_mode.enabled || host.is_some() {
+            return None;
+        }
+        let lines = inspection.risky_lines();
+        if lines.is_empty() {
+            return None;
+        }
+        let safe_mode = safe_mode.clone();
+        let cwd = local_cwd(cwd);
+        let profile = profile.to_string();
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        std::thread::spawn(move || {
+            for (index, command) in lines {
+                let preview = safe_mode.analyze_with_profile(&command, &cwd, Some(&profile));
+                if sender.send((index, preview)).is_err() {
+                    break;
+                }
+            }
+        });
+        Some(receiver)
+    }
+
+    /// Judge a command by its rules, returning its preview when it must be
+    /// confirmed, with the directory to dry-run it in
+    ///
+    /// This runs on the UI thread, so the dry run is left to [`Self::hold`].
+    /// A command for another `host` is not dry-run at all: local files say
+    /// nothing about what it would delete there, and the trash rewrite
+    /// would name a program that only exists here.
+    fn screen(
+        &self,
+        origin: CommandOrigin,
+        command: &str,
+        host: Option<&str>,
+        cwd: Option<PathBuf>,
+        profile: Option<&str>,
+        broadcasting: bool,
+    ) -> Option<(CommandPreview, Option<PathBuf>)> {
+        let config = crate::app::config_manager()
+            .map(|cm| cm.read().config().safe_mode)
+            .unwrap_or_default();
+        // A typed line runs as typed, so it is never rewritten for the trash
+        let trash_program =
+            (config.trash_deletions && origin != CommandOrigin::Typed && host.is_none())
+                .then(std::env::current_exe)
+                .and_then(Result::ok)
+                .map(|exe| exe.to_string_lossy().into_owned());
+        self.safe_mode.borrow_mut().set_trash_program(trash_program);
+
+        let safe_mode = self.safe_mode.borrow();
+        if !safe_mode.enabled {
+            return None;
+        }
+        let cwd = host.is_none().then(|| local_cwd(cwd));
+        let preview = match cwd {
+            Some(ref cwd) => safe_mode.judge_with_profile(command, cwd, profile),
+            None => safe_mode.classify_with_profile(command, None, profile),
+        };
+        let hold = needs_confirmation(origin.threshold(&config.confirm), preview.risk)
+            || (broadcasting
+                && needs_confirmation(
+                    CommandOrigin::Broadcast.threshold(&config.confirm),
+                    preview.risk,
+                ));
+        hold.then_some((preview, cwd))
+    }
+
+    /// Show `preview` and wait for the user, cancelling any command that was
+    /// already waiting; with `dry_run_in`, the preview gains what the command
+    /// would do to files there once another thread has worked it out
+    fn hold(&self, preview: &CommandPreview, dry_run_in: Option<PathBuf>, pending: Pending) {
+        tracing::info!(
+            "Safe Mode: holding {} command ({}): {}",
+            pending.origin.label(),
+            preview.risk.label(),
+            preview.command
+        );
+        let replaced = self.pending.borrow_mut().replace(pending);
+        if let Some(on_decided) = replaced.and_then(|pending| pending.on_decided) {
+            on_decided(Delivery::Cancelled);
+        }
+        self.preview.show_preview(preview);
+
+        let held = self.held.get() + 1;
+        self.held.set(held);
+        let Some(cwd) = dry_run_in else {
+            return;
+        };
+        let safe_mode = self.safe_mode.borrow().clone();
+        let command = preview.command.clone();
+        let profile = preview.profile.clone();
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = sender.send(safe_mode.analyze_with_profile(&command, &cwd, profile.as_deref()));
+        });
+        let widget = self.preview.clone();
+        let current = self.held.clone();
+        glib::timeout_add_local(std::time::Duration::from_millis(100), move || {
+            match receiver.try_recv() {
+                Err(crossbeam_channel::TryRecvError::Empty) => glib::ControlFlow::Continue,
+                Ok(preview) => {
+                    // The command may have been decided or replaced meanwhile
+                    if current.get() == held && widget.is_visible() {
+                        widget.show_preview(&preview);
+                    }
+                    glib::ControlFlow::Break
+                }
+                Err(_) => glib::ControlFlow::Break,
+            }
+        });
+    }
+
+    /// Send the previewed command, which may have been rewritten (for
+    /// example to move deleted files to the trash)
+    fn approve(&self, command: &str) {
+        let Some(pending) = self.pending.borrow_mut().take() else {
+            return;
+        };
+        let delivery = match pending.approval {
+            Approval::Send(target) => self.deliver(pending.origin, command, target),
+            Approval::Accept(accept) => {
+                accept();
+                Delivery::Sent
+            }
+        };
+        if let Some(on_decided) = pending.on_decided {
+            on_decided(delivery);
+        }
+    }
+
+    fn cancel(&self) {
+        let Some(pending) = self.pending.borrow_mut().take() else {
+            return;
+        };
+        tracing::info!("Safe Mode: {} command cancelled", pending.origin.label());
+        if let Some(on_decided) = pending.on_decided {
+            on_decided(Delivery::Cancelled);
+        }
+    }
+
+    fn deliver(&self, origin: CommandOrigin, command: &str, target: CommandTarget) -> Delivery {
+        let sent = self
+            .tabs
+            .with_split_pane_at(target.tab, |sp| {
+                if target.pane.is_none() && sp.is_broadcast_enabled() {
+                    sp.broadcast_command(command) > 0
+                } else {
+                    sp.with_terminal(target.pane, |tv| tv.send_command(command))
+                        .is_some()
+                }
+            })
+            .unwrap_or(false);
+        if sent {
+            tracing::info!("Sent {} command: {}", origin.label(), command);
+            Delivery::Sent
+        } else {
+            tracing::warn!("No terminal for {} command: {}", origin.label(), command);
+            Delivery::NoTerminal
+        }
+    }
+}
+
+/// A session's working directory, or the home directory when it is unknown
+fn local_cwd(cwd: Option<PathBuf>) -> PathBuf {
+    cwd.or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_confirmation() {
+        use RiskLevel::*;
+        let risks = [Safe, Unknown, Caution, Danger];
+        let held = |threshold| {
+            risks
+                .iter()
+                .map(|&risk| needs_confirmation(threshold, risk))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(held(ConfirmAt::Always), [true, true, true, true]);
+        assert_eq!(held(ConfirmAt::Caution), [false, true, true, true]);
+        assert_eq!(held(ConfirmAt::Danger), [false, false, false, true]);
+        assert_eq!(held(ConfirmAt::Never), [false, false, false, false]);
+    }
+
+    #[test]
+    fn test_origin_thresholds() {
+        let config = CommandConfirmConfig {
+            snippet: ConfirmAt::Always,
+            ..CommandConfirmConfig::default()
+        };
+        assert_eq!(CommandOrigin::Snippet.threshold(&config), ConfirmAt::Always);
+        assert_eq!(CommandOrigin::Ai.threshold(&config), ConfirmAt::Caution);
+        assert_eq!(CommandOrigin::Tool.threshold(&config), ConfirmAt::Danger);
+        assert_eq!(CommandOrigin::Ipc.threshold(&config), ConfirmAt::Caution);
+    }
+}
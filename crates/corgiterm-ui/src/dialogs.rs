@@ -937,7 +937,7 @@ pub fn show_preferences<W: IsA<Window> + IsA<gtk4::Widget>>(
     // Hyperlinks
     let hyperlinks_row = libadwaita::SwitchRow::builder()
         .title("Clickable Hyperlinks")
-        .subtitle("Detect and highlight URLs for clicking")
+        .subtitle("Follow links printed by programs (OSC 8) with Ctrl+click")
         .active(hyperlinks)
         .build();
     behavior_group.add(&hyperlinks_row);
@@ -977,6 +977,78 @@ pub fn show_preferences<W: IsA<Window> + IsA<gtk4::Widget>>(
         }
     });
 
+    // Clipboard reads (OSC 52)
+    let clipboard_read_idx = if let Some(config_manager) = get_config() {
+        match config_manager.read().config().terminal.clipboard_read {
+            corgiterm_config::ClipboardReadPolicy::Ask => 0,
+            corgiterm_config::ClipboardReadPolicy::Allow => 1,
+            corgiterm_config::ClipboardReadPolicy::Deny => 2,
+        }
+    } else {
+        0 // Ask by default
+    };
+    let clipboard_read_row = libadwaita::ComboRow::builder()
+        .title("Clipboard Access")
+        .subtitle("When programs ask to read the clipboard (OSC 52)")
+        .build();
+    let clipboard_read_options = ["Ask", "Always Allow", "Always Deny"];
+    clipboard_read_row.set_model(Some(&gtk4::StringList::new(&clipboard_read_options)));
+    clipboard_read_row.set_selected(clipboard_read_idx);
+    behavior_group.add(&clipboard_read_row);
+
+    clipboard_read_row.connect_selected_notify(move |row| {
+        let selected = row.selected() as usize;
+        if let Some(config_manager) = get_config() {
+            config_manager.read().update(|config| {
+                config.terminal.clipboard_read = match selected {
+                    1 => corgiterm_config::ClipboardReadPolicy::Allow,
+                    2 => corgiterm_config::ClipboardReadPolicy::Deny,
+                    _ => corgiterm_config::ClipboardReadPolicy::Ask,
+                };
+            });
+            let _ = config_manager.read().save();
+        }
+    });
+
+    // Paste review
+    let paste_review_idx = if let Some(config_manager) = get_config() {
+        match config_manager
+            .read()
+            .config()
+            .terminal
+            .paste_protection
+            .review
+        {
+            corgiterm_config::PasteReview::Suspicious => 0,
+            corgiterm_config::PasteReview::Always => 1,
+            corgiterm_config::PasteReview::Never => 2,
+        }
+    } else {
+        0 // Suspicious by default
+    };
+    let paste_review_row = libadwaita::ComboRow::builder()
+        .title("Review Pastes")
+        .subtitle("Show pasted lines and their risk before sending them")
+        .build();
+    let paste_review_options = ["When Suspicious", "Always", "Never"];
+    paste_review_row.set_model(Some(&gtk4::StringList::new(&paste_review_options)));
+    paste_review_row.set_selected(paste_review_idx);
+    behavior_group.add(&paste_review_row);
+
+    paste_review_row.connect_selected_notify(move |row| {
+        let selected = row.selected() as usize;
+        if let Some(config_manager) = get_config() {
+            config_manager.read().update(|config| {
+                config.terminal.paste_protection.review = match selected {
+                    1 => corgiterm_config::PasteReview::Always,
+                    2 => corgiterm_config::PasteReview::Never,
+                    _ => corgiterm_config::PasteReview::Suspicious,
+                };
+            });
+            let _ = config_manager.read().save();
+        }
+    });
+
     // Bell style
     let bell_style_idx = if let Some(config_manager) = get_config() {
         match config_manager.read().config().terminal.bell_style {
@@ -1693,6 +1765,73 @@ pub fn show_preferences<W: IsA<Window> + IsA<gtk4::Widget>>(
         }
     });
 
+    let trash_deletions = get_config()
+        .map(|cm| cm.read().config().safe_mode.trash_deletions)
+        .unwrap_or(false);
+    let trash_row = libadwaita::SwitchRow::builder()
+        .title("Move Deleted Files to Trash")
+        .subtitle("Approved rm commands move files to a trash you can undo from")
+        .active(trash_deletions)
+        .build();
+    safe_group.add(&trash_row);
+
+    trash_row.connect_active_notify(move |row| {
+        let active = row.is_active();
+        if let Some(config_manager) = get_config() {
+            config_manager.read().update(|config| {
+                config.safe_mode.trash_deletions = active;
+            });
+            let _ = config_manager.read().save();
+        }
+    });
+
+    let check_typed = get_config()
+        .map(|cm| cm.read().config().safe_mode.check_typed)
+        .unwrap_or(false);
+    let check_typed_row = libadwaita::SwitchRow::builder()
+        .title("Check Typed Commands")
+        .subtitle("Preview dangerous commands typed at the prompt before they run (new tabs)")
+        .active(check_typed)
+        .build();
+    safe_group.add(&check_typed_row);
+
+    check_typed_row.connect_active_notify(move |row| {
+        let active = row.is_active();
+        if let Some(config_manager) = get_config() {
+            config_manager.read().update(|config| {
+                config.safe_mode.check_typed = active;
+            });
+            let _ = config_manager.read().save();
+        }
+    });
+
+    let profiles = crate::command_gateway::profile_names();
+    let current_profile = get_config()
+        .map(|cm| cm.read().config().safe_mode.profile)
+        .unwrap_or_default();
+    let profile_row = libadwaita::ComboRow::builder()
+        .title("Default Profile")
+        .subtitle("For tabs whose project or SSH host does not choose one")
+        .build();
+    let profile_strs: Vec<&str> = profiles.iter().map(String::as_str).collect();
+    profile_row.set_model(Some(&gtk4::StringList::new(&profile_strs)));
+    if let Some(pos) = profiles.iter().position(|p| *p == current_profile) {
+        profile_row.set_selected(pos as u32);
+    }
+    safe_group.add(&profile_row);
+
+    profile_row.connect_selected_notify(move |row| {
+        let Some(profile) = profiles.get(row.selected() as usize) else {
+            return;
+        };
+        if let Some(config_manager) = get_config() {
+            config_manager.read().update(|config| {
+                config.safe_mode.profile = profile.clone();
+            });
+            let _ = config_manager.read().save();
+        }
+    });
+
     safe_page.add(&safe_group);
     dialog.add(&safe_page);
 
@@ -2530,6 +2669,50 @@ where
     dialog.show();
 }
 
+/// Restore the files of the last deletion Safe Mode moved to the trash and
+/// report what came back
+pub fn show_undo_deletion_dialog<W: IsA<Window> + IsA<gtk4::Widget>>(parent: &W) {
+    let result = corgiterm_core::Trash::default_dir()
+        .map(|dir| corgiterm_core::Trash::open(dir).undo_last());
+    let (heading, body) = match result {
+        Some(Ok(Some(restored))) => {
+            let mut body = restored.command.clone();
+            for path in &restored.restored {
+                body.push_str(&format!("\n• Restored {}", path.display()));
+            }
+            for path in &restored.conflicts {
+                body.push_str(&format!(
+                    "\n• Kept in trash: {} already exists",
+                    path.display()
+                ));
+            }
+            let heading = if restored.restored.is_empty() {
+                "Nothing Restored"
+            } else {
+                "Deletion Undone"
+            };
+            (heading.to_string(), body)
+        }
+        Some(Ok(None)) | None => (
+            "Nothing to Undo".to_string(),
+            "No deletions are in the CorgiTerm trash.".to_string(),
+        ),
+        Some(Err(e)) => {
+            tracing::error!("Failed to undo deletion: {}", e);
+            ("Undo Failed".to_string(), e.to_string())
+        }
+    };
+
+    let dialog = libadwaita::AlertDialog::builder()
+        .heading(heading)
+        .body(body)
+        .build();
+    dialog.add_response("close", "Close");
+    dialog.set_default_response(Some("close"));
+    dialog.set_close_response("close");
+    dialog.present(Some(parent));
+}
+
 /// Show Safe Mode preview dialog for a command
 /// Returns true if user confirms execution, false if cancelled
 pub fn show_safe_mode_preview<F>(
@@ -2603,6 +2786,17 @@ pub fn show_safe_mode_preview<F>(
         }
     }
 
+    // Flag-by-flag breakdown from the built-in command reference
+    if let Some(breakdown) = crate::widgets::safe_mode_preview::breakdown_text(preview) {
+        let expander = gtk4::Expander::new(Some("Flag by flag"));
+        let text = gtk4::Label::new(Some(&breakdown));
+        text.set_xalign(0.0);
+        text.set_selectable(true);
+        text.add_css_class("monospace");
+        expander.set_child(Some(&text));
+        main_box.append(&expander);
+    }
+
     // Affected files
     if let Some(count) = preview.affected_count {
         let size_str = preview
@@ -2620,12 +2814,29 @@ pub fn show_safe_mode_preview<F>(
             })
             .unwrap_or_default();
 
+        let at_least = if preview.impact_truncated {
+            "at least "
+        } else {
+            ""
+        };
         let affected = gtk4::Label::new(Some(&format!(
-            "• Will affect {} file(s){}",
-            count, size_str
+            "• Will affect {}{} file(s){}",
+            at_least, count, size_str
         )));
         affected.set_xalign(0.0);
         main_box.append(&affected);
+
+        for impact in &preview.impact {
+            let path = gtk4::Label::new(Some(&format!(
+                "    {} ({})",
+                impact.path.display(),
+                impact.effect.label()
+            )));
+            path.set_xalign(0.0);
+            path.add_css_class("monospace");
+            path.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
+            main_box.append(&path);
+        }
     }
 
     // Network access warning
@@ -1,15 +1,96 @@
 //! Searchable command history dialog
 //!
-//! Provides Ctrl+R style fuzzy search through command history
+//! Provides Ctrl+R style fuzzy search through command history, across all
+//! history or just the current session, project or host
 
+use corgiterm_core::HistoryScope;
 use gtk4::glib;
 use gtk4::prelude::*;
-use gtk4::{Box, Entry, Label, ListBox, ListBoxRow, Orientation, ScrolledWindow, Window};
+use gtk4::{Box, DropDown, Entry, Label, ListBox, ListBoxRow, Orientation, ScrolledWindow, Window};
 use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::app::history_store;
 
+/// The terminal history search was opened from, for its scope filters
+#[derive(Debug, Clone, Default)]
+pub struct HistoryContext {
+    /// Session id of the terminal, made once per terminal view
+    pub session: Option<String>,
+    /// Id and name of the project the terminal is in
+    pub project: Option<(String, String)>,
+    /// Remote host, `None` on this machine
+    pub host: Option<String>,
+}
+
+impl HistoryContext {
+    /// Scopes to offer, with their labels; global first
+    pub fn scopes(&self) -> Vec<(String, HistoryScope)> {
+        let mut scopes = vec![("All history".to_string(), HistoryScope::Global)];
+        if let Some(ref session) = self.session {
+            scopes.push((
+                "This session".to_string(),
+                HistoryScope::Session(session.clone()),
+            ));
+        }
+        if let Some((ref id, ref name)) = self.project {
+            scopes.push((
+                format!("Project {}", name),
+                HistoryScope::Project(id.clone()),
+            ));
+        }
+        let host = match self.host {
+            Some(ref host) => format!("Host {}", host),
+            None => "This machine".to_string(),
+        };
+        scopes.push((host, HistoryScope::Host(self.host.clone())));
+        scopes
+    }
+}
+
+/// Steps through recalled commands, newest first, for putting them on the
+/// prompt one at a time
+#[derive(Debug, Default)]
+pub struct HistoryRecall {
+    commands: Vec<String>,
+    /// Index of the command on the prompt, `None` before the first
+    position: Option<usize>,
+}
+
+impl HistoryRecall {
+    /// Recall from `commands`, newest first
+    ///
+    /// Recalled commands are typed into the shell's line editor, so ones with
+    /// control characters are left out: a line break would run a half-recalled
+    /// line, and other controls are editing keys. Invisible characters are
+    /// stripped so the line shows everything it contains.
+    pub fn new(commands: Vec<String>) -> Self {
+        Self {
+            commands: commands
+                .into_iter()
+                .filter(|c| !c.chars().any(char::is_control))
+                .map(|c| corgiterm_core::paste::strip_hidden(&c))
+                .collect(),
+            position: None,
+        }
+    }
+
+    /// The next older command, or `None` when there is none
+    pub fn older(&mut self) -> Option<&str> {
+        let next = self.position.map_or(0, |p| p + 1);
+        let command = self.commands.get(next)?;
+        self.position = Some(next);
+        Some(command)
+    }
+
+    /// The next newer command, or `None` once past the newest
+    pub fn newer(&mut self) -> Option<&str> {
+        let next = self.position?.checked_sub(1);
+        self.position = next;
+        next.map(|p| self.commands[p].as_str())
+    }
+}
+
 /// History search popup/dialog
 pub struct HistorySearch {
     container: Box,
@@ -17,10 +98,16 @@ pub struct HistorySearch {
     results_list: ListBox,
     selected_command: Rc<RefCell<Option<String>>>,
     on_select: Rc<RefCell<Option<std::boxed::Box<dyn Fn(&str)>>>>,
+    scope: Rc<RefCell<HistoryScope>>,
 }
 
 impl HistorySearch {
     pub fn new() -> Self {
+        Self::with_context(&HistoryContext::default())
+    }
+
+    /// History search offering scopes for the terminal described by `context`
+    pub fn with_context(context: &HistoryContext) -> Self {
         let container = Box::new(Orientation::Vertical, 8);
         container.set_margin_top(12);
         container.set_margin_bottom(12);
@@ -29,17 +116,33 @@ impl HistorySearch {
         container.add_css_class("history-search");
 
         // Header
+        let header_box = Box::new(Orientation::Horizontal, 8);
+        header_box.set_margin_bottom(8);
         let header = Label::new(Some("Search Command History"));
         header.add_css_class("title-4");
-        header.set_margin_bottom(8);
-        container.append(&header);
-
-        // Search entry
+        header.set_hexpand(true);
+        header.set_xalign(0.0);
+        header_box.append(&header);
+        let import_button = gtk4::Button::with_label("Import Shell History");
+        import_button.set_tooltip_text(Some(
+            "Import bash, zsh, fish and atuin history from their usual locations",
+        ));
+        header_box.append(&import_button);
+        container.append(&header_box);
+
+        // Search entry and scope
+        let search_box = Box::new(Orientation::Horizontal, 8);
         let entry = Entry::new();
         entry.set_placeholder_text(Some("Type to search..."));
         entry.add_css_class("monospace");
         entry.set_hexpand(true);
-        container.append(&entry);
+        search_box.append(&entry);
+        let scopes = context.scopes();
+        let labels: Vec<&str> = scopes.iter().map(|(label, _)| label.as_str()).collect();
+        let scope_dropdown = DropDown::from_strings(&labels);
+        scope_dropdown.set_tooltip_text(Some("Which commands to search"));
+        search_box.append(&scope_dropdown);
+        container.append(&search_box);
 
         // Results list
         let results_list = ListBox::new();
@@ -53,6 +156,14 @@ impl HistorySearch {
         scrolled.set_max_content_height(400);
         container.append(&scrolled);
 
+        // Import status
+        let import_status = Label::new(None);
+        import_status.add_css_class("caption");
+        import_status.set_xalign(0.0);
+        import_status.set_wrap(true);
+        import_status.set_visible(false);
+        container.append(&import_status);
+
         // Help text
         let help = Label::new(Some("↑/↓ to navigate • Enter to select • Esc to cancel"));
         help.add_css_class("dim-label");
@@ -69,10 +180,13 @@ impl HistorySearch {
             results_list,
             selected_command,
             on_select,
+            scope: Rc::new(RefCell::new(HistoryScope::Global)),
         };
 
         // Wire up search
         search.setup_search();
+        search.setup_scope(&scope_dropdown, scopes);
+        search.setup_import(&import_button, &import_status);
 
         // Initial population with recent commands
         search.update_results("");
@@ -89,9 +203,10 @@ impl HistorySearch {
         self.entry.connect_changed({
             let results_list = results_list.clone();
             let selected_cmd = selected_cmd.clone();
+            let scope = self.scope.clone();
             move |entry| {
                 let query = entry.text().to_string();
-                Self::update_results_static(&results_list, &query, &selected_cmd);
+                Self::update_results_static(&results_list, &query, &scope.borrow(), &selected_cmd);
             }
         });
 
@@ -146,13 +261,111 @@ impl HistorySearch {
         });
     }
 
+    fn setup_scope(&self, dropdown: &DropDown, scopes: Vec<(String, HistoryScope)>) {
+        let results_list = self.results_list.clone();
+        let selected_cmd = self.selected_command.clone();
+        let entry = self.entry.clone();
+        let scope = self.scope.clone();
+        dropdown.connect_selected_notify(move |dropdown| {
+            let Some((_, selected)) = scopes.get(dropdown.selected() as usize) else {
+                return;
+            };
+            *scope.borrow_mut() = selected.clone();
+            Self::update_results_static(
+                &results_list,
+                &entry.text(),
+                &scope.borrow(),
+                &selected_cmd,
+            );
+        });
+    }
+
+    fn setup_import(&self, button: &gtk4::Button, status: &Label) {
+        let results_list = self.results_list.clone();
+        let selected_cmd = self.selected_command.clone();
+        let entry = self.entry.clone();
+        let scope = self.scope.clone();
+        let status = status.clone();
+        button.connect_clicked(move |button| {
+            let Some(store) = history_store() else {
+                return;
+            };
+            let Some(db) = store.read().database().cloned() else {
+                status.set_text("Command history is not using the history database");
+                status.set_visible(true);
+                return;
+            };
+            button.set_sensitive(false);
+            status.set_text("Importing shell history…");
+            status.set_visible(true);
+
+            let (sender, receiver) = crossbeam_channel::unbounded::<String>();
+            std::thread::spawn(move || {
+                let reports = corgiterm_core::history_import::import_detected(&db);
+                let summary = if reports.is_empty() {
+                    "No bash, zsh, fish or atuin history found".to_string()
+                } else {
+                    reports
+                        .iter()
+                        .map(|report| match report {
+                            Ok(report) => format!(
+                                "{}: {} of {} commands imported",
+                                report.format.name(),
+                                report.imported,
+                                report.read
+                            ),
+                            Err(e) => format!("Import failed: {}", e),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                };
+                let _ = sender.send(summary);
+            });
+
+            let results_list = results_list.clone();
+            let selected_cmd = selected_cmd.clone();
+            let entry = entry.clone();
+            let scope = scope.clone();
+            let status = status.clone();
+            let button = button.clone();
+            glib::timeout_add_local(
+                std::time::Duration::from_millis(100),
+                move || match receiver.try_recv() {
+                    Ok(summary) => {
+                        store.write().reload();
+                        Self::update_results_static(
+                            &results_list,
+                            &entry.text(),
+                            &scope.borrow(),
+                            &selected_cmd,
+                        );
+                        status.set_text(&summary);
+                        button.set_sensitive(true);
+                        glib::ControlFlow::Break
+                    }
+                    Err(crossbeam_channel::TryRecvError::Empty) => glib::ControlFlow::Continue,
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                        button.set_sensitive(true);
+                        glib::ControlFlow::Break
+                    }
+                },
+            );
+        });
+    }
+
     fn update_results(&self, query: &str) {
-        Self::update_results_static(&self.results_list, query, &self.selected_command);
+        Self::update_results_static(
+            &self.results_list,
+            query,
+            &self.scope.borrow(),
+            &self.selected_command,
+        );
     }
 
     fn update_results_static(
         results_list: &ListBox,
         query: &str,
+        scope: &HistoryScope,
         selected_cmd: &Rc<RefCell<Option<String>>>,
     ) {
         // Clear existing results
@@ -162,7 +375,7 @@ impl HistorySearch {
 
         if let Some(store) = history_store() {
             let store = store.read();
-            let results = store.fuzzy_search(query, 20);
+            let results = store.fuzzy_search_in(query, scope, 20);
 
             for (entry, score) in results {
                 let row_box = Box::new(Orientation::Vertical, 4);
@@ -198,7 +411,7 @@ impl HistorySearch {
                 // Update selected command
                 if let Some(store) = history_store() {
                     let store = store.read();
-                    let results = store.fuzzy_search(query, 1);
+                    let results = store.fuzzy_search_in(query, scope, 1);
                     if let Some((entry, _)) = results.first() {
                         *selected_cmd.borrow_mut() = Some(entry.command.clone());
                     }
@@ -279,12 +492,16 @@ impl Default for HistorySearch {
     }
 }
 
-/// Create and show history search popup
-pub fn show_history_search_dialog<F>(parent: &impl IsA<gtk4::Widget>, on_select: F)
-where
+/// Create and show history search popup for the terminal described by
+/// `context`
+pub fn show_history_search_dialog<F>(
+    parent: &impl IsA<gtk4::Widget>,
+    context: &HistoryContext,
+    on_select: F,
+) where
     F: Fn(&str) + 'static + Clone,
 {
-    let search = HistorySearch::new();
+    let search = HistorySearch::with_context(context);
     search.set_on_select(on_select.clone());
 
     // Create popup window
@@ -385,3 +602,51 @@ where
     window.present();
     search.focus();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes() {
+        let local = HistoryContext {
+            session: Some("pid 7".to_string()),
+            project: Some(("id-1".to_string(), "app".to_string())),
+            host: None,
+        };
+        let scopes = local.scopes();
+        let labels: Vec<&str> = scopes.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["All history", "This session", "Project app", "This machine"]
+        );
+        assert_eq!(scopes[2].1, HistoryScope::Project("id-1".to_string()));
+        assert_eq!(scopes[3].1, HistoryScope::Host(None));
+
+        let remote = HistoryContext {
+            host: Some("web1".to_string()),
+            ..HistoryContext::default()
+        };
+        let scopes = remote.scopes();
+        assert_eq!(scopes.len(), 2);
+        assert_eq!(scopes[1].0, "Host web1");
+    }
+
+    #[test]
+    fn test_recall() {
+        let mut recall = HistoryRecall::new(vec![
+            "make".to_string(),
+            "printf '\x1b'\x1b".to_string(),
+            "echo one\necho two".to_string(),
+            "echo one\r".to_string(),
+            "l\u{200b}s".to_string(),
+        ]);
+        assert_eq!(recall.newer(), None);
+        assert_eq!(recall.older(), Some("make"));
+        assert_eq!(recall.older(), Some("ls"));
+        assert_eq!(recall.older(), None);
+        assert_eq!(recall.newer(), Some("make"));
+        assert_eq!(recall.newer(), None);
+        assert_eq!(recall.older(), Some("make"));
+    }
+}
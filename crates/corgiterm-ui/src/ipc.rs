@@ -0,0 +1,205 @@
+//! IPC control socket handler
+//!
+//! Binds the socket configured by `advanced.ipc_socket` and answers
+//! [`corgiterm_core::ipc`] requests on the GTK main thread, where the tabs
+//! and terminal views live. See the core module for the method table.
+//!
+//! Commands run with `pane.send_text` go through the [`CommandGateway`] like
+//! any other command that was not typed; `pane.send_raw` bypasses it and is
+//! refused unless `advanced.ipc_raw_input` is on.
+
+use corgiterm_core::ipc::{IpcCall, IpcError, IpcRequest, IpcServer, INTERNAL_ERROR, SOCKET_ENV};
+use serde_json::{json, Value};
+use std::rc::Rc;
+
+use crate::command_gateway::{CommandGateway, CommandOrigin, CommandTarget, Delivery};
+use crate::split_pane::SplitDirection;
+use crate::tab_bar::TerminalTabs;
+
+/// How often pending requests are drained
+const POLL_INTERVAL_MS: u64 = 50;
+
+/// Bind the configured control socket, if any
+///
+/// Called before the first terminal is spawned so every shell inherits
+/// `CORGITERM_SOCKET` and `corgiterm msg` works without `--socket`.
+pub fn bind_configured() -> Option<IpcServer> {
+    let path = crate::app::config_manager()?
+        .read()
+        .config()
+        .advanced
+        .ipc_socket
+        .clone()?;
+
+    match IpcServer::bind(&path) {
+        Ok(server) => {
+            std::env::set_var(SOCKET_ENV, server.path());
+            Some(server)
+        }
+        Err(e) => {
+            tracing::error!("Failed to start IPC socket at {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Start answering requests against the window's tabs
+pub fn attach(server: IpcServer, gateway: Rc<CommandGateway>) {
+    gtk4::glib::timeout_add_local(
+        std::time::Duration::from_millis(POLL_INTERVAL_MS),
+        move || {
+            for call in server.calls().try_iter() {
+                handle_call(call, &gateway);
+            }
+            gtk4::glib::ControlFlow::Continue
+        },
+    );
+}
+
+fn handle_call(call: IpcCall, gateway: &CommandGateway) {
+    tracing::debug!("IPC request: {}", call.request.method);
+    let outcome = dispatch(&call.request, gateway);
+    if let Err(ref e) = outcome {
+        tracing::debug!("IPC request {} failed: {}", call.request.method, e.message);
+    }
+    call.respond(outcome);
+}
+
+fn dispatch(request: &IpcRequest, gateway: &CommandGateway) -> Result<Value, IpcError> {
+    let tabs = gateway.tabs();
+    match request.method.as_str() {
+        "ping" => Ok(json!({ "version": crate::version() })),
+        "tab.list" => to_value(tabs.tab_infos()),
+        "tab.new" => {
+            let title = request.param_str("title").unwrap_or("Terminal");
+            let page = tabs.add_terminal_tab(title, request.param_str("cwd"));
+            Ok(json!({ "index": tabs.position_of(&page) }))
+        }
+        "pane.split" => {
+            let direction = match request.param_str("direction").unwrap_or("horizontal") {
+                "horizontal" => SplitDirection::Horizontal,
+                "vertical" => SplitDirection::Vertical,
+                other => {
+                    return Err(IpcError::invalid_params(format!(
+                        "Unknown direction: {} (expected horizontal or vertical)",
+                        other
+                    )))
+                }
+            };
+            tabs.with_split_pane_at(request.param_index("tab"), |sp| {
+                sp.split(direction);
+                json!({ "panes": sp.pane_count() })
+            })
+            .ok_or_else(no_terminal_tab)
+        }
+        "pane.send_text" => {
+            let text = required_text(request)?;
+            if text.chars().any(char::is_control) {
+                return Err(IpcError::invalid_params(
+                    "Text contains line breaks or control characters; pass newline to run \
+                     it, or use pane.send_raw",
+                ));
+            }
+            if !request.param_bool("newline") {
+                // Only typed at the prompt: nothing runs until Enter
+                return with_pane(request, tabs, |tv| {
+                    tv.send_bytes(text.as_bytes());
+                    json!({ "sent": text.len(), "status": "typed" })
+                });
+            }
+            let pane = request.param_index("pane");
+            let target = CommandTarget {
+                tab: request.param_index("tab"),
+                pane,
+            };
+            let status = match gateway.submit_to(CommandOrigin::Ipc, text, target, None) {
+                Delivery::Sent => "sent",
+                Delivery::Held => "held",
+                Delivery::Cancelled => "cancelled",
+                Delivery::NoTerminal => {
+                    return Err(IpcError::invalid_params(format!(
+                        "No pane {}",
+                        pane.unwrap_or(0)
+                    )))
+                }
+            };
+            Ok(json!({ "sent": text.len(), "status": status }))
+        }
+        "pane.send_raw" => {
+            let allowed = crate::app::config_manager()
+                .is_some_and(|cm| cm.read().config().advanced.ipc_raw_input);
+            if !allowed {
+                return Err(IpcError::invalid_params(
+                    "Raw input is off; set advanced.ipc_raw_input to allow it",
+                ));
+            }
+            let text = required_text(request)?;
+            with_pane(request, tabs, |tv| {
+                tv.send_bytes(text.as_bytes());
+                json!({ "sent": text.len() })
+            })
+        }
+        "pane.get_text" => with_pane(
+            request,
+            tabs,
+            |tv| json!({ "lines": tv.get_visible_lines(usize::MAX) }),
+        ),
+        "session.list" => {
+            let manager = crate::app::session_manager()
+                .ok_or_else(|| IpcError::new(INTERNAL_ERROR, "Sessions not loaded yet"))?;
+            let manager = manager.read();
+            let projects: Vec<Value> = manager
+                .projects()
+                .iter()
+                .map(|project| {
+                    let sessions: Vec<Value> = project
+                        .sessions()
+                        .iter()
+                        .map(|session| {
+                            json!({
+                                "id": session.id.to_string(),
+                                "name": session.name,
+                                "cwd": session.cwd,
+                                "is_alive": session.is_alive,
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "id": project.id,
+                        "name": project.name,
+                        "path": project.path,
+                        "sessions": sessions,
+                    })
+                })
+                .collect();
+            Ok(Value::Array(projects))
+        }
+        method => Err(IpcError::method_not_found(method)),
+    }
+}
+
+/// Resolve the `tab`/`pane` params and run `f` on that terminal
+fn with_pane<F>(request: &IpcRequest, tabs: &TerminalTabs, f: F) -> Result<Value, IpcError>
+where
+    F: FnOnce(&crate::terminal_view::TerminalView) -> Value,
+{
+    let pane = request.param_index("pane");
+    tabs.with_split_pane_at(request.param_index("tab"), |sp| sp.with_terminal(pane, f))
+        .ok_or_else(no_terminal_tab)?
+        .ok_or_else(|| IpcError::invalid_params(format!("No pane {}", pane.unwrap_or(0))))
+}
+
+fn required_text(request: &IpcRequest) -> Result<&str, IpcError> {
+    request
+        .param_str("text")
+        .ok_or_else(|| IpcError::invalid_params("Missing required parameter: text"))
+}
+
+fn no_terminal_tab() -> IpcError {
+    IpcError::invalid_params("No terminal tab at that position")
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> Result<Value, IpcError> {
+    serde_json::to_value(value)
+        .map_err(|e| IpcError::new(INTERNAL_ERROR, format!("Failed to serialize result: {}", e)))
+}
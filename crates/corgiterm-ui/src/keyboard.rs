@@ -44,6 +44,14 @@ pub enum ShortcutAction {
     ZoomOut,
     ResetZoom,
 
+    // Command blocks
+    PreviousPrompt,
+    NextPrompt,
+    CopyLastOutput,
+    ToggleOutputCollapse,
+    PreviousProjectCommand,
+    NextProjectCommand,
+
     // UI features
     ToggleAi,
     ToggleSidebar,
@@ -53,6 +61,7 @@ pub enum ShortcutAction {
     AsciiArt,
     OpenFile,
     HistorySearch,
+    OutputSearch,
 
     // Application
     Quit,
@@ -67,7 +76,14 @@ pub struct ShortcutDefinition {
     pub description: &'static str,
 }
 
-pub const SHORTCUT_GROUPS: &[&str] = &["Tabs", "Panes", "Terminal", "Tools and UI", "Application"];
+pub const SHORTCUT_GROUPS: &[&str] = &[
+    "Tabs",
+    "Panes",
+    "Terminal",
+    "Command Blocks",
+    "Tools and UI",
+    "Application",
+];
 
 pub const SHORTCUT_DEFINITIONS: &[ShortcutDefinition] = &[
     ShortcutDefinition {
@@ -232,6 +248,42 @@ pub const SHORTCUT_DEFINITIONS: &[ShortcutDefinition] = &[
         title: "Reset Zoom",
         description: "Reset terminal font size",
     },
+    ShortcutDefinition {
+        action: ShortcutAction::PreviousPrompt,
+        group: "Command Blocks",
+        title: "Previous Prompt",
+        description: "Scroll to the previous command's prompt",
+    },
+    ShortcutDefinition {
+        action: ShortcutAction::NextPrompt,
+        group: "Command Blocks",
+        title: "Next Prompt",
+        description: "Scroll to the next command's prompt",
+    },
+    ShortcutDefinition {
+        action: ShortcutAction::CopyLastOutput,
+        group: "Command Blocks",
+        title: "Copy Last Output",
+        description: "Copy the output of the last finished command",
+    },
+    ShortcutDefinition {
+        action: ShortcutAction::ToggleOutputCollapse,
+        group: "Command Blocks",
+        title: "Collapse Output",
+        description: "Fold or unfold the output of the command at the top of the view",
+    },
+    ShortcutDefinition {
+        action: ShortcutAction::PreviousProjectCommand,
+        group: "Command Blocks",
+        title: "Previous Project Command",
+        description: "Put an older command from this project's history on the prompt",
+    },
+    ShortcutDefinition {
+        action: ShortcutAction::NextProjectCommand,
+        group: "Command Blocks",
+        title: "Next Project Command",
+        description: "Put a newer command from this project's history on the prompt",
+    },
     ShortcutDefinition {
         action: ShortcutAction::ToggleAi,
         group: "Tools and UI",
@@ -280,6 +332,12 @@ pub const SHORTCUT_DEFINITIONS: &[ShortcutDefinition] = &[
         title: "History Search",
         description: "Search command history",
     },
+    ShortcutDefinition {
+        action: ShortcutAction::OutputSearch,
+        group: "Tools and UI",
+        title: "Output Search",
+        description: "Search output from every session",
+    },
     ShortcutDefinition {
         action: ShortcutAction::Quit,
         group: "Application",
@@ -318,6 +376,12 @@ impl ShortcutAction {
             ShortcutAction::ZoomIn => config.zoom_in.as_deref(),
             ShortcutAction::ZoomOut => config.zoom_out.as_deref(),
             ShortcutAction::ResetZoom => config.reset_zoom.as_deref(),
+            ShortcutAction::PreviousPrompt => config.previous_prompt.as_deref(),
+            ShortcutAction::NextPrompt => config.next_prompt.as_deref(),
+            ShortcutAction::CopyLastOutput => config.copy_last_output.as_deref(),
+            ShortcutAction::ToggleOutputCollapse => config.toggle_output_collapse.as_deref(),
+            ShortcutAction::PreviousProjectCommand => config.previous_project_command.as_deref(),
+            ShortcutAction::NextProjectCommand => config.next_project_command.as_deref(),
             ShortcutAction::ToggleAi => config.toggle_ai.as_deref(),
             ShortcutAction::ToggleSidebar => config.toggle_sidebar.as_deref(),
             ShortcutAction::QuickSwitcher => config.quick_switcher.as_deref(),
@@ -326,6 +390,7 @@ impl ShortcutAction {
             ShortcutAction::AsciiArt => config.ascii_art.as_deref(),
             ShortcutAction::OpenFile => config.open_file.as_deref(),
             ShortcutAction::HistorySearch => config.history_search.as_deref(),
+            ShortcutAction::OutputSearch => config.output_search.as_deref(),
             ShortcutAction::Quit => config.quit.as_deref(),
         }
     }
@@ -359,6 +424,12 @@ impl ShortcutAction {
             ShortcutAction::ZoomIn => config.zoom_in = value,
             ShortcutAction::ZoomOut => config.zoom_out = value,
             ShortcutAction::ResetZoom => config.reset_zoom = value,
+            ShortcutAction::PreviousPrompt => config.previous_prompt = value,
+            ShortcutAction::NextPrompt => config.next_prompt = value,
+            ShortcutAction::CopyLastOutput => config.copy_last_output = value,
+            ShortcutAction::ToggleOutputCollapse => config.toggle_output_collapse = value,
+            ShortcutAction::PreviousProjectCommand => config.previous_project_command = value,
+            ShortcutAction::NextProjectCommand => config.next_project_command = value,
             ShortcutAction::ToggleAi => config.toggle_ai = value,
             ShortcutAction::ToggleSidebar => config.toggle_sidebar = value,
             ShortcutAction::QuickSwitcher => config.quick_switcher = value,
@@ -367,6 +438,7 @@ impl ShortcutAction {
             ShortcutAction::AsciiArt => config.ascii_art = value,
             ShortcutAction::OpenFile => config.open_file = value,
             ShortcutAction::HistorySearch => config.history_search = value,
+            ShortcutAction::OutputSearch => config.output_search = value,
             ShortcutAction::Quit => config.quit = value,
         }
     }
@@ -541,6 +613,10 @@ pub fn shortcut_to_gtk_accelerator(shortcut: &str) -> Option<String> {
         "enter" | "return" => "Return".to_string(),
         "pageup" | "pgup" => "Page_Up".to_string(),
         "pagedown" | "pgdn" => "Page_Down".to_string(),
+        "up" => "Up".to_string(),
+        "down" => "Down".to_string(),
+        "left" => "Left".to_string(),
+        "right" => "Right".to_string(),
         other => other.to_string(),
     };
 
@@ -584,5 +660,9 @@ mod tests {
             shortcut_to_gtk_accelerator("Ctrl+Plus").as_deref(),
             Some("<Ctrl>plus")
         );
+        assert_eq!(
+            shortcut_to_gtk_accelerator("Ctrl+Shift+Up").as_deref(),
+            Some("<Ctrl><Shift>Up")
+        );
     }
 }
@@ -27,11 +27,18 @@
 pub mod ai_panel;
 pub mod app;
 pub mod ascii_art_dialog;
+pub mod command_gateway;
 pub mod dialogs;
 pub mod document_view;
 pub mod emoji_picker;
 pub mod history_search;
+#[cfg(unix)]
+pub mod ipc;
 pub mod keyboard;
+#[cfg(unix)]
+pub mod mcp_backend;
+pub mod output_search;
+pub mod paste_review;
 pub mod recording_panel;
 pub mod sidebar;
 pub mod snippets;
@@ -44,6 +51,8 @@ pub mod theme_creator;
 pub mod widgets;
 pub mod window;
 
+pub use app::RunOptions;
+
 use gtk4::gio;
 use gtk4::prelude::*;
 use gtk4::{glib, Application};
@@ -53,6 +62,13 @@ pub const APP_ID: &str = "dev.corgiterm.CorgiTerm";
 
 /// Initialize and run the application
 pub fn run() -> glib::ExitCode {
+    run_with(RunOptions::default())
+}
+
+/// Initialize and run the application with command-line options
+pub fn run_with(options: RunOptions) -> glib::ExitCode {
+    app::set_run_options(options);
+
     // Initialize GTK
     gtk4::init().expect("Failed to initialize GTK");
 
@@ -0,0 +1,420 @@
+//! MCP terminal backend for the running window
+//!
+//! When `ai.mcp.enabled` is set, the MCP server is served on a Unix socket from
+//! a background tokio runtime (see [`corgiterm_ai::mcp_transport`]) and
+//! `corgiterm mcp` relays agents to it. Tool calls are turned into tasks that
+//! run on the GTK main thread, where the tabs and terminal views live.
+//!
+//! Sessions are terminal panes, identified as `"<tab>:<pane>"` using the same
+//! 0-based tab bar position and pane index as `corgiterm msg`. A bare `"<tab>"`
+//! means the focused pane of that tab.
+//!
+//! Commands go through the [`CommandGateway`] as [`CommandOrigin::Mcp`]; one
+//! held for confirmation answers the agent once the user decides. A command
+//! with a working directory runs in a subshell, so the user's shell stays
+//! where it was. When the shell reports commands (OSC 133) the agent gets the
+//! real exit code; otherwise the command is taken as done once the screen
+//! settles.
+
+use async_trait::async_trait;
+use corgiterm_ai::mcp::{HistoryEntry, McpServer, SessionInfo, TerminalBackend};
+use corgiterm_ai::mcp_transport;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::command_gateway::{CommandGateway, CommandOrigin, CommandTarget, Delivery};
+use crate::tab_bar::TerminalTabs;
+use crate::terminal_view::{FinishedCommand, TerminalView};
+
+/// How often pending tasks are drained on the GTK thread
+const POLL_INTERVAL_MS: u64 = 50;
+/// How often the screen is sampled while a command runs
+const SCREEN_POLL: Duration = Duration::from_millis(100);
+/// Without shell integration, a command is considered finished once the
+/// screen stops changing this long
+const SCREEN_SETTLE: Duration = Duration::from_millis(500);
+/// Timeout for `execute_command` when the agent does not pass one
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+
+/// Work for the GTK thread
+type UiTask = Box<dyn FnOnce(&CommandGateway) + Send>;
+
+/// A pane: tab bar position and pane index, `None` meaning the current one
+type PaneRef = (Option<usize>, Option<usize>);
+
+/// Start the MCP socket if `ai.mcp.enabled` is set
+pub fn start(gateway: Rc<CommandGateway>) {
+    let Some(config) = crate::app::config_manager() else {
+        return;
+    };
+    let mcp = config.read().config().ai.mcp.clone();
+    if !mcp.enabled {
+        return;
+    }
+    let path = mcp
+        .socket
+        .unwrap_or_else(mcp_transport::default_socket_path);
+
+    let (task_tx, task_rx) = crossbeam_channel::unbounded::<UiTask>();
+    let backend = Arc::new(UiTerminalBackend { tasks: task_tx });
+
+    std::thread::spawn(move || {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                tracing::error!("Failed to start MCP runtime: {}", e);
+                return;
+            }
+        };
+        let server = Arc::new(McpServer::with_backend(backend));
+        if let Err(e) = rt.block_on(mcp_transport::serve_unix(server, &path)) {
+            tracing::error!("MCP socket at {} stopped: {}", path.display(), e);
+        }
+    });
+
+    gtk4::glib::timeout_add_local(Duration::from_millis(POLL_INTERVAL_MS), move || {
+        for task in task_rx.try_iter() {
+            task(&gateway);
+        }
+        gtk4::glib::ControlFlow::Continue
+    });
+}
+
+/// [`TerminalBackend`] that drives the window's tabs and panes
+struct UiTerminalBackend {
+    tasks: crossbeam_channel::Sender<UiTask>,
+}
+
+impl UiTerminalBackend {
+    /// Run `f` on the GTK thread and wait for its result
+    async fn on_ui<R, F>(&self, f: F) -> Result<R, String>
+    where
+        R: Send + 'static,
+        F: FnOnce(&TerminalTabs) -> Result<R, String> + Send + 'static,
+    {
+        self.on_gateway(move |gateway| f(gateway.tabs())).await
+    }
+
+    /// Run `f` with the command gateway on the GTK thread and wait for its
+    /// result
+    async fn on_gateway<R, F>(&self, f: F) -> Result<R, String>
+    where
+        R: Send + 'static,
+        F: FnOnce(&CommandGateway) -> Result<R, String> + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = async_channel::bounded(1);
+        self.tasks
+            .send(Box::new(move |gateway| {
+                let _ = reply_tx.try_send(f(gateway));
+            }))
+            .map_err(|_| window_closed())?;
+        reply_rx.recv().await.map_err(|_| window_closed())?
+    }
+
+    /// Visible rows of a pane
+    async fn read_screen(&self, pane: PaneRef) -> Result<Vec<String>, String> {
+        self.on_ui(move |tabs| with_pane(tabs, pane, screen_lines))
+            .await
+    }
+
+    /// Visible rows of a pane, whether its shell reports commands, and the
+    /// last command it reported finishing
+    async fn read_status(
+        &self,
+        pane: PaneRef,
+    ) -> Result<(Vec<String>, bool, Option<FinishedCommand>), String> {
+        self.on_ui(move |tabs| {
+            with_pane(tabs, pane, |tv| {
+                (
+                    screen_lines(tv),
+                    tv.reports_commands(),
+                    tv.finished_command(),
+                )
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl TerminalBackend for UiTerminalBackend {
+    async fn execute_command(
+        &self,
+        command: &str,
+        cwd: Option<&str>,
+        timeout_ms: Option<u64>,
+    ) -> Result<String, String> {
+        let line = match cwd {
+            Some(dir) => format!("(cd {} && {})", shell_quote(dir), command),
+            None => command.to_string(),
+        };
+
+        // Pin the pane now so the result is read from it even if the user
+        // switches tabs while the command runs
+        let (decided_tx, decided_rx) = async_channel::bounded(1);
+        let submitted = line.clone();
+        let (delivery, target, finished_before) = self
+            .on_gateway(move |gateway| {
+                let tab = current_tab(gateway.tabs())?;
+                let pane = gateway
+                    .tabs()
+                    .with_split_pane_at(Some(tab), |sp| sp.focused_index())
+                    .ok_or_else(no_terminal)?;
+                let target = CommandTarget {
+                    tab: Some(tab),
+                    pane: Some(pane),
+                };
+                let pane_ref = (Some(tab), Some(pane));
+                let finished_before = with_pane(gateway.tabs(), pane_ref, |tv| {
+                    tv.finished_command().map_or(0, |finished| finished.serial)
+                })?;
+                let delivery = gateway.submit_to(
+                    CommandOrigin::Mcp,
+                    &submitted,
+                    target,
+                    Some(Box::new(move |delivery| {
+                        let _ = decided_tx.try_send(delivery);
+                    })),
+                );
+                Ok((delivery, pane_ref, finished_before))
+            })
+            .await?;
+
+        // A held command waits for the user, however long that takes, and
+        // may have been rewritten on approval (e.g. to use the trash)
+        let held = delivery == Delivery::Held;
+        let delivery = match delivery {
+            Delivery::Held => decided_rx.recv().await.map_err(|_| window_closed())?,
+            delivery => delivery,
+        };
+        match delivery {
+            Delivery::Sent => {}
+            Delivery::Cancelled | Delivery::Held => {
+                return Err("The user declined to run this command in CorgiTerm".to_string())
+            }
+            Delivery::NoTerminal => return Err(no_terminal()),
+        }
+
+        let timeout_ms = timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        let mut screen = Vec::new();
+        let mut stable_since = Instant::now();
+        let mut exit_code = None;
+        let mut timed_out = false;
+        loop {
+            tokio::time::sleep(SCREEN_POLL).await;
+            let (current, reports_commands, finished) = self.read_status(target).await?;
+            let ours = finished.filter(|finished| {
+                finished.serial > finished_before
+                    && (held || finished.command.trim() == line.trim())
+            });
+            if let Some(finished) = ours {
+                screen = current;
+                exit_code = finished.exit_code;
+                break;
+            }
+            if current != screen {
+                screen = current;
+                stable_since = Instant::now();
+            } else if !reports_commands && stable_since.elapsed() >= SCREEN_SETTLE {
+                break;
+            }
+            if Instant::now() >= deadline {
+                timed_out = true;
+                break;
+            }
+        }
+
+        // Start from the last echo of the command line
+        let start = screen
+            .iter()
+            .rposition(|l| l.contains(command))
+            .unwrap_or(0);
+        let mut output = screen[start..].join("\n");
+        if timed_out {
+            output.push_str(&format!(
+                "\n[still running after {}ms; use get_terminal_output to check on it]",
+                timeout_ms
+            ));
+        } else if let Some(exit_code) = exit_code {
+            output.push_str(&format!("\n[exit code: {}]", exit_code));
+        }
+        Ok(output)
+    }
+
+    async fn get_output(&self, lines: u64, session_id: Option<&str>) -> Result<String, String> {
+        let pane = match session_id {
+            Some(id) => parse_session_id(id)?,
+            None => (None, None),
+        };
+        let screen = self.read_screen(pane).await?;
+        let start = screen.len().saturating_sub(lines as usize);
+        Ok(screen[start..].join("\n"))
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<SessionInfo>, String> {
+        self.on_ui(|tabs| Ok(list_panes(tabs))).await
+    }
+
+    async fn create_session(
+        &self,
+        name: Option<&str>,
+        cwd: Option<&str>,
+        _project_id: Option<&str>,
+    ) -> Result<SessionInfo, String> {
+        let name = name.unwrap_or("Agent").to_string();
+        let cwd = cwd.map(str::to_string);
+        self.on_ui(move |tabs| {
+            let page = tabs.add_terminal_tab(&name, cwd.as_deref());
+            let tab = tabs.position_of(&page).ok_or_else(no_terminal)?;
+            Ok(SessionInfo {
+                id: format!("{}:0", tab),
+                name,
+                cwd: cwd.unwrap_or_default(),
+                project_id: None,
+                is_active: true,
+            })
+        })
+        .await
+    }
+
+    async fn switch_session(&self, session_id: &str) -> Result<(), String> {
+        let (tab, pane) = parse_session_id(session_id)?;
+        self.on_ui(move |tabs| {
+            let tab = tab.ok_or_else(no_terminal)?;
+            let panes = tabs
+                .with_split_pane_at(Some(tab), |sp| sp.pane_count())
+                .ok_or_else(no_terminal)?;
+            let pane = pane.unwrap_or(0);
+            if pane >= panes {
+                return Err(format!("No pane {} in tab {}", pane, tab));
+            }
+            tabs.select_tab_by_index(tab);
+            tabs.with_split_pane_at(Some(tab), |sp| sp.focus_pane(pane));
+            Ok(())
+        })
+        .await
+    }
+
+    async fn search_history(
+        &self,
+        query: &str,
+        search_type: &str,
+    ) -> Result<Vec<HistoryEntry>, String> {
+        let store = crate::app::history_store().ok_or("Command history not loaded yet")?;
+        let store = store.read();
+        corgiterm_ai::mcp::search_command_history(&store, query, search_type)
+    }
+}
+
+/// Describe every pane of every terminal tab in the active location
+fn list_panes(tabs: &TerminalTabs) -> Vec<SessionInfo> {
+    let mut sessions = Vec::new();
+    for info in tabs.tab_infos() {
+        if info.kind != "terminal" {
+            continue;
+        }
+        tabs.with_split_pane_at(Some(info.index), |sp| {
+            let focused = sp.focused_index();
+            for pane in 0..info.panes {
+                let cwd = sp
+                    .with_terminal(Some(pane), |tv| tv.working_directory())
+                    .flatten()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                let name = if info.panes > 1 {
+                    format!("{} (pane {})", info.title, pane)
+                } else {
+                    info.title.clone()
+                };
+                sessions.push(SessionInfo {
+                    id: format!("{}:{}", info.index, pane),
+                    name,
+                    cwd,
+                    project_id: None,
+                    is_active: info.active && pane == focused,
+                });
+            }
+        });
+    }
+    sessions
+}
+
+/// Visible rows of `tv`, without trailing blank ones
+fn screen_lines(tv: &TerminalView) -> Vec<String> {
+    let mut lines = tv.get_visible_lines(usize::MAX);
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Position of the selected tab in the tab bar
+fn current_tab(tabs: &TerminalTabs) -> Result<usize, String> {
+    tabs.tab_infos()
+        .iter()
+        .find(|t| t.active)
+        .map(|t| t.index)
+        .ok_or_else(no_terminal)
+}
+
+fn with_pane<F, R>(tabs: &TerminalTabs, (tab, pane): PaneRef, f: F) -> Result<R, String>
+where
+    F: FnOnce(&TerminalView) -> R,
+{
+    tabs.with_split_pane_at(tab, |sp| sp.with_terminal(pane, f))
+        .flatten()
+        .ok_or_else(no_terminal)
+}
+
+/// Parse `"<tab>"` or `"<tab>:<pane>"`
+fn parse_session_id(id: &str) -> Result<PaneRef, String> {
+    let invalid = || {
+        format!(
+            "Invalid session id: {} (expected <tab> or <tab>:<pane>)",
+            id
+        )
+    };
+    let (tab, pane) = match id.split_once(':') {
+        Some((tab, pane)) => (tab, Some(pane)),
+        None => (id, None),
+    };
+    let tab = tab.trim().parse().map_err(|_| invalid())?;
+    let pane = pane
+        .map(|p| p.trim().parse().map_err(|_| invalid()))
+        .transpose()?;
+    Ok((Some(tab), pane))
+}
+
+/// Quote a path for POSIX shells
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn no_terminal() -> String {
+    "No terminal pane for that session".to_string()
+}
+
+fn window_closed() -> String {
+    "The CorgiTerm window is closed".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_session_id() {
+        assert_eq!(parse_session_id("2"), Ok((Some(2), None)));
+        assert_eq!(parse_session_id("1:3"), Ok((Some(1), Some(3))));
+        assert!(parse_session_id("abc").is_err());
+        assert!(parse_session_id("1:").is_err());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/tmp/a b"), "'/tmp/a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,364 @@
+//! Global output search
+//!
+//! Searches the output of every terminal session indexed in the history
+//! database (see [`corgiterm_core::output_index`]), with regex and time
+//! filters. Picking a result jumps to its session and scrolls the matching
+//! line into view while it is still in the scrollback.
+
+use chrono::{DateTime, Duration, Local, Utc};
+use corgiterm_core::{OutputQuery, SearchResult};
+use gtk4::glib;
+use gtk4::prelude::*;
+use gtk4::{
+    Box, CheckButton, DropDown, Entry, Label, ListBox, ListBoxRow, Orientation, ScrolledWindow,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Results shown at most
+const MAX_RESULTS: usize = 200;
+
+/// Wait after the last keystroke before searching
+const SEARCH_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// How far back to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeRange {
+    AnyTime,
+    LastHour,
+    LastDay,
+    LastWeek,
+}
+
+impl TimeRange {
+    pub const ALL: [TimeRange; 4] = [
+        TimeRange::AnyTime,
+        TimeRange::LastHour,
+        TimeRange::LastDay,
+        TimeRange::LastWeek,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TimeRange::AnyTime => "Any time",
+            TimeRange::LastHour => "Last hour",
+            TimeRange::LastDay => "Last 24 hours",
+            TimeRange::LastWeek => "Last 7 days",
+        }
+    }
+
+    /// Earliest time included, counting back from `now`
+    pub fn since(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            TimeRange::AnyTime => None,
+            TimeRange::LastHour => Some(now - Duration::hours(1)),
+            TimeRange::LastDay => Some(now - Duration::days(1)),
+            TimeRange::LastWeek => Some(now - Duration::days(7)),
+        }
+    }
+}
+
+/// Show the output search dialog
+///
+/// `on_jump(session, line)` shows the session a result came from and returns
+/// `None` if it is closed, or `Some(false)` if the line is gone from its
+/// scrollback.
+pub fn show_output_search_dialog<F>(parent: &impl IsA<gtk4::Widget>, on_jump: F)
+where
+    F: Fn(&str, Option<usize>) -> Option<bool> + 'static,
+{
+    let window = gtk4::Window::builder()
+        .title("Search Terminal Output")
+        .modal(true)
+        .default_width(720)
+        .default_height(500)
+        .build();
+    if let Some(parent_window) = parent
+        .root()
+        .and_then(|r| r.downcast::<gtk4::Window>().ok())
+    {
+        window.set_transient_for(Some(&parent_window));
+    }
+
+    let container = Box::new(Orientation::Vertical, 8);
+    container.set_margin_top(12);
+    container.set_margin_bottom(12);
+    container.set_margin_start(12);
+    container.set_margin_end(12);
+
+    let header = Label::new(Some("Search Everything I've Seen"));
+    header.add_css_class("title-4");
+    header.set_xalign(0.0);
+    container.append(&header);
+
+    // Query and filters
+    let query_box = Box::new(Orientation::Horizontal, 8);
+    let entry = Entry::new();
+    entry.set_placeholder_text(Some("Search output from every session..."));
+    entry.add_css_class("monospace");
+    entry.set_hexpand(true);
+    query_box.append(&entry);
+    let regex_check = CheckButton::with_label("Regex");
+    query_box.append(&regex_check);
+    let labels: Vec<&str> = TimeRange::ALL.iter().map(|range| range.label()).collect();
+    let range_dropdown = DropDown::from_strings(&labels);
+    query_box.append(&range_dropdown);
+    container.append(&query_box);
+
+    let results_list = ListBox::new();
+    results_list.add_css_class("boxed-list");
+    results_list.set_selection_mode(gtk4::SelectionMode::Single);
+    let scrolled = ScrolledWindow::new();
+    scrolled.set_child(Some(&results_list));
+    scrolled.set_vexpand(true);
+    scrolled.set_min_content_height(300);
+    container.append(&scrolled);
+
+    let status = Label::new(None);
+    status.add_css_class("caption");
+    status.set_xalign(0.0);
+    status.set_wrap(true);
+    container.append(&status);
+
+    window.set_child(Some(&container));
+
+    let results: Rc<RefCell<Vec<SearchResult>>> = Rc::new(RefCell::new(Vec::new()));
+    // Bumped for every search so stale results are dropped
+    let generation = Rc::new(Cell::new(0u64));
+
+    let search = {
+        let entry = entry.clone();
+        let regex_check = regex_check.clone();
+        let range_dropdown = range_dropdown.clone();
+        let results_list = results_list.clone();
+        let results = results.clone();
+        let status = status.clone();
+        let generation = generation.clone();
+        Rc::new(move || {
+            let current = generation.get() + 1;
+            generation.set(current);
+            let pattern = entry.text().to_string();
+            if pattern.is_empty() {
+                clear_list(&results_list);
+                results.borrow_mut().clear();
+                status.set_text("");
+                return;
+            }
+            let Some(db) = crate::app::history_db() else {
+                status.set_text("Output history needs the history database");
+                return;
+            };
+            let range = TimeRange::ALL
+                .get(range_dropdown.selected() as usize)
+                .copied()
+                .unwrap_or(TimeRange::AnyTime);
+            let query = OutputQuery {
+                regex: regex_check.is_active(),
+                since: range.since(Utc::now()),
+                limit: MAX_RESULTS,
+                ..OutputQuery::new(pattern)
+            };
+
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            std::thread::spawn(move || {
+                let _ = sender.send(db.search_output(&query));
+            });
+
+            let results_list = results_list.clone();
+            let results = results.clone();
+            let status = status.clone();
+            let generation = generation.clone();
+            glib::timeout_add_local(std::time::Duration::from_millis(100), move || {
+                let found = match receiver.try_recv() {
+                    Ok(found) => found,
+                    Err(crossbeam_channel::TryRecvError::Empty) => {
+                        return glib::ControlFlow::Continue
+                    }
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                        return glib::ControlFlow::Break
+                    }
+                };
+                if generation.get() != current {
+                    return glib::ControlFlow::Break;
+                }
+                clear_list(&results_list);
+                match found {
+                    Ok(found) => {
+                        for result in &found {
+                            results_list.append(&result_row(result));
+                        }
+                        if let Some(first) = results_list.row_at_index(0) {
+                            results_list.select_row(Some(&first));
+                        }
+                        status.set_text(&match found.len() {
+                            0 => "No matches".to_string(),
+                            MAX_RESULTS => format!("First {} matches", MAX_RESULTS),
+                            n => format!("{} matches", n),
+                        });
+                        *results.borrow_mut() = found;
+                    }
+                    Err(e) => {
+                        results.borrow_mut().clear();
+                        status.set_text(&e.to_string());
+                    }
+                }
+                glib::ControlFlow::Break
+            })
+        })
+    };
+
+    // Search shortly after typing stops, and at once when a filter changes
+    entry.connect_changed({
+        let search = search.clone();
+        let generation = generation.clone();
+        move |_| {
+            let search = search.clone();
+            let generation = generation.clone();
+            // Supersedes any search still pending
+            let scheduled = generation.get() + 1;
+            generation.set(scheduled);
+            glib::timeout_add_local_once(SEARCH_DELAY, move || {
+                if generation.get() == scheduled {
+                    search();
+                }
+            });
+        }
+    });
+    regex_check.connect_toggled({
+        let search = search.clone();
+        move |_| search()
+    });
+    range_dropdown.connect_selected_notify({
+        let search = search.clone();
+        move |_| search()
+    });
+
+    let jump = {
+        let window = window.clone();
+        let results = results.clone();
+        let status = status.clone();
+        move |index: i32| {
+            let result = {
+                let results = results.borrow();
+                let Some(result) = usize::try_from(index).ok().and_then(|i| results.get(i)) else {
+                    return;
+                };
+                (result.session.clone(), result.line)
+            };
+            let (Some(session), line) = result else {
+                status.set_text("This output was not recorded with its session");
+                return;
+            };
+            match on_jump(&session, line) {
+                Some(true) => window.close(),
+                Some(false) => status
+                    .set_text("The session is still open, but this output has left its scrollback"),
+                None => status.set_text("The session that printed this output has been closed"),
+            }
+        }
+    };
+    let jump = Rc::new(jump);
+
+    results_list.connect_row_activated({
+        let jump = jump.clone();
+        move |_, row| jump(row.index())
+    });
+    entry.connect_activate({
+        let results_list = results_list.clone();
+        move |_| {
+            if let Some(row) = results_list.selected_row() {
+                jump(row.index());
+            }
+        }
+    });
+
+    let key_controller = gtk4::EventControllerKey::new();
+    key_controller.connect_key_pressed({
+        let window = window.clone();
+        let results_list = results_list.clone();
+        move |_, key, _keycode, _state| {
+            let step = match key {
+                gtk4::gdk::Key::Escape => {
+                    window.close();
+                    return glib::Propagation::Stop;
+                }
+                gtk4::gdk::Key::Up => -1,
+                gtk4::gdk::Key::Down => 1,
+                _ => return glib::Propagation::Proceed,
+            };
+            let index = results_list
+                .selected_row()
+                .map_or(0, |row| row.index() + step);
+            if let Some(row) = results_list.row_at_index(index.max(0)) {
+                results_list.select_row(Some(&row));
+            }
+            glib::Propagation::Stop
+        }
+    });
+    window.add_controller(key_controller);
+
+    window.present();
+    entry.grab_focus();
+}
+
+fn clear_list(list: &ListBox) {
+    while let Some(row) = list.first_child() {
+        list.remove(&row);
+    }
+}
+
+fn result_row(result: &SearchResult) -> ListBoxRow {
+    let row_box = Box::new(Orientation::Vertical, 4);
+    row_box.set_margin_top(8);
+    row_box.set_margin_bottom(8);
+    row_box.set_margin_start(8);
+    row_box.set_margin_end(8);
+
+    let context = Label::new(Some(&result.context));
+    context.add_css_class("monospace");
+    context.set_xalign(0.0);
+    context.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    row_box.append(&context);
+
+    let mut info = vec![result
+        .timestamp
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()];
+    if let Some(command) = &result.command {
+        info.push(format!("$ {}", command));
+    }
+    if let Some(session) = &result.session {
+        info.push(session.clone());
+    }
+    let info = Label::new(Some(&info.join(" • ")));
+    info.add_css_class("dim-label");
+    info.add_css_class("caption");
+    info.set_xalign(0.0);
+    info.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    row_box.append(&info);
+
+    let row = ListBoxRow::new();
+    row.set_child(Some(&row_box));
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_range() {
+        let now = Utc::now();
+        assert_eq!(TimeRange::AnyTime.since(now), None);
+        assert_eq!(
+            TimeRange::LastHour.since(now),
+            Some(now - Duration::hours(1))
+        );
+        assert_eq!(
+            TimeRange::LastWeek.since(now),
+            Some(now - Duration::days(7))
+        );
+        assert_eq!(TimeRange::ALL.len(), 4);
+    }
+}
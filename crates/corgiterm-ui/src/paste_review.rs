@@ -0,0 +1,224 @@
+//! Paste review
+//!
+//! Before pasted text reaches the PTY it is inspected (see
+//! [`corgiterm_core::paste`]) and, when `terminal.paste_protection` asks for
+//! it, shown line by line with each line's Safe Mode risk so the user can
+//! paste or cancel. What the risky lines would touch on disk is added to
+//! the dialog as the dry runs finish.
+
+use corgiterm_config::{PasteProtectionConfig, PasteReview};
+use corgiterm_core::{CommandPreview, PasteInspection, RiskLevel};
+use gtk4::glib;
+use gtk4::prelude::*;
+
+use crate::command_gateway::needs_confirmation;
+
+/// Whether `inspection` must be reviewed under `config`
+pub fn needs_review(config: &PasteProtectionConfig, inspection: &PasteInspection) -> bool {
+    match config.review {
+        PasteReview::Always => true,
+        PasteReview::Never => false,
+        PasteReview::Suspicious => {
+            inspection.has_hidden_content()
+                || (config.review_multiline && inspection.is_multiline())
+                || (config.review_newlines && inspection.runs_immediately())
+                || needs_confirmation(config.review_risk, inspection.risk)
+        }
+    }
+}
+
+/// Show `inspection` for review, adding each line's dry run from `impact`
+/// as it arrives; `on_paste` is called if the user pastes
+pub fn show<F>(
+    parent: &impl IsA<gtk4::Widget>,
+    inspection: &PasteInspection,
+    impact: Option<crossbeam_channel::Receiver<(usize, CommandPreview)>>,
+    on_paste: F,
+) where
+    F: Fn() + 'static,
+{
+    let dialog = libadwaita::Dialog::builder()
+        .title("🐕 Review Paste")
+        .build();
+    dialog.set_follows_content_size(true);
+
+    let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+    main_box.set_margin_start(24);
+    main_box.set_margin_end(24);
+    main_box.set_margin_top(24);
+    main_box.set_margin_bottom(24);
+    main_box.set_width_request(560);
+
+    // What was found
+    for issue in &inspection.issues {
+        let label = gtk4::Label::new(Some(&format!("• {}", issue.describe())));
+        label.set_xalign(0.0);
+        label.set_wrap(true);
+        if issue.is_hidden() {
+            label.add_css_class("warning");
+        }
+        main_box.append(&label);
+    }
+
+    // Each line with its risk
+    let lines_box = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+    let mut line_boxes = Vec::with_capacity(inspection.lines.len());
+    for line in &inspection.lines {
+        let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 8);
+        let risk = line.preview.as_ref().map(|p| p.risk);
+        let emoji = gtk4::Label::new(Some(risk.map_or(" ", |r| r.emoji())));
+        emoji.set_valign(gtk4::Align::Start);
+        row.append(&emoji);
+
+        let text_box = gtk4::Box::new(gtk4::Orientation::Vertical, 2);
+        let text = gtk4::Label::new(Some(&line.visible));
+        text.set_xalign(0.0);
+        text.set_selectable(true);
+        text.set_wrap(true);
+        text.set_wrap_mode(gtk4::pango::WrapMode::Char);
+        text.add_css_class("monospace");
+        if risk == Some(RiskLevel::Danger) {
+            text.add_css_class("error");
+        }
+        text_box.append(&text);
+        if let Some(first) = line
+            .preview
+            .as_ref()
+            .filter(|p| p.risk != RiskLevel::Safe)
+            .and_then(|p| p.explanation.first())
+        {
+            let why = gtk4::Label::new(Some(first));
+            why.set_xalign(0.0);
+            why.set_wrap(true);
+            why.add_css_class("dim-label");
+            text_box.append(&why);
+        }
+        row.append(&text_box);
+        lines_box.append(&row);
+        line_boxes.push(text_box.downgrade());
+    }
+    if let Some(impact) = impact {
+        glib::timeout_add_local(std::time::Duration::from_millis(100), move || loop {
+            match impact.try_recv() {
+                Ok((index, preview)) => {
+                    // Closing the dialog drops its rows and stops the dry runs
+                    let Some(text_box) = line_boxes.get(index).and_then(|b| b.upgrade()) else {
+                        return glib::ControlFlow::Break;
+                    };
+                    if let Some(summary) = impact_summary(&preview) {
+                        let label = gtk4::Label::new(Some(&summary));
+                        label.set_xalign(0.0);
+                        label.set_wrap(true);
+                        label.add_css_class("dim-label");
+                        text_box.append(&label);
+                    }
+                }
+                Err(crossbeam_channel::TryRecvError::Empty)
+                    if line_boxes.iter().any(|b| b.upgrade().is_some()) =>
+                {
+                    return glib::ControlFlow::Continue;
+                }
+                Err(_) => return glib::ControlFlow::Break,
+            }
+        });
+    }
+    let scrolled = gtk4::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .min_content_height(80)
+        .max_content_height(360)
+        .propagate_natural_height(true)
+        .child(&lines_box)
+        .build();
+    main_box.append(&scrolled);
+
+    // Buttons
+    let button_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 12);
+    button_box.set_margin_top(12);
+    button_box.set_halign(gtk4::Align::End);
+
+    let cancel_btn = gtk4::Button::with_label("Cancel");
+    cancel_btn.add_css_class("pill");
+    let dialog_for_cancel = dialog.clone();
+    cancel_btn.connect_clicked(move |_| {
+        dialog_for_cancel.close();
+    });
+    button_box.append(&cancel_btn);
+
+    let paste_btn = gtk4::Button::with_label("Paste");
+    paste_btn.add_css_class("pill");
+    paste_btn.add_css_class(
+        if inspection.risk == RiskLevel::Danger || inspection.has_hidden_content() {
+            "destructive-action"
+        } else {
+            "suggested-action"
+        },
+    );
+    let dialog_for_paste = dialog.clone();
+    paste_btn.connect_clicked(move |_| {
+        on_paste();
+        dialog_for_paste.close();
+    });
+    button_box.append(&paste_btn);
+
+    main_box.append(&button_box);
+
+    dialog.set_child(Some(&main_box));
+    dialog.present(Some(parent));
+}
+
+/// What a line's dry run found, such as "Will affect 12 file(s) (4.0 KB)"
+fn impact_summary(preview: &CommandPreview) -> Option<String> {
+    let count = preview.affected_count?;
+    let at_least = if preview.impact_truncated {
+        "at least "
+    } else {
+        ""
+    };
+    let size = preview
+        .affected_size
+        .map(|s| {
+            format!(
+                " ({})",
+                crate::widgets::safe_mode_preview::humanize_bytes(s)
+            )
+        })
+        .unwrap_or_default();
+    Some(format!("Will affect {}{} file(s){}", at_least, count, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corgiterm_config::ConfirmAt;
+    use corgiterm_core::paste::inspect;
+
+    #[test]
+    fn test_needs_review() {
+        let config = PasteProtectionConfig::default();
+        assert!(!needs_review(&config, &inspect("ls -la", true)));
+        assert!(needs_review(&config, &inspect("ls\npwd", true)));
+        assert!(needs_review(&config, &inspect("make\n", false)));
+        assert!(needs_review(&config, &inspect("echo \u{200B}hi", true)));
+
+        let mut risky = inspect("rm -rf build", true);
+        risky.risk = RiskLevel::Danger;
+        assert!(needs_review(&config, &risky));
+
+        let lenient = PasteProtectionConfig {
+            review_multiline: false,
+            review_newlines: false,
+            review_risk: ConfirmAt::Never,
+            ..PasteProtectionConfig::default()
+        };
+        assert!(!needs_review(&lenient, &inspect("ls\npwd\n", false)));
+        assert!(!needs_review(&lenient, &risky));
+        // Hidden characters are always reviewed unless review is off
+        assert!(needs_review(&lenient, &inspect("ls\x1b[201~", true)));
+
+        let never = PasteProtectionConfig {
+            review: PasteReview::Never,
+            ..PasteProtectionConfig::default()
+        };
+        assert!(!needs_review(&never, &inspect("ls\x1b[201~", true)));
+    }
+}
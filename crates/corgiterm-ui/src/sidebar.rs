@@ -4,13 +4,14 @@
 //! Projects persist across restarts using the SessionManager.
 //! Clicking a project folder opens a terminal in that directory.
 //! Clicking a file shortcut opens it in a document editor tab.
+//! Each project chooses the Safe Mode profile for terminals inside it.
 
 use gtk4::gio;
 use gtk4::prelude::*;
 use gtk4::{Button, FileDialog, Label, ListBox, Orientation, ScrolledWindow};
 use libadwaita::prelude::*;
 use std::cell::RefCell;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use crate::app::session_manager;
@@ -181,6 +182,7 @@ impl Sidebar {
                                     .activatable(true)
                                     .build();
                                 row.add_prefix(&Label::new(Some("📁")));
+                                row.add_suffix(&profile_dropdown(&path));
 
                                 // Connect click
                                 let n = name.clone();
@@ -348,6 +350,7 @@ impl Sidebar {
             .activatable(true)
             .build();
         row.add_prefix(&Label::new(Some("📁")));
+        row.add_suffix(&profile_dropdown(path));
 
         // Connect click handler
         let n = name.clone();
@@ -508,3 +511,60 @@ fn get_file_icon(path: &PathBuf) -> &'static str {
         _ => "\u{1F4C4}", // page facing up
     }
 }
+
+/// Drop-down choosing a project's Safe Mode profile, "Default" meaning the
+/// one in Preferences
+fn profile_dropdown(path: &Path) -> gtk4::DropDown {
+    let profiles = crate::command_gateway::profile_names();
+    let mut labels = vec!["Default"];
+    labels.extend(profiles.iter().map(String::as_str));
+    let dropdown = gtk4::DropDown::from_strings(&labels);
+    dropdown.set_valign(gtk4::Align::Center);
+    dropdown.add_css_class("flat");
+    dropdown.set_tooltip_text(Some("Safe Mode profile for terminals in this folder"));
+
+    let current = session_manager().and_then(|sm| {
+        let session_mgr = sm.read();
+        session_mgr
+            .projects()
+            .iter()
+            .find(|p| p.path == path)
+            .and_then(|p| p.settings.safe_mode_profile.clone())
+    });
+    if let Some(pos) = current.and_then(|current| profiles.iter().position(|p| *p == current)) {
+        dropdown.set_selected(pos as u32 + 1);
+    }
+
+    let path = path.to_path_buf();
+    dropdown.connect_selected_notify(move |dropdown| {
+        let profile = (dropdown.selected() as usize)
+            .checked_sub(1)
+            .and_then(|index| profiles.get(index).cloned());
+        let Some(sm) = session_manager() else {
+            return;
+        };
+        let mut session_mgr = sm.write();
+        match session_mgr
+            .projects_mut()
+            .iter_mut()
+            .find(|p| p.path == path)
+        {
+            Some(project) => project.settings.safe_mode_profile = profile.clone(),
+            None => {
+                session_mgr
+                    .open_project(path.clone())
+                    .settings
+                    .safe_mode_profile = profile.clone()
+            }
+        }
+        if let Err(e) = session_mgr.save() {
+            tracing::error!("Failed to save projects: {}", e);
+        }
+        tracing::info!(
+            "Safe Mode profile for {}: {}",
+            path.display(),
+            profile.as_deref().unwrap_or("default")
+        );
+    });
+    dropdown
+}
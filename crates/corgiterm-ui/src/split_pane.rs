@@ -393,8 +393,13 @@ impl SplitPane {
 
     /// Split a specific pane node
     fn split_node(&self, node: Rc<RefCell<PaneNode>>, direction: SplitDirection) {
-        let working_dir = self.working_dir.borrow();
-        let working_path = working_dir.as_ref().map(|p| p.as_path());
+        // New panes start where the split terminal is, else in the tab's directory
+        let working_dir = node
+            .borrow()
+            .as_terminal()
+            .and_then(|tv| tv.working_directory())
+            .or_else(|| self.working_dir.borrow().clone());
+        let working_path = working_dir.as_deref();
 
         // Get the old widget before we modify the node
         let old_widget = node.borrow().widget();
@@ -651,6 +656,22 @@ impl SplitPane {
         false
     }
 
+    /// Send a command to every pane broadcast input reaches (all panes when
+    /// no targets are set); returns how many terminals received it
+    pub fn broadcast_command(&self, command: &str) -> usize {
+        let targets = self.broadcast_targets.borrow();
+        let mut sent = 0;
+        for (idx, pane) in self.all_panes.borrow().iter().enumerate() {
+            if targets.is_empty() || targets.contains(&idx) {
+                if let Some(tv) = pane.borrow().as_terminal() {
+                    tv.send_command(command);
+                    sent += 1;
+                }
+            }
+        }
+        sent
+    }
+
     /// Send raw bytes to the focused terminal (no newline)
     pub fn send_bytes(&self, bytes: &[u8]) {
         let focused = self.focused_pane.borrow();
@@ -667,6 +688,24 @@ impl SplitPane {
         }
     }
 
+    /// Run `f` on a terminal by pane index (focus-cycling order), or on the
+    /// focused terminal when `index` is `None`
+    pub fn with_terminal<F, R>(&self, index: Option<usize>, f: F) -> Option<R>
+    where
+        F: FnOnce(&TerminalView) -> R,
+    {
+        let node = match index {
+            Some(idx) => self.all_panes.borrow().get(idx).cloned()?,
+            None => self
+                .focused_pane
+                .borrow()
+                .clone()
+                .unwrap_or_else(|| self.root.clone()),
+        };
+        let node = node.borrow();
+        node.as_terminal().map(f)
+    }
+
     /// Get visible lines from focused terminal (for thumbnails)
     pub fn get_visible_lines(&self, max_lines: usize) -> Vec<String> {
         // Try focused first
@@ -701,6 +740,11 @@ impl SplitPane {
         "Terminal".to_string()
     }
 
+    /// Safe Mode profile of the focused terminal
+    pub fn safe_mode_profile(&self) -> Option<String> {
+        self.with_terminal(None, |tv| tv.safe_mode_profile())
+    }
+
     /// Move focus between panes
     pub fn focus_next(&self) {
         let panes = self.all_panes.borrow();
@@ -753,6 +797,41 @@ impl SplitPane {
         }
     }
 
+    /// Index of the focused pane (focus-cycling order)
+    pub fn focused_index(&self) -> usize {
+        let focused = self.focused_pane.borrow();
+        focused
+            .as_ref()
+            .and_then(|node| {
+                self.all_panes
+                    .borrow()
+                    .iter()
+                    .position(|p| Rc::ptr_eq(p, node))
+            })
+            .unwrap_or(0)
+    }
+
+    /// Focus a pane by index; returns false if there is no such pane
+    pub fn focus_pane(&self, index: usize) -> bool {
+        let Some(node) = self.all_panes.borrow().get(index).cloned() else {
+            return false;
+        };
+        if let Some(tv) = node.borrow().as_terminal() {
+            tv.widget().grab_focus();
+        }
+        *self.focused_pane.borrow_mut() = Some(node);
+        true
+    }
+
+    /// Index of the pane running the terminal session `session`
+    pub fn pane_for_session(&self, session: &str) -> Option<usize> {
+        self.all_panes.borrow().iter().position(|node| {
+            node.borrow()
+                .as_terminal()
+                .is_some_and(|tv| tv.session_id() == session)
+        })
+    }
+
     /// Check if this pane is split
     pub fn is_split(&self) -> bool {
         !matches!(self.root.borrow().content, PaneContent::Terminal(_))
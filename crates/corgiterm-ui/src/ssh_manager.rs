@@ -882,6 +882,23 @@ impl SshManager {
         tags_entry.add_suffix(&tags_hint);
         org_group.add(&tags_entry);
 
+        // Safe Mode profile, "Default" meaning the one in Preferences
+        let profiles = crate::command_gateway::profile_names();
+        let mut profile_strs = vec!["Default"];
+        profile_strs.extend(profiles.iter().map(String::as_str));
+        let profile_row = libadwaita::ComboRow::builder()
+            .title("Safe Mode Profile")
+            .subtitle("How carefully commands on this host are checked")
+            .build();
+        profile_row.set_model(Some(&StringList::new(&profile_strs)));
+        if let Some(pos) = host
+            .and_then(|h| h.safe_mode_profile.as_ref())
+            .and_then(|profile| profiles.iter().position(|p| p == profile))
+        {
+            profile_row.set_selected(pos as u32 + 1);
+        }
+        org_group.add(&profile_row);
+
         content_box.append(&org_group);
 
         // Buttons
@@ -943,6 +960,9 @@ impl SshManager {
                         .filter(|s| !s.is_empty())
                         .collect(),
                     favorite: original_host.as_ref().map(|h| h.favorite).unwrap_or(false),
+                    safe_mode_profile: (profile_row.selected() as usize)
+                        .checked_sub(1)
+                        .and_then(|index| profiles.get(index).cloned()),
                 };
 
                 // Update state
@@ -1994,6 +2014,7 @@ impl SshManager {
                     options: Vec::new(),
                     tags: Vec::new(),
                     favorite: false,
+                    safe_mode_profile: None,
                 };
 
                 // Create dummy state for connect_to_host
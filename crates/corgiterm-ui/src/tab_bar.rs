@@ -52,6 +52,29 @@ impl TabContent {
         }
     }
 
+    /// Show the terminal running session `session`, switching location, tab
+    /// and pane as needed, and scroll output line `line` into view
+    ///
+    /// Returns `None` if no open terminal runs the session, or `Some(false)`
+    /// if the line has already left its scrollback.
+    pub fn reveal_session(&self, session: &str, line: Option<usize>) -> Option<bool> {
+        let (scope, page, pane) = self.entries.borrow().iter().find_map(|entry| {
+            let pane = entry.content.as_split_pane()?.pane_for_session(session)?;
+            Some((entry.scope.clone(), entry.page.clone(), pane))
+        })?;
+        self.set_active_scope(&scope);
+        self.tab_view.set_selected_page(&page);
+        self.with_current_split_pane(|sp| {
+            sp.focus_pane(pane);
+            match line {
+                Some(line) => sp
+                    .with_terminal(Some(pane), |tv| tv.reveal_line(line))
+                    .unwrap_or(false),
+                None => true,
+            }
+        })
+    }
+
     /// Split the current pane horizontally
     pub fn split_horizontal(&self) {
         if let TabContent::Terminal(sp) = self {
@@ -67,12 +90,28 @@ impl TabContent {
     }
 }
 
+/// Summary of a visible tab (used by the IPC control socket)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TabInfo {
+    /// Position in the tab bar
+    pub index: usize,
+    pub title: String,
+    /// "terminal" or "document"
+    pub kind: &'static str,
+    /// Number of terminal panes (0 for documents)
+    pub panes: usize,
+    /// Is this the selected tab?
+    pub active: bool,
+}
+
 struct TabEntry {
     title: String,
     scope: String,
     content: TabContent,
     page: TabPage,
     visible: bool,
+    /// Safe Mode profile shown on the tab
+    profile: Option<String>,
 }
 
 /// Tab manager with libadwaita TabView
@@ -315,6 +354,7 @@ impl TerminalTabs {
             content: TabContent::Terminal(split_pane),
             page: page.clone(),
             visible: true,
+            profile: None,
         });
 
         self.rebuild_visible_indices();
@@ -347,6 +387,7 @@ impl TerminalTabs {
             content: TabContent::Document(document),
             page: page.clone(),
             visible: true,
+            profile: None,
         });
 
         self.rebuild_visible_indices();
@@ -486,6 +527,70 @@ impl TerminalTabs {
         None
     }
 
+    /// Tab bar position and pane index of the terminal session `session`
+    /// in the active location
+    pub fn locate_session(&self, session: &str) -> Option<(usize, usize)> {
+        self.rebuild_visible_indices();
+        let entries = self.entries.borrow();
+        let visible = self.visible_indices.borrow();
+        visible.iter().enumerate().find_map(|(position, &idx)| {
+            let pane = entries
+                .get(idx)?
+                .content
+                .as_split_pane()?
+                .pane_for_session(session)?;
+            Some((position, pane))
+        })
+    }
+
+    /// Access the split pane of a terminal tab by tab bar position, or of the
+    /// current tab when `position` is `None`
+    pub fn with_split_pane_at<F, R>(&self, position: Option<usize>, f: F) -> Option<R>
+    where
+        F: FnOnce(&SplitPane) -> R,
+    {
+        let idx = match position {
+            Some(position) => {
+                self.rebuild_visible_indices();
+                self.visible_indices.borrow().get(position).copied()?
+            }
+            None => self.current_content()?,
+        };
+        let entries = self.entries.borrow();
+        entries.get(idx)?.content.as_split_pane().map(f)
+    }
+
+    /// Describe the tabs in the active location, in tab bar order
+    pub fn tab_infos(&self) -> Vec<TabInfo> {
+        let current = self.current_content();
+        let entries = self.entries.borrow();
+        self.visible_indices
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter_map(|(position, &idx)| {
+                let entry = entries.get(idx)?;
+                let (kind, panes) = match &entry.content {
+                    TabContent::Terminal(sp) => ("terminal", sp.pane_count()),
+                    TabContent::Document(_) => ("document", 0),
+                };
+                Some(TabInfo {
+                    index: position,
+                    title: entry.page.title().to_string(),
+                    kind,
+                    panes,
+                    active: current == Some(idx),
+                })
+            })
+            .collect()
+    }
+
+    /// Get the tab bar position of a page in the active location
+    pub fn position_of(&self, page: &TabPage) -> Option<usize> {
+        let position = self.tab_view.page_position(page);
+        (position >= 0).then_some(position as usize)
+    }
+
     /// Split the current pane horizontally
     pub fn split_current_horizontal(&self) {
         if let Some(idx) = self.current_content() {
@@ -568,6 +673,23 @@ impl TerminalTabs {
                     entry.title = dir_name.clone();
                     entry.page.set_title(&dir_name);
                 }
+
+                // Show the Safe Mode profile unless it is the standard one
+                let profile = sp.safe_mode_profile();
+                if entry.profile != profile {
+                    let icon = profile
+                        .as_deref()
+                        .filter(|profile| *profile != corgiterm_core::DEFAULT_PROFILE)
+                        .map(|_| gio::ThemedIcon::new("security-high-symbolic"));
+                    entry.page.set_indicator_icon(icon.as_ref());
+                    entry.page.set_indicator_tooltip(
+                        &profile
+                            .as_deref()
+                            .map(|profile| format!("Safe Mode profile: {}", profile))
+                            .unwrap_or_default(),
+                    );
+                    entry.profile = profile;
+                }
             }
         }
     }
@@ -4,21 +4,24 @@ use gtk4::gio::{Menu, SimpleAction};
 use gtk4::glib;
 use gtk4::prelude::*;
 use gtk4::{
-    Adjustment, Box, DrawingArea, Entry, EventControllerKey, EventControllerMotion,
-    EventControllerScroll, EventControllerScrollFlags, GestureClick, GestureDrag, Label,
-    Orientation, PopoverMenu, Revealer, RevealerTransitionType, Scrollbar,
+    Adjustment, Box, Button, CheckButton, DrawingArea, Entry, EventControllerKey,
+    EventControllerMotion, EventControllerScroll, EventControllerScrollFlags, GestureClick,
+    GestureDrag, Label, Orientation, PopoverMenu, Revealer, RevealerTransitionType, Scrollbar,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::app::config_manager;
+use crate::command_gateway::{CommandOrigin, CommandTarget, Delivery};
+use crate::history_search::HistoryRecall;
 use crate::keyboard::{KeyboardShortcuts, ShortcutAction};
 use corgiterm_config::themes::ThemeManager;
 use corgiterm_core::{
-    AlacrittyEngine, CellColor, HintDetector, HintModeState, Pty, PtySize, RenderCell,
-    TerminalEngine, TerminalSize,
+    AlacrittyEngine, CellColor, HintDetector, HintModeState, HistoryScope, OutputIndexer, Pty,
+    PtySize, RenderCell, TerminalEngine, TerminalSize,
 };
 use std::path::Path;
 
@@ -26,6 +29,9 @@ use std::path::Path;
 static URL_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"https?://[^\s<>\[\]{}|\\^`\x00-\x1f\x7f]+").unwrap());
 
+/// How often output is indexed while a command keeps printing
+const OUTPUT_INDEX_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
 const ACTION_COPY: &str = "copy";
 const ACTION_PASTE: &str = "paste";
 const ACTION_COPY_SCROLLBACK: &str = "copy-scrollback";
@@ -102,6 +108,49 @@ struct Selection {
     end: (usize, usize),
 }
 
+/// Commands seen through shell integration (OSC 133)
+#[derive(Debug, Default)]
+struct ShellCommands {
+    /// The shell has sent marks, so it reports its own commands
+    active: bool,
+    /// Command line, directory and start time of the command now running
+    running: Option<(String, Option<ReportedCwd>, std::time::Instant)>,
+    /// The last mark was B: the shell is reading a command line
+    editing: bool,
+    /// Working directory the shell last reported (OSC 7)
+    cwd: Option<ReportedCwd>,
+    /// The last command the shell reported finishing
+    finished: Option<FinishedCommand>,
+    /// The shell reported vi key bindings for its line editor
+    vi_keys: bool,
+}
+
+/// A command the shell reported finishing (OSC 133)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinishedCommand {
+    /// Counts up with every finished command in the session
+    pub serial: u64,
+    pub command: String,
+    pub exit_code: Option<i32>,
+}
+
+/// Working directory reported by the shell through OSC 7
+#[derive(Debug, Clone)]
+struct ReportedCwd {
+    path: std::path::PathBuf,
+    /// Host name when the directory is on another machine (e.g. over ssh)
+    remote_host: Option<String>,
+}
+
+/// Programs asking to read the clipboard (OSC 52)
+#[derive(Debug, Default)]
+struct ClipboardReads {
+    /// Answer the user chose to keep for this session
+    remembered: Option<bool>,
+    /// Request waiting on the prompt: primary selection, reply
+    pending: Option<(bool, corgiterm_core::ClipboardReply)>,
+}
+
 /// Default ANSI colors (fallback if theme not loaded)
 const DEFAULT_COLORS: [(f64, f64, f64); 16] = [
     // Standard colors 0-7
@@ -224,6 +273,10 @@ pub struct TerminalView {
     hint_mode: Rc<RefCell<HintModeState>>,
     /// Hint detector for scanning terminal buffer
     hint_detector: Rc<HintDetector>,
+    /// Shell integration command tracking
+    shell_commands: Rc<RefCell<ShellCommands>>,
+    /// Session id in command and output history
+    session_id: Rc<str>,
 }
 
 impl TerminalView {
@@ -256,20 +309,43 @@ impl TerminalView {
         let event_rx = Rc::new(event_rx);
 
         // Create PTY and spawn shell
-        let (shell, term) = crate::app::config_manager()
+        let (shell, term, shell_integration, check_typed) = crate::app::config_manager()
             .map(|cm| {
                 let config = cm.read().config();
-                (config.general.shell.clone(), config.terminal.term.clone())
+                (
+                    config.general.shell.clone(),
+                    config.terminal.term.clone(),
+                    config.terminal.shell_integration,
+                    config.safe_mode.check_typed,
+                )
             })
             .unwrap_or_else(|| {
                 (
                     std::env::var("SHELL").unwrap_or("/bin/bash".to_string()),
                     "xterm-256color".to_string(),
+                    true,
+                    false,
                 )
             });
+        let shell_integration = if !shell_integration {
+            corgiterm_core::shell_integration::Level::Off
+        } else if check_typed || crate::app::run_options().safe_mode {
+            corgiterm_core::shell_integration::Level::CheckCommands
+        } else {
+            corgiterm_core::shell_integration::Level::Marks
+        };
         let pty = Rc::new(RefCell::new(None));
+        // Names this terminal in command and output history; shell PIDs
+        // are reused, so they cannot tell old terminals from new ones
+        let session_id: Rc<str> = Rc::from(uuid::Uuid::new_v4().to_string());
         {
-            match Pty::spawn(Some(&shell), PtySize::default(), working_dir, Some(&term)) {
+            match Pty::spawn_shell(
+                Some(&shell),
+                PtySize::default(),
+                working_dir,
+                Some(&term),
+                shell_integration,
+            ) {
                 Ok(p) => {
                     *pty.borrow_mut() = Some(p);
                     tracing::info!("PTY spawned successfully");
@@ -315,6 +391,10 @@ impl TerminalView {
         // Hint mode state for URL/path keyboard navigation (foot-style)
         let hint_mode: Rc<RefCell<HintModeState>> = Rc::new(RefCell::new(HintModeState::new()));
         let hint_detector: Rc<HintDetector> = Rc::new(HintDetector::new());
+        let shell_commands = Rc::new(RefCell::new(ShellCommands::default()));
+        // Cairo surfaces for the inline images on screen, by image ID
+        let image_surfaces: Rc<RefCell<HashMap<corgiterm_core::ImageId, cairo::ImageSurface>>> =
+            Rc::new(RefCell::new(HashMap::new()));
 
         // Set up drawing callback with Pango for text rendering
         let term_for_draw = terminal.clone();
@@ -330,7 +410,8 @@ impl TerminalView {
         let pty_cols_for_draw = pty_cols.clone();
         let colors_for_draw = colors.clone();
         let hint_mode_for_draw = hint_mode.clone();
-        drawing_area.set_draw_func(move |area, cr, _width, _height| {
+        let image_surfaces_for_draw = image_surfaces.clone();
+        drawing_area.set_draw_func(move |area, cr, width, height| {
             // Use cached theme colors (updated via reload_theme_colors())
             let current_colors = *colors_for_draw.borrow();
             let engine = term_for_draw.borrow();
@@ -488,16 +569,66 @@ impl TerminalView {
                 }
             };
 
+            // Command block gutter: a bar beside each command and its output,
+            // green on success, red on failure, dim while running or unknown.
+            let blocks = engine.command_blocks();
+            if !blocks.is_empty() {
+                for (row, line) in engine.viewport_lines().into_iter().enumerate() {
+                    let Some(block) = blocks.block_at(line).map(|i| &blocks.blocks()[i]) else {
+                        continue;
+                    };
+                    // The prompt still waiting for a command has no bar yet
+                    if block.output_line.is_none() {
+                        continue;
+                    }
+                    let (r, g, b) = match block.succeeded() {
+                        Some(true) => current_colors[2],
+                        Some(false) => current_colors[1],
+                        None => current_colors[8],
+                    };
+                    cr.set_source_rgb(r, g, b);
+                    let y = padding + row as f64 * cell_h;
+                    // A gap above each prompt separates neighbouring blocks
+                    let gap = if line == block.prompt_line { 2.0 } else { 0.0 };
+                    cr.rectangle(2.0, y + gap, 3.0, cell_h - gap);
+                    cr.fill().ok();
+                }
+            }
+
+            // Explicit OSC 8 hyperlinks first, so they win over URLs in their text
+            let mut urls: Vec<DetectedUrl> = if hyperlinks_enabled() {
+                corgiterm_core::hyperlink_spans(&cells)
+                    .into_iter()
+                    .filter(|span| span.link.is_openable())
+                    .map(|span| DetectedUrl {
+                        url: span.link.uri().to_string(),
+                        row: span.row,
+                        start_col: span.col_start,
+                        end_col: span.col_end.saturating_sub(1),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let links = urls.len();
+
             // Detect URLs in the visible rows (engine provides row text directly).
-            let mut urls = Vec::new();
             for (row_idx, line_text) in rows_text.iter().enumerate() {
                 for mat in URL_REGEX.find_iter(line_text) {
-                    urls.push(DetectedUrl {
-                        url: mat.as_str().to_string(),
-                        row: row_idx,
-                        start_col: mat.start(),
-                        end_col: mat.end().saturating_sub(1),
+                    let (start_col, end_col) = (mat.start(), mat.end().saturating_sub(1));
+                    let inside_link = urls[..links].iter().any(|link| {
+                        link.row == row_idx
+                            && start_col <= link.end_col
+                            && end_col >= link.start_col
                     });
+                    if !inside_link {
+                        urls.push(DetectedUrl {
+                            url: mat.as_str().to_string(),
+                            row: row_idx,
+                            start_col,
+                            end_col,
+                        });
+                    }
                 }
             }
             *detected_urls_for_draw.borrow_mut() = urls.clone();
@@ -570,6 +701,50 @@ impl TerminalView {
                 }
             }
 
+            // Inline images (kitty graphics, sixel), over the cells they cover
+            let images = engine.visible_images();
+            let mut surfaces = image_surfaces_for_draw.borrow_mut();
+            surfaces.retain(|id, _| images.iter().any(|visible| visible.image.id == *id));
+            for visible in &images {
+                let image = visible.image;
+                if !surfaces.contains_key(&image.id) {
+                    let Some(surface) = image_surface(image) else {
+                        continue;
+                    };
+                    surfaces.insert(image.id, surface);
+                }
+                let box_w = visible.width_cells as f64 * cell_w;
+                let box_h = visible.height_cells as f64 * cell_h;
+                let (image_w, image_h) = (image.width as f64, image.height as f64);
+                // Images sized by their pixels keep them; images given a size
+                // in cells are scaled to fill it
+                let natural = (box_w - cell_w..=box_w).contains(&image_w)
+                    && (box_h - cell_h..=box_h).contains(&image_h);
+                let (scale_x, scale_y) = if natural {
+                    (1.0, 1.0)
+                } else {
+                    (box_w / image_w, box_h / image_h)
+                };
+                cr.save().ok();
+                // Rows above the view are cut off at the top padding
+                cr.rectangle(
+                    0.0,
+                    padding,
+                    width as f64,
+                    (height as f64 - padding).max(0.0),
+                );
+                cr.clip();
+                cr.translate(
+                    padding + visible.col as f64 * cell_w,
+                    padding + visible.row as f64 * cell_h,
+                );
+                cr.scale(scale_x, scale_y);
+                cr.set_source_surface(&surfaces[&image.id], 0.0, 0.0).ok();
+                cr.paint().ok();
+                cr.restore().ok();
+            }
+            drop(surfaces);
+
             // Cursor. The engine reports visibility (DECTCEM hide, scrolled out of
             // view); we additionally honor the blink phase from config.
             let cursor_is_visible = *cursor_visible_for_draw.borrow();
@@ -738,6 +913,10 @@ impl TerminalView {
             if cell_w <= 0.0 || cell_h <= 0.0 {
                 return;
             }
+            // Images sent in pixels are sized in cells of this size
+            term_for_resize
+                .borrow_mut()
+                .set_cell_pixel_size(cell_w as u32, cell_h as u32);
 
             // Calculate new terminal dimensions
             let padding = 8.0;
@@ -819,12 +998,20 @@ impl TerminalView {
         let pty_for_input = pty.clone();
         let drawing_area_for_clipboard = drawing_area.clone();
         let terminal_for_copy = terminal.clone();
+        let shell_commands_for_paste = shell_commands.clone();
         let terminal_for_key = terminal.clone();
         let scroll_offset_for_key = scroll_offset.clone();
         let hint_mode_for_key = hint_mode.clone();
         let hint_detector_for_key = hint_detector.clone();
         let drawing_area_for_hint = drawing_area.clone();
-        key_controller.connect_key_pressed(move |_, key, _keycode, modifier| {
+        // Hardware keycodes whose press reached the program, so it also gets
+        // their repeats and releases
+        let forwarded_keys: Rc<RefCell<std::collections::HashSet<u32>>> = Rc::default();
+        let forwarded_keys_for_press = forwarded_keys.clone();
+        // Project history being stepped through on the prompt
+        let project_recall: Rc<RefCell<Option<HistoryRecall>>> = Rc::default();
+        let session_for_recall = session_id.clone();
+        key_controller.connect_key_pressed(move |controller, key, keycode, modifier| {
             use gtk4::gdk::Key;
 
             // Handle hint mode keyboard interaction
@@ -853,7 +1040,8 @@ impl TerminalView {
 
                                 // Execute action based on hint type
                                 match hint_type {
-                                    corgiterm_core::HintType::Url => {
+                                    corgiterm_core::HintType::Url
+                                    | corgiterm_core::HintType::Hyperlink => {
                                         // Open URL in browser
                                         if let Err(e) = open::that(&text) {
                                             tracing::warn!("Failed to open URL {}: {}", text, e);
@@ -895,12 +1083,40 @@ impl TerminalView {
 
             let shortcuts = KeyboardShortcuts::current();
 
+            let older = shortcuts.matches(ShortcutAction::PreviousProjectCommand, key, modifier);
+            if older || shortcuts.matches(ShortcutAction::NextProjectCommand, key, modifier) {
+                recall_project_command(
+                    &pty_for_input,
+                    &session_for_recall,
+                    &shell_commands_for_paste,
+                    &terminal_for_key,
+                    &project_recall,
+                    older,
+                );
+                return glib::Propagation::Stop;
+            }
+            // Any other key ends the recall
+            if !is_modifier_key(key) {
+                project_recall.borrow_mut().take();
+            }
+
             if shortcuts.matches(ShortcutAction::ActivateHints, key, modifier) {
-                // Collect visible lines as strings
-                let lines: Vec<String> = terminal_for_key.borrow().rows_text();
+                // Collect visible lines as strings, plus any OSC 8 hyperlinks
+                let (lines, hyperlinks) = {
+                    let engine = terminal_for_key.borrow();
+                    let hyperlinks = if hyperlinks_enabled() {
+                        corgiterm_core::hyperlink_spans(&engine.render_cells())
+                            .into_iter()
+                            .filter(|span| span.link.is_openable())
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    (engine.rows_text(), hyperlinks)
+                };
 
                 // Scan for hints
-                let hints = hint_detector_for_key.scan(&lines);
+                let hints = hint_detector_for_key.scan_with_hyperlinks(&lines, &hyperlinks);
 
                 if !hints.is_empty() {
                     tracing::info!("Hint mode activated: {} hints found", hints.len());
@@ -938,14 +1154,14 @@ impl TerminalView {
             if shortcuts.matches(ShortcutAction::Paste, key, modifier) {
                 // Handle paste from clipboard
                 let clipboard = drawing_area_for_clipboard.clipboard();
+                let drawing_area = drawing_area_for_clipboard.clone();
                 let pty_clone = pty_for_input.clone();
+                let terminal = terminal_for_copy.clone();
+                let shell_commands = shell_commands_for_paste.clone();
 
                 clipboard.read_text_async(None::<&gtk4::gio::Cancellable>, move |result| {
                     if let Ok(Some(text)) = result {
-                        if let Some(ref pty) = *pty_clone.borrow() {
-                            // Write clipboard text to PTY
-                            let _ = pty.write(text.as_bytes());
-                        }
+                        paste_text(&drawing_area, &pty_clone, &terminal, &shell_commands, &text);
                     }
                 });
 
@@ -992,9 +1208,77 @@ impl TerminalView {
                 return glib::Propagation::Stop;
             }
 
+            let previous_prompt = shortcuts.matches(ShortcutAction::PreviousPrompt, key, modifier);
+            if previous_prompt || shortcuts.matches(ShortcutAction::NextPrompt, key, modifier) {
+                let mut term = terminal_for_copy.borrow_mut();
+                let top = term.viewport_lines().first().copied().unwrap_or(0);
+                let blocks = term.command_blocks();
+                let target = if previous_prompt {
+                    blocks.previous_prompt(top)
+                } else {
+                    blocks.next_prompt(top)
+                };
+                match target {
+                    Some(line) => term.scroll_to_line(line),
+                    // Past the last prompt: back to the live output
+                    None if !previous_prompt => term.scroll_to_bottom(),
+                    None => {}
+                }
+                *scroll_offset_for_key.borrow_mut() = term.display_offset();
+                drawing_area_for_clipboard.queue_draw();
+                return glib::Propagation::Stop;
+            }
+
+            if shortcuts.matches(ShortcutAction::CopyLastOutput, key, modifier) {
+                let term = terminal_for_copy.borrow();
+                let blocks = term.command_blocks();
+                let output = blocks
+                    .last_finished()
+                    .and_then(|index| blocks.blocks()[index].output_range());
+                if let Some(range) = output {
+                    let lines = term.all_text();
+                    let end = range.end.min(lines.len());
+                    let text = lines[range.start.min(end)..end].join("\n");
+                    drawing_area_for_clipboard
+                        .clipboard()
+                        .set_text(text.trim_end());
+                    tracing::info!("Copied {} lines of command output", range.len());
+                } else {
+                    tracing::debug!("No finished command to copy output from");
+                }
+                return glib::Propagation::Stop;
+            }
+
+            if shortcuts.matches(ShortcutAction::ToggleOutputCollapse, key, modifier) {
+                let mut term = terminal_for_copy.borrow_mut();
+                // At the bottom, act on the last command; scrolled up, on the
+                // command at the top of the view
+                let blocks = term.command_blocks();
+                let index = if term.display_offset() == 0 {
+                    blocks.last_finished()
+                } else {
+                    term.viewport_lines()
+                        .first()
+                        .and_then(|&line| blocks.block_at(line))
+                };
+                if let Some(index) = index {
+                    if term.toggle_block_collapsed(index) {
+                        *scroll_offset_for_key.borrow_mut() = term.display_offset();
+                        drawing_area_for_clipboard.queue_draw();
+                    }
+                }
+                return glib::Propagation::Stop;
+            }
+
             if let Some(ref pty) = *pty_for_input.borrow() {
-                // Convert GDK key to bytes
-                let bytes = key_to_bytes(key, modifier);
+                let kind = if forwarded_keys_for_press.borrow_mut().insert(keycode) {
+                    corgiterm_core::KeyEventKind::Press
+                } else {
+                    corgiterm_core::KeyEventKind::Repeat
+                };
+                let event = key_event(controller, key, keycode, modifier, kind);
+                let flags = terminal_for_key.borrow().keyboard_flags();
+                let bytes = corgiterm_core::encode_key(&event, flags);
                 if !bytes.is_empty() {
                     // Scroll to bottom on keystroke if enabled
                     let scroll_on_keystroke = crate::app::config_manager()
@@ -1008,6 +1292,32 @@ impl TerminalView {
             }
             glib::Propagation::Stop
         });
+        // Releases only reach programs that asked for them through the
+        // kitty keyboard protocol
+        let pty_for_release = pty.clone();
+        let terminal_for_release = terminal.clone();
+        key_controller.connect_key_released(move |controller, key, keycode, modifier| {
+            if !forwarded_keys.borrow_mut().remove(&keycode) {
+                return;
+            }
+            let flags = terminal_for_release.borrow().keyboard_flags();
+            if !flags.report_event_types {
+                return;
+            }
+            if let Some(ref pty) = *pty_for_release.borrow() {
+                let event = key_event(
+                    controller,
+                    key,
+                    keycode,
+                    modifier,
+                    corgiterm_core::KeyEventKind::Release,
+                );
+                let bytes = corgiterm_core::encode_key(&event, flags);
+                if !bytes.is_empty() {
+                    let _ = pty.write(&bytes);
+                }
+            }
+        });
         drawing_area.add_controller(key_controller);
 
         // Add motion controller for URL hover tracking
@@ -1092,7 +1402,9 @@ impl TerminalView {
         right_click_gesture.set_button(3); // Right mouse button
         let drawing_area_for_context = drawing_area.clone();
         let terminal_for_context = terminal.clone();
+        let shell_commands_for_context = shell_commands.clone();
         let pty_for_context = pty.clone();
+        let session_for_context = session_id.clone();
         let container_for_context = container.clone();
         let cell_width_for_context = cell_width.clone();
         let cell_height_for_context = cell_height.clone();
@@ -1156,14 +1468,17 @@ impl TerminalView {
             let paste_action = SimpleAction::new(ACTION_PASTE, None);
             let pty_paste = pty_for_context.clone();
             let da_paste = drawing_area_for_context.clone();
+            let terminal_paste = terminal_for_context.clone();
+            let shell_commands_paste = shell_commands_for_context.clone();
             paste_action.connect_activate(move |_, _| {
                 let clipboard = da_paste.clipboard();
+                let drawing_area = da_paste.clone();
                 let pty_clone = pty_paste.clone();
+                let terminal = terminal_paste.clone();
+                let shell_commands = shell_commands_paste.clone();
                 clipboard.read_text_async(None::<&gtk4::gio::Cancellable>, move |result| {
                     if let Ok(Some(text)) = result {
-                        if let Some(ref pty) = *pty_clone.borrow() {
-                            let _ = pty.write(text.as_bytes());
-                        }
+                        paste_text(&drawing_area, &pty_clone, &terminal, &shell_commands, &text);
                     }
                 });
             });
@@ -1255,12 +1570,12 @@ impl TerminalView {
 
             let insert_snippet_action = SimpleAction::new(ACTION_INSERT_SNIPPET, None);
             let parent_for_snippet = parent_window.clone();
-            let pty_for_snippet = pty_for_context.clone();
+            let session_for_snippet = session_for_context.clone();
             insert_snippet_action.connect_activate(move |_, _| {
                 if let Some(parent) = parent_for_snippet.clone() {
-                    let pty = pty_for_snippet.clone();
+                    let session = session_for_snippet.clone();
                     crate::snippets::show_quick_insert_dialog(&parent, move |snippet| {
-                        if !write_terminal_command(&pty, &snippet) {
+                        if !submit_to_pane(&session, CommandOrigin::Snippet, &snippet) {
                             tracing::warn!("No active terminal for snippet insertion");
                         }
                     });
@@ -1272,13 +1587,18 @@ impl TerminalView {
             let insert_ascii_art_action = SimpleAction::new(ACTION_INSERT_ASCII_ART, None);
             let parent_for_ascii = parent_window.clone();
             let pty_for_ascii = pty_for_context.clone();
+            let da_ascii = drawing_area_for_context.clone();
+            let terminal_ascii = terminal_for_context.clone();
+            let shell_commands_ascii = shell_commands_for_context.clone();
             insert_ascii_art_action.connect_activate(move |_, _| {
                 if let Some(parent) = parent_for_ascii.clone() {
                     let pty = pty_for_ascii.clone();
+                    let drawing_area = da_ascii.clone();
+                    let terminal = terminal_ascii.clone();
+                    let shell_commands = shell_commands_ascii.clone();
+                    // Art is text, not a command: it goes in like a paste
                     crate::dialogs::show_ascii_art_dialog(&parent, move |art| {
-                        if !write_terminal_command(&pty, art) {
-                            tracing::warn!("No active terminal for ASCII art insertion");
-                        }
+                        paste_text(&drawing_area, &pty, &terminal, &shell_commands, art);
                     });
                 }
             });
@@ -1288,14 +1608,25 @@ impl TerminalView {
             let insert_history_action = SimpleAction::new(ACTION_INSERT_HISTORY, None);
             let parent_for_history = parent_window.clone();
             let pty_for_history = pty_for_context.clone();
+            let session_for_history = session_for_context.clone();
+            let shell_commands_for_history = shell_commands_for_context.clone();
             insert_history_action.connect_activate(move |_, _| {
                 if let Some(parent) = parent_for_history.clone() {
-                    let pty = pty_for_history.clone();
-                    crate::history_search::show_history_search_dialog(&parent, move |command| {
-                        if !write_terminal_command(&pty, command) {
-                            tracing::warn!("No active terminal for history insertion");
-                        }
-                    });
+                    let context = history_context(
+                        &pty_for_history,
+                        &session_for_history,
+                        &shell_commands_for_history,
+                    );
+                    let session = session_for_history.clone();
+                    crate::history_search::show_history_search_dialog(
+                        &parent,
+                        &context,
+                        move |command| {
+                            if !submit_to_pane(&session, CommandOrigin::History, command) {
+                                tracing::warn!("No active terminal for history insertion");
+                            }
+                        },
+                    );
                 }
             });
             insert_history_action.set_enabled(parent_window.is_some());
@@ -1370,7 +1701,71 @@ impl TerminalView {
         let scroll_offset_for_reset = scroll_offset.clone();
         let event_rx_for_poll = event_rx.clone();
         let pty_for_events = pty.clone();
+        let session_for_events = session_id.clone();
         let bell_flash_for_events = bell_flash.clone();
+        let shell_commands_for_events = shell_commands.clone();
+
+        // Non-modal prompt for programs asking to read the clipboard (OSC 52);
+        // the terminal stays usable while it is shown
+        let clipboard_reads = Rc::new(RefCell::new(ClipboardReads::default()));
+        let clipboard_bar = Box::new(Orientation::Horizontal, 8);
+        clipboard_bar.set_margin_start(8);
+        clipboard_bar.set_margin_end(8);
+        clipboard_bar.set_margin_top(4);
+        clipboard_bar.set_margin_bottom(4);
+        clipboard_bar.add_css_class("clipboard-prompt");
+        let clipboard_label = Label::new(None);
+        clipboard_label.set_hexpand(true);
+        clipboard_label.set_xalign(0.0);
+        clipboard_bar.append(&clipboard_label);
+        let clipboard_remember = CheckButton::with_label("Remember for this session");
+        clipboard_bar.append(&clipboard_remember);
+        let clipboard_allow = Button::with_label("Allow");
+        clipboard_allow.add_css_class("suggested-action");
+        clipboard_bar.append(&clipboard_allow);
+        let clipboard_deny = Button::with_label("Deny");
+        clipboard_bar.append(&clipboard_deny);
+
+        let clipboard_revealer = Revealer::new();
+        clipboard_revealer.set_transition_type(RevealerTransitionType::SlideDown);
+        clipboard_revealer.set_transition_duration(150);
+        clipboard_revealer.set_child(Some(&clipboard_bar));
+        clipboard_revealer.set_reveal_child(false);
+        container.append(&clipboard_revealer);
+
+        for (button, allowed) in [(&clipboard_allow, true), (&clipboard_deny, false)] {
+            let reads = clipboard_reads.clone();
+            let revealer = clipboard_revealer.clone();
+            let remember = clipboard_remember.clone();
+            let da = drawing_area.clone();
+            let pty_for_answer = pty.clone();
+            let session_for_answer = session_id.clone();
+            button.connect_clicked(move |_| {
+                revealer.set_reveal_child(false);
+                let pending = {
+                    let mut reads = reads.borrow_mut();
+                    if remember.is_active() {
+                        reads.remembered = Some(allowed);
+                    }
+                    reads.pending.take()
+                };
+                remember.set_active(false);
+                if let Some((primary, reply)) = pending {
+                    answer_clipboard_read(
+                        &da,
+                        &pty_for_answer,
+                        &session_for_answer,
+                        primary,
+                        reply,
+                        allowed,
+                    );
+                }
+                da.grab_focus();
+            });
+        }
+
+        let clipboard_reads_for_events = clipboard_reads.clone();
+        let clipboard_revealer_for_events = clipboard_revealer.clone();
 
         // Create channel for PTY data (background thread -> main loop)
         let (pty_data_tx, pty_data_rx) = crossbeam_channel::unbounded::<Vec<u8>>();
@@ -1418,6 +1813,14 @@ impl TerminalView {
             });
         }
 
+        // Finished output lines are indexed for global search
+        let mut output_indexer = pty
+            .borrow()
+            .as_ref()
+            .map(|_| OutputIndexer::new(session_id.to_string()));
+        let mut output_pending = false;
+        let mut output_indexed_at = std::time::Instant::now();
+
         // Poll channel for PTY data in GTK main loop (non-blocking)
         glib::timeout_add_local(std::time::Duration::from_millis(16), move || {
             // Feed any PTY data received from the reader thread. alacritty_terminal
@@ -1428,6 +1831,7 @@ impl TerminalView {
                 received_any = true;
             }
             if received_any {
+                output_pending = true;
                 let scroll_on_output = crate::app::config_manager()
                     .map(|cm| cm.read().config().terminal.scroll_on_output)
                     .unwrap_or(false);
@@ -1440,6 +1844,7 @@ impl TerminalView {
 
             // Drain terminal events: forward PTY replies (DSR, bracketed-paste acks,
             // device attributes) back to the child, and flash the visual bell.
+            let mut command_finished = false;
             while let Ok(event) = event_rx_for_poll.try_recv() {
                 match event {
                     corgiterm_core::TerminalEvent::PtyWrite(bytes) => {
@@ -1460,9 +1865,168 @@ impl TerminalView {
                         );
                         drawing_area_clone.queue_draw();
                     }
+                    corgiterm_core::TerminalEvent::OutputStart { command } => {
+                        let mut commands = shell_commands_for_events.borrow_mut();
+                        commands.active = true;
+                        commands.editing = false;
+                        let cwd = commands.cwd.clone();
+                        commands.running = command
+                            .filter(|c| !c.trim().is_empty())
+                            .map(|c| (c, cwd, std::time::Instant::now()));
+                    }
+                    corgiterm_core::TerminalEvent::CommandFinished { exit_code } => {
+                        command_finished = true;
+                        let running = {
+                            let mut commands = shell_commands_for_events.borrow_mut();
+                            commands.editing = false;
+                            let running = commands.running.take();
+                            if let Some((command, _, _)) = &running {
+                                let serial = commands.finished.as_ref().map_or(0, |f| f.serial);
+                                commands.finished = Some(FinishedCommand {
+                                    serial: serial + 1,
+                                    command: command.clone(),
+                                    exit_code,
+                                });
+                            }
+                            running
+                        };
+                        if let Some((command, cwd, started)) = running {
+                            let pty = pty_for_events.borrow();
+                            let session = Some(session_for_events.to_string());
+                            let host = pty.as_ref().and_then(|pty| session_host(cwd.as_ref(), pty));
+                            // Without OSC 7, the command is done, so the
+                            // foreground process is the shell again
+                            let directory = cwd
+                                .map(|cwd| cwd.path)
+                                .or_else(|| pty.as_ref().and_then(pty_working_directory))
+                                .map(|p| p.display().to_string())
+                                .unwrap_or_default();
+                            crate::app::record_command(
+                                command,
+                                directory,
+                                session,
+                                host,
+                                exit_code,
+                                Some(started.elapsed().as_millis() as u64),
+                            );
+                        }
+                    }
+                    corgiterm_core::TerminalEvent::PromptStart => {
+                        let mut commands = shell_commands_for_events.borrow_mut();
+                        commands.active = true;
+                        commands.editing = false;
+                    }
+                    corgiterm_core::TerminalEvent::CommandStart => {
+                        let mut commands = shell_commands_for_events.borrow_mut();
+                        commands.active = true;
+                        commands.editing = true;
+                    }
+                    corgiterm_core::TerminalEvent::Clipboard(
+                        corgiterm_core::ClipboardAction::Paste { primary, reply },
+                    ) => {
+                        let remembered = clipboard_reads_for_events.borrow().remembered;
+                        let policy = crate::app::config_manager()
+                            .map(|cm| cm.read().config().terminal.clipboard_read)
+                            .unwrap_or(corgiterm_config::ClipboardReadPolicy::Ask);
+                        let answer = remembered.or(match policy {
+                            corgiterm_config::ClipboardReadPolicy::Allow => Some(true),
+                            corgiterm_config::ClipboardReadPolicy::Deny => Some(false),
+                            corgiterm_config::ClipboardReadPolicy::Ask => None,
+                        });
+                        match answer {
+                            Some(allowed) => answer_clipboard_read(
+                                &drawing_area_clone,
+                                &pty_for_events,
+                                &session_for_events,
+                                primary,
+                                reply,
+                                allowed,
+                            ),
+                            None => {
+                                // A newer request replaces one still waiting
+                                let program = pty_for_events
+                                    .borrow()
+                                    .as_ref()
+                                    .and_then(foreground_program)
+                                    .unwrap_or_else(|| "A program".to_string());
+                                let source = if primary {
+                                    "primary selection"
+                                } else {
+                                    "clipboard"
+                                };
+                                clipboard_label
+                                    .set_text(&format!("{} wants to read the {}", program, source));
+                                clipboard_reads_for_events.borrow_mut().pending =
+                                    Some((primary, reply));
+                                clipboard_revealer_for_events.set_reveal_child(true);
+                            }
+                        }
+                    }
+                    corgiterm_core::TerminalEvent::CommandCheck { id, command } => {
+                        // Only the shell's line editor sends checks; the same
+                        // sequence printed by a running program is ignored
+                        let at_prompt = {
+                            let commands = shell_commands_for_events.borrow();
+                            commands.editing && commands.running.is_none()
+                        };
+                        if !at_prompt {
+                            tracing::warn!("Ignoring command check outside the shell prompt");
+                            continue;
+                        }
+                        // The shell holds the line until this check is answered
+                        let pty = pty_for_events.clone();
+                        let accept = std::boxed::Box::new(move || {
+                            if let Some(ref pty) = *pty.borrow() {
+                                let _ =
+                                    pty.write(&corgiterm_core::shell_integration::accept_line(&id));
+                            }
+                        });
+                        let (host, cwd, profile) =
+                            session_profile(&pty_for_events, &shell_commands_for_events);
+                        match crate::command_gateway::gateway() {
+                            Some(gateway) => {
+                                gateway.check_typed(
+                                    &command,
+                                    host.as_deref(),
+                                    cwd,
+                                    &profile,
+                                    accept,
+                                );
+                            }
+                            None => accept(),
+                        }
+                    }
+                    corgiterm_core::TerminalEvent::CwdChanged { host, path } => {
+                        let remote_host = host.filter(|host| {
+                            !corgiterm_core::shell_integration::is_local_host(Some(host))
+                        });
+                        shell_commands_for_events.borrow_mut().cwd =
+                            Some(ReportedCwd { path, remote_host });
+                    }
+                    corgiterm_core::TerminalEvent::EditingMode { vi } => {
+                        shell_commands_for_events.borrow_mut().vi_keys = vi;
+                    }
                     _ => {}
                 }
             }
+
+            // Index new output after each command, and every couple of
+            // seconds while output keeps coming
+            if output_pending
+                && (command_finished || output_indexed_at.elapsed() >= OUTPUT_INDEX_INTERVAL)
+            {
+                output_pending = false;
+                output_indexed_at = std::time::Instant::now();
+                let index_output = crate::app::config_manager()
+                    .map(|cm| cm.read().config().history.index_output)
+                    .unwrap_or(true);
+                if let Some(indexer) = output_indexer.as_mut().filter(|_| index_output) {
+                    let chunks = indexer.collect(&*term_for_read.borrow());
+                    if !chunks.is_empty() {
+                        crate::app::record_output(chunks);
+                    }
+                }
+            }
             glib::ControlFlow::Continue
         });
 
@@ -1777,6 +2341,8 @@ impl TerminalView {
             pty_cols,
             hint_mode,
             hint_detector,
+            shell_commands,
+            session_id,
         }
     }
 
@@ -1825,16 +2391,29 @@ impl TerminalView {
     /// Send a command to the terminal (appends newline)
     pub fn send_command(&self, command: &str) {
         if let Some(ref pty) = *self.pty.borrow() {
-            // Record command for AI learning
-            let directory = self
-                .working_directory()
-                .map(|p| p.display().to_string())
-                .unwrap_or_else(|| {
-                    std::env::current_dir()
-                        .map(|p| p.display().to_string())
-                        .unwrap_or_default()
-                });
-            crate::app::record_command(command.to_string(), directory, None);
+            // Record command for AI learning, unless shell integration will
+            // report it along with its exit code
+            if !self.shell_commands.borrow().active {
+                let reported = self.reported_cwd();
+                let host = session_host(reported.as_ref(), pty);
+                let directory = reported
+                    .map(|cwd| cwd.path)
+                    .or_else(|| pty_working_directory(pty))
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| {
+                        std::env::current_dir()
+                            .map(|p| p.display().to_string())
+                            .unwrap_or_default()
+                    });
+                crate::app::record_command(
+                    command.to_string(),
+                    directory,
+                    Some(self.session_id.to_string()),
+                    host,
+                    None,
+                    None,
+                );
+            }
 
             // Send command with newline
             let cmd_with_newline = format!("{}\n", command);
@@ -1846,6 +2425,41 @@ impl TerminalView {
         }
     }
 
+    /// Session id used in command and output history, made once per
+    /// terminal view
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// The session, project and host this terminal's history is scoped to
+    pub fn history_context(&self) -> crate::history_search::HistoryContext {
+        history_context(&self.pty, &self.session_id, &self.shell_commands)
+    }
+
+    /// Whether the shell reports its commands through shell integration
+    pub fn reports_commands(&self) -> bool {
+        self.shell_commands.borrow().active
+    }
+
+    /// The last command the shell reported finishing, with its exit code
+    pub fn finished_command(&self) -> Option<FinishedCommand> {
+        self.shell_commands.borrow().finished.clone()
+    }
+
+    /// Scroll the indexed output line `serial` to the top of the view
+    ///
+    /// Returns false if the line has already left the scrollback.
+    pub fn reveal_line(&self, serial: usize) -> bool {
+        let mut term = self.terminal.borrow_mut();
+        let Some(line) = serial.checked_sub(term.lines_evicted()) else {
+            return false;
+        };
+        term.scroll_to_line(line);
+        *self.scroll_offset.borrow_mut() = term.display_offset();
+        self.drawing_area.queue_draw();
+        true
+    }
+
     /// Send raw bytes to the terminal (no newline added)
     pub fn send_bytes(&self, bytes: &[u8]) {
         if let Some(ref pty) = *self.pty.borrow() {
@@ -1855,74 +2469,49 @@ impl TerminalView {
         }
     }
 
-    /// Get current working directory from terminal (if available)
-    pub fn working_directory(&self) -> Option<std::path::PathBuf> {
-        if let Some(ref pty) = *self.pty.borrow() {
-            // Try to get foreground process group first (the actual running command)
-            // If that fails, fall back to the shell PID
-            let pid = pty.foreground_pid().unwrap_or_else(|| pty.pid());
+    fn reported_cwd(&self) -> Option<ReportedCwd> {
+        self.shell_commands.borrow().cwd.clone()
+    }
 
-            #[cfg(target_os = "linux")]
-            {
-                // Linux: Read /proc/<pid>/cwd symlink
-                let proc_cwd = format!("/proc/{}/cwd", pid);
-                match std::fs::read_link(&proc_cwd) {
-                    Ok(path) => Some(path),
-                    Err(e) => {
-                        tracing::debug!("Failed to read {}: {}", proc_cwd, e);
-                        None
-                    }
-                }
-            }
+    /// Get current working directory on this machine (if available)
+    ///
+    /// Uses the shell's OSC 7 report, falling back to the foreground process's
+    /// cwd when the shell never sent one. `None` while the session is on
+    /// another machine.
+    pub fn working_directory(&self) -> Option<std::path::PathBuf> {
+        session_location(&self.pty, &self.shell_commands).1
+    }
 
-            #[cfg(target_os = "macos")]
-            {
-                // macOS: Use lsof to get the cwd of the process
-                use std::process::Command;
-                match Command::new("lsof")
-                    .args(["-a", "-p", &pid.to_string(), "-d", "cwd", "-Fn"])
-                    .output()
-                {
-                    Ok(output) => {
-                        if output.status.success() {
-                            // Parse lsof output: lines starting with 'n' contain the path
-                            let output_str = String::from_utf8_lossy(&output.stdout);
-                            for line in output_str.lines() {
-                                if let Some(path) = line.strip_prefix('n') {
-                                    return Some(std::path::PathBuf::from(path));
-                                }
-                            }
-                        }
-                        None
-                    }
-                    Err(e) => {
-                        tracing::debug!("Failed to run lsof for pid {}: {}", pid, e);
-                        None
-                    }
-                }
-            }
+    /// The Safe Mode profile for this terminal's SSH host or project
+    pub fn safe_mode_profile(&self) -> String {
+        self.safe_mode_session().2
+    }
 
-            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-            {
-                // Fallback for other platforms (Windows, etc.)
-                tracing::debug!("Working directory detection not implemented for this platform");
-                None
-            }
-        } else {
-            None
-        }
+    /// Remote host, working directory on this machine and Safe Mode profile,
+    /// for judging a command sent to this terminal
+    pub fn safe_mode_session(&self) -> (Option<String>, Option<std::path::PathBuf>, String) {
+        session_profile(&self.pty, &self.shell_commands)
     }
 
     /// Get the current directory name for display (just the last component)
+    ///
+    /// Directories on another machine are shown as `host:name`.
     pub fn current_directory_name(&self) -> String {
-        self.working_directory()
-            .and_then(|path| {
-                // Try to get just the directory name
-                path.file_name()
-                    .and_then(|name| name.to_str())
-                    .map(|s| s.to_string())
-            })
-            .unwrap_or_else(|| "Terminal".to_string())
+        let (path, host) = match self.reported_cwd() {
+            Some(cwd) => (Some(cwd.path), cwd.remote_host),
+            None => (self.working_directory(), None),
+        };
+        let name = path.and_then(|path| {
+            // Try to get just the directory name, or "/" for the root
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .or_else(|| path.has_root().then(|| "/".to_string()))
+        });
+        match (host, name) {
+            (Some(host), Some(name)) => format!("{}:{}", host, name),
+            (None, Some(name)) => name,
+            _ => "Terminal".to_string(),
+        }
     }
 
     /// Get visible lines as strings for thumbnail rendering
@@ -2021,6 +2610,13 @@ fn url_at_position(
         .map(|url| url.url.clone())
 }
 
+/// Whether OSC 8 hyperlinks are honored (`terminal.hyperlinks`)
+fn hyperlinks_enabled() -> bool {
+    crate::app::config_manager()
+        .map(|cm| cm.read().config().terminal.hyperlinks)
+        .unwrap_or(true)
+}
+
 fn emoji_font_description(font_size: f32) -> pango::FontDescription {
     let font_string = format!("{} {}", emoji_font_family_list(), font_size as u32);
     pango::FontDescription::from_string(&font_string)
@@ -2066,12 +2662,20 @@ fn write_terminal_bytes(pty_handle: &Rc<RefCell<Option<Pty>>>, bytes: &[u8]) ->
     }
 }
 
-fn write_terminal_command(pty_handle: &Rc<RefCell<Option<Pty>>>, command: &str) -> bool {
-    let mut command = command.to_string();
-    if !command.ends_with('\n') && !command.ends_with('\r') {
-        command.push('\n');
-    }
-    write_terminal_bytes(pty_handle, command.as_bytes())
+/// Submit `command` through the command gateway to the pane of `session`;
+/// returns false when that pane cannot be found
+fn submit_to_pane(session: &str, origin: CommandOrigin, command: &str) -> bool {
+    let Some(gateway) = crate::command_gateway::gateway() else {
+        return false;
+    };
+    let Some((tab, pane)) = gateway.tabs().locate_session(session) else {
+        return false;
+    };
+    let target = CommandTarget {
+        tab: Some(tab),
+        pane: Some(pane),
+    };
+    gateway.submit_to(origin, command, target, None) != Delivery::NoTerminal
 }
 
 fn add_window_forward_action(
@@ -2097,53 +2701,480 @@ fn add_window_forward_action(
     action_group.add_action(&action);
 }
 
-/// Convert GDK key press to terminal bytes
-fn key_to_bytes(key: gtk4::gdk::Key, modifier: gtk4::gdk::ModifierType) -> Vec<u8> {
-    use gtk4::gdk::Key;
-    use gtk4::gdk::ModifierType;
-
-    let ctrl = modifier.contains(ModifierType::CONTROL_MASK);
-
-    match key {
-        Key::Return | Key::KP_Enter => vec![b'\r'],
-        Key::BackSpace => vec![0x7f],
-        Key::Tab => vec![b'\t'],
-        Key::Escape => vec![0x1b],
-        Key::Up => vec![0x1b, b'[', b'A'],
-        Key::Down => vec![0x1b, b'[', b'B'],
-        Key::Right => vec![0x1b, b'[', b'C'],
-        Key::Left => vec![0x1b, b'[', b'D'],
-        Key::Home => vec![0x1b, b'[', b'H'],
-        Key::End => vec![0x1b, b'[', b'F'],
-        Key::Page_Up => vec![0x1b, b'[', b'5', b'~'],
-        Key::Page_Down => vec![0x1b, b'[', b'6', b'~'],
-        Key::Insert => vec![0x1b, b'[', b'2', b'~'],
-        Key::Delete => vec![0x1b, b'[', b'3', b'~'],
-        Key::F1 => vec![0x1b, b'O', b'P'],
-        Key::F2 => vec![0x1b, b'O', b'Q'],
-        Key::F3 => vec![0x1b, b'O', b'R'],
-        Key::F4 => vec![0x1b, b'O', b'S'],
-        Key::F5 => vec![0x1b, b'[', b'1', b'5', b'~'],
-        Key::F6 => vec![0x1b, b'[', b'1', b'7', b'~'],
-        Key::F7 => vec![0x1b, b'[', b'1', b'8', b'~'],
-        Key::F8 => vec![0x1b, b'[', b'1', b'9', b'~'],
-        Key::F9 => vec![0x1b, b'[', b'2', b'0', b'~'],
-        Key::F10 => vec![0x1b, b'[', b'2', b'1', b'~'],
-        Key::F11 => vec![0x1b, b'[', b'2', b'3', b'~'],
-        Key::F12 => vec![0x1b, b'[', b'2', b'4', b'~'],
+/// Translate a GDK key event for the core key encoder
+fn key_event(
+    controller: &EventControllerKey,
+    key: gtk4::gdk::Key,
+    keycode: u32,
+    modifier: gtk4::gdk::ModifierType,
+    kind: corgiterm_core::KeyEventKind,
+) -> corgiterm_core::KeyEvent {
+    use corgiterm_core::KeyCode;
+    use gtk4::gdk::{Key, ModifierType};
+    use gtk4::glib::translate::IntoGlib;
+
+    let display = controller.widget().map(|widget| widget.display());
+    let group = controller.group() as i32;
+    // The character the key types without Shift, in the active and the
+    // first layout
+    let unshifted = |group: i32| {
+        display
+            .as_ref()?
+            .translate_key(keycode, ModifierType::empty(), group)
+            .and_then(|(key, ..)| key.to_unicode())
+            .filter(|c| !c.is_control())
+    };
+    let typed = key.to_unicode().filter(|c| !c.is_control());
+
+    let code = match key {
+        Key::Return => Some(KeyCode::Enter),
+        Key::Tab | Key::ISO_Left_Tab => Some(KeyCode::Tab),
+        Key::BackSpace => Some(KeyCode::Backspace),
+        Key::Escape => Some(KeyCode::Escape),
+        Key::Up => Some(KeyCode::Up),
+        Key::Down => Some(KeyCode::Down),
+        Key::Left => Some(KeyCode::Left),
+        Key::Right => Some(KeyCode::Right),
+        Key::Home => Some(KeyCode::Home),
+        Key::End => Some(KeyCode::End),
+        Key::Page_Up => Some(KeyCode::PageUp),
+        Key::Page_Down => Some(KeyCode::PageDown),
+        Key::Insert => Some(KeyCode::Insert),
+        Key::Delete => Some(KeyCode::Delete),
+        Key::KP_Enter => Some(KeyCode::KeypadEnter),
+        Key::KP_Decimal => Some(KeyCode::KeypadDecimal),
+        Key::KP_Divide => Some(KeyCode::KeypadDivide),
+        Key::KP_Multiply => Some(KeyCode::KeypadMultiply),
+        Key::KP_Subtract => Some(KeyCode::KeypadSubtract),
+        Key::KP_Add => Some(KeyCode::KeypadAdd),
+        Key::KP_Equal => Some(KeyCode::KeypadEqual),
+        Key::Shift_L => Some(KeyCode::LeftShift),
+        Key::Shift_R => Some(KeyCode::RightShift),
+        Key::Control_L => Some(KeyCode::LeftControl),
+        Key::Control_R => Some(KeyCode::RightControl),
+        Key::Alt_L => Some(KeyCode::LeftAlt),
+        Key::Alt_R => Some(KeyCode::RightAlt),
+        Key::Super_L => Some(KeyCode::LeftSuper),
+        Key::Super_R => Some(KeyCode::RightSuper),
+        Key::Caps_Lock => Some(KeyCode::CapsLock),
+        Key::Num_Lock => Some(KeyCode::NumLock),
+        Key::Scroll_Lock => Some(KeyCode::ScrollLock),
         _ => {
-            if let Some(c) = key.to_unicode() {
-                if ctrl && c.is_ascii_alphabetic() {
-                    // Ctrl+letter: send control character
-                    vec![(c.to_ascii_lowercase() as u8) - b'a' + 1]
-                } else {
-                    c.to_string().into_bytes()
+            let keypad_digit = (Key::KP_0.into_glib()..=Key::KP_9.into_glib())
+                .contains(&key.into_glib())
+                .then(|| (key.into_glib() - Key::KP_0.into_glib()) as u8);
+            let function = (Key::F1.into_glib()..=Key::F35.into_glib())
+                .contains(&key.into_glib())
+                .then(|| (key.into_glib() - Key::F1.into_glib() + 1) as u8);
+            keypad_digit
+                .map(KeyCode::KeypadDigit)
+                .or(function.map(KeyCode::F))
+                .or_else(|| {
+                    unshifted(group)
+                        .or(typed)
+                        .map(|c| KeyCode::Char(c.to_lowercase().next().unwrap_or(c)))
+                })
+        }
+    };
+
+    let shift = modifier.contains(ModifierType::SHIFT_MASK);
+    let keyboard = display
+        .as_ref()
+        .and_then(|display| display.default_seat())
+        .and_then(|seat| seat.keyboard());
+    let mut event = corgiterm_core::KeyEvent {
+        code,
+        modifiers: corgiterm_core::KeyModifiers {
+            shift,
+            alt: modifier.contains(ModifierType::ALT_MASK),
+            ctrl: modifier.contains(ModifierType::CONTROL_MASK),
+            super_key: modifier.contains(ModifierType::SUPER_MASK),
+            caps_lock: modifier.contains(ModifierType::LOCK_MASK),
+            num_lock: keyboard.is_some_and(|keyboard| keyboard.is_num_locked()),
+        },
+        kind,
+        text: typed.map(String::from),
+        shifted: typed.filter(|_| shift),
+        base_layout: None,
+    };
+    if group != 0 {
+        event.base_layout = unshifted(0).filter(|&c| Some(KeyCode::Char(c)) != code);
+    }
+    event
+}
+
+/// Convert an image's RGBA pixels into a cairo surface (premultiplied ARGB in
+/// native byte order)
+fn image_surface(image: &corgiterm_core::InlineImage) -> Option<cairo::ImageSurface> {
+    let width = i32::try_from(image.width).ok()?;
+    let height = i32::try_from(image.height).ok()?;
+    let stride = cairo::Format::ARgb32.stride_for_width(image.width).ok()?;
+    let mut data = vec![0u8; stride as usize * image.height as usize];
+    let rows = image.data.chunks_exact(image.width.max(1) as usize * 4);
+    for (y, row) in rows.take(image.height as usize).enumerate() {
+        for (x, pixel) in row.chunks_exact(4).enumerate() {
+            let alpha = pixel[3] as u32;
+            let premultiplied = |channel: u8| channel as u32 * alpha / 255;
+            let argb = alpha << 24
+                | premultiplied(pixel[0]) << 16
+                | premultiplied(pixel[1]) << 8
+                | premultiplied(pixel[2]);
+            let offset = y * stride as usize + x * 4;
+            data[offset..offset + 4].copy_from_slice(&argb.to_ne_bytes());
+        }
+    }
+    cairo::ImageSurface::create_for_data(data, cairo::Format::ARgb32, width, height, stride).ok()
+}
+
+/// Answer an OSC 52 clipboard read and record it in the audit log
+fn answer_clipboard_read(
+    drawing_area: &DrawingArea,
+    pty: &Rc<RefCell<Option<Pty>>>,
+    session: &str,
+    primary: bool,
+    reply: corgiterm_core::ClipboardReply,
+    allowed: bool,
+) {
+    let record = match pty.borrow().as_ref() {
+        Some(pty) => corgiterm_core::clipboard_audit::ClipboardReadRecord {
+            timestamp: chrono::Utc::now(),
+            session: session.to_string(),
+            program: foreground_program(pty),
+            directory: pty_working_directory(pty),
+            primary,
+            allowed,
+            bytes: 0,
+        },
+        None => return,
+    };
+    let audit = |record: corgiterm_core::clipboard_audit::ClipboardReadRecord| {
+        let Some(path) = corgiterm_core::clipboard_audit::default_log_path() else {
+            return;
+        };
+        if let Err(e) = corgiterm_core::clipboard_audit::append(&path, &record) {
+            tracing::warn!(
+                "Failed to write clipboard audit log {}: {}",
+                path.display(),
+                e
+            );
+        }
+    };
+    if !allowed {
+        tracing::info!("Denied clipboard read from {}", record.session);
+        audit(record);
+        return;
+    }
+
+    let clipboard = if primary {
+        drawing_area.primary_clipboard()
+    } else {
+        drawing_area.clipboard()
+    };
+    let pty = pty.clone();
+    clipboard.read_text_async(None::<&gtk4::gio::Cancellable>, move |result| {
+        let text = result
+            .ok()
+            .flatten()
+            .map(|t| t.to_string())
+            .unwrap_or_default();
+        if let Some(ref pty) = *pty.borrow() {
+            let _ = pty.write(&reply.format(&text));
+        }
+        tracing::info!("Allowed clipboard read from {}", record.session);
+        audit(corgiterm_core::clipboard_audit::ClipboardReadRecord {
+            bytes: text.len(),
+            ..record
+        });
+    });
+}
+
+/// Paste `text` into the PTY, bracketed when the program asked for it,
+/// holding it for review first when `terminal.paste_protection` says so
+fn paste_text(
+    drawing_area: &DrawingArea,
+    pty: &Rc<RefCell<Option<Pty>>>,
+    terminal: &Rc<RefCell<AlacrittyEngine>>,
+    shell_commands: &Rc<RefCell<ShellCommands>>,
+    text: &str,
+) {
+    use corgiterm_core::paste;
+
+    let config = config_manager()
+        .map(|cm| cm.read().config().terminal)
+        .unwrap_or_default();
+    let allow_bracketed = config.bracketed_paste;
+    let bracketed = allow_bracketed && terminal.borrow().bracketed_paste();
+    let protection = config.paste_protection;
+
+    let mut inspection = paste::inspect(text, bracketed);
+    let gateway = crate::command_gateway::gateway()
+        .filter(|_| protection.review != corgiterm_config::PasteReview::Never);
+    let session = gateway.as_ref().map(|gateway| {
+        let (host, cwd, profile) = session_profile(pty, shell_commands);
+        gateway.analyze_paste(&mut inspection, host.as_deref(), cwd.clone(), &profile);
+        (host, cwd, profile)
+    });
+    if !crate::paste_review::needs_review(&protection, &inspection) {
+        write_terminal_bytes(pty, &paste::encode(text, bracketed));
+        return;
+    }
+
+    tracing::info!(
+        "Holding paste for review ({} line(s), {})",
+        inspection.lines.len(),
+        inspection.risk.label()
+    );
+    let text = if protection.strip_hidden {
+        paste::strip_hidden(text)
+    } else {
+        text.to_string()
+    };
+    // Only the lines the dialog shows as risky are dry-run
+    let impact = gateway
+        .zip(session)
+        .and_then(|(gateway, (host, cwd, profile))| {
+            gateway.paste_impact(&inspection, host.as_deref(), cwd, &profile)
+        });
+    let pty = pty.clone();
+    let terminal = terminal.clone();
+    crate::paste_review::show(drawing_area, &inspection, impact, move || {
+        // The program may have changed modes while the dialog was open
+        let bracketed = allow_bracketed && terminal.borrow().bracketed_paste();
+        write_terminal_bytes(&pty, &paste::encode(&text, bracketed));
+    });
+}
+
+/// The host a session is on when it is another machine, and otherwise its
+/// working directory on this one
+fn session_location(
+    pty: &Rc<RefCell<Option<Pty>>>,
+    shell_commands: &Rc<RefCell<ShellCommands>>,
+) -> (Option<String>, Option<std::path::PathBuf>) {
+    let reported = shell_commands.borrow().cwd.clone();
+    let pty = pty.borrow();
+    let Some(pty) = pty.as_ref() else {
+        return (None, reported.map(|cwd| cwd.path));
+    };
+    if let Some(host) = session_host(reported.as_ref(), pty) {
+        return (Some(host), None);
+    }
+    let cwd = reported
+        .map(|cwd| cwd.path)
+        .or_else(|| pty_working_directory(pty));
+    (None, cwd)
+}
+
+/// Remote host, working directory on this machine and Safe Mode profile of
+/// a session
+fn session_profile(
+    pty: &Rc<RefCell<Option<Pty>>>,
+    shell_commands: &Rc<RefCell<ShellCommands>>,
+) -> (Option<String>, Option<std::path::PathBuf>, String) {
+    let (host, cwd) = session_location(pty, shell_commands);
+    let profile = crate::command_gateway::profile_for(host.as_deref(), cwd.as_deref());
+    (host, cwd, profile)
+}
+
+/// Commands offered by project history recall
+const RECALL_LIMIT: usize = 500;
+
+/// Replace the line being edited with an older or newer command from the
+/// project's history (or this host's, outside projects)
+///
+/// The line is edited with Ctrl+E Ctrl+U and typed text, so the shell's own
+/// history is left alone. Those keys are emacs bindings, so nothing is
+/// recalled while the shell reports vi key bindings.
+fn recall_project_command(
+    pty: &Rc<RefCell<Option<Pty>>>,
+    session: &str,
+    shell_commands: &Rc<RefCell<ShellCommands>>,
+    terminal: &Rc<RefCell<AlacrittyEngine>>,
+    recall: &Rc<RefCell<Option<HistoryRecall>>>,
+    older: bool,
+) {
+    // Only at a prompt
+    if terminal.borrow().alternate_screen() || shell_commands.borrow().running.is_some() {
+        return;
+    }
+    if shell_commands.borrow().vi_keys {
+        tracing::debug!("Not recalling a command: the shell uses vi key bindings");
+        return;
+    }
+    let mut recall = recall.borrow_mut();
+    let recall = recall.get_or_insert_with(|| {
+        let context = history_context(pty, session, shell_commands);
+        let scope = match context.project {
+            Some((id, _)) => HistoryScope::Project(id),
+            None => HistoryScope::Host(context.host),
+        };
+        let commands = crate::app::history_store()
+            .map(|store| store.read().recall(&scope, RECALL_LIMIT))
+            .unwrap_or_default();
+        HistoryRecall::new(commands)
+    });
+    let recalled = if older {
+        recall.older()
+    } else {
+        recall.newer()
+    };
+    let mut line = b"\x05\x15".to_vec();
+    match recalled {
+        Some(command) => line.extend_from_slice(command.as_bytes()),
+        // Nothing older: leave the line as it is
+        None if older => return,
+        // Past the newest: back to an empty line
+        None => {}
+    }
+    if let Some(ref pty) = *pty.borrow() {
+        if let Err(e) = pty.write(&line) {
+            tracing::error!("Failed to recall command: {}", e);
+        }
+    }
+}
+
+/// Whether `key` is a modifier on its own
+fn is_modifier_key(key: gtk4::gdk::Key) -> bool {
+    use gtk4::gdk::Key;
+    matches!(
+        key,
+        Key::Shift_L
+            | Key::Shift_R
+            | Key::Control_L
+            | Key::Control_R
+            | Key::Alt_L
+            | Key::Alt_R
+            | Key::Meta_L
+            | Key::Meta_R
+            | Key::Super_L
+            | Key::Super_R
+            | Key::ISO_Level3_Shift
+    )
+}
+
+/// The session, project and host a terminal's history search is scoped to
+fn history_context(
+    pty: &Rc<RefCell<Option<Pty>>>,
+    session: &str,
+    shell_commands: &Rc<RefCell<ShellCommands>>,
+) -> crate::history_search::HistoryContext {
+    let pty = pty.borrow();
+    let Some(pty) = pty.as_ref() else {
+        return Default::default();
+    };
+    let reported = shell_commands.borrow().cwd.clone();
+    let host = session_host(reported.as_ref(), pty);
+    // Projects are folders on this machine
+    let project = host
+        .is_none()
+        .then(|| {
+            reported
+                .map(|cwd| cwd.path)
+                .or_else(|| pty_working_directory(pty))
+        })
+        .flatten()
+        .and_then(|cwd| crate::app::project_for(&cwd));
+    crate::history_search::HistoryContext {
+        session: Some(session.to_string()),
+        project,
+        host,
+    }
+}
+
+/// Name of the PTY's foreground process, e.g. `nvim`
+fn foreground_program(pty: &Pty) -> Option<String> {
+    let pid = pty.foreground_pid().unwrap_or_else(|| pty.pid());
+    #[cfg(target_os = "linux")]
+    {
+        std::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .ok()
+            .map(|name| name.trim_end().to_string())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+/// The machine the terminal is on when it is not this one: the host a
+/// remote shell reports (OSC 7), or the destination of a foreground `ssh`
+fn session_host(cwd: Option<&ReportedCwd>, pty: &Pty) -> Option<String> {
+    if let Some(host) = cwd.and_then(|cwd| cwd.remote_host.clone()) {
+        return Some(host);
+    }
+    if foreground_program(pty).as_deref() != Some("ssh") {
+        return None;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let pid = pty.foreground_pid()?;
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        let cmdline = String::from_utf8_lossy(&cmdline);
+        let args: Vec<&str> = cmdline
+            .split('\0')
+            .skip(1)
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        corgiterm_core::safe_mode_profiles::ssh_destination(&args).map(str::to_string)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Working directory of the PTY's foreground process, falling back to the shell
+fn pty_working_directory(pty: &Pty) -> Option<std::path::PathBuf> {
+    // Try to get foreground process group first (the actual running command)
+    // If that fails, fall back to the shell PID
+    let pid = pty.foreground_pid().unwrap_or_else(|| pty.pid());
+
+    #[cfg(target_os = "linux")]
+    {
+        // Linux: Read /proc/<pid>/cwd symlink
+        let proc_cwd = format!("/proc/{}/cwd", pid);
+        match std::fs::read_link(&proc_cwd) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                tracing::debug!("Failed to read {}: {}", proc_cwd, e);
+                None
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // macOS: Use lsof to get the cwd of the process
+        use std::process::Command;
+        match Command::new("lsof")
+            .args(["-a", "-p", &pid.to_string(), "-d", "cwd", "-Fn"])
+            .output()
+        {
+            Ok(output) => {
+                if output.status.success() {
+                    // Parse lsof output: lines starting with 'n' contain the path
+                    let output_str = String::from_utf8_lossy(&output.stdout);
+                    for line in output_str.lines() {
+                        if let Some(path) = line.strip_prefix('n') {
+                            return Some(std::path::PathBuf::from(path));
+                        }
+                    }
                 }
-            } else {
-                vec![]
+                None
+            }
+            Err(e) => {
+                tracing::debug!("Failed to run lsof for pid {}: {}", pid, e);
+                None
             }
         }
     }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        // Fallback for other platforms (Windows, etc.)
+        tracing::debug!("Working directory detection not implemented for this platform");
+        None
+    }
 }
 
 #[cfg(test)]
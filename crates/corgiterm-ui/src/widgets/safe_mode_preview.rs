@@ -119,6 +119,18 @@ impl SafeModePreviewWidget {
         });
         self.container.append(&risk_label);
 
+        // Profile, when the session's project or host chose a stricter one
+        if let Some(profile) = preview
+            .profile
+            .as_deref()
+            .filter(|profile| *profile != corgiterm_core::DEFAULT_PROFILE)
+        {
+            let profile_label = Label::new(Some(&format!("Safe Mode profile: {}", profile)));
+            profile_label.set_xalign(0.0);
+            profile_label.add_css_class("dim-label");
+            self.container.append(&profile_label);
+        }
+
         // Explanation
         if !preview.explanation.is_empty() {
             let exp_box = GtkBox::new(Orientation::Vertical, 4);
@@ -136,10 +148,26 @@ impl SafeModePreviewWidget {
             self.container.append(&exp_box);
         }
 
+        // Flag-by-flag breakdown from the built-in command reference
+        if let Some(breakdown) = breakdown_text(preview) {
+            let expander = gtk4::Expander::new(Some("Flag by flag"));
+            let text = Label::new(Some(&breakdown));
+            text.set_xalign(0.0);
+            text.set_selectable(true);
+            text.add_css_class("monospace");
+            expander.set_child(Some(&text));
+            self.container.append(&expander);
+        }
+
         // Affected files
         if let Some(count) = preview.affected_count {
             let affected = Label::new(Some(&format!(
-                "• Will affect {} file(s) {}",
+                "• Will affect {}{} file(s) {}",
+                if preview.impact_truncated {
+                    "at least "
+                } else {
+                    ""
+                },
                 count,
                 preview
                     .affected_size
@@ -148,6 +176,21 @@ impl SafeModePreviewWidget {
             )));
             affected.set_xalign(0.0);
             self.container.append(&affected);
+
+            for impact in &preview.impact {
+                let mut detail = impact.effect.label().to_string();
+                if impact.entries > 1 {
+                    detail.push_str(&format!(", {} items", impact.entries));
+                }
+                if impact.bytes > 0 {
+                    detail.push_str(&format!(", {}", humanize_bytes(impact.bytes)));
+                }
+                let path = Label::new(Some(&format!("    {} ({})", impact.path.display(), detail)));
+                path.set_xalign(0.0);
+                path.add_css_class("monospace");
+                path.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
+                self.container.append(&path);
+            }
         }
 
         // Undo hint
@@ -178,8 +221,14 @@ impl SafeModePreviewWidget {
             }
         }
 
-        // Store current command for execute callback
-        *self.current_command.borrow_mut() = Some(preview.command.clone());
+        // Store current command for execute callback, with `rm` moving
+        // files to the trash when that is turned on
+        *self.current_command.borrow_mut() = Some(
+            preview
+                .trash_rewrite
+                .clone()
+                .unwrap_or_else(|| preview.command.clone()),
+        );
 
         // Buttons
         let button_box = GtkBox::new(Orientation::Horizontal, 8);
@@ -257,7 +306,8 @@ impl Default for SafeModePreviewWidget {
     }
 }
 
-fn humanize_bytes(bytes: u64) -> String {
+/// `bytes` in the largest unit that keeps it above one
+pub fn humanize_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
     const GB: u64 = MB * 1024;
@@ -272,3 +322,20 @@ fn humanize_bytes(bytes: u64) -> String {
         format!("{} bytes", bytes)
     }
 }
+
+/// Every command in the preview with what its words mean, or `None` when
+/// there is nothing beyond the program names to explain
+pub fn breakdown_text(preview: &CommandPreview) -> Option<String> {
+    let explains_words = preview
+        .breakdown
+        .iter()
+        .any(|explanation| explanation.words.len() > 1);
+    explains_words.then(|| {
+        preview
+            .breakdown
+            .iter()
+            .map(|explanation| explanation.to_string())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    })
+}
@@ -12,6 +12,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::ai_panel::AiPanel;
+use crate::command_gateway::{CommandGateway, CommandOrigin, CommandTarget};
 use crate::dialogs;
 use crate::keyboard::{KeyboardShortcuts, ShortcutAction};
 use crate::recording_panel::show_recording_dialog;
@@ -19,27 +20,29 @@ use crate::sidebar::Sidebar;
 use crate::tab_bar::TerminalTabs;
 use crate::widgets::natural_language_input::NaturalLanguageInput;
 use crate::widgets::safe_mode_preview::SafeModePreviewWidget;
-use corgiterm_core::SafeMode;
-use std::path::PathBuf;
+use corgiterm_core::{ProfileSet, RuleSet, SafeMode};
 
 const ACTION_ASCII_ART: &str = "ascii_art";
 const ACTION_EMOJIS: &str = "emojis";
 const ACTION_HISTORY_SEARCH: &str = "history_search";
+const ACTION_OUTPUT_SEARCH: &str = "output_search";
 const ACTION_SESSION_RECORDING: &str = "session_recording";
 const ACTION_SSH_MANAGER: &str = "ssh_manager";
+const ACTION_SSH_CONNECT: &str = "ssh-connect";
+const ACTION_UNDO_DELETION: &str = "undo_deletion";
 
 const TOOL_MENU_ITEMS: &[(&str, &str)] = &[
     ("_SSH Manager", ACTION_SSH_MANAGER),
     ("_ASCII Art Generator", ACTION_ASCII_ART),
     ("_Emojis", ACTION_EMOJIS),
     ("_History Search", ACTION_HISTORY_SEARCH),
+    ("Search All _Output", ACTION_OUTPUT_SEARCH),
     ("_Session Recording", ACTION_SESSION_RECORDING),
 ];
 
 /// Main application window
 pub struct MainWindow {
     window: ApplicationWindow,
-    #[allow(dead_code)]
     tabs: Rc<TerminalTabs>,
     #[allow(dead_code)]
     sidebar: Rc<Sidebar>,
@@ -53,6 +56,7 @@ pub struct MainWindow {
     nl_input: Rc<NaturalLanguageInput>,
     #[allow(dead_code)]
     safe_mode_preview: Rc<SafeModePreviewWidget>,
+    command_gateway: Rc<CommandGateway>,
 }
 
 impl MainWindow {
@@ -114,6 +118,10 @@ impl MainWindow {
             tools_menu.append(Some(label), Some(&detailed_action));
         }
         menu.append_submenu(Some("_Tools"), &tools_menu);
+        menu.append(
+            Some("Undo Last _Deletion"),
+            Some(&format!("win.{ACTION_UNDO_DELETION}")),
+        );
 
         menu.append(Some("_Preferences"), Some("win.preferences"));
         menu.append(Some("_Keyboard Shortcuts"), Some("win.shortcuts"));
@@ -165,6 +173,32 @@ impl MainWindow {
         });
         header.pack_end(&safe_mode_indicator);
 
+        // Create Safe Mode preview widget and analyzer
+        let safe_mode_preview = Rc::new(SafeModePreviewWidget::new());
+        let safe_mode_config = crate::app::config_manager()
+            .map(|cm| cm.read().config().safe_mode)
+            .unwrap_or_default();
+        let (safe_mode_rules, rule_warnings) = RuleSet::with_user_layer(
+            Some(&safe_mode_config.rules_path()),
+            &safe_mode_config.dangerous_patterns,
+            &safe_mode_config.safe_patterns,
+        );
+        for warning in rule_warnings {
+            tracing::warn!("Safe Mode rules: {}", warning);
+        }
+        let (safe_mode_profiles, profile_warnings) =
+            ProfileSet::with_user_dir(&safe_mode_config.profiles_dir());
+        for warning in profile_warnings {
+            tracing::warn!("Safe Mode profiles: {}", warning);
+        }
+        let safe_mode = Rc::new(RefCell::new(SafeMode::with_rules(safe_mode_rules)));
+        safe_mode.borrow_mut().set_profiles(safe_mode_profiles);
+        safe_mode.borrow_mut().set_enabled(true); // Enable by default for safety
+
+        // Commands that are not typed go through Safe Mode here
+        let command_gateway =
+            CommandGateway::new(tabs.clone(), safe_mode, safe_mode_preview.clone());
+
         // Add window actions
         let prefs_action = SimpleAction::new("preferences", None);
         let win_for_prefs = window.clone();
@@ -196,11 +230,11 @@ impl MainWindow {
 
         let ascii_art_action = SimpleAction::new(ACTION_ASCII_ART, None);
         let win_for_ascii = window.clone();
-        let tabs_for_ascii = tabs.clone();
+        let gateway_for_ascii = command_gateway.clone();
         ascii_art_action.connect_activate(move |_, _| {
-            let tabs = tabs_for_ascii.clone();
+            let gateway = gateway_for_ascii.clone();
             dialogs::show_ascii_art_dialog(&win_for_ascii, move |art| {
-                if tabs.send_command_to_current(art) {
+                if gateway.submit(CommandOrigin::Tool, art) {
                     tracing::info!("Inserted ASCII art into terminal ({} bytes)", art.len());
                 } else {
                     tracing::warn!("No active terminal for ASCII art insertion");
@@ -218,6 +252,24 @@ impl MainWindow {
         });
         window.add_action(&ssh_manager_action);
 
+        // SSH manager connections, each in a new tab
+        let ssh_connect_action =
+            SimpleAction::new(ACTION_SSH_CONNECT, Some(gtk4::glib::VariantTy::STRING));
+        let gateway_for_ssh = command_gateway.clone();
+        ssh_connect_action.connect_activate(move |_, param| {
+            let Some(command) = param.and_then(|param| param.get::<String>()) else {
+                return;
+            };
+            let tabs = gateway_for_ssh.tabs();
+            let page = tabs.add_terminal_tab("SSH", None);
+            let target = CommandTarget {
+                tab: tabs.position_of(&page),
+                pane: None,
+            };
+            gateway_for_ssh.submit_to(CommandOrigin::Ssh, &command, target, None);
+        });
+        window.add_action(&ssh_connect_action);
+
         // Emojis action
         let emojis_action = SimpleAction::new(ACTION_EMOJIS, None);
         let win_for_emojis = window.clone();
@@ -237,19 +289,38 @@ impl MainWindow {
         // History Search action
         let history_action = SimpleAction::new(ACTION_HISTORY_SEARCH, None);
         let win_for_history = window.clone();
+        let gateway_for_history = command_gateway.clone();
         let tabs_for_history = tabs.clone();
         history_action.connect_activate(move |_, _| {
-            let tabs = tabs_for_history.clone();
-            crate::history_search::show_history_search_dialog(&win_for_history, move |cmd| {
-                if tabs.send_command_to_current(cmd) {
-                    tracing::info!("History search: inserted command {}", cmd);
-                } else {
-                    tracing::warn!("No active terminal for history search");
-                }
-            });
+            let gateway = gateway_for_history.clone();
+            let context = current_history_context(&tabs_for_history);
+            crate::history_search::show_history_search_dialog(
+                &win_for_history,
+                &context,
+                move |cmd| {
+                    if gateway.submit(CommandOrigin::History, cmd) {
+                        tracing::info!("History search: inserted command {}", cmd);
+                    } else {
+                        tracing::warn!("No active terminal for history search");
+                    }
+                },
+            );
         });
         window.add_action(&history_action);
 
+        // Output Search action
+        let output_search_action = SimpleAction::new(ACTION_OUTPUT_SEARCH, None);
+        let win_for_output_search = window.clone();
+        let tabs_for_output_search = tabs.clone();
+        output_search_action.connect_activate(move |_, _| {
+            let tabs = tabs_for_output_search.clone();
+            crate::output_search::show_output_search_dialog(
+                &win_for_output_search,
+                move |session, line| tabs.reveal_session(session, line),
+            );
+        });
+        window.add_action(&output_search_action);
+
         // Session Recording action
         let recording_action = SimpleAction::new(ACTION_SESSION_RECORDING, None);
         let win_for_recording = window.clone();
@@ -258,6 +329,14 @@ impl MainWindow {
         });
         window.add_action(&recording_action);
 
+        // Undo the last deletion Safe Mode moved to the trash
+        let undo_deletion_action = SimpleAction::new(ACTION_UNDO_DELETION, None);
+        let win_for_undo = window.clone();
+        undo_deletion_action.connect_activate(move |_, _| {
+            dialogs::show_undo_deletion_dialog(&win_for_undo);
+        });
+        window.add_action(&undo_deletion_action);
+
         // Main layout with header + content
         let main_box = Box::new(Orientation::Vertical, 0);
         main_box.set_hexpand(true);
@@ -295,11 +374,6 @@ impl MainWindow {
         nl_status.set_visible(false);
         nl_container.append(&nl_status);
 
-        // Create Safe Mode preview widget and analyzer
-        let safe_mode_preview = Rc::new(SafeModePreviewWidget::new());
-        let safe_mode = Rc::new(RefCell::new(SafeMode::new()));
-        safe_mode.borrow_mut().set_enabled(true); // Enable by default for safety
-
         // Terminal area (NL input removed - using AI panel on the right instead)
         let terminal_area = Box::new(Orientation::Vertical, 0);
         terminal_area.set_hexpand(true); // Expand horizontally to fill available space
@@ -309,19 +383,6 @@ impl MainWindow {
         tabs.tab_view_widget().set_vexpand(true);
         tabs.tab_view_widget().set_hexpand(true); // Ensure tabs expand horizontally
 
-        // Wire safe mode execute callback
-        let tabs_for_safe_exec = tabs.clone();
-        safe_mode_preview.set_on_execute(move |command| {
-            if tabs_for_safe_exec.send_command_to_current(&command) {
-                tracing::info!("Safe mode: Executed command: {}", command);
-            }
-        });
-
-        // Wire safe mode cancel callback (no-op, just for logging)
-        safe_mode_preview.set_on_cancel(|| {
-            tracing::info!("Safe mode: Command cancelled");
-        });
-
         // Sidebar setup with Paned layout (original working approach)
         let sidebar_widget = sidebar.widget().clone();
         // Don't set width_request - it prevents shrinking below that size
@@ -382,7 +443,7 @@ impl MainWindow {
         });
 
         // Connect NL input to AI translation and terminal execution
-        let tabs_for_nl = tabs.clone();
+        let gateway_for_nl = command_gateway.clone();
         let nl_input_for_activate = nl_input.clone();
         let nl_spinner_ref = nl_spinner.clone();
         let nl_status_ref = nl_status.clone();
@@ -422,8 +483,6 @@ impl MainWindow {
         // On Enter: execute the translated command (with safe mode check)
         // If no pattern matched, use AI to translate first
         let nl_input_for_exec = nl_input.clone();
-        let safe_mode_for_activate = safe_mode.clone();
-        let safe_mode_preview_for_activate = safe_mode_preview.clone();
         nl_input.connect_activate(move || {
             let translation = translation_for_activate.borrow().clone();
             let user_text = nl_input_for_exec.text();
@@ -434,8 +493,7 @@ impl MainWindow {
 
             if let Some(cmd) = translation {
                 // We have a pattern-matched command - execute it
-                execute_command(&cmd, &user_text, &tabs_for_nl, &safe_mode_for_activate,
-                    &safe_mode_preview_for_activate, &nl_input_for_exec, &nl_status_ref);
+                execute_command(&cmd, &user_text, &gateway_for_nl, &nl_input_for_exec, &nl_status_ref);
             } else {
                 // No pattern match - use AI to translate
                 // Show spinner
@@ -480,9 +538,7 @@ impl MainWindow {
                     ];
 
                     // Clone refs for async handler
-                    let tabs = tabs_for_nl.clone();
-                    let safe_mode = safe_mode_for_activate.clone();
-                    let safe_mode_preview = safe_mode_preview_for_activate.clone();
+                    let gateway = gateway_for_nl.clone();
                     let nl_input = nl_input_for_exec.clone();
                     let nl_status = nl_status_ref.clone();
                     let spinner = nl_spinner_ref.clone();
@@ -533,8 +589,7 @@ impl MainWindow {
                                 spinner.set_visible(false);
 
                                 // Execute the AI-generated command
-                                execute_command(&cmd, &user_text_for_log, &tabs, &safe_mode,
-                                    &safe_mode_preview, &nl_input, &nl_status);
+                                execute_command(&cmd, &user_text_for_log, &gateway, &nl_input, &nl_status);
 
                                 gtk4::glib::ControlFlow::Break
                             }
@@ -574,9 +629,9 @@ impl MainWindow {
         let ai_panel = Rc::new(RefCell::new(AiPanel::new()));
 
         // Wire AI panel execute button to send commands to the active terminal
-        let tabs_for_ai = tabs.clone();
+        let gateway_for_ai = command_gateway.clone();
         ai_panel.borrow().set_execute_callback(move |command| {
-            if tabs_for_ai.send_command_to_current(command) {
+            if gateway_for_ai.submit(CommandOrigin::Ai, command) {
                 tracing::info!("AI panel executed command: {}", command);
             } else {
                 tracing::warn!(
@@ -675,6 +730,7 @@ impl MainWindow {
         let sidebar_toggle_for_keys = sidebar_toggle_btn.clone();
         let sidebar_reopen_for_keys = sidebar_reopen_btn.clone();
         let safe_mode_preview_for_keys = safe_mode_preview.clone();
+        let gateway_for_keys = command_gateway.clone();
         key_controller.connect_key_pressed(move |_, key, _keycode, modifier| {
             use gtk4::gdk::Key;
 
@@ -799,30 +855,39 @@ impl MainWindow {
             }
             if shortcuts_for_event.matches(ShortcutAction::Snippets, key, modifier) {
                 let win = window_for_keys.clone();
-                let tabs = tabs_for_keys.clone();
+                let gateway = gateway_for_keys.clone();
                 crate::snippets::show_snippets_dialog(&win, move |snippet| {
-                    tabs.send_command_to_current(&snippet);
+                    gateway.submit(CommandOrigin::Snippet, &snippet);
                 });
                 return gtk4::glib::Propagation::Stop;
             }
             if shortcuts_for_event.matches(ShortcutAction::AsciiArt, key, modifier) {
                 let win = window_for_keys.clone();
-                let tabs = tabs_for_keys.clone();
+                let gateway = gateway_for_keys.clone();
                 dialogs::show_ascii_art_dialog(&win, move |art| {
-                    tabs.send_command_to_current(&format!("echo '{}'\n", art));
+                    gateway.submit(CommandOrigin::Tool, &format!("echo '{}'\n", art));
                 });
                 return gtk4::glib::Propagation::Stop;
             }
             if shortcuts_for_event.matches(ShortcutAction::HistorySearch, key, modifier) {
                 let win = window_for_keys.clone();
-                let tabs = tabs_for_keys.clone();
-                crate::history_search::show_history_search_dialog(&win, move |cmd| {
-                    if tabs.send_command_to_current(cmd) {
+                let gateway = gateway_for_keys.clone();
+                let context = current_history_context(&tabs_for_keys);
+                crate::history_search::show_history_search_dialog(&win, &context, move |cmd| {
+                    if gateway.submit(CommandOrigin::History, cmd) {
                         tracing::info!("History search: executed {}", cmd);
                     }
                 });
                 return gtk4::glib::Propagation::Stop;
             }
+            if shortcuts_for_event.matches(ShortcutAction::OutputSearch, key, modifier) {
+                let tabs = tabs_for_keys.clone();
+                crate::output_search::show_output_search_dialog(
+                    &window_for_keys,
+                    move |session, line| tabs.reveal_session(session, line),
+                );
+                return gtk4::glib::Propagation::Stop;
+            }
             if shortcuts_for_event.matches(ShortcutAction::OpenFile, key, modifier) {
                 let tabs = tabs_for_keys.clone();
                 let win = window_for_keys.clone();
@@ -895,6 +960,7 @@ impl MainWindow {
             ai_revealer,
             nl_input,
             safe_mode_preview,
+            command_gateway,
         }
     }
 
@@ -1047,6 +1113,17 @@ impl MainWindow {
     pub fn widget(&self) -> &ApplicationWindow {
         &self.window
     }
+
+    /// Gateway that commands from AI, snippets, MCP and other non-keyboard
+    /// sources must be sent through
+    pub fn command_gateway(&self) -> Rc<CommandGateway> {
+        self.command_gateway.clone()
+    }
+
+    /// Get the window's tab manager
+    pub fn tabs(&self) -> Rc<TerminalTabs> {
+        self.tabs.clone()
+    }
 }
 
 fn toggle_sidebar(
@@ -1103,36 +1180,16 @@ fn shortcut_tooltip(label: &str, action: ShortcutAction) -> String {
     }
 }
 
-/// Execute a command with safe mode checking
+/// Send a translated command through the command gateway
 fn execute_command(
     cmd: &str,
     user_text: &str,
-    tabs: &Rc<TerminalTabs>,
-    safe_mode: &Rc<RefCell<SafeMode>>,
-    safe_mode_preview: &Rc<crate::widgets::safe_mode_preview::SafeModePreviewWidget>,
+    gateway: &CommandGateway,
     nl_input: &Rc<NaturalLanguageInput>,
     nl_status: &Label,
 ) {
-    // Check if safe mode is enabled
-    let analyzer = safe_mode.borrow();
-    if analyzer.enabled {
-        // Get current working directory from terminal (or use home)
-        let cwd = PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/".to_string()));
-        let preview = analyzer.analyze(cmd, &cwd);
-
-        // Show preview for risky commands
-        if preview.risk != corgiterm_core::RiskLevel::Safe {
-            drop(analyzer); // Drop borrow before showing preview
-            safe_mode_preview.show_preview(&preview);
-            nl_input.clear();
-            return;
-        }
-    }
-    drop(analyzer);
-
-    // Safe command - execute directly
-    if tabs.send_command_to_current(cmd) {
-        tracing::info!("Executed NL command: {} -> {}", user_text, cmd);
+    if gateway.submit(CommandOrigin::Ai, cmd) {
+        tracing::info!("NL command: {} -> {}", user_text, cmd);
         nl_input.clear();
     } else {
         nl_status.set_text("No terminal tab selected");
@@ -1319,6 +1376,13 @@ fn extract_port(text: &str) -> Option<u16> {
     None
 }
 
+/// History scopes for the focused terminal
+fn current_history_context(tabs: &TerminalTabs) -> crate::history_search::HistoryContext {
+    tabs.with_current_split_pane(|sp| sp.with_terminal(None, |tv| tv.history_context()))
+        .flatten()
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1334,6 +1398,7 @@ mod tests {
                 ACTION_ASCII_ART,
                 ACTION_EMOJIS,
                 ACTION_HISTORY_SEARCH,
+                ACTION_OUTPUT_SEARCH,
                 ACTION_SESSION_RECORDING,
             ]
         );
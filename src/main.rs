@@ -6,7 +6,8 @@
 //!   /　 つ   The friendliest terminal ever.
 //! ```
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use corgiterm_config::Config;
 use std::path::PathBuf;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -23,11 +24,28 @@ struct Args {
     #[arg(short = 'e', long)]
     execute: Option<String>,
 
+    /// With --execute, print the final screen instead of the raw output stream
+    #[arg(long, requires = "execute")]
+    dump_screen: bool,
+
+    /// With --execute, kill the command after this many seconds
+    #[arg(long, value_name = "SECONDS", requires = "execute")]
+    timeout: Option<u64>,
+
+    /// With --execute, terminal width in columns
+    #[arg(long, default_value_t = 80, requires = "execute")]
+    cols: usize,
+
+    /// With --execute, terminal height in rows
+    #[arg(long, default_value_t = 24, requires = "execute")]
+    rows: usize,
+
     /// Open a specific project
     #[arg(short = 'p', long)]
     project: Option<PathBuf>,
 
-    /// Start in Safe Mode
+    /// Start in Safe Mode: typed commands are checked before the shell runs
+    /// them, and --execute refuses dangerous commands
     #[arg(long)]
     safe_mode: bool,
 
@@ -46,11 +64,198 @@ struct Args {
     /// Enable debug logging
     #[arg(long)]
     debug: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Control a running CorgiTerm over its IPC socket
+    Msg {
+        /// Socket path (defaults to $CORGITERM_SOCKET, then advanced.ipc_socket)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        #[command(subcommand)]
+        verb: MsgVerb,
+    },
+    /// Serve MCP to an AI agent over stdin/stdout
+    ///
+    /// Relays to a running CorgiTerm when its MCP socket is up (ai.mcp.enabled),
+    /// otherwise runs each command in a private PTY.
+    Mcp {
+        /// Socket path (defaults to ai.mcp.socket, then $XDG_RUNTIME_DIR/corgiterm-mcp.sock)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Inspect Safe Mode's rules
+    SafeMode {
+        #[command(subcommand)]
+        action: SafeModeAction,
+    },
+    /// Manage the trash Safe Mode moves deleted files into
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Manage command history
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryAction {
+    /// Import another shell's history; without --format, imports every
+    /// bash, zsh, fish and atuin history found in its default location
+    Import {
+        /// History format: bash, zsh, fish or atuin
+        #[arg(long, value_name = "FORMAT")]
+        format: Option<corgiterm_core::ShellHistoryFormat>,
+        /// History file (defaults to the format's usual location)
+        #[arg(requires = "format")]
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TrashAction {
+    /// Move files into the trash; takes `rm`'s options, so Safe Mode can
+    /// run it in place of `rm`
+    Put {
+        /// Ignore missing files
+        #[arg(short, long)]
+        force: bool,
+        /// Trash directories and their contents
+        #[arg(short, short_alias = 'R', long)]
+        recursive: bool,
+        /// Trash empty directories
+        #[arg(short, long)]
+        dir: bool,
+        /// Print each path as it is trashed
+        #[arg(short, long)]
+        verbose: bool,
+        /// Accepted for `rm` compatibility
+        #[arg(long, hide = true)]
+        preserve_root: bool,
+        /// Accepted for `rm` compatibility; `/` is never trashed
+        #[arg(long, hide = true)]
+        no_preserve_root: bool,
+        /// Accepted for `rm` compatibility
+        #[arg(long, hide = true)]
+        one_file_system: bool,
+        /// Files and directories to trash
+        paths: Vec<PathBuf>,
+    },
+    /// List trashed deletions, newest first
+    List,
+    /// Restore the files of the last deletion
+    Undo,
+}
+
+#[derive(Subcommand, Debug)]
+enum SafeModeAction {
+    /// Show the risk of a command line and which rules fired
+    Check {
+        /// Directory whose project rules apply (defaults to the current directory)
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<PathBuf>,
+        /// Safe Mode profile to check with, such as `production` (defaults to
+        /// the configured one)
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+        /// Print the full preview as JSON
+        #[arg(long)]
+        json: bool,
+        /// Command line to check
+        command: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MsgVerb {
+    /// Check that the terminal is listening
+    Ping,
+    /// List tabs in the active location
+    Ls,
+    /// List projects and their sessions
+    Sessions,
+    /// Open a new terminal tab
+    NewTab {
+        /// Tab title
+        #[arg(long)]
+        title: Option<String>,
+        /// Working directory
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+    },
+    /// Split the focused pane of a tab
+    Split {
+        /// Tab position (defaults to the current tab)
+        #[arg(long)]
+        tab: Option<usize>,
+        /// Split direction
+        #[arg(long, default_value = "horizontal", value_parser = ["horizontal", "vertical"])]
+        direction: String,
+    },
+    /// Type a line into a pane
+    SendText {
+        /// Tab position (defaults to the current tab)
+        #[arg(long)]
+        tab: Option<usize>,
+        /// Pane index within the tab (defaults to the focused pane)
+        #[arg(long)]
+        pane: Option<usize>,
+        /// Run the line, once Safe Mode lets it through
+        #[arg(long)]
+        newline: bool,
+        /// Text to send
+        text: String,
+    },
+    /// Write text to a pane as-is, control characters included, without
+    /// Safe Mode (needs advanced.ipc_raw_input)
+    SendRaw {
+        /// Tab position (defaults to the current tab)
+        #[arg(long)]
+        tab: Option<usize>,
+        /// Pane index within the tab (defaults to the focused pane)
+        #[arg(long)]
+        pane: Option<usize>,
+        /// Text to send
+        text: String,
+    },
+    /// Print the visible text of a pane
+    GetText {
+        /// Tab position (defaults to the current tab)
+        #[arg(long)]
+        tab: Option<usize>,
+        /// Pane index within the tab (defaults to the focused pane)
+        #[arg(long)]
+        pane: Option<usize>,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    // Read once; the commands below only look at it
+    let config = corgiterm_config::ConfigManager::new()
+        .map(|cm| cm.config())
+        .unwrap_or_default();
+    configure_redaction(&config);
+
+    // Remote control and MCP own stdout, so skip logging and core setup
+    match &args.command {
+        Some(Command::Msg { socket, verb }) => return send_msg(&config, socket.clone(), verb),
+        Some(Command::Mcp { socket }) => return serve_mcp(&config, socket.clone()),
+        Some(Command::SafeMode { action }) => return safe_mode_command(&config, action),
+        Some(Command::Trash { action }) => return trash_command(&config, action),
+        Some(Command::History { action }) => return history_command(action),
+        None => {}
+    }
+
     // Set up logging
     let log_level = if args.debug { "debug" } else { "info" };
     tracing_subscriber::registry()
@@ -81,16 +286,449 @@ fn main() -> anyhow::Result<()> {
     // Handle command execution mode
     if let Some(ref cmd) = args.execute {
         tracing::info!("Executing command: {}", cmd);
-        // TODO: Execute command in headless mode
-        return Ok(());
+        let exit_code = execute_headless(&config, &args, cmd)?;
+        std::process::exit(exit_code);
     }
 
     // Run the GTK4 application
-    let exit_code = corgiterm_ui::run();
+    let exit_code = corgiterm_ui::run_with(corgiterm_ui::RunOptions {
+        safe_mode: args.safe_mode,
+    });
 
     std::process::exit(exit_code.into());
 }
 
+/// Redact secrets as configured in `privacy`; errors go to stderr, since
+/// stdout may belong to MCP
+fn configure_redaction(config: &Config) {
+    if let Err(e) = corgiterm_ai::redaction::configure(&config.privacy) {
+        eprintln!("corgiterm: secret redaction: {}", e);
+    }
+}
+
+/// Run `--execute` through a real PTY and return the child's exit code
+fn execute_headless(config: &Config, args: &Args, cmd: &str) -> anyhow::Result<i32> {
+    use corgiterm_core::{headless, HeadlessOptions, HeadlessOutput, TerminalSize};
+
+    let mut options = HeadlessOptions::new(cmd);
+    options.size = TerminalSize {
+        rows: args.rows.max(1),
+        cols: args.cols.max(1),
+    };
+    options.working_dir = args.directory.clone();
+    options.timeout = args.timeout.map(std::time::Duration::from_secs);
+    options.safe_mode = args.safe_mode;
+    if args.safe_mode {
+        let (mut rules, mut warnings) = load_safe_mode_rules(config);
+        let (profiles, profile_warnings) = load_safe_mode_profiles(config);
+        warnings.extend(profile_warnings);
+        let profile = &config.safe_mode.profile;
+        match profiles.get(profile) {
+            Some(profile) => {
+                let (with_profile, profile_warnings) = profile.apply(&rules);
+                rules = with_profile;
+                warnings.extend(profile_warnings);
+            }
+            None => warnings.push(format!("unknown Safe Mode profile \"{}\"", profile)),
+        }
+        for warning in warnings {
+            tracing::warn!("Safe Mode rules: {}", warning);
+        }
+        options.safe_mode_rules = Some(rules);
+    }
+    options.output = if args.dump_screen {
+        HeadlessOutput::Screen
+    } else {
+        HeadlessOutput::Stream
+    };
+
+    let stdout = std::io::stdout();
+    let result = headless::run(&options, &mut stdout.lock())?;
+
+    if let Some(preview) = result.blocked {
+        eprintln!(
+            "{} Safe Mode blocked this command ({})",
+            preview.risk.emoji(),
+            preview.risk.label()
+        );
+        for line in &preview.explanation {
+            eprintln!("  • {}", line);
+        }
+    } else if result.timed_out {
+        eprintln!("Command timed out after {}s", args.timeout.unwrap_or(0));
+    }
+
+    Ok(result.exit_code)
+}
+
+/// Built-in rules plus the user's rule file and configured patterns
+fn load_safe_mode_rules(config: &Config) -> (corgiterm_core::RuleSet, Vec<String>) {
+    let config = &config.safe_mode;
+    corgiterm_core::RuleSet::with_user_layer(
+        Some(&config.rules_path()),
+        &config.dangerous_patterns,
+        &config.safe_patterns,
+    )
+}
+
+/// Built-in and user Safe Mode profiles, with problems loading the user's
+fn load_safe_mode_profiles(config: &Config) -> (corgiterm_core::ProfileSet, Vec<String>) {
+    corgiterm_core::ProfileSet::with_user_dir(&config.safe_mode.profiles_dir())
+}
+
+/// Run a `corgiterm safe-mode` action
+fn safe_mode_command(config: &Config, action: &SafeModeAction) -> anyhow::Result<()> {
+    use corgiterm_core::SafeMode;
+
+    let SafeModeAction::Check {
+        cwd,
+        profile,
+        json,
+        command,
+    } = action;
+    let cwd = match cwd {
+        Some(cwd) => cwd.clone(),
+        None => std::env::current_dir()?,
+    };
+    let (rules, mut warnings) = load_safe_mode_rules(config);
+    let (profiles, profile_warnings) = load_safe_mode_profiles(config);
+    warnings.extend(profile_warnings);
+    let profile = profile
+        .clone()
+        .unwrap_or_else(|| config.safe_mode.profile.clone());
+    let mut safe_mode = SafeMode::with_rules(rules);
+    safe_mode.set_profiles(profiles);
+    if config.safe_mode.trash_deletions {
+        safe_mode.set_trash_program(
+            std::env::current_exe()
+                .ok()
+                .map(|exe| exe.to_string_lossy().into_owned()),
+        );
+    }
+    let (rules, project_warnings) = safe_mode.rules_with_profile(&cwd, Some(&profile));
+    warnings.extend(project_warnings);
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    let preview = safe_mode.analyze_with_profile(command, &cwd, Some(&profile));
+    if *json {
+        println!("{}", serde_json::to_string_pretty(&preview)?);
+        return Ok(());
+    }
+
+    println!("{} {}", preview.risk.emoji(), preview.risk.label());
+    if profile != corgiterm_core::DEFAULT_PROFILE {
+        println!("Profile: {}", profile);
+    }
+    for checked in &preview.commands {
+        println!();
+        println!("{}  [{}]", checked.command, checked.risk.label());
+        if checked.rules.is_empty() {
+            println!("  no rule matched");
+        }
+        for name in &checked.rules {
+            match rules.get(name) {
+                Some(rule) => println!("  rule: {}", rule),
+                None => println!("  check: {}", name),
+            }
+        }
+        for line in &checked.explanation {
+            println!("  • {}", line);
+        }
+    }
+    for explanation in &preview.breakdown {
+        println!();
+        println!("{}", explanation);
+    }
+    if let Some(count) = preview.affected_count {
+        println!();
+        println!(
+            "Affects {}{} file(s), {} bytes",
+            if preview.impact_truncated {
+                "at least "
+            } else {
+                ""
+            },
+            count,
+            preview.affected_size.unwrap_or(0)
+        );
+        for impact in &preview.impact {
+            println!("  {} ({})", impact.path.display(), impact.effect.label());
+        }
+    }
+    if let Some(rewrite) = &preview.trash_rewrite {
+        println!();
+        println!("Runs as: {}", rewrite);
+    }
+    if let Some(undo) = &preview.undo_hint {
+        println!();
+        println!("Undo: {}", undo);
+    }
+    for alternative in &preview.alternatives {
+        println!();
+        println!(
+            "Alternative: {}  ({})",
+            alternative.command, alternative.description
+        );
+    }
+    Ok(())
+}
+
+/// Run a `corgiterm trash` action
+fn trash_command(config: &Config, action: &TrashAction) -> anyhow::Result<()> {
+    use corgiterm_core::Trash;
+
+    let config = &config.safe_mode;
+    let Some(trash_dir) = Trash::default_dir() else {
+        anyhow::bail!("no data directory to keep the trash in");
+    };
+    let trash = Trash::open(trash_dir);
+
+    match action {
+        TrashAction::Put {
+            force,
+            recursive,
+            dir,
+            verbose,
+            paths,
+            ..
+        } => {
+            let mut failed = paths.is_empty() && !force;
+            if failed {
+                eprintln!("corgiterm trash: missing operand");
+            }
+            // Check operands the way `rm` would before moving anything
+            let mut trashable = Vec::new();
+            for path in paths {
+                let name = path.file_name().and_then(|name| name.to_str());
+                if path.as_os_str().is_empty() || name.is_none() || matches!(name, Some("." | ".."))
+                {
+                    eprintln!("corgiterm trash: refusing to remove '{}'", path.display());
+                    failed = true;
+                    continue;
+                }
+                match std::fs::symlink_metadata(path) {
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound && *force => {}
+                    Err(e) => {
+                        eprintln!("corgiterm trash: cannot remove '{}': {}", path.display(), e);
+                        failed = true;
+                    }
+                    Ok(metadata)
+                        if metadata.is_dir()
+                            && !recursive
+                            && !(*dir
+                                && std::fs::read_dir(path)
+                                    .map(|mut children| children.next().is_none())
+                                    .unwrap_or(false)) =>
+                    {
+                        eprintln!(
+                            "corgiterm trash: cannot remove '{}': Is a directory",
+                            path.display()
+                        );
+                        failed = true;
+                    }
+                    Ok(_) => trashable.push(path.clone()),
+                }
+            }
+
+            let command = std::iter::once("rm".to_string())
+                .chain(recursive.then(|| "-r".to_string()))
+                .chain(paths.iter().map(|path| format!("'{}'", path.display())))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let outcome = trash.put(&trashable, &command);
+            for (path, e) in &outcome.failed {
+                eprintln!("corgiterm trash: cannot remove '{}': {}", path.display(), e);
+                failed = true;
+            }
+            if *verbose {
+                for item in outcome.entry.iter().flat_map(|entry| &entry.items) {
+                    println!("trashed '{}'", item.original.display());
+                }
+            }
+
+            let max_age =
+                std::time::Duration::from_secs(u64::from(config.trash_max_age_days) * 24 * 60 * 60);
+            let max_bytes = config.trash_max_size_mb.saturating_mul(1024 * 1024);
+            if let Err(e) = trash.expire(max_age, max_bytes) {
+                eprintln!("corgiterm trash: failed to expire old deletions: {}", e);
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        TrashAction::List => {
+            let entries = trash.entries();
+            if entries.is_empty() {
+                println!("The trash is empty");
+            }
+            for entry in entries.iter().rev() {
+                println!(
+                    "{}  {}  ({} bytes)",
+                    entry
+                        .timestamp
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M"),
+                    entry.command,
+                    entry.bytes()
+                );
+                for item in &entry.items {
+                    println!("  {}", item.original.display());
+                }
+            }
+        }
+        TrashAction::Undo => match trash.undo_last()? {
+            None => println!("Nothing to undo"),
+            Some(restored) => {
+                println!("Undoing: {}", restored.command);
+                for path in &restored.restored {
+                    println!("  restored {}", path.display());
+                }
+                for path in &restored.conflicts {
+                    println!("  kept in trash, {} exists", path.display());
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Run a `corgiterm history` action
+fn history_command(action: &HistoryAction) -> anyhow::Result<()> {
+    use corgiterm_core::{history_import, HistoryDb};
+
+    let HistoryAction::Import { format, path } = action;
+    let sources = match (format, path) {
+        (Some(format), Some(path)) => vec![(*format, path.clone())],
+        (Some(format), None) => match format.default_path() {
+            Some(path) => vec![(*format, path)],
+            None => anyhow::bail!("no home directory to find {} history in", format.name()),
+        },
+        (None, _) => corgiterm_core::ShellHistoryFormat::detect(),
+    };
+    if sources.is_empty() {
+        println!("No bash, zsh, fish or atuin history found");
+        return Ok(());
+    }
+
+    let db = HistoryDb::open_default()?;
+    let mut failed = false;
+    for (format, path) in sources {
+        match history_import::import(&db, format, &path) {
+            Ok(report) => println!(
+                "{}: {} commands read, {} imported from {}",
+                format.name(),
+                report.read,
+                report.imported,
+                path.display()
+            ),
+            Err(e) => {
+                eprintln!(
+                    "{}: failed to import {}: {}",
+                    format.name(),
+                    path.display(),
+                    e
+                );
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Send one `corgiterm msg` request and print the result
+#[cfg(unix)]
+fn send_msg(config: &Config, socket: Option<PathBuf>, verb: &MsgVerb) -> anyhow::Result<()> {
+    use corgiterm_core::ipc;
+    use serde_json::json;
+
+    let socket = socket
+        .or_else(|| std::env::var_os(ipc::SOCKET_ENV).map(PathBuf::from))
+        .or_else(|| config.advanced.ipc_socket.clone())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No IPC socket: pass --socket, set {}, or configure advanced.ipc_socket",
+                ipc::SOCKET_ENV
+            )
+        })?;
+
+    let (method, params) = match verb {
+        MsgVerb::Ping => ("ping", None),
+        MsgVerb::Ls => ("tab.list", None),
+        MsgVerb::Sessions => ("session.list", None),
+        MsgVerb::NewTab { title, cwd } => ("tab.new", Some(json!({ "title": title, "cwd": cwd }))),
+        MsgVerb::Split { tab, direction } => (
+            "pane.split",
+            Some(json!({ "tab": tab, "direction": direction })),
+        ),
+        MsgVerb::SendText {
+            tab,
+            pane,
+            newline,
+            text,
+        } => (
+            "pane.send_text",
+            Some(json!({ "tab": tab, "pane": pane, "text": text, "newline": newline })),
+        ),
+        MsgVerb::SendRaw { tab, pane, text } => (
+            "pane.send_raw",
+            Some(json!({ "tab": tab, "pane": pane, "text": text })),
+        ),
+        MsgVerb::GetText { tab, pane } => {
+            ("pane.get_text", Some(json!({ "tab": tab, "pane": pane })))
+        }
+    };
+
+    let result = ipc::send_request(&socket, method, params)?;
+    match verb {
+        MsgVerb::GetText { .. } => {
+            let lines = result["lines"].as_array().cloned().unwrap_or_default();
+            for line in lines {
+                println!("{}", line.as_str().unwrap_or_default());
+            }
+        }
+        _ => println!("{}", serde_json::to_string_pretty(&result)?),
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_msg(_config: &Config, _socket: Option<PathBuf>, _verb: &MsgVerb) -> anyhow::Result<()> {
+    anyhow::bail!("corgiterm msg requires Unix-domain sockets")
+}
+
+/// Serve MCP on stdio, relaying to the running app when it is listening
+fn serve_mcp(config: &Config, socket: Option<PathBuf>) -> anyhow::Result<()> {
+    use corgiterm_ai::mcp::{HeadlessBackend, McpServer};
+    use corgiterm_ai::mcp_transport;
+    use std::sync::Arc;
+
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        #[cfg(unix)]
+        {
+            let socket = socket
+                .or_else(|| config.ai.mcp.socket.clone())
+                .unwrap_or_else(mcp_transport::default_socket_path);
+            // Never relay an agent to a socket planted by another user
+            corgiterm_core::ipc::check_owner(&socket)?;
+            if let Ok(stream) = tokio::net::UnixStream::connect(&socket).await {
+                return Ok(mcp_transport::bridge_stdio(stream).await?);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (config, socket);
+
+        let server = Arc::new(McpServer::with_backend(Arc::new(HeadlessBackend::new())));
+        mcp_transport::serve_stdio(server).await?;
+        Ok(())
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +748,208 @@ mod tests {
         assert!(args.no_ai);
         assert_eq!(args.directory, Some(PathBuf::from("/tmp")));
     }
+
+    #[test]
+    fn test_arg_parsing_headless_options() {
+        let args = Args::try_parse_from([
+            "corgiterm",
+            "-e",
+            "ls",
+            "--dump-screen",
+            "--timeout",
+            "5",
+            "--cols",
+            "120",
+            "--rows",
+            "40",
+        ])
+        .unwrap();
+        assert_eq!(args.execute.as_deref(), Some("ls"));
+        assert!(args.dump_screen);
+        assert_eq!(args.timeout, Some(5));
+        assert_eq!(args.cols, 120);
+        assert_eq!(args.rows, 40);
+    }
+
+    #[test]
+    fn test_headless_options_require_execute() {
+        assert!(Args::try_parse_from(["corgiterm", "--dump-screen"]).is_err());
+    }
+
+    #[test]
+    fn test_msg_subcommand_parsing() {
+        let args = Args::try_parse_from([
+            "corgiterm",
+            "msg",
+            "--socket",
+            "/tmp/corgi.sock",
+            "send-text",
+            "--tab",
+            "1",
+            "--newline",
+            "ls -la",
+        ])
+        .unwrap();
+        match args.command {
+            Some(Command::Msg {
+                socket,
+                verb:
+                    MsgVerb::SendText {
+                        tab,
+                        pane,
+                        newline,
+                        text,
+                    },
+            }) => {
+                assert_eq!(socket, Some(PathBuf::from("/tmp/corgi.sock")));
+                assert_eq!(tab, Some(1));
+                assert_eq!(pane, None);
+                assert!(newline);
+                assert_eq!(text, "ls -la");
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        let args = Args::try_parse_from(["corgiterm", "msg", "send-raw", "\x03"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Msg {
+                verb: MsgVerb::SendRaw { .. },
+                ..
+            })
+        ));
+
+        assert!(
+            Args::try_parse_from(["corgiterm", "msg", "split", "--direction", "diagonal"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_safe_mode_check_parsing() {
+        let args = Args::try_parse_from(["corgiterm", "safe-mode", "check", "--json", "rm -rf /"])
+            .unwrap();
+        match args.command {
+            Some(Command::SafeMode {
+                action:
+                    SafeModeAction::Check {
+                        cwd,
+                        profile,
+                        json,
+                        command,
+                    },
+            }) => {
+                assert_eq!(cwd, None);
+                assert_eq!(profile, None);
+                assert!(json);
+                assert_eq!(command, "rm -rf /");
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        let args = Args::try_parse_from([
+            "corgiterm",
+            "safe-mode",
+            "check",
+            "--profile",
+            "production",
+            "terraform apply",
+        ])
+        .unwrap();
+        match args.command {
+            Some(Command::SafeMode {
+                action: SafeModeAction::Check { profile, .. },
+            }) => assert_eq!(profile.as_deref(), Some("production")),
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        assert!(Args::try_parse_from(["corgiterm", "safe-mode", "check"]).is_err());
+    }
+
+    #[test]
+    fn test_trash_put_takes_rm_options() {
+        let args = Args::try_parse_from(["corgiterm", "trash", "put", "-rf", "--", "build", "-x"])
+            .unwrap();
+        match args.command {
+            Some(Command::Trash {
+                action:
+                    TrashAction::Put {
+                        force,
+                        recursive,
+                        dir,
+                        paths,
+                        ..
+                    },
+            }) => {
+                assert!(force);
+                assert!(recursive);
+                assert!(!dir);
+                assert_eq!(paths, [PathBuf::from("build"), PathBuf::from("-x")]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        assert!(Args::try_parse_from(["corgiterm", "trash", "put", "-R", "a"]).is_ok());
+        assert!(Args::try_parse_from(["corgiterm", "trash", "put", "-i", "a"]).is_err());
+        assert!(matches!(
+            Args::try_parse_from(["corgiterm", "trash", "undo"])
+                .unwrap()
+                .command,
+            Some(Command::Trash {
+                action: TrashAction::Undo
+            })
+        ));
+    }
+
+    #[test]
+    fn test_history_import_parsing() {
+        let args = Args::try_parse_from(["corgiterm", "history", "import"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::History {
+                action: HistoryAction::Import {
+                    format: None,
+                    path: None
+                }
+            })
+        ));
+
+        let args = Args::try_parse_from([
+            "corgiterm",
+            "history",
+            "import",
+            "--format",
+            "zsh",
+            "/tmp/zsh_history",
+        ])
+        .unwrap();
+        match args.command {
+            Some(Command::History {
+                action: HistoryAction::Import { format, path },
+            }) => {
+                assert_eq!(format, Some(corgiterm_core::ShellHistoryFormat::Zsh));
+                assert_eq!(path, Some(PathBuf::from("/tmp/zsh_history")));
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        assert!(Args::try_parse_from(["corgiterm", "history", "import", "/tmp/h"]).is_err());
+        assert!(
+            Args::try_parse_from(["corgiterm", "history", "import", "--format", "tcsh"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_mcp_subcommand_parsing() {
+        let args = Args::try_parse_from(["corgiterm", "mcp"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Mcp { socket: None })));
+
+        let args =
+            Args::try_parse_from(["corgiterm", "mcp", "--socket", "/tmp/corgi-mcp.sock"]).unwrap();
+        match args.command {
+            Some(Command::Mcp { socket }) => {
+                assert_eq!(socket, Some(PathBuf::from("/tmp/corgi-mcp.sock")))
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
 }